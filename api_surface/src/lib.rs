@@ -0,0 +1,426 @@
+//! Public API of a library, extracted from its sources, for finding semver-breaking changes between releases.
+//!
+//! This is only a syntactic approximation. Items are identified by their path in the module tree,
+//! and compared by their normalized signatures. Items generated by macros or `cfg`-dependent are not seen,
+//! and items moved to private modules and re-exported via globs are given the benefit of the doubt.
+
+use log::debug;
+use quote::ToTokens;
+use semver::Version as SemVer;
+use serde_derive::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use syn::{Attribute, Fields, FnArg, Generics, ImplItem, Item, ReturnType, Signature, TraitItem, Type, UseTree, Visibility};
+
+/// Limits recursion through `mod` declarations
+const MAX_MODULE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiSurface {
+    /// `"kind path"` (e.g. `"fn io::read"`, `"field Config.name"`) → normalized signature
+    pub items: BTreeMap<String, String>,
+    /// Trait items without a default impl (their key → key of their trait)
+    pub required_trait_items: BTreeMap<String, String>,
+    /// Modules with `pub use …::*`, where anything could have been re-exported
+    pub glob_reexports: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiChange {
+    /// item key
+    Removed(String),
+    /// item key, old signature, new signature
+    Changed(String, String, String),
+    /// A trait got a new item that all implementations must provide
+    AddedRequired(String),
+}
+
+/// A release that has been published as semver-compatible, but has breaking API changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SemverViolation {
+    pub prev_version: String,
+    pub version: String,
+    pub changes: Vec<ApiChange>,
+}
+
+impl ApiChange {
+    pub fn item(&self) -> &str {
+        match self {
+            Self::Removed(item) | Self::Changed(item, ..) | Self::AddedRequired(item) => item,
+        }
+    }
+}
+
+impl ApiSurface {
+    /// `lib_path` is the library's root file, e.g. `src/lib.rs`. `files` are all Rust sources of the crate, by their path relative to the crate root.
+    ///
+    /// `None` if the root file is missing or doesn't parse.
+    pub fn from_sources(lib_path: &Path, files: &BTreeMap<PathBuf, String>) -> Option<Self> {
+        let lib = syn::parse_file(files.get(lib_path)?).map_err(|e| debug!("{}: {e}", lib_path.display())).ok()?;
+        let mut extractor = Extractor { files, surface: Self::default(), impl_items: Vec::new() };
+        extractor.items(&lib.items, "", lib_path.parent().unwrap_or(Path::new("")), 0);
+        Some(extractor.finish())
+    }
+
+    /// Kind (`"fn"`, `"struct"`, etc.) and the last path segment of every item
+    pub fn item_names(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.items.keys().filter_map(|k| k.split_once(' '))
+            .map(|(kind, path)| (kind, path.rsplit("::").next().unwrap_or(path)))
+    }
+
+    /// Changes in `newer` that would break users of `self`. Additions are not breaking, except required trait items.
+    pub fn breaking_changes(&self, newer: &Self) -> Vec<ApiChange> {
+        let newer_paths: BTreeSet<_> = newer.items.keys().filter_map(|k| k.split_once(' ')).map(|(_, path)| path).collect();
+        let mut changes = Vec::new();
+        for (key, old_sig) in &self.items {
+            match newer.items.get(key) {
+                // re-exports are fine to point elsewhere as long as the name still exists
+                Some(new_sig) => if new_sig != old_sig && !key.starts_with("use ") {
+                    changes.push(ApiChange::Changed(key.clone(), old_sig.clone(), new_sig.clone()));
+                },
+                None => {
+                    let path = key.split_once(' ').map_or(key.as_str(), |(_, path)| path);
+                    let module = path.rsplit_once("::").map_or("", |(module, _)| module);
+                    // could have been replaced with a re-export or a type alias
+                    if newer_paths.contains(path) || newer.glob_reexports.contains(module) {
+                        continue;
+                    }
+                    changes.push(ApiChange::Removed(key.clone()));
+                },
+            }
+        }
+        for (key, trait_key) in &newer.required_trait_items {
+            if !self.required_trait_items.contains_key(key) && self.items.contains_key(trait_key) {
+                changes.push(ApiChange::AddedRequired(key.clone()));
+            }
+        }
+        changes
+    }
+}
+
+/// True if Cargo considers `newer` a compatible upgrade from `older`, so it must not have breaking changes
+pub fn is_compatible_upgrade(older: &SemVer, newer: &SemVer) -> bool {
+    if !older.pre.is_empty() || !newer.pre.is_empty() || newer <= older {
+        return false;
+    }
+    match (older.major, newer.major) {
+        // 0.0.x are all incompatible
+        (0, 0) => older.minor != 0 && older.minor == newer.minor,
+        (a, b) => a == b,
+    }
+}
+
+struct Extractor<'a> {
+    files: &'a BTreeMap<PathBuf, String>,
+    surface: ApiSurface,
+    /// Name of the `Self` type, item key, signature. Impls can be anywhere, so they're added once all public types are known.
+    impl_items: Vec<(Option<String>, String, String)>,
+}
+
+impl Extractor<'_> {
+    /// `dir` is where files of child modules are
+    fn items(&mut self, items: &[Item], module: &str, dir: &Path, depth: usize) {
+        for item in items {
+            match item {
+                Item::Fn(f) if is_public(&f.vis, &f.attrs) => {
+                    self.insert("fn", join(module, &f.sig.ident), fn_signature(&f.sig));
+                },
+                Item::Struct(s) if is_public(&s.vis, &s.attrs) => {
+                    self.add_struct("struct", join(module, &s.ident), &s.generics, &s.fields, &s.attrs);
+                },
+                Item::Union(u) if is_public(&u.vis, &u.attrs) => {
+                    self.add_struct("union", join(module, &u.ident), &u.generics, &Fields::Named(u.fields.clone()), &u.attrs);
+                },
+                Item::Enum(e) if is_public(&e.vis, &e.attrs) => {
+                    let path = join(module, &e.ident);
+                    let variants = e.variants.iter().filter(|v| !is_doc_hidden(&v.attrs)).map(|v| format!("{}{}", v.ident, fields_signature(&v.fields)));
+                    if has_attr(&e.attrs, "non_exhaustive") {
+                        for v in variants {
+                            self.insert("variant", format!("{path}::{v}"), String::new());
+                        }
+                        self.insert("enum", path, generics_signature(&e.generics));
+                    } else {
+                        // adding variants breaks exhaustive matches
+                        let variants = variants.collect::<Vec<_>>().join(", ");
+                        self.insert("enum", path, format!("{} {{ {variants} }}", generics_signature(&e.generics)));
+                    }
+                },
+                Item::Trait(t) if is_public(&t.vis, &t.attrs) => {
+                    let path = join(module, &t.ident);
+                    let supertraits = t.supertraits.iter().map(tokens).collect::<Vec<_>>().join(" + ");
+                    // sealed traits can't be implemented outside of the crate, so new items are fine
+                    let is_sealed = supertraits.contains("Sealed");
+                    let trait_key = format!("trait {path}");
+                    for ti in &t.items {
+                        let (kind, name, sig, is_required) = match ti {
+                            TraitItem::Method(m) => ("fn", &m.sig.ident, fn_signature(&m.sig), m.default.is_none()),
+                            TraitItem::Type(ty) => ("type", &ty.ident, ty.bounds.iter().map(tokens).collect::<Vec<_>>().join(" + "), ty.default.is_none()),
+                            TraitItem::Const(c) => ("const", &c.ident, tokens(&c.ty), c.default.is_none()),
+                            _ => continue,
+                        };
+                        let key = format!("{kind} {path}::{name}");
+                        if is_required && !is_sealed {
+                            self.surface.required_trait_items.insert(key.clone(), trait_key.clone());
+                        }
+                        self.surface.items.insert(key, sig);
+                    }
+                    let sig = format!("{}{}: {supertraits}", if t.unsafety.is_some() { "unsafe " } else { "" }, generics_signature(&t.generics));
+                    self.surface.items.insert(trait_key, sig);
+                },
+                Item::Type(t) if is_public(&t.vis, &t.attrs) => {
+                    self.insert("type", join(module, &t.ident), format!("{} = {}", generics_signature(&t.generics), tokens(&t.ty)));
+                },
+                Item::Const(c) if is_public(&c.vis, &c.attrs) => {
+                    self.insert("const", join(module, &c.ident), tokens(&c.ty));
+                },
+                Item::Static(s) if is_public(&s.vis, &s.attrs) => {
+                    self.insert("static", join(module, &s.ident), format!("{}{}", if s.mutability.is_some() { "mut " } else { "" }, tokens(&s.ty)));
+                },
+                Item::Macro(m) if m.mac.path.is_ident("macro_rules") && has_attr(&m.attrs, "macro_export") && !is_doc_hidden(&m.attrs) => {
+                    // exported macros are always at the crate root
+                    if let Some(name) = &m.ident {
+                        self.insert("macro", name.to_string(), String::new());
+                    }
+                },
+                Item::Mod(m) if is_public(&m.vis, &m.attrs) && !is_cfg_test(&m.attrs) && depth < MAX_MODULE_DEPTH => {
+                    let path = join(module, &m.ident);
+                    let child_dir = dir.join(m.ident.to_string());
+                    match &m.content {
+                        Some((_, items)) => self.items(items, &path, &child_dir, depth + 1),
+                        None => {
+                            let parsed = self.module_file(dir, &m.ident.to_string(), &m.attrs)
+                                .and_then(|source| syn::parse_file(source).map_err(|e| debug!("{path}: {e}")).ok());
+                            if let Some(file) = parsed {
+                                self.items(&file.items, &path, &child_dir, depth + 1);
+                            }
+                        },
+                    }
+                },
+                Item::Use(u) if is_public(&u.vis, &u.attrs) => {
+                    self.add_use(module, &u.tree, String::new());
+                },
+                Item::Impl(i) if i.trait_.is_none() => {
+                    let self_name = match type_name(&i.self_ty) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    for ii in &i.items {
+                        if let ImplItem::Method(m) = ii {
+                            if is_public(&m.vis, &m.attrs) {
+                                let key = format!("fn {}::{}", join(module, &self_name), m.sig.ident);
+                                let sig = format!("{}{}", generics_signature(&i.generics), fn_signature(&m.sig));
+                                self.impl_items.push((Some(self_name.clone()), key, sig));
+                            }
+                        }
+                    }
+                },
+                Item::Impl(i) => {
+                    if let Some((None, trait_path, _)) = &i.trait_ {
+                        if !is_doc_hidden(&i.attrs) {
+                            // removing trait impls is breaking, but the bounds are too hard to compare
+                            let key = format!("impl {} for {}", tokens(trait_path), tokens(&i.self_ty));
+                            self.impl_items.push((type_name(&i.self_ty), key, String::new()));
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Adds impls of types that are public. Types are matched only by name, since their paths aren't resolved.
+    fn finish(mut self) -> ApiSurface {
+        let public_types: BTreeSet<String> = self.surface.item_names()
+            .filter(|&(kind, _)| matches!(kind, "struct" | "enum" | "union" | "type" | "use"))
+            .map(|(_, name)| name.to_owned())
+            .collect();
+        for (self_name, key, sig) in self.impl_items {
+            // impls for references, tuples, etc. can't be checked
+            if self_name.map_or(true, |name| public_types.contains(&name)) {
+                self.surface.items.insert(key, sig);
+            }
+        }
+        self.surface
+    }
+
+    fn insert(&mut self, kind: &str, path: String, signature: String) {
+        self.surface.items.insert(format!("{kind} {path}"), signature);
+    }
+
+    fn add_struct(&mut self, kind: &str, path: String, generics: &Generics, fields: &Fields, attrs: &[Attribute]) {
+        let pub_fields = fields.iter().enumerate()
+            .filter(|(_, f)| matches!(f.vis, Visibility::Public(_)) && !is_doc_hidden(&f.attrs))
+            .map(|(i, f)| (f.ident.as_ref().map_or_else(|| i.to_string(), |i| i.to_string()), tokens(&f.ty)));
+        let can_be_constructed = !has_attr(attrs, "non_exhaustive") && fields.iter().all(|f| matches!(f.vis, Visibility::Public(_)));
+        if can_be_constructed {
+            // adding fields breaks struct literals and patterns
+            let fields = pub_fields.map(|(name, ty)| format!("{name}: {ty}")).collect::<Vec<_>>().join(", ");
+            self.insert(kind, path, format!("{} {{ {fields} }}", generics_signature(generics)));
+        } else {
+            for (name, ty) in pub_fields {
+                self.insert("field", format!("{path}.{name}"), ty);
+            }
+            self.insert(kind, path, generics_signature(generics));
+        }
+    }
+
+    fn add_use(&mut self, module: &str, tree: &UseTree, prefix: String) {
+        match tree {
+            UseTree::Path(p) => self.add_use(module, &p.tree, format!("{prefix}{}::", p.ident)),
+            UseTree::Name(n) => {
+                let target = format!("{prefix}{}", n.ident);
+                let name = if n.ident == "self" { prefix.trim_end_matches("::").rsplit("::").next().unwrap_or_default().to_owned() } else { n.ident.to_string() };
+                if !name.is_empty() {
+                    self.insert("use", join(module, &name), target);
+                }
+            },
+            UseTree::Rename(r) => if r.rename != "_" {
+                self.insert("use", join(module, &r.rename), format!("{prefix}{}", r.ident));
+            },
+            UseTree::Glob(_) => {
+                self.surface.glob_reexports.insert(module.to_owned());
+            },
+            UseTree::Group(g) => for t in &g.items {
+                self.add_use(module, t, prefix.clone());
+            },
+        }
+    }
+
+    /// Source of `mod name;` declared in a file whose child modules are in `dir`
+    fn module_file(&self, dir: &Path, name: &str, attrs: &[Attribute]) -> Option<&str> {
+        let custom_path = attrs.iter().filter(|a| a.path.is_ident("path")).find_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(s), .. })) => Some(s.value()),
+            _ => None,
+        });
+        let candidates = match custom_path {
+            // relative to the declaring file, which is either in `dir` or its parent
+            Some(p) => vec![dir.join(&p), dir.parent().unwrap_or(dir).join(&p)],
+            None => vec![dir.join(format!("{name}.rs")), dir.join(name).join("mod.rs")],
+        };
+        candidates.iter().find_map(|p| self.files.get(p)).map(|s| s.as_str())
+    }
+}
+
+/// Last segment of a path type
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+}
+
+fn join(module: &str, name: &impl ToString) -> String {
+    let name = name.to_string();
+    if module.is_empty() { name } else { format!("{module}::{name}") }
+}
+
+/// Argument types without their names, since renaming arguments isn't a breaking change
+fn fn_signature(sig: &Signature) -> String {
+    let inputs = sig.inputs.iter().map(|arg| match arg {
+        FnArg::Receiver(r) => tokens(r),
+        FnArg::Typed(t) => tokens(&t.ty),
+    }).collect::<Vec<_>>().join(", ");
+    let output = match &sig.output {
+        ReturnType::Default => String::new(),
+        ReturnType::Type(_, ty) => format!(" -> {}", tokens(ty)),
+    };
+    format!("{}{}fn{}({inputs}){output}{}",
+        if sig.unsafety.is_some() { "unsafe " } else { "" },
+        if sig.asyncness.is_some() { "async " } else { "" },
+        tokens(&sig.generics),
+        sig.generics.where_clause.as_ref().map(|w| format!(" {}", tokens(w))).unwrap_or_default())
+}
+
+fn generics_signature(generics: &Generics) -> String {
+    format!("{}{}", tokens(generics), generics.where_clause.as_ref().map(|w| format!(" {}", tokens(w))).unwrap_or_default())
+}
+
+fn fields_signature(fields: &Fields) -> String {
+    let types = fields.iter().map(|f| match &f.ident {
+        Some(name) => format!("{name}: {}", tokens(&f.ty)),
+        None => tokens(&f.ty),
+    }).collect::<Vec<_>>().join(", ");
+    match fields {
+        Fields::Named(_) => format!(" {{ {types} }}"),
+        Fields::Unnamed(_) => format!("({types})"),
+        Fields::Unit => String::new(),
+    }
+}
+
+/// `proc_macro2` puts spaces between all tokens
+fn tokens(t: &impl ToTokens) -> String {
+    let mut s = t.to_token_stream().to_string();
+    for (from, to) in [(" :: ", "::"), (":: ", "::"), (" : ", ": "), (" < ", "<"), ("< ", "<"), (" >", ">"), (" ,", ","), ("& ", "&"), (" (", "("), ("( ", "("), (" )", ")"), ("[ ", "["), (" ]", "]"), (" ;", ";")] {
+        s = s.replace(from, to);
+    }
+    s
+}
+
+fn is_public(vis: &Visibility, attrs: &[Attribute]) -> bool {
+    matches!(vis, Visibility::Public(_)) && !is_doc_hidden(attrs)
+}
+
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|a| a.path.is_ident(name))
+}
+
+fn is_doc_hidden(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|a| a.path.is_ident("doc") && a.tokens.to_string().contains("hidden"))
+}
+
+fn is_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|a| a.path.is_ident("cfg") && a.tokens.to_string() == "(test)")
+}
+
+#[test]
+fn detects_breaking_changes() {
+    let v1 = r#"
+        pub mod io;
+        pub struct Config { pub name: String }
+        pub struct Opaque { pub id: u32, secret: u8 }
+        pub enum Mode { A, B }
+        pub trait Codec { fn decode(&self, data: &[u8]) -> Vec<u8>; }
+        pub fn old() {}
+        pub fn moved() {}
+        impl Config { pub fn new(name: String) -> Self { Self { name } } }
+        struct Private;
+        impl Private { pub fn not_public() {} }
+        impl Clone for Private { fn clone(&self) -> Self { Private } }
+    "#;
+    let v2 = r#"
+        pub mod io;
+        mod inner { pub fn moved() {} }
+        pub use inner::moved;
+        pub struct Config { pub name: String, pub extra: bool }
+        pub struct Opaque { pub id: u32, secret: u8, more: u16 }
+        pub enum Mode { A, B }
+        pub trait Codec { fn decode(&self, input: &[u8]) -> Vec<u8>; fn encode(&self); }
+        impl Config { pub fn new(renamed: String) -> Self { Self { name: renamed, extra: false } } }
+    "#;
+    let io = "pub fn read() -> u8 { 0 } #[cfg(test)] pub mod tests {}".to_string();
+    let surface = |lib: &str| {
+        let files = [(PathBuf::from("src/lib.rs"), lib.to_string()), (PathBuf::from("src/io.rs"), io.clone())].into_iter().collect();
+        ApiSurface::from_sources(Path::new("src/lib.rs"), &files).unwrap()
+    };
+    let (a, b) = (surface(v1), surface(v2));
+    assert_eq!(a.items["fn io::read"], "fn() -> u8");
+    assert_eq!(a.items["fn Config::new"], "fn(String) -> Self");
+    assert!(a.items.contains_key("field Opaque.id"));
+    assert!(!a.items.keys().any(|k| k.contains("tests")));
+    assert!(!a.items.keys().any(|k| k.contains("Private")));
+
+    let changes = a.breaking_changes(&b);
+    assert_eq!(changes, [
+        ApiChange::Removed("fn old".into()),
+        ApiChange::Changed("struct Config".into(), " { name: String }".into(), " { name: String, extra: bool }".into()),
+        ApiChange::AddedRequired("fn Codec::encode".into()),
+    ]);
+    assert!(b.breaking_changes(&b).is_empty());
+
+    let v = |s: &str| s.parse::<SemVer>().unwrap();
+    assert!(is_compatible_upgrade(&v("1.2.3"), &v("1.3.0")));
+    assert!(is_compatible_upgrade(&v("0.2.3"), &v("0.2.4")));
+    assert!(!is_compatible_upgrade(&v("0.2.3"), &v("0.3.0")));
+    assert!(!is_compatible_upgrade(&v("0.0.1"), &v("0.0.2")));
+    assert!(!is_compatible_upgrade(&v("1.0.0"), &v("2.0.0-beta.1")));
+}
@@ -0,0 +1,500 @@
+use crate::parse::DIVIDER;
+use crate::CrateToRun;
+#[cfg(test)]
+use ahash::HashMap;
+use crate_db::builddb::FeatureSet;
+use crate_db::builddb::RustcMinorVersion;
+#[cfg(test)]
+use kitchen_sink::SemVer;
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::Arc;
+
+pub type BResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Runs `cargo check` of crates with specific rustc versions.
+///
+/// The output is in the format expected by `parse_analyses`: one section per crate,
+/// each starting with `DIVIDER` on both stdout and stderr.
+pub trait BuildExecutor: Send {
+    /// Called once before any builds, e.g. to install toolchains
+    fn prepare(&self) -> BResult<()>;
+
+    /// Checks all crates in parallel, each with its own `rustc_ver`. Returns (stdout, stderr).
+    fn run(&self, versions: &[CrateToRun]) -> BResult<(String, String)>;
+
+    /// Where the builds eat disk space, if they do
+    fn scratch_dir(&self) -> Option<&Path>;
+}
+
+/// Builds in the `rustops/crates-build-env` image, with all toolchains installed in the image
+pub struct DockerExecutor {
+    docker_root: PathBuf,
+    rust_versions: Vec<RustcMinorVersion>,
+}
+
+const DOCKER_NAME: &str = "rustesting2";
+const DOCKERFILE_DEFAULT_RUSTC: RustcMinorVersion = 61;
+const DOCKERFILE_PRELUDE: &str = r##"
+FROM rustops/crates-build-env
+RUN useradd -u 4321 --create-home --user-group -s /bin/bash rustyuser
+RUN chown -R 4321:4321 /home/rustyuser
+RUN swapoff -a || true
+USER rustyuser
+WORKDIR /home/rustyuser
+RUN curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --profile minimal --default-toolchain 1.61.0 --verbose
+ENV PATH="$PATH:/home/rustyuser/.cargo/bin"
+RUN rustup set profile minimal
+"##; // must have newline!
+
+const TEMP_JUNK_DIR: &str = "/var/tmp/crates_env";
+
+/// Tarballs downloaded by our crates-io client
+const TARBALLS_DIR: &str = "/var/lib/crates-server/tarballs";
+
+impl DockerExecutor {
+    pub fn new(docker_root: PathBuf, rust_versions: &[RustcMinorVersion]) -> Self {
+        Self { docker_root, rust_versions: rust_versions.to_vec() }
+    }
+}
+
+impl BuildExecutor for DockerExecutor {
+    fn prepare(&self) -> BResult<()> {
+        let _ = std::fs::create_dir_all(&self.docker_root);
+
+        for &p in &["git","registry","target","job_inputs"] {
+            let p = Path::new(TEMP_JUNK_DIR).join(p);
+            let _ = std::fs::create_dir_all(&p);
+        }
+        // let _ = Command::new("chmod").arg("-R").arg("a+rwX").arg(TEMP_JUNK_DIR).status()?;
+        // let _ = Command::new("chown").arg("-R").arg("4321:4321").arg(TEMP_JUNK_DIR).status()?;
+
+        let mut child = Command::new("docker")
+            .current_dir(&self.docker_root)
+            .arg("build")
+            .arg("-t").arg(DOCKER_NAME)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(DOCKERFILE_PRELUDE.as_bytes())?;
+        for &v in &self.rust_versions {
+            if v != DOCKERFILE_DEFAULT_RUSTC {
+                writeln!(stdin, "RUN rustup toolchain add {}", rustc_minor_ver_to_version(v))?;
+            }
+        }
+        stdin.write_all(b"RUN rustup toolchain list\nRUN chmod -R a-w ~/.rustup ~/.cargo/bin ~/.cargo/env\n")?;
+        drop(stdin);
+
+        let res = child.wait()?;
+        if !res.success() {
+            Err("failed build")?;
+        }
+
+        let _ = Command::new("docker")
+            .current_dir(&self.docker_root)
+            .arg("run")
+            .arg("--rm")
+            .arg("-v").arg(format!("{TEMP_JUNK_DIR}/git:/home/rustyuser/.cargo/git"))
+            .arg("-v").arg(format!("{TEMP_JUNK_DIR}/registry:/home/rustyuser/.cargo/registry"))
+            .arg(DOCKER_NAME)
+            .arg("bash").arg("-c").arg("cargo install libc --vers 99.9.9 --color=always -vv") // force index update
+            .status()?;
+        Ok(())
+    }
+
+    fn run(&self, versions: &[CrateToRun]) -> BResult<(String, String)> {
+        link_cached_tarballs(&Path::new(TEMP_JUNK_DIR).join("registry"), versions);
+
+        let job_inputs_root = Path::new(TEMP_JUNK_DIR).join("job_inputs");
+        write_job_inputs(&job_inputs_root, versions)?;
+
+        let script = build_script(versions, &ScriptPaths {
+            target_root: "/home/rustyuser/cargo_target",
+            registry: "/home/rustyuser/.cargo/registry",
+            job_inputs_root: "/home/rustyuser/job_inputs",
+        });
+
+        let mut cmd = Command::new("nice");
+        cmd.current_dir(&self.docker_root)
+            .arg("docker")
+            .arg("run")
+            .arg("--rm")
+            .arg("-v").arg(format!("{TEMP_JUNK_DIR}/git:/home/rustyuser/.cargo/git"))
+            .arg("-v").arg(format!("{TEMP_JUNK_DIR}/registry:/home/rustyuser/.cargo/registry"))
+            .arg("-v").arg(format!("{TEMP_JUNK_DIR}/target:/home/rustyuser/cargo_target"))
+            .arg("-v").arg(format!("{}:/home/rustyuser/job_inputs:ro", job_inputs_root.display()))
+            .arg("-e").arg("CARGO_INCREMENTAL=0")
+            // .arg("-e").arg("CARGO_UNSTABLE_AVOID_DEV_DEPS=true") // breaks --locked
+            .arg("-e").arg("CARGO_UNSTABLE_BINDEPS=true")
+            .arg("-e").arg("CARGO_BUILD_JOBS=2")
+            .arg("-m2500m")
+            .arg("--cpus=2")
+            .arg(DOCKER_NAME)
+            .arg("bash").arg("-c").arg(script);
+        run_and_capture(cmd)
+    }
+
+    fn scratch_dir(&self) -> Option<&Path> {
+        Some(Path::new(TEMP_JUNK_DIR))
+    }
+}
+
+/// Builds with toolchains already installed on the host via rustup.
+///
+/// Uses bubblewrap (`bwrap`) to keep the builds away from the rest of the filesystem.
+/// Without it build scripts and proc macros would run with the same access as the builder itself,
+/// so that has to be explicitly allowed.
+pub struct LocalExecutor {
+    /// `CARGO_HOME`, target dirs and job inputs live here
+    work_dir: PathBuf,
+    rust_versions: Vec<RustcMinorVersion>,
+    sandboxed: bool,
+}
+
+impl LocalExecutor {
+    /// Fails if `bwrap` isn't installed, unless `allow_unsandboxed` is set
+    pub fn new(work_dir: PathBuf, rust_versions: &[RustcMinorVersion], allow_unsandboxed: bool) -> BResult<Self> {
+        let sandboxed = Command::new("bwrap").arg("--version").output().map_or(false, |out| out.status.success());
+        if !sandboxed {
+            if !allow_unsandboxed {
+                return Err("bwrap not found; install bubblewrap or pass --allow-unsandboxed to run untrusted builds without a sandbox".into());
+            }
+            warn!("bwrap not found, builds won't be sandboxed");
+        }
+        Ok(Self { work_dir, rust_versions: rust_versions.to_vec(), sandboxed })
+    }
+
+    fn cargo_home(&self) -> PathBuf {
+        self.work_dir.join("cargo_home")
+    }
+}
+
+impl BuildExecutor for LocalExecutor {
+    fn prepare(&self) -> BResult<()> {
+        for &p in &["cargo_home/registry", "target", "job_inputs", "builds"] {
+            std::fs::create_dir_all(self.work_dir.join(p))?;
+        }
+
+        let out = Command::new("rustup").arg("toolchain").arg("list").output()?;
+        let installed = String::from_utf8_lossy(&out.stdout);
+        let missing: Vec<_> = self.rust_versions.iter()
+            .map(|&v| rustc_minor_ver_to_version(v))
+            .filter(|v| !installed.lines().any(|l| l.starts_with(v.as_str())))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("missing toolchains, install them with: rustup toolchain add {}", missing.join(" ")).into());
+        }
+
+        let _ = Command::new("cargo")
+            .env("CARGO_HOME", self.cargo_home())
+            .arg("install").arg("libc").arg("--vers").arg("99.9.9").arg("--color=always") // force index update
+            .status()?;
+        Ok(())
+    }
+
+    fn run(&self, versions: &[CrateToRun]) -> BResult<(String, String)> {
+        let cargo_home = self.cargo_home();
+        link_cached_tarballs(&cargo_home.join("registry"), versions);
+
+        let job_inputs_root = self.work_dir.join("job_inputs");
+        write_job_inputs(&job_inputs_root, versions)?;
+
+        let target_root = self.work_dir.join("target");
+        let registry = cargo_home.join("registry");
+        let script = build_script(versions, &ScriptPaths {
+            target_root: &target_root.to_string_lossy(),
+            registry: &registry.to_string_lossy(),
+            job_inputs_root: &job_inputs_root.to_string_lossy(),
+        });
+
+        let builds_dir = self.work_dir.join("builds");
+        let mut cmd = Command::new("nice");
+        if self.sandboxed {
+            // everything read-only, except the work dir and a private /tmp
+            cmd.arg("bwrap")
+                .arg("--ro-bind").arg("/").arg("/")
+                .arg("--dev").arg("/dev")
+                .arg("--proc").arg("/proc")
+                .arg("--tmpfs").arg("/tmp")
+                .arg("--bind").arg(&self.work_dir).arg(&self.work_dir)
+                .arg("--ro-bind").arg(&job_inputs_root).arg(&job_inputs_root)
+                .arg("--unshare-all")
+                .arg("--share-net") // cargo fetch
+                .arg("--die-with-parent")
+                .arg("--chdir").arg(&builds_dir);
+        }
+        cmd.current_dir(&builds_dir)
+            .env("CARGO_HOME", &cargo_home)
+            .env("CARGO_INCREMENTAL", "0")
+            .env("CARGO_UNSTABLE_BINDEPS", "true")
+            .env("CARGO_BUILD_JOBS", "2")
+            .arg("bash").arg("-c").arg(script);
+        run_and_capture(cmd)
+    }
+
+    fn scratch_dir(&self) -> Option<&Path> {
+        Some(&self.work_dir)
+    }
+}
+
+/// Doesn't build anything. Returns canned cargo output for known crate versions, for testing the builder without toolchains.
+///
+/// Crates without any output are reported as broken builds, which `parse_analyses` skips.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeExecutor {
+    outputs: HashMap<(RustcMinorVersion, String, SemVer), (String, String)>,
+    /// Every `run` call, in order
+    pub runs: Mutex<Vec<Vec<(RustcMinorVersion, Arc<str>, SemVer)>>>,
+}
+
+#[cfg(test)]
+impl FakeExecutor {
+    /// `stdout` is cargo's `--message-format=json` output
+    pub fn add_output(&mut self, rustc_ver: RustcMinorVersion, crate_name: &str, version: &str, stdout: &str, stderr: &str) {
+        self.outputs.insert((rustc_ver, crate_name.into(), version.parse().expect("semver")), (stdout.into(), stderr.into()));
+    }
+
+    /// Output of a successful `cargo check` of the crate without any dependencies
+    pub fn add_success(&mut self, rustc_ver: RustcMinorVersion, crate_name: &str, version: &str) {
+        let artifact = format!(r#"{{"reason":"compiler-artifact","package_id":"{crate_name} {version} (registry+https://github.com/rust-lang/crates.io-index)","target":{{"kind":["lib"],"name":"{crate_name}","edition":"2018"}},"filenames":["/tmp/target/debug/deps/lib{crate_name}.rmeta"]}}
+{{"reason":"build-finished","success":true}}"#);
+        self.add_output(rustc_ver, crate_name, version, &artifact, "");
+    }
+}
+
+#[cfg(test)]
+impl BuildExecutor for FakeExecutor {
+    fn prepare(&self) -> BResult<()> {
+        Ok(())
+    }
+
+    fn run(&self, versions: &[CrateToRun]) -> BResult<(String, String)> {
+        self.runs.lock().push(versions.iter().map(|c| (c.rustc_ver, c.crate_name.clone(), c.version.clone())).collect());
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        for c in versions {
+            let rustc = rustc_minor_ver_to_version(c.rustc_ver);
+            let mode = c.feature_set.as_ref().map(|f| format!(" {f}")).unwrap_or_default();
+            stdout += &format!("{DIVIDER}\nCHECKING {rustc} {} {}{mode}\n", c.crate_name, c.version);
+            stderr += &format!("{DIVIDER}\n");
+            if let Some((out, err)) = self.outputs.get(&(c.rustc_ver, c.crate_name.to_string(), c.version.clone())) {
+                stdout += out;
+                stdout.push('\n');
+                stderr += err;
+                stderr.push('\n');
+            }
+        }
+        Ok((stdout, stderr))
+    }
+
+    fn scratch_dir(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Paths as seen by the build script
+struct ScriptPaths<'a> {
+    target_root: &'a str,
+    registry: &'a str,
+    job_inputs_root: &'a str,
+}
+
+/// Build mode argument of the bash script below
+fn job_mode(c: &CrateToRun) -> String {
+    c.feature_set.as_ref().map_or_else(|| "default".into(), |f| f.to_string())
+}
+
+// must match bash script below
+fn job_inputs_dir(root: &Path, c: &CrateToRun) -> PathBuf {
+    root.join(format!("crate-{}-{}--{}-{}-job", c.crate_name, c.version, rustc_minor_ver_to_version(c.rustc_ver), job_mode(c)))
+}
+
+// reuse tarballs cached by our crates-io client
+fn link_cached_tarballs(registry: &Path, versions: &[CrateToRun]) {
+    for c in versions {
+        let dest = registry.join("cache/github.com-1ecc6299db9ec823").join(format!("{}-{}.crate", c.crate_name, c.version));
+        if !dest.exists() {
+            let src = Path::new(TARBALLS_DIR).join(format!("{}/{}.crate", c.crate_name, c.version));
+            let _ = std::fs::hard_link(&src, &dest).map_err(|e| warn!("tarball {} -> {}: {}", src.display(), dest.display(), e));
+        }
+    }
+}
+
+fn write_job_inputs(job_inputs_root: &Path, versions: &[CrateToRun]) -> BResult<()> {
+    for c in versions {
+        let dir = job_inputs_dir(job_inputs_root, c);
+        let _ = std::fs::create_dir(&dir);
+
+        let mut cargo_toml = format!("[package]\nname=\"_____\"\nversion=\"0.0.0\"\n[profile.dev]\ndebug=false\n[dependencies]\n{} = {}\n", c.crate_name, dependency_toml(c));
+        for (c, v) in &c.required_deps {
+            use std::fmt::Write;
+            let _ = writeln!(&mut cargo_toml, "{c} = \"<= {v}, {}{}\"", if v.major == 0 {"0."} else {""}, if v.major == 0 { v.minor } else { v.major });
+        }
+        debug!("{}", cargo_toml);
+        std::fs::write(dir.join("Cargo.toml"), cargo_toml)?;
+    }
+    Ok(())
+}
+
+/// Requirement for the tested crate. Builds of feature sets are compared with each other, so they're pinned to the exact version.
+fn dependency_toml(c: &CrateToRun) -> String {
+    let feature_set = match &c.feature_set {
+        Some(f) => f,
+        None => return format!("\"{}\"", c.version),
+    };
+    let default_features = !matches!(feature_set, FeatureSet::NoDefaultFeatures | FeatureSet::Feature(_));
+    let features = c.features.iter().map(|f| format!("{f:?}")).collect::<Vec<_>>().join(", ");
+    format!("{{ version = \"={}\", default-features = {default_features}, features = [{features}] }}", c.version)
+}
+
+fn build_script(versions: &[CrateToRun], paths: &ScriptPaths<'_>) -> String {
+    format!(r##"
+        set -euo pipefail
+        function yeet {{
+            mv "$1" "$1-delete" && rm -rf "$1-delete" # atomic delete
+        }}
+        function cleanup() {{
+            local rustver="$1"
+            local crate_name="$2"
+            local libver="$3"
+            export CARGO_TARGET_DIR="{target_root}/$rustver";
+            rm -rf "$CARGO_TARGET_DIR"/debug/*/"$crate_name-"*; # eats disk space
+            rm -rf "$CARGO_TARGET_DIR"/debug/*/"lib$crate_name-"*;
+            yeet "{registry}"/src/*/"$crate_name-$libver";
+        }}
+        function check_crate_with_rustc() {{
+            local rustver="$1"
+            local crate_name="$2"
+            local libver="$3"
+            local mode="$4"
+            local stdoutfile="$5"
+            local stderrfile="$6"
+            local job_inputs_dir="{job_inputs_root}/crate-$crate_name-$libver--$rustver-$mode-job"
+            mkdir -p "crate-$crate_name-$libver-$mode/src";
+            cd "crate-$crate_name-$libver-$mode";
+            touch src/lib.rs
+            cp "$job_inputs_dir/Cargo.toml" Cargo.toml
+            export CARGO_TARGET_DIR="{target_root}/$rustver";
+            if [ "$mode" == "minimal-versions" ]; then
+                echo >"$stdoutfile" "CHECKING $rustver $crate_name $libver $mode"
+                rm -f Cargo.lock
+                RUSTC_BOOTSTRAP=1 timeout 60 cargo +"$rustver" -Z minimal-versions generate-lockfile;
+                timeout 90 nice cargo +$rustver check -j3 --locked --message-format=json >>"$stdoutfile" 2>"$stderrfile";
+            elif [ "$mode" != "default" ]; then
+                echo >"$stdoutfile" "CHECKING $rustver $crate_name $libver $mode"
+                RUSTC_BOOTSTRAP=1 timeout 20 cargo +"$rustver" -Z no-index-update fetch || CARGO_NET_GIT_FETCH_WITH_CLI=true timeout 60 cargo +"$rustver" fetch --color=always -vv;
+                timeout 90 nice cargo +$rustver check -j3 --locked --message-format=json >>"$stdoutfile" 2>"$stderrfile";
+            else {{
+                echo >"$stdoutfile" "CHECKING $rustver $crate_name $libver"
+                RUSTC_BOOTSTRAP=1 timeout 20 cargo +"$rustver" -Z no-index-update fetch || CARGO_NET_GIT_FETCH_WITH_CLI=true timeout 60 cargo +"$rustver" fetch --color=always -vv;
+                timeout 90 nice cargo +$rustver {check_command} -j3 --locked --message-format=json >>"$stdoutfile" 2>"$stderrfile";
+            }} || {{
+                local rustfetchver="$rustver"
+                if [ "$rustver" == "1.20.0" ]; then
+                    rustfetchver="1.25.0"
+                fi
+                echo >>"$stdoutfile" "CHECKING $rustver $crate_name $libver (minimal-versions)"
+                printf > Cargo.toml '[package]\nname="_____"\nversion="0.0.0"\n[profile.dev]\ndebug=false\n[dependencies]\n%s = "=%s"\n[dev-dependencies]\nminimal-versions-are-broken="1"' "$crate_name" "$libver";
+                rm -f Cargo.lock
+                RUSTC_BOOTSTRAP=1 timeout 20 cargo +"$rustfetchver" -Z no-index-update -Z minimal-versions generate-lockfile;
+                timeout 40 nice cargo +$rustver {check_command} -j3 --locked --message-format=json >>"$stdoutfile" 2>>"$stderrfile";
+            }}
+            fi
+        }}
+        for job in {jobs}; do
+            (
+                check_crate_with_rustc $job "/tmp/output-$job" "/tmp/outputerr-$job" && echo "# R.$job done OK" || echo "# R.$job failed"
+            ) &
+        done
+        wait
+        for job in {jobs}; do
+            echo "{divider}"
+            cat "/tmp/output-$job";
+            echo >&2 "{divider}"
+            cat >&2 "/tmp/outputerr-$job";
+            cleanup $job
+        done
+    "##,
+        check_command = if versions.iter().all(|v| v.is_app && v.rustc_ver >= 58) { "install \"$crate_name\" --version \"$libver\" --debug --root=./install-test" } else { "check" },
+        divider = DIVIDER,
+        jobs = versions.iter().map(|c| format!("\"{} {} {} {}\"", rustc_minor_ver_to_version(c.rustc_ver), c.crate_name, c.version, job_mode(c))).collect::<Vec<_>>().join(" "),
+        target_root = paths.target_root,
+        registry = paths.registry,
+        job_inputs_root = paths.job_inputs_root,
+    )
+}
+
+fn run_and_capture(mut cmd: Command) -> BResult<(String, String)> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = streamfetch("stdout", child.stdout.take().unwrap());
+    let stderr = streamfetch("stderr", child.stderr.take().unwrap());
+
+    let status = child.wait()?;
+
+    let stdout = std::mem::take(&mut *stdout.lock());
+    let mut stderr = std::mem::take(&mut *stderr.lock());
+
+    if !status.success() {
+        info!("build exited with {status:?}");
+        stderr += &format!("\nexit failure {status:?}\n");
+    }
+
+    Ok((stdout, stderr))
+}
+
+pub fn rustc_minor_ver_to_version(rustc_minor_ver: u16) -> String {
+    if rustc_minor_ver == 56 {
+        "1.56.1".into()
+    } else {
+        format!("1.{rustc_minor_ver}.0")
+    }
+}
+
+fn streamfetch(prefix: &'static str, inp: impl std::io::Read + Send + 'static) -> Arc<Mutex<String>> {
+    let out = Arc::new(Mutex::new(String::new()));
+    let out2 = out.clone();
+    std::thread::spawn(move || {
+        let buf = BufReader::new(inp);
+        for line in buf.lines() {
+            let mut line = line.unwrap();
+            let mut tmp = out.lock();
+            tmp.push_str(&line);
+            tmp.push('\n');
+            if line.len() > 230 && line.is_char_boundary(230) {
+                line.truncate(230);
+            }
+            println!("{prefix}: {line}");
+        }
+    });
+    out2
+}
+
+#[test]
+fn script_paths() {
+    let c = CrateToRun { rustc_ver: 56, crate_name: "foo".into(), version: "1.0.0".parse().unwrap(), required_deps: vec![], is_app: false, feature_set: None, features: vec![] };
+    let f = CrateToRun { rustc_ver: 61, feature_set: Some(FeatureSet::Feature("serde".into())), features: vec!["serde".into()], ..c.clone() };
+    assert_eq!(r#"{ version = "=1.0.0", default-features = false, features = ["serde"] }"#, dependency_toml(&f));
+    let script = build_script(&[c, f], &ScriptPaths { target_root: "/t", registry: "/r", job_inputs_root: "/j" });
+    assert!(script.contains(r#"for job in "1.56.1 foo 1.0.0 default" "1.61.0 foo 1.0.0 feature=serde"; do"#));
+    assert!(script.contains(r#"CARGO_TARGET_DIR="/t/$rustver""#));
+    assert!(script.contains(r#"yeet "/r"/src/*/"#));
+    assert!(script.contains(r#"job_inputs_dir="/j/crate-"#));
+    assert!(!script.contains("/home/rustyuser"));
+}
@@ -1,41 +1,18 @@
 use std::collections::BTreeMap;
 use ahash::HashSet;
 use ahash::HashSetExt;
-use std::path::PathBuf;
 use std::time::Duration;
 use futures::future::try_join_all;
 use log::debug;
 use log::error;
 use log::info;
-use log::warn;
-use parking_lot::Mutex;
 use rand::Rng;
 use rand::seq::SliceRandom;
-
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Write;
 use std::sync::Arc;
 
 const CONCURRENCY: usize = 4; // builds in parallel
 const MAX_OLD_VERSIONS: usize = 3;
 
-const DOCKER_NAME: &str = "rustesting2";
-const DOCKERFILE_DEFAULT_RUSTC: RustcMinorVersion = 61;
-const DOCKERFILE_PRELUDE: &str = r##"
-FROM rustops/crates-build-env
-RUN useradd -u 4321 --create-home --user-group -s /bin/bash rustyuser
-RUN chown -R 4321:4321 /home/rustyuser
-RUN swapoff -a || true
-USER rustyuser
-WORKDIR /home/rustyuser
-RUN curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --profile minimal --default-toolchain 1.61.0 --verbose
-ENV PATH="$PATH:/home/rustyuser/.cargo/bin"
-RUN rustup set profile minimal
-"##; // must have newline!
-
-const TEMP_JUNK_DIR: &str = "/var/tmp/crates_env";
-
 const RUST_VERSIONS: [RustcMinorVersion; 15] = [
     61,
     60,
@@ -55,14 +32,14 @@ const RUST_VERSIONS: [RustcMinorVersion; 15] = [
 ];
 
 use crate_db::builddb::*;
+mod executor;
 mod parse;
 
+use executor::*;
 use kitchen_sink::*;
 use parse::*;
 
 use std::path::Path;
-use std::process::Command;
-use std::process::Stdio;
 
 struct ToCheck {
     score: u32,
@@ -70,10 +47,14 @@ struct ToCheck {
     version: SemVer,
     rustc_compat: CompatRanges,
     is_app: bool,
+    /// `None` for builds with default features, which check MSRV
+    feature_set: Option<FeatureSet>,
+    /// Features to enable explicitly
+    features: Vec<Box<str>>,
 }
 
-fn out_of_disk_space() -> bool {
-    match fs2::available_space(TEMP_JUNK_DIR) {
+fn out_of_disk_space(scratch_dir: &Path) -> bool {
+    match fs2::available_space(scratch_dir) {
         Ok(size) => {
             info!("free disk space: {}MB", size / 1_000_000);
             size < 5_000_000_000 // cargo easily chews gigabytes of disk space per build
@@ -90,9 +71,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let crates = Arc::new(kitchen_sink::KitchenSink::new_default().await?);
 
     let db = BuildDb::new(crates.main_cache_dir().join("builds.db"))?;
-    let docker_root = crates.main_cache_dir().join("docker");
+
+    let mut filters: Vec<_> = std::env::args().skip(1).collect();
+    let mut do_all = false;
+    let mut local = false;
+    let mut allow_unsandboxed = false;
+    let mut feature_sets = false;
+    filters.retain(|v| match v.as_str() {
+        "--all" => { do_all = true; false },
+        // check other features and minimal versions of dependencies instead of MSRV
+        "--features" => { feature_sets = true; false },
+        // use toolchains installed with rustup instead of docker
+        "--local" => { local = true; false },
+        // with --local, run builds even if bwrap isn't installed
+        "--allow-unsandboxed" => { allow_unsandboxed = true; false },
+        _ => true,
+    });
+
+    let executor: Box<dyn BuildExecutor> = if local {
+        Box::new(LocalExecutor::new(crates.main_cache_dir().join("local_builds"), &RUST_VERSIONS, allow_unsandboxed)?)
+    } else {
+        Box::new(DockerExecutor::new(crates.main_cache_dir().join("docker"), &RUST_VERSIONS))
+    };
     eprintln!("starting…");
-    prepare_docker(&docker_root)?;
+    executor.prepare()?;
 
     let (s, r) = crossbeam_channel::bounded::<Vec<_>>(200);
 
@@ -104,7 +106,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         while let Ok(mut next_batch) = r.recv() {
             std::thread::sleep(Duration::from_millis(500)); // wait for more data
-            if stopped() || out_of_disk_space() {
+            if stopped() || executor.scratch_dir().map_or(false, out_of_disk_space) {
                 eprintln!("Stopping early");
                 break;
             }
@@ -114,82 +116,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 candidates.append(&mut tmp);
             }
 
-            // biggest gap, then latest ver; best at the end, because pops
-            candidates.sort_unstable_by(|a,b| a.score.cmp(&b.score).then(a.version.cmp(&b.version)));
-
             let mut available_rust_versions = RUST_VERSIONS.to_vec();
             available_rust_versions.shuffle(&mut rng);
 
-
-            let max_to_waste_trying = (candidates.len()/2).max(RUST_VERSIONS.len());
-            let versions: Vec<_> = std::iter::from_fn(|| candidates.pop())
-            .take(max_to_waste_trying)
-            .filter_map(|x| {
-                let max_ver = x.rustc_compat.oldest_ok_certain().unwrap_or(999);
-                let min_ver = x.rustc_compat.newest_bad_likely().unwrap_or(18);
-
-                // cargo install --message-format is v1.58+ :(
-                let upper_limit = x.rustc_compat.oldest_ok().unwrap_or(if x.is_app {62} else {55});
-                // don't pick 1.29 as the first choice
-                let lower_limit = x.rustc_compat.newest_bad().unwrap_or(if x.is_app {58} else {43});
-
-                // min_ver ignores bad deps, but that often leads it to be stuck on last edition
-                // which is super wasteful
-                let min_ver = min_ver.max((lower_limit.min(max_ver)).saturating_sub(5));
-                // same for approx max ver that may come from msrv or binaries
-                let max_ver = max_ver.min(upper_limit.max(min_ver) + 10);
-
-                let best_ver = (upper_limit * 5 + lower_limit * 11)/16; // bias towards lower ver, because lower versions see features from newer versions
-
-                let origin = Origin::from_crates_io_name(&x.crate_name);
-                let mut existing_info = db.get_compat_raw(&origin).unwrap_or_default();
-                existing_info.retain(|inf| inf.crate_version == x.version);
-
-                let possible_rusts = available_rust_versions.iter().enumerate()
-                .filter(|&(_, &minor)| {
-                    minor > min_ver && minor < max_ver
-                })
-                .filter(|&(_, &v)| {
-                    !existing_info.iter().any(|inf| inf.rustc_version == v)
-                });
-                let maybe_rustc_idx = if !x.rustc_compat.has_ever_built() {
-                    // pick latest to avoid building with a compiler that may not understand new manifest or edition (cargo failures give worse info)
-                    possible_rusts.min_by_key(|&(_, &v)| (upper_limit as i32 - v as i32).abs())
-                    .map(|(k,v)| { debug!("{}-{} never built, trying latest R.{}", x.crate_name, x.version, v); (k,v) })
-                } else {
-                    possible_rusts.min_by_key(|&(_, &minor)| ((minor as i32) - best_ver as i32).abs())
-                };
-                let rustc_idx = match maybe_rustc_idx {
-                    Some((idx, _)) => idx,
-                    None => {
-                        debug!("Can't find rust for {}@{}, because it needs r{}-{}, but only got {:?}", x.crate_name, x.version, min_ver, max_ver, available_rust_versions);
-                        return None;
-                    },
-                };
-
-                // it's better to make multiple passes, and re-evaluate the crate after pass/fail of this version
-                if !one_crate_version.insert(x.crate_name.clone()) {
-                    return None;
-                }
-
-                let rustc_ver = available_rust_versions.swap_remove(rustc_idx);
-                let mut required_deps = Vec::new();
-                for (c,(v, newest_bad)) in x.rustc_compat.required_deps() {
-                    if newest_bad <= rustc_ver { // this check is useless, since newest_bad is for wrong version
-                        required_deps.push((c.into(), v.clone()));
-                    }
-                }
-
-                Some(CrateToRun {
-                    rustc_ver,
-                    crate_name: x.crate_name,
-                    version: x.version,
-                    required_deps,
-                    is_app: x.is_app,
-                })
-            })
-            .take((RUST_VERSIONS.len() *2/3).min(CONCURRENCY)) // max concurrency
-            .collect();
+            let versions = select_versions_to_run(&mut candidates, &db, available_rust_versions, &mut one_crate_version);
 
             eprintln!("\nselected: {}/{} ({})", versions.len(), candidates.len(), versions.iter().take(10).map(|c| format!("{} {}", c.crate_name, c.version)).collect::<Vec<_>>().join(", "));
 
@@ -197,17 +127,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 candidates.drain(..candidates.len()/2);
             }
 
-            if let Err(e) = run_and_analyze_versions(&db, &docker_root, versions) {
+            if let Err(e) = run_and_analyze_versions(&db, &*executor, versions) {
                 eprintln!("•• {e}");
             }
         }
         eprintln!("builder end");
     });
 
-    let mut filters: Vec<_> = std::env::args().skip(1).collect();
-    let mut do_all = false;
-    filters.retain(|v| if v == "--all" { do_all = true; false } else { true });
-
     let all_crates_map = crates.all_crates_io_crates();
     let mut map_iter;
     let mut recent_iter;
@@ -237,7 +163,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
 
-        match find_versions_to_build(all, &crates).await {
+        let to_build = if feature_sets {
+            find_feature_sets_to_build(all, &crates).await
+        } else {
+            find_versions_to_build(all, &crates).await
+        };
+        match to_build {
             Ok(vers) => {
                 s.send(vers).unwrap();
             },
@@ -318,6 +249,8 @@ async fn find_versions_to_build(all: &CratesIndexCrate, crates: &KitchenSink) ->
                 rustc_compat: compat,
                 crate_name: crate_name.clone(),
                 is_app: false,
+                feature_set: None,
+                features: Vec::new(),
             }
         })
         .filter(|c| {
@@ -371,33 +304,229 @@ async fn find_versions_to_build(all: &CratesIndexCrate, crates: &KitchenSink) ->
     Ok(candidates)
 }
 
+/// Builds of the latest stable release without default features, with all features, with each of its own features alone,
+/// and with the oldest dependency versions that its requirements allow.
+///
+/// Only crates that already built with default features are checked, because otherwise all of them would fail the same way.
+async fn find_feature_sets_to_build(all: &CratesIndexCrate, crates: &KitchenSink) -> Result<Vec<ToCheck>, Box<dyn std::error::Error>> {
+    let crate_name: Arc<str> = all.name().into();
+    let origin = &Origin::from_crates_io_name(&crate_name);
+
+    let popularity_factor = crates.crate_ranking_for_builder(origin).await.unwrap_or(0.3);
+    if popularity_factor < rand::thread_rng().gen_range(0.15..0.33) {
+        return Ok(vec![]);
+    }
+
+    let latest = match all.versions().iter().rev().find(|v| !v.is_yanked() && SemVer::parse(v.version()).map_or(false, |v| v.pre.is_empty())) {
+        Some(v) => v,
+        None => return Ok(vec![]),
+    };
+    let version = SemVer::parse(latest.version())?;
+
+    let krate = crates.rich_crate_async(origin).await?;
+    let rustc_compat = crates.rustc_compatibility_for_builder(&krate, 51).await
+        .map_err(|_| "rustc_compatibility")?
+        .remove(&version).unwrap_or_default();
+    if !rustc_compat.has_ever_built() {
+        return Ok(vec![]);
+    }
+    let meta = crates.crate_files_summary_from_crates_io_tarball(&crate_name, latest.version()).await?;
+    if meta.lib_file.is_none() {
+        return Ok(vec![]);
+    }
+
+    let features = latest.features();
+    let deps = latest.dependencies();
+    // `dep:` syntax disables the implicit feature of the optional dependency
+    let is_dependency_feature = |name: &str, enables: &[String]| !enables.is_empty() && enables.iter().all(|f| f.strip_prefix("dep:") == Some(name));
+    let mut own_features: Vec<Box<str>> = features.iter()
+        .filter(|&(k, v)| k != "default" && !k.starts_with('_') && !is_dependency_feature(k, v))
+        .map(|(k, _)| k.as_str().into())
+        .collect();
+    own_features.retain(|f| is_safe_feature_name(f));
+    own_features.sort();
+    let mut all_features: Vec<Box<str>> = features.keys().filter(|&k| k != "default").map(|k| k.as_str().into())
+        .chain(deps.iter()
+            .filter(|d| d.is_optional() && !features.values().flatten().any(|f| f.strip_prefix("dep:") == Some(d.name())))
+            .map(|d| d.name().into()))
+        .collect();
+    all_features.sort();
+    all_features.dedup();
+
+    let job = |score: u32, feature_set, features| ToCheck {
+        score: (score as f64 * popularity_factor) as u32,
+        crate_name: crate_name.clone(),
+        version: version.clone(),
+        rustc_compat: rustc_compat.clone(),
+        is_app: false,
+        feature_set: Some(feature_set),
+        features,
+    };
+    let mut candidates = Vec::new();
+    if deps.iter().any(|d| d.kind() != DependencyKind::Dev) {
+        candidates.push(job(10, FeatureSet::MinimalVersions, vec![]));
+    }
+    if features.contains_key("default") {
+        candidates.push(job(8, FeatureSet::NoDefaultFeatures, vec![]));
+    }
+    if let Some(bad) = all_features.iter().find(|f| !is_safe_feature_name(f)) {
+        debug!("{crate_name} {version} has feature {bad:?} that can't be passed to the build script");
+    } else if !all_features.is_empty() {
+        candidates.push(job(8, FeatureSet::AllFeatures, all_features));
+    }
+    candidates.extend(own_features.into_iter().map(|f| job(5, FeatureSet::Feature(f.clone()), vec![f])));
+    debug!("{crate_name} {version}\t{} feature sets", candidates.len());
+    Ok(candidates)
+}
+
+/// Feature names end up in the bash build script and dir names, so only plain ASCII is allowed
+fn is_safe_feature_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(['-', '.']) && !name.contains("..") &&
+        name.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'+' | b'-' | b'/' | b'.'))
+}
+
+/// Picks the best candidates to build now, and rustc versions to build them with.
+/// Each rustc version is used at most once, so that builds don't fight over the same target dir.
+fn select_versions_to_run(candidates: &mut Vec<ToCheck>, db: &BuildDb, mut available_rust_versions: Vec<RustcMinorVersion>, one_crate_version: &mut HashSet<Arc<str>>) -> Vec<CrateToRun> {
+    // biggest gap, then latest ver; best at the end, because pops
+    candidates.sort_unstable_by(|a,b| a.score.cmp(&b.score).then(a.version.cmp(&b.version)));
+
+    let max_to_waste_trying = (candidates.len()/2).max(RUST_VERSIONS.len());
+    std::iter::from_fn(|| candidates.pop())
+    .take(max_to_waste_trying)
+    .filter_map(|x| {
+        if x.feature_set.is_some() {
+            return select_rustc_for_feature_set(x, db, &mut available_rust_versions);
+        }
+
+        let max_ver = x.rustc_compat.oldest_ok_certain().unwrap_or(999);
+        let min_ver = x.rustc_compat.newest_bad_likely().unwrap_or(18);
+
+        // cargo install --message-format is v1.58+ :(
+        let upper_limit = x.rustc_compat.oldest_ok().unwrap_or(if x.is_app {62} else {55});
+        // don't pick 1.29 as the first choice
+        let lower_limit = x.rustc_compat.newest_bad().unwrap_or(if x.is_app {58} else {43});
+
+        // min_ver ignores bad deps, but that often leads it to be stuck on last edition
+        // which is super wasteful
+        let min_ver = min_ver.max((lower_limit.min(max_ver)).saturating_sub(5));
+        // same for approx max ver that may come from msrv or binaries
+        let max_ver = max_ver.min(upper_limit.max(min_ver) + 10);
+
+        let best_ver = (upper_limit * 5 + lower_limit * 11)/16; // bias towards lower ver, because lower versions see features from newer versions
+
+        let origin = Origin::from_crates_io_name(&x.crate_name);
+        let mut existing_info = db.get_compat_raw(&origin).unwrap_or_default();
+        existing_info.retain(|inf| inf.crate_version == x.version);
+
+        let possible_rusts = available_rust_versions.iter().enumerate()
+        .filter(|&(_, &minor)| {
+            minor > min_ver && minor < max_ver
+        })
+        .filter(|&(_, &v)| {
+            !existing_info.iter().any(|inf| inf.rustc_version == v)
+        });
+        let maybe_rustc_idx = if !x.rustc_compat.has_ever_built() {
+            // pick latest to avoid building with a compiler that may not understand new manifest or edition (cargo failures give worse info)
+            possible_rusts.min_by_key(|&(_, &v)| (upper_limit as i32 - v as i32).abs())
+            .map(|(k,v)| { debug!("{}-{} never built, trying latest R.{}", x.crate_name, x.version, v); (k,v) })
+        } else {
+            possible_rusts.min_by_key(|&(_, &minor)| ((minor as i32) - best_ver as i32).abs())
+        };
+        let rustc_idx = match maybe_rustc_idx {
+            Some((idx, _)) => idx,
+            None => {
+                debug!("Can't find rust for {}@{}, because it needs r{}-{}, but only got {:?}", x.crate_name, x.version, min_ver, max_ver, available_rust_versions);
+                return None;
+            },
+        };
+
+        // it's better to make multiple passes, and re-evaluate the crate after pass/fail of this version
+        if !one_crate_version.insert(x.crate_name.clone()) {
+            return None;
+        }
+
+        let rustc_ver = available_rust_versions.swap_remove(rustc_idx);
+        let mut required_deps = Vec::new();
+        for (c,(v, newest_bad)) in x.rustc_compat.required_deps() {
+            if newest_bad <= rustc_ver { // this check is useless, since newest_bad is for wrong version
+                required_deps.push((c.into(), v.clone()));
+            }
+        }
+
+        Some(CrateToRun {
+            rustc_ver,
+            crate_name: x.crate_name,
+            version: x.version,
+            required_deps,
+            is_app: x.is_app,
+            feature_set: None,
+            features: Vec::new(),
+        })
+    })
+    .take((RUST_VERSIONS.len() *2/3).min(CONCURRENCY)) // max concurrency
+    .collect()
+}
+
+/// Default features are known to work with any rustc since `oldest_ok`, so the newest one is a fair test of the feature set
+fn select_rustc_for_feature_set(x: ToCheck, db: &BuildDb, available_rust_versions: &mut Vec<RustcMinorVersion>) -> Option<CrateToRun> {
+    let feature_set = x.feature_set?;
+    let origin = Origin::from_crates_io_name(&x.crate_name);
+    let existing_info = db.get_feature_compat(&origin).unwrap_or_default();
+    if existing_info.iter().any(|inf| inf.crate_version == x.version && inf.feature_set == feature_set) {
+        return None;
+    }
+
+    let oldest_ok = x.rustc_compat.oldest_ok()?;
+    let (rustc_idx, _) = available_rust_versions.iter().enumerate()
+        .filter(|&(_, &v)| v >= oldest_ok)
+        .max_by_key(|&(_, &v)| v)?;
+    Some(CrateToRun {
+        rustc_ver: available_rust_versions.swap_remove(rustc_idx),
+        crate_name: x.crate_name,
+        version: x.version,
+        required_deps: Vec::new(),
+        is_app: false,
+        feature_set: Some(feature_set),
+        features: x.features,
+    })
+}
+
+#[derive(Clone)]
 struct CrateToRun {
     rustc_ver: RustcMinorVersion,
     crate_name: Arc<str>,
     version: SemVer,
     required_deps: Vec<(Box<str>, SemVer)>,
     is_app: bool,
+    /// Builds with other features don't affect MSRV data
+    feature_set: Option<FeatureSet>,
+    features: Vec<Box<str>>,
 }
 
-fn run_and_analyze_versions(db: &BuildDb, docker_root: &Path, versions: Vec<CrateToRun>) -> Result<(), Box<dyn std::error::Error>> {
+fn run_and_analyze_versions(db: &BuildDb, executor: &dyn BuildExecutor, versions: Vec<CrateToRun>) -> Result<(), Box<dyn std::error::Error>> {
     if versions.is_empty() {
         return Ok(());
     }
 
-    // reuse tarballs cached by our crates-io client
-    for c in &versions {
-        let dest = Path::new(TEMP_JUNK_DIR).join("registry/cache/github.com-1ecc6299db9ec823").join(format!("{}-{}.crate", c.crate_name, c.version));
-        if !dest.exists() {
-            let src = Path::new("/var/lib/crates-server/tarballs").join(format!("{}/{}.crate", c.crate_name, c.version));
-            let _ = std::fs::hard_link(&src, &dest).map_err(|e| warn!("tarball {} -> {}: {}", src.display(), dest.display(), e));
-        }
-    }
-
-    let (stdout, stderr) = do_builds(docker_root, &versions)?;
+    let (stdout, stderr) = executor.run(&versions)?;
 
     let mut to_set = BTreeMap::new();
+    let mut diagnostics = BTreeMap::new();
+    let mut feature_results = Vec::new();
     for f in parse_analyses(&stdout, &stderr) {
+        if let Some(feature_set) = &f.feature_set {
+            // errors of dependencies are in the reason of the top-level crate
+            if let (Some(rustc_version), Some((crate_name, crate_version)), Some((compat, reason))) = (f.rustc_version, &f.top_level_crate, f.top_level_result()) {
+                feature_results.push((Origin::from_crates_io_name(crate_name), crate_version.clone(), rustc_version, feature_set.clone(), compat, reason.to_owned()));
+            }
+            continue;
+        }
         if let Some(rustc_version) = f.rustc_version {
+            for (crate_name, crate_version, diag) in f.diagnostics {
+                diagnostics.entry((Origin::from_crates_io_name(&crate_name), crate_version, rustc_version))
+                    .or_insert_with(Vec::new).push(diag);
+            }
             for (rustc_override, crate_name, crate_version, new_compat, reason) in f.crates {
                 let origin = Origin::from_crates_io_name(&crate_name);
                 let rustc_version = rustc_override.unwrap_or(rustc_version);
@@ -421,199 +550,89 @@ fn run_and_analyze_versions(db: &BuildDb, docker_root: &Path, versions: Vec<Crat
         std::thread::sleep(Duration::from_secs(1));
         db.set_compat_multi(&tmp)?;
     }
-    Ok(())
-}
-
-fn prepare_docker(docker_root: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let _ = std::fs::create_dir_all(docker_root);
-
-    for &p in &["git","registry","target","job_inputs"] {
-        let p = Path::new(TEMP_JUNK_DIR).join(p);
-        let _ = std::fs::create_dir_all(&p);
-    }
-    // let _ = Command::new("chmod").arg("-R").arg("a+rwX").arg(TEMP_JUNK_DIR).status()?;
-    // let _ = Command::new("chown").arg("-R").arg("4321:4321").arg(TEMP_JUNK_DIR).status()?;
-
-    let mut child = Command::new("docker")
-        .current_dir(docker_root)
-        .arg("build")
-        .arg("-t").arg(DOCKER_NAME)
-        .arg("-")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .spawn()?;
-
-    let mut stdin = child.stdin.take().unwrap();
-    stdin.write_all(DOCKERFILE_PRELUDE.as_bytes())?;
-    for v in RUST_VERSIONS {
-        if v != DOCKERFILE_DEFAULT_RUSTC {
-            writeln!(stdin, "RUN rustup toolchain add {}", rustc_minor_ver_to_version(v))?;
-        }
+    for ((origin, crate_version, rustc_version), diags) in diagnostics {
+        db.set_diagnostics(&origin, &crate_version, rustc_version, &diags)?;
     }
-    stdin.write_all(b"RUN rustup toolchain list\nRUN chmod -R a-w ~/.rustup ~/.cargo/bin ~/.cargo/env\n")?;
-    drop(stdin);
-
-    let res = child.wait()?;
-    if !res.success() {
-        Err("failed build")?;
+    for (origin, crate_version, rustc_version, feature_set, compat, reason) in feature_results {
+        db.set_feature_compat(&origin, &crate_version, rustc_version, &feature_set, compat, &reason)?;
     }
-
-    let _ = Command::new("docker")
-        .current_dir(docker_root)
-        .arg("run")
-        .arg("--rm")
-        .arg("-v").arg(format!("{TEMP_JUNK_DIR}/git:/home/rustyuser/.cargo/git"))
-        .arg("-v").arg(format!("{TEMP_JUNK_DIR}/registry:/home/rustyuser/.cargo/registry"))
-        .arg(DOCKER_NAME)
-        .arg("bash").arg("-c").arg("cargo install libc --vers 99.9.9 --color=always -vv") // force index update
-        .status()?;
     Ok(())
 }
 
-// must match bash script below
-fn job_inputs_dir(root: &Path, c: &CrateToRun) -> PathBuf {
-    root.join(format!("crate-{}-{}--{}-job", c.crate_name, c.version, rustc_minor_ver_to_version(c.rustc_ver)))
+#[test]
+fn selects_rustc_between_ok_and_bad() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = BuildDb::new(tmp.path().join("builds.db")).unwrap();
+
+    let mut rustc_compat = CompatRanges::default();
+    rustc_compat.add_compat(60, Compat::VerifiedWorks, None);
+    rustc_compat.add_compat(47, Compat::DefinitelyIncompatible, None);
+    let mut candidates = vec![
+        ToCheck { score: 1, crate_name: "fixture".into(), version: "1.0.0".parse().unwrap(), rustc_compat: rustc_compat.clone(), is_app: false, feature_set: None, features: vec![] },
+        ToCheck { score: 5, crate_name: "fixture".into(), version: "1.1.0".parse().unwrap(), rustc_compat, is_app: false, feature_set: None, features: vec![] },
+    ];
+
+    let selected = select_versions_to_run(&mut candidates, &db, RUST_VERSIONS.to_vec(), &mut HashSet::new());
+    // one version of a crate at a time
+    assert_eq!(1, selected.len());
+    assert_eq!("1.1.0", selected[0].version.to_string());
+    assert_eq!(51, selected[0].rustc_ver);
 }
 
-// versions is (rustc version, crate version)
-fn do_builds(docker_root: &Path, versions: &[CrateToRun]) -> Result<(String, String), Box<dyn std::error::Error>> {
-
-    let job_inputs_root = Path::new(TEMP_JUNK_DIR).join("job_inputs");
-    for c in versions {
-        let dir = job_inputs_dir(&job_inputs_root, c);
-        let _ = std::fs::create_dir(&dir);
-
-        let mut cargo_toml = format!("[package]\nname=\"_____\"\nversion=\"0.0.0\"\n[profile.dev]\ndebug=false\n[dependencies]\n{} = \"{}\"\n", c.crate_name, c.version);
-        for (c, v) in &c.required_deps {
-            use std::fmt::Write;
-            let _ = writeln!(&mut cargo_toml, "{c} = \"<= {v}, {}{}\"", if v.major == 0 {"0."} else {""}, if v.major == 0 { v.minor } else { v.major });
-        }
-        debug!("{}", cargo_toml);
-        std::fs::write(dir.join("Cargo.toml"), cargo_toml)?;
-    }
-
-    let script = format!(r##"
-        set -euo pipefail
-        function yeet {{
-            mv "$1" "$1-delete" && rm -rf "$1-delete" # atomic delete
-        }}
-        function cleanup() {{
-            local rustver="$1"
-            local crate_name="$2"
-            local libver="$3"
-            export CARGO_TARGET_DIR=/home/rustyuser/cargo_target/$rustver;
-            rm -rf "$CARGO_TARGET_DIR"/debug/*/"$crate_name-"*; # eats disk space
-            rm -rf "$CARGO_TARGET_DIR"/debug/*/"lib$crate_name-"*;
-            yeet ~/.cargo/registry/src/*/"$crate_name-$libver";
-        }}
-        function check_crate_with_rustc() {{
-            local rustver="$1"
-            local crate_name="$2"
-            local libver="$3"
-            local stdoutfile="$4"
-            local stderrfile="$5"
-            local job_inputs_dir="/home/rustyuser/job_inputs/crate-$crate_name-$libver--$rustver-job"
-            mkdir -p "crate-$crate_name-$libver/src";
-            cd "crate-$crate_name-$libver";
-            touch src/lib.rs
-            cp "$job_inputs_dir/Cargo.toml" Cargo.toml
-            export CARGO_TARGET_DIR=/home/rustyuser/cargo_target/$rustver;
-            {{
-                echo >"$stdoutfile" "CHECKING $rustver $crate_name $libver"
-                RUSTC_BOOTSTRAP=1 timeout 20 cargo +"$rustver" -Z no-index-update fetch || CARGO_NET_GIT_FETCH_WITH_CLI=true timeout 60 cargo +"$rustver" fetch --color=always -vv;
-                timeout 90 nice cargo +$rustver {check_command} -j3 --locked --message-format=json >>"$stdoutfile" 2>"$stderrfile";
-            }} || {{
-                local rustfetchver="$rustver"
-                if [ "$rustver" == "1.20.0" ]; then
-                    rustfetchver="1.25.0"
-                fi
-                echo >>"$stdoutfile" "CHECKING $rustver $crate_name $libver (minimal-versions)"
-                printf > Cargo.toml '[package]\nname="_____"\nversion="0.0.0"\n[profile.dev]\ndebug=false\n[dependencies]\n%s = "=%s"\n[dev-dependencies]\nminimal-versions-are-broken="1"' "$crate_name" "$libver";
-                rm -f Cargo.lock
-                RUSTC_BOOTSTRAP=1 timeout 20 cargo +"$rustfetchver" -Z no-index-update -Z minimal-versions generate-lockfile;
-                timeout 40 nice cargo +$rustver {check_command} -j3 --locked --message-format=json >>"$stdoutfile" 2>>"$stderrfile";
-            }}
-        }}
-        for job in {jobs}; do
-            (
-                check_crate_with_rustc $job "/tmp/output-$job" "/tmp/outputerr-$job" && echo "# R.$job done OK" || echo "# R.$job failed"
-            ) &
-        done
-        wait
-        for job in {jobs}; do
-            echo "{divider}"
-            cat "/tmp/output-$job";
-            echo >&2 "{divider}"
-            cat >&2 "/tmp/outputerr-$job";
-            cleanup $job
-        done
-    "##,
-        check_command = if versions.iter().all(|v| v.is_app && v.rustc_ver >= 58) { "install \"$crate_name\" --version \"$libver\" --debug --root=./install-test" } else { "check" },
-        divider = parse::DIVIDER,
-        jobs = versions.iter().map(|c| format!("\"{} {} {}\"", rustc_minor_ver_to_version(c.rustc_ver), c.crate_name, c.version)).collect::<Vec<_>>().join(" "),
-    );
-
-    let mut child = Command::new("nice")
-        .current_dir(docker_root)
-        .arg("docker")
-        .arg("run")
-        .arg("--rm")
-        .arg("-v").arg(format!("{TEMP_JUNK_DIR}/git:/home/rustyuser/.cargo/git"))
-        .arg("-v").arg(format!("{TEMP_JUNK_DIR}/registry:/home/rustyuser/.cargo/registry"))
-        .arg("-v").arg(format!("{TEMP_JUNK_DIR}/target:/home/rustyuser/cargo_target"))
-        .arg("-v").arg(format!("{}:/home/rustyuser/job_inputs:ro", job_inputs_root.display()))
-        .arg("-e").arg("CARGO_INCREMENTAL=0")
-        // .arg("-e").arg("CARGO_UNSTABLE_AVOID_DEV_DEPS=true") // breaks --locked
-        .arg("-e").arg("CARGO_UNSTABLE_BINDEPS=true")
-        .arg("-e").arg("CARGO_BUILD_JOBS=2")
-        .arg("-m2500m")
-        .arg("--cpus=2")
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .arg(DOCKER_NAME)
-        .arg("bash").arg("-c").arg(script)
-        .spawn()?;
-
-    let stdout = streamfetch("stdout", child.stdout.take().unwrap());
-    let stderr = streamfetch("stderr", child.stderr.take().unwrap());
-
-    let status = child.wait()?;
-
-    let stdout = std::mem::take(&mut *stdout.lock());
-    let mut stderr = std::mem::take(&mut *stderr.lock());
-
-    if !status.success() {
-        stderr += &format!("\nexit failure {status:?}\n");
-    }
-
-    Ok((stdout, stderr))
+#[test]
+fn fake_builds_update_db() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = BuildDb::new(tmp.path().join("builds.db")).unwrap();
+
+    let mut executor = FakeExecutor::default();
+    executor.add_success(61, "fixture-ok", "1.0.0");
+    executor.add_output(48, "fixture-new", "0.2.0", "", "error: package `fixture-new v0.2.0` cannot be built because it requires rustc 1.56 or newer, while the currently active rustc version is 1.48.0");
+    let to_run = |rustc_ver, crate_name: &str, version: &str| CrateToRun { rustc_ver, crate_name: crate_name.into(), version: version.parse().unwrap(), required_deps: vec![], is_app: false, feature_set: None, features: vec![] };
+
+    run_and_analyze_versions(&db, &executor, vec![
+        to_run(61, "fixture-ok", "1.0.0"),
+        to_run(48, "fixture-new", "0.2.0"),
+        to_run(53, "fixture-no-output", "1.0.0"),
+    ]).unwrap();
+    assert_eq!(3, executor.runs.lock()[0].len());
+
+    let ok = db.get_compat_raw(&Origin::from_crates_io_name("fixture-ok")).unwrap();
+    assert!(ok.iter().any(|c| c.rustc_version == 61 && c.compat == Compat::VerifiedWorks), "{ok:?}");
+    let new = db.get_compat_raw(&Origin::from_crates_io_name("fixture-new")).unwrap();
+    assert!(new.iter().any(|c| c.rustc_version == 55 && c.compat == Compat::DefinitelyIncompatible), "{new:?}");
+    assert!(db.get_compat_raw(&Origin::from_crates_io_name("fixture-no-output")).unwrap().is_empty());
 }
 
-fn rustc_minor_ver_to_version(rustc_minor_ver: u16) -> String {
-    if rustc_minor_ver == 56 {
-        "1.56.1".into()
-    } else {
-        format!("1.{rustc_minor_ver}.0")
-    }
-}
-
-fn streamfetch(prefix: &'static str, inp: impl std::io::Read + Send + 'static) -> Arc<Mutex<String>> {
-    let out = Arc::new(Mutex::new(String::new()));
-    let out2 = out.clone();
-    std::thread::spawn(move || {
-        let buf = BufReader::new(inp);
-        for line in buf.lines() {
-            let mut line = line.unwrap();
-            let mut tmp = out.lock();
-            tmp.push_str(&line);
-            tmp.push('\n');
-            if line.len() > 230 && line.is_char_boundary(230) {
-                line.truncate(230);
-            }
-            println!("{prefix}: {line}");
-        }
-    });
-    out2
+#[test]
+fn feature_set_builds_dont_affect_msrv() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = BuildDb::new(tmp.path().join("builds.db")).unwrap();
+
+    let mut executor = FakeExecutor::default();
+    executor.add_success(61, "fixture", "1.0.0");
+    executor.add_output(63, "fixture", "1.0.0", r#"{"message":{"children":[],"code":null,"level":"error","message":"cannot find derive macro `Serialize` in this scope","rendered":null,"spans":[]},"package_id":"fixture 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)","reason":"compiler-message","target":{"kind":["lib"],"name":"fixture","edition":"2015"}}
+{"reason":"build-finished","success":false}"#, "");
+    let serde = FeatureSet::Feature("serde".into());
+    let to_run = |rustc_ver, feature_set: &FeatureSet| CrateToRun { rustc_ver, crate_name: "fixture".into(), version: "1.0.0".parse().unwrap(), required_deps: vec![], is_app: false, feature_set: Some(feature_set.clone()), features: vec![] };
+
+    run_and_analyze_versions(&db, &executor, vec![to_run(61, &FeatureSet::MinimalVersions), to_run(63, &serde)]).unwrap();
+
+    let origin = Origin::from_crates_io_name("fixture");
+    assert!(db.get_compat_raw(&origin).unwrap().is_empty());
+    let res = db.get_feature_compat(&origin).unwrap();
+    assert_eq!(2, res.len());
+    assert!(res.iter().any(|r| r.feature_set == FeatureSet::MinimalVersions && r.compat.successful()), "{res:?}");
+    assert!(res.iter().any(|r| r.feature_set == serde && r.rustc_version == 63 && !r.compat.successful()), "{res:?}");
+
+    // already checked
+    let mut rustc_compat = CompatRanges::default();
+    rustc_compat.add_compat(56, Compat::VerifiedWorks, None);
+    let mut candidates = vec![
+        ToCheck { score: 1, crate_name: "fixture".into(), version: "1.0.0".parse().unwrap(), rustc_compat: rustc_compat.clone(), is_app: false, feature_set: Some(serde), features: vec![] },
+        ToCheck { score: 1, crate_name: "fixture".into(), version: "1.0.0".parse().unwrap(), rustc_compat, is_app: false, feature_set: Some(FeatureSet::NoDefaultFeatures), features: vec![] },
+    ];
+    let selected = select_versions_to_run(&mut candidates, &db, RUST_VERSIONS.to_vec(), &mut HashSet::new());
+    assert_eq!(1, selected.len());
+    assert_eq!(Some(FeatureSet::NoDefaultFeatures), selected[0].feature_set);
+    assert_eq!(63, selected[0].rustc_ver);
 }
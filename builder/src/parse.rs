@@ -1,6 +1,6 @@
 use kitchen_sink::SemVer;
 use ahash::HashMap;
-use crate_db::builddb::{Compat, RustcMinorVersion};
+use crate_db::builddb::{BuildDiagnostic, Compat, FeatureSet, RustcMinorVersion};
 use regex::Regex;
 use serde_derive::*;
 use ahash::HashSet;
@@ -12,6 +12,19 @@ pub const DIVIDER: &str = "---XBdt8MziQTMWYwcSsHz1---";
 pub struct CompilerMessageInner {
     level: String,
     message: Option<String>,
+    code: Option<CompilerMessageCode>,
+    #[serde(default)]
+    children: Vec<CompilerMessageChild>,
+}
+
+#[derive(Deserialize)]
+pub struct CompilerMessageCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompilerMessageChild {
+    message: String,
 }
 
 #[derive(Deserialize)]
@@ -37,6 +50,27 @@ pub struct Findings {
     pub crates: HashSet<(Option<RustcMinorVersion>, String, SemVer, Compat, String)>,
     pub rustc_version: Option<RustcMinorVersion>,
     pub check_time: Option<f32>,
+    /// Errors with a code or feature gate, by crate name and version
+    pub diagnostics: HashSet<(String, SemVer, BuildDiagnostic)>,
+    pub top_level_crate: Option<(String, SemVer)>,
+    /// `None` for the default build
+    pub feature_set: Option<FeatureSet>,
+}
+
+impl Findings {
+    /// Outcome of the build for the crate being checked, ignoring guesses about other rustc versions.
+    /// Errors in its own code take precedence over broken dependencies.
+    pub fn top_level_result(&self) -> Option<(Compat, &str)> {
+        let (name, ver) = self.top_level_crate.as_ref()?;
+        self.crates.iter()
+            .filter(|(rustc_override, n, v, ..)| rustc_override.is_none() && n == name && v == ver)
+            .max_by_key(|(.., compat, _)| (match compat {
+                c if c.successful() => 0,
+                Compat::BrokenDeps | Compat::BrokenDepsLikely => 1,
+                _ => 2,
+            }, compat.certainity()))
+            .map(|(.., compat, reason)| (*compat, reason.as_str()))
+    }
 }
 
 pub fn parse_analyses(stdout: &str, stderr: &str) -> Vec<Findings> {
@@ -613,6 +647,10 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
     findings.rustc_version = Some(rustc_version_semver.minor as u16);
     let top_level_crate_name = fl.next().ok_or("no top-level crate name")?;
     let top_level_crate_ver = SemVer::parse(fl.next().ok_or("first line 2")?).map_err(|e| e.to_string())?;
+    findings.top_level_crate = Some((top_level_crate_name.to_owned(), top_level_crate_ver.clone()));
+    if let Some(mode) = fl.next() {
+        findings.feature_set = Some(mode.parse().map_err(|_| format!("bad build mode {mode}"))?);
+    }
 
     let mut printed = HashSet::default();
     for line in lines.filter(|l| l.starts_with('{')) {
@@ -639,6 +677,12 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
 
                 debug!("{}@{} > {}@{}: {} {} {}", top_level_crate_name, top_level_crate_ver, name, ver, level, desc.unwrap_or(""), reason);
 
+                if level == "error" {
+                    if let Some(diag) = msg.message.as_ref().and_then(|m| diagnostic_from_message(m, &feature_flags)) {
+                        findings.diagnostics.insert((name.clone(), ver.clone(), diag));
+                    }
+                }
+
                 if let Some(desc) = desc {
                     if desc.starts_with("couldn't read /") ||
                         desc.starts_with("Current directory is invalid:") ||
@@ -902,7 +946,9 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
             }
         }
         // manifest parsing handling
-        else if let Some(rest) = line.strip_prefix("  failed to parse manifest at `/home/rustyuser/.cargo/registry/src/github.com-1ecc6299db9ec823/") {
+        // registry location depends on the executor
+        else if let Some(rest) = line.strip_prefix("  failed to parse manifest at `")
+            .and_then(|path| path.split_once("/registry/src/")).and_then(|(_, rest)| rest.split_once('/')).map(|(_, rest)| rest) {
             let pattern = regex::Regex::new(r"([^.+/; ]+?)-([0-9]+\.[^/; ]+)/Cargo.toml").expect("regex syntax");
             if let Some(cap) = pattern.captures(rest) {
                 if let Ok(ver) = SemVer::parse(&cap[2]) {
@@ -914,6 +960,7 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
         }
         else if line.starts_with("  feature `profile-overrides` is required") {
             if let Some((name, ver)) = last_broken_manifest_crate.take() {
+                findings.diagnostics.insert((name.clone(), ver.clone(), cargo_feature_diagnostic("profile-overrides", 41, line)));
                 findings.crates.insert((Some(40), name, ver, Compat::DefinitelyIncompatible, line.into()));
             }
         }
@@ -924,16 +971,19 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
         }
         else if line.starts_with("  feature `default-run` is required") {
             if let Some((name, ver)) = last_broken_manifest_crate.take() {
+                findings.diagnostics.insert((name.clone(), ver.clone(), cargo_feature_diagnostic("default-run", 37, line)));
                 findings.crates.insert((Some(36), name, ver, Compat::DefinitelyIncompatible, line.into()));
             }
         }
         else if line.starts_with("  editions are unstable") || line.starts_with("  feature `rename-dependency` is required") {
             if let Some((name, ver)) = last_broken_manifest_crate.take() {
+                findings.diagnostics.insert((name.clone(), ver.clone(), cargo_feature_diagnostic(if line.contains("rename-dependency") { "rename-dependency" } else { "edition" }, 31, line)));
                 findings.crates.insert((Some(30), name, ver, Compat::DefinitelyIncompatible, line.into()));
             }
         }
         else if line.starts_with("  unknown cargo feature `resolver`") || line.starts_with("  feature `resolver` is required") {
             if let Some((name, ver)) = last_broken_manifest_crate.take() {
+                findings.diagnostics.insert((name.clone(), ver.clone(), cargo_feature_diagnostic("resolver", 51, line)));
                 findings.crates.insert((Some(50), name, ver, Compat::DefinitelyIncompatible, line.into()));
             }
         }
@@ -941,6 +991,7 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
         line.starts_with("  supported edition values are `2015` or `2018`, but `2021` is unknown") ||
         line.starts_with("  feature `edition2021` is required") {
             if let Some((name, ver)) = last_broken_manifest_crate.take() {
+                findings.diagnostics.insert((name.clone(), ver.clone(), cargo_feature_diagnostic("edition2021", 56, line)));
                 findings.crates.insert((Some(55), name, ver, Compat::DefinitelyIncompatible, line.into()));
             }
         }
@@ -954,6 +1005,17 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
         return Err("no info found".into());
     }
 
+    // patterns above know when some features were stabilized
+    let diagnostics = std::mem::take(&mut findings.diagnostics);
+    findings.diagnostics = diagnostics.into_iter().map(|(name, ver, mut d)| {
+        if d.stable_since.is_none() {
+            d.stable_since = findings.crates.iter()
+                .filter(|(_, n, v, c, reason)| !c.successful() && *n == name && *v == ver && *reason == d.message)
+                .filter_map(|c| c.0).max().map(|r| r + 1);
+        }
+        (name, ver, d)
+    }).collect();
+
     // this is slightly inaccurate, because we don't know if older deps would work
     // but not marking it as failure makes builder retry the crate over and over again
     let has_toplevel_crate_compat = findings.crates.iter().any(|c| c.1 == top_level_crate_name);
@@ -972,6 +1034,25 @@ fn parse_analysis(stdout: &str, stderr: &str) -> Result<Findings, String> {
     Ok(findings)
 }
 
+/// Error code and feature gate of a rustc error, if it has any
+fn diagnostic_from_message(msg: &CompilerMessageInner, feature_flags: &HashMap<&str, u16>) -> Option<BuildDiagnostic> {
+    let message = msg.message.as_deref()?;
+    let code = msg.code.as_ref().map(|c| c.code.clone());
+    let feature = message.strip_prefix("use of unstable library feature ")
+        .and_then(|f| f.split(|c| c == '\'' || c == '`').nth(1))
+        .or_else(|| msg.children.iter().find_map(|c| c.message.strip_prefix("add `#![feature(")?.split(')').next()))
+        .map(String::from);
+    if code.is_none() && feature.is_none() {
+        return None;
+    }
+    let stable_since = feature.as_deref().and_then(|f| feature_flags.get(f).copied());
+    Some(BuildDiagnostic { code, feature, stable_since, message: message.into() })
+}
+
+fn cargo_feature_diagnostic(feature: &str, stable_since: RustcMinorVersion, line: &str) -> BuildDiagnostic {
+    BuildDiagnostic { code: None, feature: Some(feature.into()), stable_since: Some(stable_since), message: line.trim().into() }
+}
+
 #[test]
 fn parse_cargo() {
     let stderr = r##"
@@ -1098,3 +1179,36 @@ exit failure
     assert!(res[2].crates.get(&(None, "proc_vector2d".into(), "1.0.2".parse().unwrap(), Compat::SuspectedIncompatible, "function-like proc macros are currently unstable (see issue #38356)".into())).is_some(), "{res:#?}");
     assert!(res[2].crates.get(&(None, "toplevelcrate".into(), "1.0.1-testcrate".parse().unwrap(), Compat::BrokenDeps, "proc_vector2d@1.0.2: function-like proc macros are currently unstable (see issue #38356)".into())).is_some(), "{res:#?}");
 }
+
+#[test]
+fn parse_diagnostics() {
+    let out = r##"CHECKING 1.30.0 toplevel 1.0.0
+{"message":{"children":[{"children":[],"code":null,"level":"help","message":"add `#![feature(async_await)]` to the crate attributes to enable","rendered":null,"spans":[]}],"code":{"code":"E0658","explanation":null},"level":"error","message":"async fn is unstable","rendered":"error[E0658]: async fn is unstable\n","spans":[]},"package_id":"dep 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)","reason":"compiler-message","target":{"kind":["lib"],"name":"dep","edition":"2015"}}
+{"message":{"children":[],"code":{"code":"E0658","explanation":null},"level":"error","message":"use of unstable library feature 'thread_id'","rendered":null,"spans":[]},"package_id":"dep 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)","reason":"compiler-message","target":{"kind":["lib"],"name":"dep","edition":"2015"}}
+{"message":{"children":[],"code":null,"level":"error","message":"aborting due to 2 previous errors","rendered":null,"spans":[]},"package_id":"dep 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)","reason":"compiler-message","target":{"kind":["lib"],"name":"dep","edition":"2015"}}
+"##;
+    let f = parse_analysis(out, "").unwrap();
+    assert_eq!(30, f.rustc_version.unwrap());
+    let mut diags: Vec<_> = f.diagnostics.into_iter().collect();
+    diags.sort_by(|a, b| a.2.feature.cmp(&b.2.feature));
+    assert_eq!(2, diags.len(), "{diags:#?}");
+    assert_eq!(("dep", "0.1.0"), (diags[0].0.as_str(), diags[0].1.to_string().as_str()));
+    assert_eq!(BuildDiagnostic { code: Some("E0658".into()), feature: Some("async_await".into()), stable_since: Some(39), message: "async fn is unstable".into() }, diags[0].2);
+    assert_eq!(Some("thread_id"), diags[1].2.feature.as_deref());
+    assert_eq!(Some(19), diags[1].2.stable_since);
+}
+
+#[test]
+fn parse_feature_set() {
+    let out = r##"CHECKING 1.61.0 toplevel 1.0.0 feature=serde
+{"message":{"children":[],"code":{"code":"E0433","explanation":null},"level":"error","message":"failed to resolve: use of undeclared crate or module `serde`","rendered":null,"spans":[]},"package_id":"toplevel 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)","reason":"compiler-message","target":{"kind":["lib"],"name":"toplevel","edition":"2018"}}
+{"reason":"build-finished","success":false}
+"##;
+    let f = parse_analysis(out, "").unwrap();
+    assert_eq!(Some(FeatureSet::Feature("serde".into())), f.feature_set);
+    let (compat, reason) = f.top_level_result().unwrap();
+    assert_eq!(Compat::SuspectedIncompatible, compat);
+    assert!(reason.contains("`serde`"));
+
+    assert!(parse_analysis("CHECKING 1.61.0 toplevel 1.0.0 bogus", "").is_err());
+}
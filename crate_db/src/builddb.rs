@@ -6,6 +6,7 @@ use semver::Version as SemVer;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use ahash::HashMap;
+use std::fmt;
 use std::path::Path;
 use log::{info, error};
 
@@ -85,6 +86,12 @@ impl CompatRanges {
         self.bad.keys().rev().copied().next()
     }
 
+    /// Oldest Rust version that isn't known to fail,
+    /// or the oldest one known to work if nothing is known to fail
+    pub fn msrv(&self) -> Option<RustcMinorVersion> {
+        self.newest_bad().map(|n| n + 1).or_else(|| self.oldest_ok())
+    }
+
     pub fn newest_bad_compat(&self) -> Option<(RustcMinorVersion, Compat)> {
         self.bad.iter().rev().map(|(&c, v)| (c, v.0)).next()
     }
@@ -218,6 +225,25 @@ impl BuildDb {
                 reason TEXT
             );
             CREATE UNIQUE INDEX IF NOT EXISTS build_results_ver on build_results(origin, version, rustc_version);
+            CREATE TABLE IF NOT EXISTS build_diagnostics (
+                origin TEXT NOT NULL,
+                version TEXT NOT NULL,
+                rustc_version TEXT NOT NULL,
+                code TEXT,
+                feature TEXT,
+                stable_since INTEGER,
+                message TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS build_diagnostics_ver on build_diagnostics(origin, version, rustc_version);
+            CREATE TABLE IF NOT EXISTS feature_build_results (
+                origin TEXT NOT NULL,
+                version TEXT NOT NULL,
+                rustc_version TEXT NOT NULL,
+                feature_set TEXT NOT NULL,
+                compat TEXT NOT NULL,
+                reason TEXT
+            );
+            CREATE UNIQUE INDEX IF NOT EXISTS feature_build_results_ver on feature_build_results(origin, version, rustc_version, feature_set);
             ")?;
         Ok(Self {
             conn: Mutex::new(db),
@@ -451,6 +477,145 @@ impl BuildDb {
     pub fn set_compat(&self, origin: &Origin, ver: &SemVer, rustc_version: RustcMinorVersion, compat: Compat, reason: &str) -> Result<()> {
         self.set_compat_multi(&[SetCompatMulti {origin, ver, rustc_version, compat, reason}])
     }
+
+    /// Replaces diagnostics from earlier builds of the same crate versions with the same rustc
+    pub fn set_diagnostics(&self, origin: &Origin, ver: &SemVer, rustc_version: RustcMinorVersion, diagnostics: &[BuildDiagnostic]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let origin_str = origin.to_str();
+            let ver = ver.to_string();
+            let rustc_version = format!("1.{rustc_version}.0");
+            let mut delete = tx.prepare_cached(r"DELETE FROM build_diagnostics WHERE origin = ?1 AND version = ?2 AND rustc_version = ?3")?;
+            delete.execute([origin_str.as_str(), &ver, &rustc_version])?;
+
+            let mut insert = tx.prepare_cached(r"INSERT INTO build_diagnostics(origin, version, rustc_version, code, feature, stable_since, message) VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for d in diagnostics {
+                insert.execute(params![origin_str.as_str(), &ver, &rustc_version, d.code, d.feature, d.stable_since, d.message])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// All diagnostics of all versions of the crate, newest rustc first
+    pub fn get_diagnostics(&self, origin: &Origin) -> Result<Vec<(SemVer, RustcMinorVersion, BuildDiagnostic)>> {
+        let conn = self.conn.lock();
+        let mut get = conn.prepare_cached(r"SELECT version, rustc_version, code, feature, stable_since, message FROM build_diagnostics WHERE origin = ?1")?;
+        let res = get.query_map([origin.to_str().as_str()], |row| {
+            let rustc_version = SemVer::parse(row.get_ref(1)?.as_str()?).map_err(|e| Error::ToSqlConversionFailure(e.into()))?.minor as RustcMinorVersion;
+            Ok((garbage_parse(row.get_ref(0)?.as_str()?), rustc_version, BuildDiagnostic {
+                code: row.get(2)?,
+                feature: row.get(3)?,
+                stable_since: row.get(4)?,
+                message: row.get(5)?,
+            }))
+        })?;
+        let mut res = res.collect::<Result<Vec<_>>>()?;
+        res.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+        Ok(res)
+    }
+
+    /// Unstable or newer language features that the crate version needs, with rustc versions that stabilized them (if known), newest first.
+    ///
+    /// These explain why the crate doesn't build with older compilers.
+    pub fn features_setting_msrv(&self, origin: &Origin, ver: &SemVer) -> Result<Vec<(String, Option<RustcMinorVersion>)>> {
+        let conn = self.conn.lock();
+        let mut get = conn.prepare_cached(r"SELECT feature, MAX(stable_since) FROM build_diagnostics
+            WHERE origin = ?1 AND version = ?2 AND feature IS NOT NULL
+            GROUP BY feature
+            ORDER BY MAX(stable_since) DESC, feature")?;
+        let res = get.query_map([origin.to_str().as_str(), ver.to_string().as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        res.collect()
+    }
+
+    /// Result of a build with non-default features or dependency versions. Replaces the previous build with the same rustc.
+    pub fn set_feature_compat(&self, origin: &Origin, ver: &SemVer, rustc_version: RustcMinorVersion, feature_set: &FeatureSet, compat: Compat, reason: &str) -> Result<()> {
+        info!("https://lib.rs/compat/{}#{} R.1.{}.0 {}={:?} ({})", origin.short_crate_name(), ver, rustc_version, feature_set, compat, reason);
+        let conn = self.conn.lock();
+        let mut insert = conn.prepare_cached(r"INSERT OR REPLACE INTO feature_build_results(origin, version, rustc_version, feature_set, compat, reason) VALUES(?1, ?2, ?3, ?4, ?5, ?6)")?;
+        insert.execute([origin.to_str().as_str(), &ver.to_string(), &format!("1.{rustc_version}.0"), &feature_set.to_string(), &compat.as_char().to_string(), reason])?;
+        Ok(())
+    }
+
+    /// All feature set builds of all versions of the crate, newest crate version first
+    pub fn get_feature_compat(&self, origin: &Origin) -> Result<Vec<FeatureCompatInfo>> {
+        let conn = self.conn.lock();
+        let mut get = conn.prepare_cached(r"SELECT version, rustc_version, feature_set, compat, reason FROM feature_build_results WHERE origin = ?1")?;
+        let res = get.query_map([origin.to_str().as_str()], |row| {
+            let rustc_version = SemVer::parse(row.get_ref(1)?.as_str()?).map_err(|e| Error::ToSqlConversionFailure(e.into()))?.minor as RustcMinorVersion;
+            let feature_set = match row.get_ref(2)?.as_str()?.parse() {
+                Ok(f) => f,
+                Err(()) => return Ok(None), // written by a newer builder?
+            };
+            Ok(Some(FeatureCompatInfo {
+                crate_version: garbage_parse(row.get_ref(0)?.as_str()?),
+                rustc_version,
+                feature_set,
+                compat: Compat::from_str(row.get_ref(3)?.as_str()?),
+                reason: row.get(4)?,
+            }))
+        })?;
+        let mut res = res.filter_map(|r| r.transpose()).collect::<Result<Vec<_>>>()?;
+        res.sort_by(|a, b| b.crate_version.cmp(&a.crate_version).then_with(|| a.feature_set.cmp(&b.feature_set)).then_with(|| b.rustc_version.cmp(&a.rustc_version)));
+        Ok(res)
+    }
+}
+
+/// Way of building a crate other than with its default features and newest dependencies
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FeatureSet {
+    NoDefaultFeatures,
+    AllFeatures,
+    /// Only this feature, without the default ones
+    Feature(Box<str>),
+    /// Dependencies resolved to the oldest versions their requirements allow
+    MinimalVersions,
+}
+
+impl fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoDefaultFeatures => f.write_str("no-default-features"),
+            Self::AllFeatures => f.write_str("all-features"),
+            Self::Feature(name) => write!(f, "feature={name}"),
+            Self::MinimalVersions => f.write_str("minimal-versions"),
+        }
+    }
+}
+
+impl std::str::FromStr for FeatureSet {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s {
+            "no-default-features" => Self::NoDefaultFeatures,
+            "all-features" => Self::AllFeatures,
+            "minimal-versions" => Self::MinimalVersions,
+            _ => Self::Feature(s.strip_prefix("feature=").filter(|f| !f.is_empty()).ok_or(())?.into()),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureCompatInfo {
+    pub crate_version: SemVer,
+    pub rustc_version: RustcMinorVersion,
+    pub feature_set: FeatureSet,
+    pub compat: Compat,
+    pub reason: Option<String>,
+}
+
+/// Structured version of an error from a build
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BuildDiagnostic {
+    /// rustc error code, like `E0658`
+    pub code: Option<String>,
+    /// Unstable feature gate or Cargo feature that the code needed
+    pub feature: Option<String>,
+    /// Rust version that stabilized the feature, if known
+    pub stable_since: Option<RustcMinorVersion>,
+    pub message: String,
 }
 
 pub struct SetCompatMulti<'a> {
@@ -470,3 +635,43 @@ fn garbage_parse(v: &str) -> SemVer {
         build: semver::BuildMetadata::new("parse_error").expect("et tu"),
     })
 }
+
+#[test]
+fn diagnostics() {
+    let t = tempfile::NamedTempFile::new().unwrap();
+    let db = BuildDb::new(t.path()).unwrap();
+    let origin = Origin::from_crates_io_name("fixture");
+    let ver = SemVer::parse("1.2.3").unwrap();
+    let diag = |feature: &str, stable_since| BuildDiagnostic { code: Some("E0658".into()), feature: Some(feature.into()), stable_since, message: format!("{feature} is unstable") };
+
+    db.set_diagnostics(&origin, &ver, 40, &[diag("old_feature", Some(41)), diag("let_else", None)]).unwrap();
+    db.set_diagnostics(&origin, &ver, 50, &[diag("old_feature", Some(41))]).unwrap();
+    db.set_diagnostics(&origin, &ver, 60, &[diag("let_else", Some(65))]).unwrap();
+    // replaces previous build
+    db.set_diagnostics(&origin, &ver, 50, &[diag("async_await", Some(51))]).unwrap();
+
+    assert_eq!(3, db.get_diagnostics(&origin).unwrap().iter().filter(|d| d.1 != 60).count());
+    assert_eq!(vec![("let_else".to_string(), Some(65)), ("async_await".into(), Some(51)), ("old_feature".into(), Some(41))],
+        db.features_setting_msrv(&origin, &ver).unwrap());
+}
+
+#[test]
+fn feature_sets() {
+    let t = tempfile::NamedTempFile::new().unwrap();
+    let db = BuildDb::new(t.path()).unwrap();
+    let origin = Origin::from_crates_io_name("fixture");
+    let ver = SemVer::parse("1.2.3").unwrap();
+    let serde = FeatureSet::Feature("serde".into());
+    assert_eq!(Ok(serde.clone()), serde.to_string().parse());
+    assert_eq!(Err(()), "feature=".parse::<FeatureSet>());
+
+    db.set_feature_compat(&origin, &ver, 60, &serde, Compat::SuspectedIncompatible, "cannot find trait `Serialize`").unwrap();
+    db.set_feature_compat(&origin, &ver, 60, &FeatureSet::MinimalVersions, Compat::VerifiedWorks, "ok").unwrap();
+    // replaces previous build
+    db.set_feature_compat(&origin, &ver, 60, &serde, Compat::VerifiedWorks, "ok").unwrap();
+
+    let res = db.get_feature_compat(&origin).unwrap();
+    assert_eq!(2, res.len());
+    assert_eq!(serde, res[0].feature_set);
+    assert!(res.iter().all(|r| r.compat == Compat::VerifiedWorks && r.rustc_version == 60));
+}
@@ -80,6 +80,17 @@ pub struct DbDerived {
     pub keywords: Vec<String>,
 }
 
+/// Everything the db knows about a crate, for the public dataset
+pub struct CrateDerivedData {
+    pub origin: Origin,
+    pub ranking: f64,
+    pub recent_downloads: u32,
+    /// slug, rank weight, relevance weight
+    pub categories: Vec<(String, f64, f64)>,
+    /// keyword, weight, explicit
+    pub keywords: Vec<(String, f64, bool)>,
+}
+
 pub struct CrateOwnerStat {
     pub github_id: u64,
     pub created_at: (u16, u8, u8),
@@ -605,8 +616,10 @@ impl CrateDb {
                 debug!("ranking changed by {:0.4}; {:?} = {:0.5} => {:0.5}", (prev_ranking - score), all.origin(), prev_ranking, score);
             }
 
+            // undated versions are first seen now
+            let now = Utc::now();
             for ver in all.versions() {
-                let timestamp = ver.created_at.timestamp();
+                let timestamp = ver.created_at.unwrap_or(now).timestamp();
                 let args: &[&dyn ToSql] = &[&crate_id, &ver.num.as_str(), &timestamp];
                 insert_version.execute(args)?;
             }
@@ -640,14 +653,13 @@ impl CrateDb {
         }).await
     }
 
-    /// Replaces entire author_crates table
+    /// Replaces owners of the given crates. Owners of other crates are kept.
     pub async fn index_crate_all_owners(&self, all_owners: &[(Origin, Vec<CrateOwner>)]) -> FResult<bool> {
         self.with_write("index_crate_owners", |tx| {
             let mut get_crate_id = tx.prepare_cached("SELECT id FROM crates WHERE origin = ?1")
                 .map_err(|e| Error::Db(e, "aown"))?;
             let mut insert = tx.prepare_cached("INSERT INTO author_crates(github_id, crate_id, invited_by_github_id, invited_at) VALUES(?1, ?2, ?3, ?4)")?;
-            let mut wipe = tx.prepare_cached("DELETE FROM author_crates")?;
-            wipe.execute([])?;
+            let mut wipe = tx.prepare_cached("DELETE FROM author_crates WHERE crate_id = ?1")?;
 
             for (origin, owners) in all_owners {
                 let crate_id: u32 = match get_crate_id.query_row([&origin.to_str()], |row| row.get(0)) {
@@ -655,6 +667,7 @@ impl CrateDb {
                     Err(rusqlite::Error::QueryReturnedNoRows) => continue,
                     Err(e) => return Err(e.into()),
                 };
+                wipe.execute([crate_id])?;
                 for o in owners {
                     if let Some(github_id) = o.github_id {
                         let invited_by_github_id = match o.invited_by_github_id {
@@ -1018,6 +1031,28 @@ impl CrateDb {
         }).await
     }
 
+    /// Crates first published in the last `days`, newest first, with timestamp of the first release
+    pub async fn newly_added_crates(&self, days: u32, limit: u32) -> FResult<Vec<(Origin, u32)>> {
+        self.with_read_spawn("newly_added_crates", move |conn| {
+            let since = (Utc::now().timestamp() as u32).saturating_sub(days.saturating_mul(3600 * 24));
+            let mut query = conn.prepare_cached(r#"
+                select min(v.created), k.origin
+                    from crate_versions v
+                    join crates k on v.crate_id = k.id
+                    group by v.crate_id
+                    having min(v.created) >= ?1
+                    order by 1 desc
+                    limit ?2
+            "#)?;
+            let q = query.query_map([since, limit], |row| {
+                let origin = Origin::from_str(row.get_ref_unwrap(1).as_str()?);
+                Ok((origin, row.get(0)?))
+            })?;
+            let q = q.filter_map(|r| r.map_err(|e| error!("nac: {}", e)).ok());
+            Ok(q.collect())
+        }).await
+    }
+
     /// Newly added or updated crates in any category
     ///
     /// Returns `origin` strings
@@ -1063,6 +1098,50 @@ impl CrateDb {
         }).await
     }
 
+    /// Ranking, categories and visible keywords of all crates, sorted by origin
+    pub async fn all_crates_derived(&self) -> FResult<Vec<CrateDerivedData>> {
+        self.with_read_spawn("all_crates_derived", |conn| {
+            let mut by_id = HashMap::<u32, CrateDerivedData>::default();
+            let mut q = conn.prepare("SELECT id, origin, ranking, recent_downloads FROM crates").map_err(|e| Error::Db(e, "acd1"))?;
+            let mut rows = q.query([])?;
+            while let Some(row) = rows.next()? {
+                by_id.insert(row.get_unwrap(0), CrateDerivedData {
+                    origin: Origin::from_str(row.get::<_, String>(1)?),
+                    ranking: row.get::<_, Option<f64>>(2)?.unwrap_or(0.),
+                    recent_downloads: row.get_unwrap(3),
+                    categories: Vec::new(),
+                    keywords: Vec::new(),
+                });
+            }
+
+            let mut q = conn.prepare("SELECT crate_id, slug, rank_weight, relevance_weight FROM categories ORDER BY crate_id, rank_weight DESC").map_err(|e| Error::Db(e, "acd2"))?;
+            let mut rows = q.query([])?;
+            while let Some(row) = rows.next()? {
+                if let Some(c) = by_id.get_mut(&row.get_unwrap(0)) {
+                    c.categories.push((row.get_unwrap(1), row.get_unwrap(2), row.get_unwrap(3)));
+                }
+            }
+
+            let mut q = conn.prepare(r#"
+                SELECT ck.crate_id, k.keyword, ck.weight, ck.explicit
+                FROM crate_keywords ck
+                JOIN keywords k ON k.id = ck.keyword_id
+                WHERE k.visible
+                ORDER BY ck.crate_id, ck.weight DESC
+            "#).map_err(|e| Error::Db(e, "acd3"))?;
+            let mut rows = q.query([])?;
+            while let Some(row) = rows.next()? {
+                if let Some(c) = by_id.get_mut(&row.get_unwrap(0)) {
+                    c.keywords.push((row.get_unwrap(1), row.get_unwrap(2), row.get_unwrap::<_, i64>(3) != 0));
+                }
+            }
+
+            let mut all: Vec<_> = by_id.into_values().collect();
+            all.sort_by_cached_key(|c| c.origin.to_str());
+            Ok(all)
+        }).await
+    }
+
     /// Number of crates in every category
     pub async fn category_crate_counts(&self) -> FResult<HashMap<String, (u32, f64)>> {
         self.with_read_spawn("category_crate_counts", |conn| {
@@ -1089,6 +1168,18 @@ impl CrateDb {
         }).await
     }
 
+    /// Names of all crates.io crates that have ever been indexed
+    pub async fn crates_io_crate_names(&self) -> FResult<Vec<SmolStr>> {
+        self.with_read_spawn("crates_io_crate_names", |conn| {
+            let mut q = conn.prepare("SELECT origin FROM crates WHERE origin LIKE 'crates.io:%'")?;
+            let q = q.query_map([], |r| {
+                let s = r.get_ref_unwrap(0).as_str()?;
+                Ok(s.trim_start_matches("crates.io:").into())
+            })?.filter_map(|r| r.map_err(|e| error!("names: {}", e)).ok());
+            Ok(q.collect())
+        }).await
+    }
+
     pub async fn delete_crate(&self, origin: &Origin) -> FResult<()> {
         self.with_write("delete", |conn| {
             let origin_str = origin.to_str();
@@ -1303,3 +1394,67 @@ categories = ["1", "two", "GAMES", "science", "::science::math::"]
     rt.block_on(f).unwrap();
 }
 
+
+#[test]
+fn owners_of_unchanged_crates_are_kept() {
+    use rich_crate::OwnerKind;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let f = rt.spawn(async move {
+    let t = tempfile::NamedTempFile::new().unwrap();
+    let sy = categories::Synonyms::new(Path::new("../data/")).unwrap();
+    let db = CrateDb::new_with_synonyms(t.as_ref(), sy).unwrap();
+
+    let source_data = CrateVersionSourceData::default();
+    let origins = [Origin::from_crates_io_name("owned-a"), Origin::from_crates_io_name("owned-b")];
+    for origin in &origins {
+        let manifest = cargo_toml::Manifest::from_str(&format!("[package]\nname=\"{}\"\nversion=\"1.0.0\"\n", origin.short_crate_name())).unwrap();
+        db.index_latest(CrateVersionData {
+            source_data: &source_data,
+            manifest: &manifest,
+            origin,
+            deps_stats: &[],
+            is_build: false,
+            is_dev: false,
+            authors: &[],
+            category_slugs: &[],
+            bad_categories: &[],
+            repository: None,
+            cache_key: 1,
+            extracted_auto_keywords: Vec::new(),
+        }).await.unwrap();
+    }
+    let owner = |github_id| CrateOwner {
+        crates_io_login: format!("user{github_id}").into(),
+        invited_at: None,
+        invited_by_github_id: None,
+        github_id: Some(github_id),
+        name: None,
+        avatar: None,
+        url: None,
+        kind: OwnerKind::User,
+        last_seen_at: None,
+        contributor_only: false,
+    };
+    let owners_of = |origin: &Origin| {
+        let origin = origin.to_str();
+        db.with_read("test", move |conn| {
+            let mut q = conn.prepare("SELECT ac.github_id FROM author_crates ac JOIN crates c ON c.id = ac.crate_id WHERE c.origin = ?1 ORDER BY 1")?;
+            let ids = q.query_map([&origin], |row| row.get(0))?.collect::<Result<Vec<u32>, _>>()?;
+            Ok(ids)
+        })
+    };
+
+    // full pass, then an incremental pass with only the crate that changed owners
+    db.index_crate_all_owners(&[(origins[0].clone(), vec![owner(1)]), (origins[1].clone(), vec![owner(2)])]).await.unwrap();
+    db.index_crate_all_owners(&[(origins[1].clone(), vec![owner(2), owner(3)])]).await.unwrap();
+    assert_eq!(vec![1], owners_of(&origins[0]).await.unwrap());
+    assert_eq!(vec![2, 3], owners_of(&origins[1]).await.unwrap());
+
+    // crate that lost all owners
+    db.index_crate_all_owners(&[(origins[1].clone(), vec![])]).await.unwrap();
+    assert!(owners_of(&origins[1]).await.unwrap().is_empty());
+    assert_eq!(vec![1], owners_of(&origins[0]).await.unwrap());
+    });
+    rt.block_on(f).unwrap();
+}
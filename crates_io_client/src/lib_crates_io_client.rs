@@ -40,7 +40,11 @@ macro_rules! cioopt {
 
 impl CratesIoClient {
     pub fn new(cache_base_path: &Path) -> Result<Self, Error> {
-        let fetcher = Arc::new(Fetcher::new(4));
+        Self::new_with_fetcher(cache_base_path, Arc::new(Fetcher::new(4)))
+    }
+
+    /// The fetcher can use a cassette to replay responses in tests
+    pub fn new_with_fetcher(cache_base_path: &Path, fetcher: Arc<Fetcher>) -> Result<Self, Error> {
         Ok(Self {
             metacache: SimpleFetchCache::new(&cache_base_path.join("cratesiometadb.bin"), fetcher.clone(), true)?,
             ownerscache1: TempCacheJson::new(&cache_base_path.join("cratesioowners1.bin"), fetcher.clone(), Duration::from_secs(3600*24*14))?,
@@ -16,6 +16,13 @@ impl RustSec {
         Ok(Self { db, path })
     }
 
+    /// Uses advisories already in `root/rustsec` without fetching (an empty dir is an empty database)
+    pub fn new_local(root: &Path) -> Result<Self, Error> {
+        let path = root.join("rustsec");
+        let db = Database::open(&path)?;
+        Ok(Self { db, path })
+    }
+
     pub fn update(&mut self) ->  Result<(), Error> {
         self.db = Self::db_at_path(&self.path)?;
         Ok(())
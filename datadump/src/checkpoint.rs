@@ -0,0 +1,204 @@
+//! Remembers what has been ingested from the previous data dump, so that daily runs only need to index what has changed.
+//!
+//! Saved as `datadump_checkpoint.json.gz` in the data dir. Delete it or run with `--full` to reindex everything.
+
+use crate::{BoxErr, CrateDepsMap, CrateId, CrateOwners, CrateVersionId, CratesMap, VersionsMap};
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
+use chrono::prelude::*;
+use libflate::gzip::{Decoder, Encoder};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// Dependers' activity expires with time, even if nothing changes in the dump
+const FULL_REINDEX_AFTER_DAYS: i64 = 7;
+
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    /// YYYY-MM-DD of the last run that reindexed everything
+    pub last_full_run: Option<String>,
+    /// YYYY-MM-DD of the newest download stats ingested
+    pub last_download_day: Option<String>,
+    pub crate_names: HashMap<CrateId, String>,
+    /// Versions are immutable, except yanking
+    pub yanked_versions: HashMap<CrateVersionId, bool>,
+    /// owner id, owner kind
+    pub owners: HashMap<CrateId, Vec<(u32, u8)>>,
+}
+
+/// What needs reindexing in this run
+pub(crate) struct Changes {
+    pub full: bool,
+    /// New crates, and crates with new or (un)yanked versions
+    pub crates: HashSet<CrateId>,
+    pub owners: HashSet<CrateId>,
+    /// Downloads on this day or later need indexing
+    pub downloads_since: Option<Date<Utc>>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.crates.is_empty() && self.owners.is_empty()
+    }
+
+    /// Crates depended on by the changed crates. Their reverse dependencies need updating.
+    pub fn affected_dependencies(&self, versions: &VersionsMap, deps: &CrateDepsMap) -> HashSet<CrateId> {
+        // the ranges are based on all releases, so an old release can end when a new one is published
+        self.crates.iter().chain(&self.owners)
+            .filter_map(|crate_id| versions.get(crate_id))
+            .flatten()
+            .filter_map(|v| deps.get(&v.id))
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Option<Self> {
+        let file = std::fs::File::open(path).ok()?;
+        let res = Decoder::new(std::io::BufReader::new(file)).map_err(BoxErr::from)
+            .and_then(|d| serde_json::from_reader(d).map_err(BoxErr::from));
+        match res {
+            Ok(c) => Some(c),
+            Err(e) => {
+                eprintln!("Can't read checkpoint {}: {e}", path.display());
+                None
+            },
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BoxErr> {
+        let tmp = path.with_extension("tmp");
+        let mut enc = Encoder::new(std::io::BufWriter::new(std::fs::File::create(&tmp)?))?;
+        serde_json::to_writer(&mut enc, self)?;
+        enc.finish().into_result()?.flush()?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn needs_full_run(&self, today: Date<Utc>) -> bool {
+        match self.last_full_run.as_deref().and_then(|d| crate::date_from_str(d).ok()) {
+            Some(last) => today.signed_duration_since(last).num_days() >= FULL_REINDEX_AFTER_DAYS,
+            None => true,
+        }
+    }
+
+    pub fn changes(&self, force_full: bool) -> Changes {
+        let full = force_full || self.needs_full_run(Utc::today());
+        Changes {
+            full,
+            crates: HashSet::new(),
+            owners: HashSet::new(),
+            // the last day may have been incomplete when it was dumped
+            downloads_since: if full { None } else {
+                self.last_download_day.as_deref().and_then(|d| crate::date_from_str(d).ok()).map(|d| d - chrono::Duration::days(1))
+            },
+        }
+    }
+
+    /// Compares the checkpoint with the dump
+    pub fn find_changed_crates(&self, changes: &mut Changes, crates: &CratesMap, versions: &VersionsMap, owners: &CrateOwners) {
+        changes.crates.extend(crates.iter()
+            .filter(|&(id, name)| self.crate_names.get(id).map_or(true, |old| old.as_str() != name.as_str()))
+            .map(|(id, _)| *id));
+        changes.crates.extend(versions.iter()
+            .filter(|(_, vers)| vers.iter().any(|v| self.yanked_versions.get(&v.id) != Some(&(v.yanked == 't'))))
+            .map(|(id, _)| *id));
+        changes.owners.extend(owners.iter()
+            .filter(|&(id, rows)| self.owners.get(id).map_or(true, |old| *old != owner_ids(rows)))
+            .map(|(id, _)| *id));
+        // crates without any owners are missing from the dump
+        changes.owners.extend(self.owners.keys().filter(|id| !owners.contains_key(id)));
+    }
+
+    /// Updates the checkpoint after everything has been indexed
+    pub fn update(&mut self, changes: &Changes, crates: Option<&CratesMap>, versions: Option<&VersionsMap>, owners: Option<&CrateOwners>, last_download_day: Option<Date<Utc>>) {
+        if changes.full {
+            self.last_full_run = Some(Utc::today().format("%Y-%m-%d").to_string());
+        }
+        if let Some(day) = last_download_day {
+            self.last_download_day = Some(day.format("%Y-%m-%d").to_string());
+        }
+        if let Some(crates) = crates {
+            self.crate_names = crates.iter().map(|(&id, name)| (id, name.to_string())).collect();
+        }
+        if let Some(versions) = versions {
+            self.yanked_versions = versions.values().flatten().map(|v| (v.id, v.yanked == 't')).collect();
+        }
+        if let Some(owners) = owners {
+            self.owners = owners.iter().map(|(&id, rows)| (id, owner_ids(rows))).collect();
+        }
+    }
+}
+
+fn owner_ids(rows: &[crate::CrateOwnerRow]) -> Vec<(u32, u8)> {
+    let mut ids: Vec<_> = rows.iter().map(|o| (o.owner_id, o.owner_kind)).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[cfg(test)]
+fn owner_row(crate_id: CrateId, owner_id: u32) -> crate::CrateOwnerRow {
+    crate::CrateOwnerRow { crate_id, created_at: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0), created_by_id: None, owner_id, owner_kind: 0 }
+}
+
+#[cfg(test)]
+pub(crate) fn version_row(crate_id: CrateId, id: CrateVersionId, yanked: bool) -> crate::CrateVersionRow {
+    crate::CrateVersionRow {
+        checksum: [0; 32], crate_id, crate_size: None, created_at: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0), downloads: 0,
+        features: "{}".into(), id, license: String::new(), links: None, num: "1.0.0".into(), published_by: None,
+        updated_at: String::new(), yanked: if yanked { 't' } else { 'f' },
+    }
+}
+
+#[test]
+fn incremental_crate_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("checkpoint.json.gz");
+    let mut crates: CratesMap = [(1, "a".into()), (2, "b".into())].into_iter().collect();
+    let mut versions: VersionsMap = [(1, vec![version_row(1, 100, false)]), (2, vec![version_row(2, 200, false)])].into_iter().collect();
+    let owners = CrateOwners::new();
+
+    let mut checkpoint = Checkpoint::default();
+    let changes = checkpoint.changes(false);
+    assert!(changes.full);
+    assert!(changes.downloads_since.is_none());
+    checkpoint.update(&changes, Some(&crates), Some(&versions), Some(&owners), Some(Utc.ymd(2022, 3, 10)));
+    checkpoint.save(&path).unwrap();
+
+    // second pass from the saved file: a new crate, and a yanked release
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    crates.insert(3, "c".into());
+    versions.insert(3, vec![version_row(3, 300, false)]);
+    versions.get_mut(&2).unwrap()[0].yanked = 't';
+    let mut changes = checkpoint.changes(false);
+    assert!(!changes.full);
+    assert_eq!(changes.downloads_since, Some(Utc.ymd(2022, 3, 9)));
+    checkpoint.find_changed_crates(&mut changes, &crates, &versions, &owners);
+    assert_eq!(changes.crates, [2, 3].into_iter().collect());
+    assert!(changes.owners.is_empty());
+    assert!(checkpoint.changes(true).full);
+}
+
+#[test]
+fn incremental_owner_changes() {
+    let crates: CratesMap = [(1, "a".into()), (2, "b".into()), (3, "c".into())].into_iter().collect();
+    let versions = VersionsMap::new();
+    let mut owners: CrateOwners = [(1, vec![owner_row(1, 10)]), (2, vec![owner_row(2, 20)]), (3, vec![owner_row(3, 30)])].into_iter().collect();
+
+    let mut checkpoint = Checkpoint::default();
+    let mut changes = checkpoint.changes(false);
+    assert!(changes.full);
+    checkpoint.find_changed_crates(&mut changes, &crates, &versions, &owners);
+    checkpoint.update(&changes, Some(&crates), Some(&versions), Some(&owners), None);
+
+    // second pass: crate 2 got a new owner, crate 3 lost all owners, crate 1 is unchanged
+    owners.get_mut(&2).unwrap().push(owner_row(2, 21));
+    owners.remove(&3);
+    let mut changes = checkpoint.changes(false);
+    assert!(!changes.full);
+    checkpoint.find_changed_crates(&mut changes, &crates, &versions, &owners);
+    assert!(changes.crates.is_empty());
+    assert_eq!(changes.owners, [2, 3].into_iter().collect());
+}
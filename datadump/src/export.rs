@@ -0,0 +1,200 @@
+//! `datadump export <dir>` writes the data derived by lib.rs as a public dataset.
+//!
+//! The files are described in `schema.json` written along with them.
+//! Bump `DATASET_VERSION` when removing or changing meaning of any column. Adding columns at the end is fine.
+
+use crate::BoxErr;
+use chrono::prelude::*;
+use kitchen_sink::{CompatByCrateVersion, KitchenSink, Origin};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const DATASET_VERSION: u32 = 1;
+
+const HISTOGRAMS: [&str; 6] = ["releases", "sizes", "deps", "age", "maintenance", "languish"];
+
+#[derive(Serialize)]
+struct Schema {
+    version: u32,
+    generated_at: String,
+    files: &'static [FileSchema],
+}
+
+#[derive(Serialize)]
+struct FileSchema {
+    file: &'static str,
+    description: &'static str,
+    columns: &'static [Column],
+}
+
+#[derive(Serialize)]
+struct Column {
+    name: &'static str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    description: &'static str,
+}
+
+const ORIGIN_COLUMN: Column = Column { name: "origin", ty: "string", description: "Crate's unique id, e.g. `crates.io:serde` or `github:owner/repo/crate`" };
+
+const FILES: &[FileSchema] = &[
+    FileSchema {
+        file: "crates.csv",
+        description: "One row per crate known to lib.rs",
+        columns: &[
+            ORIGIN_COLUMN,
+            Column { name: "ranking", ty: "float", description: "lib.rs overall crate score, 0 to 1" },
+            Column { name: "recent_downloads", ty: "integer", description: "Downloads in the last 3 months, with download spam removed" },
+            Column { name: "categories", ty: "string", description: "Space-separated category slugs, most relevant first" },
+            Column { name: "dependers", ty: "integer", description: "Number of active users of crates that currently depend on it, see dependers_monthly.csv" },
+            Column { name: "rustc_oldest_ok", ty: "integer?", description: "Oldest Rust 1.x minor version that the newest stable release of the crate has been verified to build with" },
+        ],
+    },
+    FileSchema {
+        file: "crate_categories.csv",
+        description: "Categories assigned by lib.rs, which may differ from categories set by crate authors",
+        columns: &[
+            ORIGIN_COLUMN,
+            Column { name: "slug", ty: "string", description: "lib.rs category slug, e.g. `parsing` or `development-tools::testing`" },
+            Column { name: "rank_weight", ty: "float", description: "How well the crate ranks in the category" },
+            Column { name: "relevance_weight", ty: "float", description: "How confident the categorization is" },
+        ],
+    },
+    FileSchema {
+        file: "crate_keywords.csv",
+        description: "Keywords set by crate authors and extracted from crates' metadata, only those shown on lib.rs",
+        columns: &[
+            ORIGIN_COLUMN,
+            Column { name: "keyword", ty: "string", description: "Normalized keyword" },
+            Column { name: "weight", ty: "float", description: "Relevance of the keyword" },
+            Column { name: "explicit", ty: "bool", description: "Set by the crate's author, rather than guessed" },
+        ],
+    },
+    FileSchema {
+        file: "rustc_compat.csv",
+        description: "Results of test builds of crate versions with various Rust versions",
+        columns: &[
+            ORIGIN_COLUMN,
+            Column { name: "version", ty: "string", description: "Crate's version" },
+            Column { name: "rustc_oldest_ok", ty: "integer?", description: "Oldest Rust 1.x minor version that has built this crate version" },
+            Column { name: "rustc_newest_bad", ty: "integer?", description: "Newest Rust 1.x minor version that has failed to build this crate version" },
+        ],
+    },
+    FileSchema {
+        file: "dependers_monthly.csv",
+        description: "Crates.io crates starting and stopping to depend on the crate, by month",
+        columns: &[
+            ORIGIN_COLUMN,
+            Column { name: "year", ty: "integer", description: "" },
+            Column { name: "month", ty: "integer", description: "1-12" },
+            Column { name: "added", ty: "integer", description: "Crates that started depending on it" },
+            Column { name: "removed", ty: "integer", description: "Crates that released a version without the dependency" },
+            Column { name: "expired", ty: "integer", description: "Crates that still depend on it, but haven't been updated in a long time" },
+            Column { name: "users_total", ty: "integer", description: "Owners of depending crates at the end of the month, where each crate counts as at most one user" },
+        ],
+    },
+    FileSchema {
+        file: "stats_histograms.jsonl",
+        description: "Histograms of all crates.io crates shown on lib.rs/stats, one JSON object per line",
+        columns: &[
+            Column { name: "kind", ty: "string", description: "releases (number of), sizes (KB of the latest tarball), deps (number of in the latest version), age (weeks since first release), maintenance (weeks between first and last release), languish (weeks since last release)" },
+            Column { name: "value", ty: "integer", description: "Histogram bucket" },
+            Column { name: "crates", ty: "integer", description: "Number of crates in the bucket" },
+            Column { name: "examples", ty: "array of strings", description: "A few crate names from the bucket" },
+        ],
+    },
+];
+
+#[derive(Serialize)]
+struct HistogramLine<'a> {
+    kind: &'a str,
+    value: u32,
+    crates: u32,
+    examples: &'a [String],
+}
+
+pub(crate) async fn run(dir: &Path) -> Result<(), BoxErr> {
+    let ksink = KitchenSink::new_default().await?;
+    std::fs::create_dir_all(dir)?;
+
+    eprintln!("Loading crates");
+    let crates = ksink.crate_db.all_crates_derived().await?;
+    eprintln!("Loading build results");
+    let compat = ksink.all_rustc_compat()?;
+
+    tokio::task::block_in_place(|| {
+        let mut crates_csv = csv::Writer::from_path(dir.join("crates.csv"))?;
+        crates_csv.write_record(["origin", "ranking", "recent_downloads", "categories", "dependers", "rustc_oldest_ok"])?;
+        let mut categories_csv = csv::Writer::from_path(dir.join("crate_categories.csv"))?;
+        categories_csv.write_record(["origin", "slug", "rank_weight", "relevance_weight"])?;
+        let mut keywords_csv = csv::Writer::from_path(dir.join("crate_keywords.csv"))?;
+        keywords_csv.write_record(["origin", "keyword", "weight", "explicit"])?;
+        let mut dependers_csv = csv::Writer::from_path(dir.join("dependers_monthly.csv"))?;
+        dependers_csv.write_record(["origin", "year", "month", "added", "removed", "expired", "users_total"])?;
+
+        eprintln!("Writing {} crates", crates.len());
+        for c in &crates {
+            let origin = c.origin.to_str();
+            let dependers = ksink.depender_changes(&c.origin)?;
+            for m in &dependers {
+                dependers_csv.serialize((&origin, m.year, m.month0 + 1, m.added, m.removed, m.expired, m.users_total))?;
+            }
+            let categories = c.categories.iter().map(|(slug, ..)| slug.as_str()).collect::<Vec<_>>().join(" ");
+            let users = dependers.last().map_or(0, |m| m.users_total);
+            crates_csv.serialize((&origin, c.ranking, c.recent_downloads, categories, users, newest_stable_oldest_ok(compat.get(&c.origin))))?;
+            for (slug, rank_weight, relevance_weight) in &c.categories {
+                categories_csv.serialize((&origin, slug, rank_weight, relevance_weight))?;
+            }
+            for (keyword, weight, explicit) in &c.keywords {
+                keywords_csv.serialize((&origin, keyword, weight, explicit))?;
+            }
+        }
+        crates_csv.flush()?;
+        categories_csv.flush()?;
+        keywords_csv.flush()?;
+        dependers_csv.flush()?;
+
+        eprintln!("Writing build results of {} crates", compat.len());
+        let mut compat_csv = csv::Writer::from_path(dir.join("rustc_compat.csv"))?;
+        compat_csv.write_record(["origin", "version", "rustc_oldest_ok", "rustc_newest_bad"])?;
+        let mut compat: Vec<_> = compat.iter().map(|(o, c)| (o.to_str(), c)).collect();
+        compat.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for (origin, by_ver) in compat {
+            for (ver, c) in by_ver {
+                compat_csv.serialize((&origin, ver.to_string(), c.oldest_ok(), c.newest_bad()))?;
+            }
+        }
+        compat_csv.flush()?;
+
+        let mut histograms = BufWriter::new(File::create(dir.join("stats_histograms.jsonl"))?);
+        for kind in HISTOGRAMS {
+            let hist = match ksink.get_stats_histogram(kind)? {
+                Some(h) => h,
+                None => continue,
+            };
+            let mut buckets: Vec<_> = hist.iter().collect();
+            buckets.sort_unstable_by_key(|(value, _)| **value);
+            for (&value, (crates, examples)) in buckets {
+                serde_json::to_writer(&mut histograms, &HistogramLine { kind, value, crates: *crates, examples })?;
+                histograms.write_all(b"\n")?;
+            }
+        }
+        histograms.flush()?;
+
+        let schema = Schema {
+            version: DATASET_VERSION,
+            generated_at: Utc::now().to_rfc3339(),
+            files: FILES,
+        };
+        std::fs::write(dir.join("schema.json"), serde_json::to_vec_pretty(&schema)?)?;
+        eprintln!("Dataset v{DATASET_VERSION} written to {}", dir.display());
+        Ok(())
+    })
+}
+
+/// Prerelease versions are often broken
+fn newest_stable_oldest_ok(compat: Option<&CompatByCrateVersion>) -> Option<u16> {
+    compat?.iter().rev().find(|(ver, _)| ver.pre.is_empty()).and_then(|(_, c)| c.oldest_ok())
+}
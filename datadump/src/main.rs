@@ -23,6 +23,13 @@ use std::io::Read;
 use tar::Archive;
 use smartstring::alias::String as SmolStr;
 
+mod checkpoint;
+use checkpoint::Checkpoint;
+mod export;
+mod spam;
+use spam::ReviewStatus;
+
+#[derive(Clone)]
 struct Incident<Date, Set> {
     start: Date, end: Date,
     headroom: u32, lookaround: u8,
@@ -86,7 +93,17 @@ type BoxErr = Box<dyn std::error::Error + Sync + Send>;
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let path = std::env::args_os().nth(1);
+    if std::env::args_os().nth(1).map_or(false, |a| a == "export") {
+        let dir = std::env::args_os().nth(2).map_or_else(|| "dataset".into(), std::path::PathBuf::from);
+        let res = tokio::runtime::Handle::current().spawn(async move { export::run(&dir).await }).await.unwrap();
+        if let Err(e) = res {
+            eprintln!("export failed: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let force_full = std::env::args_os().any(|a| a == "--full");
+    let path = std::env::args_os().skip(1).find(|a| a != "--full");
 
     let res: Result<(), BoxErr> = tokio::runtime::Handle::current().spawn(async move {
         let handle = tokio::runtime::Handle::current();
@@ -106,6 +123,17 @@ async fn main() {
         let mut a = Archive::new(Decoder::new(res)?);
 
         tokio::task::block_in_place(move || {
+            let checkpoint_path = KitchenSink::data_path()?.join("datadump_checkpoint.json.gz");
+            let mut checkpoint = Checkpoint::load(&checkpoint_path).unwrap_or_default();
+            let mut changes = checkpoint.changes(force_full);
+            if changes.full {
+                eprintln!("Reindexing everything");
+            }
+            let mut changes_found = false;
+            // crates whose pages show different stats now
+            let mut stats_changed = HashSet::new();
+            let mut last_download_day = None;
+
             let mut crate_owners = None;
             let mut crates = None;
             let mut metadata = None;
@@ -114,6 +142,7 @@ async fn main() {
             let mut downloads = None;
             let mut versions = None;
             let mut dependencies = None;
+            let mut dependents = None;
 
             for file in a.entries()? {
                 let file = file?;
@@ -169,36 +198,90 @@ async fn main() {
                     };
 
                     if let (Some(crates), Some(versions), Some(crate_owners)) = (&crates, &versions, &crate_owners) {
+                        if !changes_found {
+                            changes_found = true;
+                            checkpoint.find_changed_crates(&mut changes, crates, versions, crate_owners);
+                            eprintln!("{} crates and {} crates' owners changed since the last dump", changes.crates.len(), changes.owners.len());
+                            stats_changed.extend(changes.crates.iter().chain(&changes.owners).copied());
+                        }
                         if let Some(dependencies) = dependencies.take() {
-                            eprintln!("Indexing dependencies for {} crates", dependencies.len());
-                            index_active_rev_dependencies(crates, versions, &dependencies, crate_owners, &ksink)?;
-                            eprintln!("Versions histogram");
-                            versions_histogram(crates, versions, &dependencies, &ksink)?;
+                            dependents = Some(reverse_dependencies(versions, &dependencies));
+                            let only_deps = if changes.full { None } else { Some(changes.affected_dependencies(versions, &dependencies)) };
+                            if let Some(only_deps) = &only_deps {
+                                stats_changed.extend(only_deps.iter().copied());
+                            }
+                            eprintln!("Indexing dependencies for {} crates", only_deps.as_ref().map_or(crates.len(), |d| d.len()));
+                            index_active_rev_dependencies(crates, versions, &dependencies, crate_owners, only_deps.as_ref(), &ksink)?;
+                            if !changes.is_empty() {
+                                eprintln!("Versions histogram");
+                                versions_histogram(crates, versions, &dependencies, &ksink)?;
+                            }
                         }
                     }
 
 
                     if let (Some(crates), Some(versions)) = (&crates, &versions) {
                         if let Some(mut downloads) = downloads.take() {
+                            eprintln!("Detecting download spam");
+                            let incidents_path = KitchenSink::data_path()?.join("download_spam_incidents.json");
+                            let mut known_incidents = spam::load_incidents(&incidents_path)?;
+                            let detected = spam::detect_incidents(crates, versions, &downloads, dependents.as_ref());
+                            spam::save_incidents(&incidents_path, &mut known_incidents, detected)?;
+                            for i in known_incidents.iter().filter(|i| i.status == ReviewStatus::Pending) {
+                                eprintln!("Suspected download spam {}..{} ({} crates, {} downloads, {:?}), review in {}", i.start, i.end, i.names.len(), i.excess_downloads, i.signals, incidents_path.display());
+                            }
+                            let confirmed: Vec<_> = known_incidents.iter()
+                                .filter(|i| i.status == ReviewStatus::Confirmed)
+                                .filter_map(|i| i.as_incident())
+                                .collect();
                             eprintln!("Despamming");
-                            filter_download_spam(crates, versions, &mut downloads);
+                            filter_download_spam(crates, versions, &mut downloads, &confirmed);
+                            last_download_day = downloads.values().flatten().map(|&(d, _, _)| d).max();
                             eprintln!("Indexing downloads for {} crates, {} dl-versions", versions.len(), downloads.len());
-                            index_downloads(crates, versions, &downloads, &ksink);
+                            stats_changed.extend(index_downloads(crates, versions, &downloads, changes.downloads_since, &ksink));
                         }
                     }
                 }
             }
 
-            if let (Some(crates), Some(teams), Some(users)) = (crates, teams, users) {
-                if let Some(crate_owners) = crate_owners.take() {
-                    handle.spawn(async move {
-                        eprintln!("Indexing owners of {} crates", crate_owners.len());
-                        let owners = process_owners(&crates, crate_owners, &teams, &users);
-                        eprintln!("Upserting {} owners", owners.len());
-                        ksink.index_crates_io_crate_all_owners(owners).await.unwrap();
-                    });
+            checkpoint.update(&changes, crates.as_ref(), versions.as_ref(), crate_owners.as_ref(), last_download_day);
+
+            if let (Some(crates), Some(teams), Some(users)) = (&crates, &teams, &users) {
+                if let Some(mut crate_owners) = crate_owners.take() {
+                    if !changes.full {
+                        crate_owners.retain(|crate_id, _| changes.owners.contains(crate_id));
+                    }
+                    // so that owners of crates that have none left are removed
+                    for &crate_id in &changes.owners {
+                        crate_owners.entry(crate_id).or_default();
+                    }
+                    eprintln!("Indexing owners of {} crates", crate_owners.len());
+                    let owners = process_owners(crates, crate_owners, teams, users);
+                    eprintln!("Upserting {} owners", owners.len());
+                    handle.block_on(ksink.index_crates_io_crate_all_owners(owners))?;
+                }
+            }
+
+            if let Some(crates) = &crates {
+                // the sparse index only knows crates it's told about. Full runs recheck all of them.
+                if changes.full {
+                    handle.block_on(ksink.update_all_crates_io_crates(crates.values().map(|name| name.as_str())));
+                } else {
+                    handle.block_on(ksink.update_crates_io_crates(changes.crates.iter().filter_map(|id| crates.get(id)).map(|name| name.as_str())));
                 }
             }
+
+            // saved only after everything has been indexed, so that a failed run is retried in full
+            checkpoint.save(&checkpoint_path)?;
+
+            if let Some(crates) = &crates {
+                let origins: Vec<_> = stats_changed.iter()
+                    .filter_map(|id| crates.get(id))
+                    .map(|name| Origin::from_crates_io_name(name))
+                    .collect();
+                eprintln!("Stats of {} crates changed", origins.len());
+                ksink.post_daily_stats_updated(&origins)?;
+            }
             Ok(())
         })
     })
@@ -216,23 +299,24 @@ async fn main() {
 }
 
 // Cap spammed download data to ~prev week's max during incident period
+//
+// `confirmed` are detected incidents that have been reviewed, in addition to the hardcoded ones
 #[inline(never)]
-fn filter_download_spam(crates: &CratesMap, versions: &VersionsMap, downloads: &mut VersionDownloads) {
+fn filter_download_spam(crates: &CratesMap, versions: &VersionsMap, downloads: &mut VersionDownloads, confirmed: &[Incident<Date<Utc>, HashSet<&str>>]) {
     // the downloads in the datadump aren't complete, so incidents can be fixed only when they're recent
     let earliest_date_available = downloads.values().flatten().map(|&(d, _, _)| d).min().unwrap();
-    let incidents: Vec<_> = DOWNLOAD_SPAM_INCIDENTS.iter().filter_map(|&Incident {start,end,headroom,lookaround,names}| {
-        let start = date_from_str(start).unwrap();
-        // otherwise it won't have enough before/after data
-        if earliest_date_available > start - chrono::Duration::days(lookaround.into()) {
-            return None;
-        }
-        Some(Incident {
-            start,
+    let incidents: Vec<_> = DOWNLOAD_SPAM_INCIDENTS.iter().map(|&Incident {start,end,headroom,lookaround,names}| {
+        Incident {
+            start: date_from_str(start).unwrap(),
             end: date_from_str(end).unwrap(),
             headroom, lookaround,
             names: names.iter().copied().collect::<HashSet<_>>(),
-        })
-    }).collect();
+        }
+    })
+    .chain(confirmed.iter().cloned())
+    // otherwise it won't have enough before/after data
+    .filter(|i| earliest_date_available <= i.start - chrono::Duration::days(i.lookaround.into()))
+    .collect();
     if incidents.is_empty() {
         return;
     }
@@ -266,26 +350,52 @@ fn filter_download_spam(crates: &CratesMap, versions: &VersionsMap, downloads: &
     }
 }
 
+/// Indexes only days `since` (and days changed by despamming), and returns crates that had any downloads to index
 #[inline(never)]
-fn index_downloads(crates: &CratesMap, versions: &VersionsMap, downloads: &VersionDownloads, ksink: &KitchenSink) {
+fn index_downloads(crates: &CratesMap, versions: &VersionsMap, downloads: &VersionDownloads, since: Option<Date<Utc>>, ksink: &KitchenSink) -> HashSet<CrateId> {
+    let mut indexed = HashSet::with_capacity(crates.len());
     for (crate_id, name) in crates {
         if let Some(vers) = versions.get(crate_id) {
-            let data = vers
+            let new_days: Vec<(&str, Vec<_>)> = vers
                 .iter()
                 .filter_map(|version| {
-                    if let Some(d) = downloads.get(&version.id) {
-                        return Some((version.num.as_str(), d.as_slice()));
+                    let d = downloads.get(&version.id)?;
+                    let d: Vec<_> = d.iter().filter(|&&(day, _, ovr)| ovr || since.map_or(true, |since| day >= since)).copied().collect();
+                    if d.is_empty() {
+                        return None;
                     }
-                    None
+                    Some((version.num.as_str(), d))
                 })
                 .collect();
+            if new_days.is_empty() {
+                continue;
+            }
+            let data = new_days.iter().map(|(num, d)| (*num, d.as_slice())).collect();
             if let Err(e) = ksink.index_crate_downloads(name, &data) {
                 eprintln!("Can't index downloads for {name}: {e}");
+                continue;
             }
+            indexed.insert(*crate_id);
         } else {
             eprintln!("Bad crate? {crate_id} {name}");
         }
     }
+    indexed
+}
+
+/// Crates that have ever depended on the crate (by crate ID)
+#[inline(never)]
+fn reverse_dependencies(versions: &VersionsMap, deps: &CrateDepsMap) -> HashMap<CrateId, Vec<CrateId>> {
+    let mut out = HashMap::<CrateId, Vec<CrateId>>::with_capacity(NUM_CRATES);
+    for (&crate_id, vers) in versions {
+        let dep_crate_ids: HashSet<_> = vers.iter().flat_map(|v| deps.get(&v.id)).flatten().copied().collect();
+        for dep_crate_id in dep_crate_ids {
+            if dep_crate_id != crate_id {
+                out.entry(dep_crate_id).or_default().push(crate_id);
+            }
+        }
+    }
+    out
 }
 
 const EXAMPLES_PER_BUCKET: usize = 5;
@@ -383,8 +493,10 @@ pub struct DepChangeAggregator {
 }
 
 /// Direct reverse dependencies, but with release dates (when first seen or last used)
+///
+/// `only_deps` limits reindexing to these dependencies
 #[inline(never)]
-fn index_active_rev_dependencies(crates: &CratesMap, versions: &VersionsMap, deps: &CrateDepsMap, owners: &CrateOwners, ksink: &KitchenSink) -> Result<(), BoxErr> {
+fn index_active_rev_dependencies(crates: &CratesMap, versions: &VersionsMap, deps: &CrateDepsMap, owners: &CrateOwners, only_deps: Option<&HashSet<CrateId>>, ksink: &KitchenSink) -> Result<(), BoxErr> {
     let mut deps_changes = HashMap::with_capacity(crates.len());
 
     for (crate_id, name) in crates {
@@ -444,6 +556,9 @@ fn index_active_rev_dependencies(crates: &CratesMap, versions: &VersionsMap, dep
                     // libcpocalypse semver trick - not relevant
                     continue;
                 }
+                if only_deps.map_or(false, |only| !only.contains(&dep_crate_id)) {
+                    continue;
+                }
                 let e = over_time.entry(dep_crate_id).or_insert(DepUse {start_date: release_date, end_date, expired, by_crate_id: *crate_id});
                 if e.end_date < end_date {
                     e.end_date = end_date;
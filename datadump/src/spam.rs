@@ -0,0 +1,310 @@
+//! Finds download spam incidents in the data dump, so that they don't have to be hand-listed in `DOWNLOAD_SPAM_INCIDENTS`.
+//!
+//! Detected incidents are saved as `pending` in `download_spam_incidents.json` in the data dir.
+//! Change their `status` to `confirmed` to have them filtered out like the hand-listed ones, or `rejected` to stop them being reported.
+
+use crate::{date_from_str, CrateId, CratesMap, Incident, VersionDownloads, VersionsMap};
+use ahash::{HashMap, HashMapExt, HashSet, HashSetExt};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use smartstring::alias::String as SmolStr;
+use std::path::Path;
+
+/// Downloads over the usual that are too small to bother with
+const MIN_EXCESS: u32 = 200;
+/// How many times the usual number of downloads is a spike
+const SPIKE_RATIO: u32 = 10;
+/// Crates with fewer daily downloads are unlikely to get a sudden burst of real users
+const UNPOPULAR_DAILY: u32 = 100;
+/// This many unrelated crates spiking on the same day is a bot
+const MANY_CRATES_TOGETHER: usize = 25;
+/// Spikes of the same crate this many days apart are one incident
+const MAX_GAP_DAYS: i64 = 2;
+/// Spikes of different crates are joined only within this window, otherwise unrelated ones chain into one long incident
+const MAX_INCIDENT_DAYS: i64 = 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SpamSignal {
+    /// Extra downloads went mostly to versions that have been superseded
+    OldVersions,
+    /// The crate normally has few downloads
+    Unpopular,
+    /// None of the crates depending on it had more downloads
+    NotFromDependents,
+    /// Lots of other crates had spikes at the same time
+    ManyCratesTogether,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReviewStatus {
+    Pending,
+    Confirmed,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DetectedIncident {
+    /// YYYY-MM-DD
+    pub start: String,
+    pub end: String,
+    pub headroom: u32,
+    pub lookaround: u8,
+    pub names: Vec<SmolStr>,
+    pub signals: Vec<SpamSignal>,
+    /// Downloads over the usual, summed for all crates and days
+    pub excess_downloads: u64,
+    pub status: ReviewStatus,
+}
+
+impl DetectedIncident {
+    /// The same as the hand-listed incidents, for `filter_download_spam`
+    pub(crate) fn as_incident(&self) -> Option<Incident<Date<Utc>, HashSet<&str>>> {
+        Some(Incident {
+            start: date_from_str(&self.start).ok()?,
+            end: date_from_str(&self.end).ok()?,
+            headroom: self.headroom,
+            lookaround: self.lookaround,
+            names: self.names.iter().map(|n| n.as_str()).collect(),
+        })
+    }
+
+    /// Detections of the same incident in subsequent dumps won't be exactly the same
+    fn is_same_as(&self, other: &Self) -> bool {
+        let overlaps = self.start <= other.end && other.start <= self.end;
+        let names: HashSet<_> = self.names.iter().collect();
+        let common = other.names.iter().filter(|n| names.contains(n)).count();
+        overlaps && common * 2 >= self.names.len().min(other.names.len())
+    }
+}
+
+/// Fails if the file exists but can't be parsed, so that reviews in it aren't overwritten
+pub(crate) fn load_incidents(path: &Path) -> Result<Vec<DetectedIncident>, crate::BoxErr> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data).map_err(|e| format!("Can't parse {}: {e}", path.display()).into()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Can't read {}: {e}", path.display()).into()),
+    }
+}
+
+/// Adds new detections as pending, keeping reviewed ones as they were
+pub(crate) fn save_incidents(path: &Path, known: &mut Vec<DetectedIncident>, detected: Vec<DetectedIncident>) -> Result<(), crate::BoxErr> {
+    for d in detected {
+        match known.iter_mut().find(|k| k.is_same_as(&d)) {
+            Some(k) if k.status == ReviewStatus::Pending => *k = d,
+            Some(_) => {},
+            None => known.push(d),
+        }
+    }
+    known.sort_by(|a, b| a.start.cmp(&b.start).then(a.end.cmp(&b.end)));
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(known)?)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct CrateDay {
+    excess: u32,
+    old_versions_excess: u32,
+}
+
+/// `dependents` are crates that have ever depended on the crate, if known
+#[inline(never)]
+pub(crate) fn detect_incidents(crates: &CratesMap, versions: &VersionsMap, downloads: &VersionDownloads, dependents: Option<&HashMap<CrateId, Vec<CrateId>>>) -> Vec<DetectedIncident> {
+    let mut spikes = HashMap::<(CrateId, Date<Utc>), CrateDay>::new();
+    let mut unpopular = HashSet::new();
+    // the most popular version's median daily downloads
+    let mut usual_daily = HashMap::<CrateId, u32>::new();
+
+    for crate_id in crates.keys() {
+        let vers = match versions.get(crate_id) {
+            Some(v) => v,
+            None => continue,
+        };
+        let mut crate_daily = HashMap::<Date<Utc>, u32>::new();
+        for v in vers {
+            let dl = match downloads.get(&v.id) {
+                Some(dl) if dl.len() >= 14 => dl,
+                _ => continue,
+            };
+            for &(day, n, _) in dl {
+                *crate_daily.entry(day).or_default() += n;
+            }
+            let usual = median(dl.iter().map(|&(_, n, _)| n).collect());
+            let crate_usual = usual_daily.entry(*crate_id).or_default();
+            *crate_usual = usual.max(*crate_usual);
+            for &(day, n, _) in dl {
+                let excess = n.saturating_sub(usual.max(1) * SPIKE_RATIO);
+                if excess == 0 {
+                    continue;
+                }
+                let crate_day = spikes.entry((*crate_id, day)).or_default();
+                crate_day.excess += excess;
+                // releases take a while to get adopted, so only versions superseded a while ago are old
+                let superseded_long_ago = vers.iter().any(|other| other.created_at > v.created_at && other.created_at.date() + chrono::Duration::days(30) < day);
+                if superseded_long_ago {
+                    crate_day.old_versions_excess += excess;
+                }
+            }
+        }
+        if median(crate_daily.into_values().collect()) < UNPOPULAR_DAILY {
+            unpopular.insert(*crate_id);
+        }
+    }
+    spikes.retain(|_, d| d.excess >= MIN_EXCESS);
+
+    let base_signals = |crate_id: CrateId, d: &CrateDay| {
+        let mut signals = Vec::with_capacity(4);
+        if d.old_versions_excess * 2 >= d.excess {
+            signals.push(SpamSignal::OldVersions);
+        }
+        if unpopular.contains(&crate_id) {
+            signals.push(SpamSignal::Unpopular);
+        }
+        signals
+    };
+
+    let mut suspicious_by_day = HashMap::<Date<Utc>, Vec<(CrateId, Vec<SpamSignal>, u32)>>::new();
+    for (&(crate_id, day), d) in &spikes {
+        let mut signals = base_signals(crate_id, d);
+        if let Some(dependents) = dependents {
+            // spam of a whole dependency tree shouldn't make the dependencies look legit
+            let explained = dependents.get(&crate_id).map_or(false, |deps| deps.iter().any(|&dep| {
+                spikes.get(&(dep, day)).map_or(false, |dep_day| base_signals(dep, dep_day).len() < 2)
+            }));
+            if explained {
+                continue;
+            }
+            signals.push(SpamSignal::NotFromDependents);
+        }
+        suspicious_by_day.entry(day).or_default().push((crate_id, signals, d.excess));
+    }
+
+    // runs of suspicious days per crate
+    let mut runs = HashMap::<CrateId, Vec<(Date<Utc>, Date<Utc>, HashSet<SpamSignal>, u64)>>::new();
+    let mut days: Vec<_> = suspicious_by_day.into_iter().collect();
+    days.sort_unstable_by_key(|(day, _)| *day);
+    for (day, mut crates_that_day) in days {
+        let many_together = crates_that_day.len() >= MANY_CRATES_TOGETHER;
+        for (crate_id, signals, excess) in &mut crates_that_day {
+            if many_together {
+                signals.push(SpamSignal::ManyCratesTogether);
+            }
+            if signals.len() < 2 {
+                continue;
+            }
+            let crate_runs = runs.entry(*crate_id).or_default();
+            match crate_runs.last_mut() {
+                Some(run) if run.1 + chrono::Duration::days(MAX_GAP_DAYS) >= day => {
+                    run.1 = day;
+                    run.2.extend(signals.iter().copied());
+                    run.3 += u64::from(*excess);
+                },
+                _ => crate_runs.push((day, day, signals.iter().copied().collect(), (*excess).into())),
+            }
+        }
+    }
+
+    // crates spammed at the same time are likely the same bot
+    let mut all_runs: Vec<_> = runs.into_iter().flat_map(|(crate_id, runs)| runs.into_iter().map(move |r| (crate_id, r))).collect();
+    all_runs.sort_unstable_by_key(|(_, r)| (r.0, r.1));
+    let mut incidents = Vec::<(Date<Utc>, Date<Utc>, Vec<CrateId>, HashSet<SpamSignal>, u64)>::new();
+    for (crate_id, (start, end, signals, excess)) in all_runs {
+        let same_bot = incidents.iter_mut().rev().find(|i| {
+            start <= i.1 + chrono::Duration::days(MAX_GAP_DAYS) &&
+            end <= i.0 + chrono::Duration::days(MAX_INCIDENT_DAYS) &&
+            !i.3.is_disjoint(&signals)
+        });
+        match same_bot {
+            Some(i) => {
+                i.1 = i.1.max(end);
+                i.2.push(crate_id);
+                i.3.extend(signals);
+                i.4 += excess;
+            },
+            _ => incidents.push((start, end, vec![crate_id], signals, excess)),
+        }
+    }
+
+    incidents.into_iter().map(|(start, end, crate_ids, signals, excess_downloads)| {
+        let mut names: Vec<SmolStr> = crate_ids.iter().filter_map(|id| crates.get(id).cloned()).collect();
+        names.sort_unstable();
+        names.dedup();
+        let mut signals: Vec<_> = signals.into_iter().collect();
+        signals.sort_unstable();
+        // downloads are cut to the level before/after the incident plus the headroom, so allow for the normal variation of a typical crate in it
+        let headroom = median(crate_ids.iter().filter_map(|id| usual_daily.get(id).copied()).collect());
+        DetectedIncident {
+            start: start.format("%Y-%m-%d").to_string(),
+            end: end.format("%Y-%m-%d").to_string(),
+            headroom,
+            lookaround: 7,
+            names,
+            signals,
+            excess_downloads,
+            status: ReviewStatus::Pending,
+        }
+    }).collect()
+}
+
+fn median(mut values: Vec<u32>) -> u32 {
+    if values.is_empty() {
+        return 0;
+    }
+    let mid = values.len() / 2;
+    *values.select_nth_unstable(mid).1
+}
+
+#[cfg(test)]
+fn synthetic_crates(daily: &[(&str, u32, &[i64])]) -> (CratesMap, VersionsMap, VersionDownloads) {
+    let first_day = Utc.ymd(2022, 1, 1);
+    let mut crates = CratesMap::new();
+    let mut versions = VersionsMap::new();
+    let mut downloads = VersionDownloads::new();
+    for (id, &(name, usual, spike_days)) in (1..).zip(daily) {
+        crates.insert(id, name.into());
+        versions.insert(id, vec![crate::checkpoint::version_row(id, id, false)]);
+        downloads.insert(id, (0..120).map(|day| (first_day + chrono::Duration::days(day), if spike_days.contains(&day) { 5000 } else { usual }, false)).collect());
+    }
+    (crates, versions, downloads)
+}
+
+#[test]
+fn detects_spam_of_unpopular_crates() {
+    let (crates, versions, downloads) = synthetic_crates(&[
+        ("spammed-a", 10, &[40, 41]),
+        ("spammed-b", 12, &[41, 42]),
+        ("quiet", 10, &[]),
+        // not enough signals: it's popular, and the spike is smaller
+        ("popular", 400, &[41]),
+    ]);
+    let no_dependents = HashMap::new();
+    let incidents = detect_incidents(&crates, &versions, &downloads, Some(&no_dependents));
+    assert_eq!(incidents.len(), 1);
+    let i = &incidents[0];
+    assert_eq!((i.start.as_str(), i.end.as_str()), ("2022-02-10", "2022-02-12"));
+    assert_eq!(i.names, ["spammed-a", "spammed-b"]);
+    assert_eq!(i.signals, [SpamSignal::Unpopular, SpamSignal::NotFromDependents]);
+    assert_eq!(i.headroom, 12);
+
+    // without dependency data there's only one signal
+    assert!(detect_incidents(&crates, &versions, &downloads, None).is_empty());
+}
+
+#[test]
+fn unrelated_spikes_dont_chain() {
+    // each crate spikes right after the previous one, for 80 days
+    let names: Vec<_> = (0..40).map(|n| format!("crate-{n:02}")).collect();
+    let spike_days: Vec<_> = (0..40).map(|n| [20 + n * 2]).collect();
+    let daily: Vec<_> = names.iter().zip(&spike_days).map(|(name, days)| (name.as_str(), 10, &days[..])).collect();
+    let (crates, versions, downloads) = synthetic_crates(&daily);
+    let incidents = detect_incidents(&crates, &versions, &downloads, Some(&HashMap::new()));
+    assert!(incidents.len() >= 3);
+    for i in &incidents {
+        let days = date_from_str(&i.end).unwrap().signed_duration_since(date_from_str(&i.start).unwrap()).num_days();
+        assert!(days <= MAX_INCIDENT_DAYS);
+    }
+    assert_eq!(incidents.iter().map(|i| i.names.len()).sum::<usize>(), 40);
+}
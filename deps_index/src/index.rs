@@ -5,7 +5,10 @@ pub use crates_index::DependencyKind;
 pub use crates_index::Version;
 use crate::deps_stats::DepsStats;
 use crate::DepsErr;
+use crate::{Resolution, ResolveQuery};
 use crate::git_crates_index::*;
+use crate::registry::{crates_io_sparse_index, load_registries, AltRegistry};
+use crate::sparse::{index_path, SparseIndex};
 use crates_index::Crate;
 use crates_index::Dependency;
 use double_checked_cell_async::DoubleCheckedCell;
@@ -140,7 +143,12 @@ impl ICrate for RichCrateVersion {
 pub struct Index {
     indexed_crates: HashMap<SmolStr, Crate>,
     pub crates_index_path: PathBuf,
+    /// If set, crates.io is read via the sparse protocol instead of the git index
+    crates_io_sparse: Option<SparseIndex>,
+    /// Self-hosted sites may only have their own registries
+    crates_io_enabled: bool,
     git_index: GitIndex,
+    registries: Vec<AltRegistry>,
 
     pub inter: RwLock<StringInterner<Sym, string_interner::backend::StringBackend<Sym>>>,
     pub cache: DashMap<(SmolStr, Features), ArcDepSet, ahash::RandomState>,
@@ -149,32 +157,40 @@ pub struct Index {
 
 impl Index {
     pub fn new(data_dir: &Path) -> Result<Self, DepsErr> {
+        Self::new_with_crates_io(data_dir, true)
+    }
+
+    /// Without crates.io, only git crates and alternative registries are available
+    pub fn new_with_crates_io(data_dir: &Path, crates_io_enabled: bool) -> Result<Self, DepsErr> {
         let crates_index_path = data_dir.join("index");
-        debug!("Scanning crates index at {}…", crates_index_path.display());
+        let crates_io_sparse = crates_io_sparse_index(data_dir)?;
         let start = Instant::now();
-        let crates_io_index = crates_index::Index::with_path(&crates_index_path, "https://github.com/rust-lang/crates.io-index")
-        .map_err(|e| DepsErr::Crates(e.to_string()))?;
-        let indexed_crates: HashMap<_,_> = crates_io_index.crates_parallel()
-            .filter_map(|c| {
-                debug_assert!(c.is_ok());
-                let c = c.ok()?;
-                if c.name() == "test+package" {
-                    return None; // crates-io bug
-                }
-                let mut name = SmolStr::from(c.name());
-                if !name.bytes().all(|c| c.is_ascii_lowercase() || c == b'_' || c == b'-') {
-                    name = c.name().to_ascii_lowercase().into();
-                }
-                debug_assert!(Origin::is_valid_crate_name(&name), "{name}");
-                Some((name, c))
-            })
-            .collect();
+        let indexed_crates: HashMap<_,_> = if !crates_io_enabled {
+            HashMap::default()
+        } else if let Some(sparse) = &crates_io_sparse {
+            debug!("Loading sparse crates index");
+            sparse.cached_crates().into_par_iter().filter_map(Self::crate_with_lowercase_name).collect()
+        } else {
+            debug!("Scanning crates index at {}…", crates_index_path.display());
+            let crates_io_index = crates_index::Index::with_path(&crates_index_path, "https://github.com/rust-lang/crates.io-index")
+            .map_err(|e| DepsErr::Crates(e.to_string()))?;
+            crates_io_index.crates_parallel()
+                .filter_map(|c| {
+                    debug_assert!(c.is_ok());
+                    Self::crate_with_lowercase_name(c.ok()?)
+                })
+                .collect()
+        };
         info!("Scanned crates index in {}…", start.elapsed().as_millis() as u32);
-        if indexed_crates.len() < 90_000 {
+        // the sparse index has only what has been fetched so far
+        if crates_io_enabled && crates_io_sparse.is_none() && indexed_crates.len() < 90_000 {
             return Err(DepsErr::IndexBroken);
         }
         Ok(Self {
+            crates_io_sparse,
+            crates_io_enabled,
             git_index: GitIndex::new(data_dir)?,
+            registries: load_registries(data_dir)?,
             cache: DashMap::with_capacity_and_hasher(10000, Default::default()),
             inter: RwLock::new(StringInterner::new()),
             deps_stats: DoubleCheckedCell::new(),
@@ -183,7 +199,85 @@ impl Index {
         })
     }
 
+    fn crate_with_lowercase_name(c: Crate) -> Option<(SmolStr, Crate)> {
+        if c.name() == "test+package" {
+            return None; // crates-io bug
+        }
+        let mut name = SmolStr::from(c.name());
+        if !name.bytes().all(|c| c.is_ascii_lowercase() || c == b'_' || c == b'-') {
+            name = c.name().to_ascii_lowercase().into();
+        }
+        debug_assert!(Origin::is_valid_crate_name(&name), "{name}");
+        Some((name, c))
+    }
+
+    /// Fetches the index. The changes are visible after the `Index` is reloaded.
+    ///
+    /// For the sparse crates.io index this doesn't check every crate, see `update_crates_io_crates`.
     pub async fn update(&self) {
+        if !self.crates_io_enabled {
+            debug!("crates.io index disabled");
+        } else if let Some(sparse) = &self.crates_io_sparse {
+            if let Err(e) = sparse.update("config.json").await {
+                error!("sparse index config: {e}");
+            }
+        } else {
+            self.update_git().await;
+        }
+        for r in &self.registries {
+            info!("Updating {} registry index", r.name);
+            r.update().await;
+        }
+    }
+
+    /// Whether crates.io crates are only the ones that have been fetched so far
+    pub fn is_crates_io_sparse(&self) -> bool {
+        self.crates_io_enabled && self.crates_io_sparse.is_some()
+    }
+
+    /// The sparse index can't be listed, so new and changed crates have to be named explicitly
+    /// (new dependencies are discovered automatically). No-op for the git index, which has all crates.
+    pub async fn update_crates_io_crates(&self, names: impl Iterator<Item = &str>) {
+        if let Some(sparse) = self.crates_io_sparse.as_ref().filter(|_| self.crates_io_enabled) {
+            let names: HashSet<_> = names.map(|n| SmolStr::from(n.to_ascii_lowercase()))
+                .filter(|n| Origin::is_valid_crate_name(n))
+                .collect();
+            info!("Updating {} crates in the sparse crates index", names.len());
+            self.fetch_sparse_crates(sparse, names).await;
+        }
+    }
+
+    /// Like `update_crates_io_crates`, but also rechecks every crate fetched before, which takes ~150K requests
+    pub async fn update_all_crates_io_crates<'a>(&'a self, names: impl Iterator<Item = &'a str>) {
+        self.update_crates_io_crates(names.chain(self.indexed_crates.keys().map(|n| n.as_str()))).await;
+    }
+
+    /// Fetches the crates if they've changed, and crawls their dependencies until there's nothing new
+    async fn fetch_sparse_crates(&self, sparse: &SparseIndex, mut new: HashSet<SmolStr>) {
+        let mut seen = HashSet::default();
+        while !new.is_empty() {
+            debug!("Fetching {} new crates", new.len());
+            seen.extend(new.iter().cloned());
+            let fetched = sparse.update_many(new.iter().map(|name| index_path(name))).await;
+            new = self.unknown_dependencies(sparse, fetched.iter().map(|s| s.as_str()));
+            new.retain(|name| !seen.contains(name));
+        }
+    }
+
+    /// Dependencies of the (locally cached) crates that aren't in the index yet
+    fn unknown_dependencies<'a>(&self, sparse: &SparseIndex, rel_paths: impl Iterator<Item = &'a str>) -> HashSet<SmolStr> {
+        rel_paths
+            .filter_map(|rel_path| Crate::from_slice(&sparse.cached(rel_path)?).ok())
+            .flat_map(|c| c.versions().iter()
+                .flat_map(|v| v.dependencies())
+                .filter(|dep| dep.registry().is_none())
+                .map(|dep| SmolStr::from(dep.crate_name().to_ascii_lowercase()))
+                .collect::<Vec<_>>())
+            .filter(|name| !self.indexed_crates.contains_key(name))
+            .collect()
+    }
+
+    async fn update_git(&self) {
         let path = self.crates_index_path.to_owned();
         tokio::task::spawn_blocking(move || {
             info!("Updating crates index");
@@ -206,13 +300,37 @@ impl Index {
         match origin {
             Origin::CratesIo(lowercase_name) => self.crates_io_crate_by_lowercase_name(lowercase_name).is_ok(),
             Origin::GitHub { .. } | Origin::GitLab { .. } => self.git_index.has(origin),
+            Origin::Registry { .. } => self.registry_crate(origin).is_ok(),
         }
     }
 
-    /// All crates available in the crates.io index and our index
+    /// All crates available in the crates.io index, alternative registries, and our index
     ///
     pub fn all_crates(&self) -> impl Iterator<Item = Origin> + '_ {
-        self.git_index.crates().cloned().chain(self.crates_io_crates().keys().map(|n| Origin::from_crates_io_name(n)))
+        let registry_crates = self.registries.iter()
+            .flat_map(|r| r.crates().keys().filter_map(move |n| Origin::try_from_registry(&r.name, n)));
+        self.git_index.crates().cloned()
+            .chain(registry_crates)
+            .chain(self.crates_io_crates().keys().map(|n| Origin::from_crates_io_name(n)))
+    }
+
+    /// Alternative registry, by name from `registries.toml`
+    pub fn registry(&self, name: &str) -> Option<&AltRegistry> {
+        self.registries.iter().find(|r| &*r.name == name)
+    }
+
+    /// Index data of a crate from an alternative registry
+    pub fn registry_crate(&self, origin: &Origin) -> Result<&Crate, DepsErr> {
+        match origin {
+            Origin::Registry { registry, package } => self.registry(registry)
+                .and_then(|r| r.crate_by_lowercase_name(package))
+                .ok_or_else(|| DepsErr::CrateNotFound(origin.clone())),
+            _ => Err(DepsErr::CrateNotFound(origin.clone())),
+        }
+    }
+
+    pub fn registry_crate_highest_version(&self, origin: &Origin, stable_only: bool) -> Result<&Version, DepsErr> {
+        Ok(Self::highest_crates_io_version(self.registry_crate(origin)?, stable_only))
     }
 
     pub async fn deps_stats(&self) -> Result<&DepsStats, DepsErr> {
@@ -236,16 +354,45 @@ impl Index {
 
     /// Changes when crates-io metadata changes (something is published or yanked)
     pub fn cache_key_for_crate(&self, name: &str) -> Result<u64, DepsErr> {
+        Ok(Self::cache_key_for_versions(self.crates_io_crate_by_lowercase_name(name)?))
+    }
+
+    /// Same as `cache_key_for_crate`, but for crates.io or alternative registries
+    pub fn cache_key_for_origin(&self, origin: &Origin) -> Result<u64, DepsErr> {
+        match origin {
+            Origin::CratesIo(name) => self.cache_key_for_crate(name),
+            Origin::Registry { .. } => Ok(Self::cache_key_for_versions(self.registry_crate(origin)?)),
+            _ => Err(DepsErr::NotAPackage(origin.clone())),
+        }
+    }
+
+    fn cache_key_for_versions(c: &Crate) -> u64 {
         use std::hash::Hash;
         use std::hash::Hasher;
         let mut hasher = fxhash::FxHasher::default();
 
-        let c = self.crates_io_crate_by_lowercase_name(name)?;
         for v in c.versions() {
             v.checksum().hash(&mut hasher);
             v.is_yanked().hash(&mut hasher);
         }
-        Ok(hasher.finish())
+        hasher.finish()
+    }
+
+    /// True if some versions match the requirement, but all of them have been yanked, so the requirement can't be used in new lockfiles
+    pub fn requirement_matches_only_yanked(&self, name: &str, requirement: &VersionReq) -> Result<bool, DepsErr> {
+        let krate = self.crates_io_crate_by_lowercase_name(name)?;
+        let mut matching = krate.versions().iter()
+            .filter(|v| SemVer::parse(v.version()).map_or(false, |v| requirement.matches(&v)))
+            .peekable();
+        Ok(matching.peek().is_some() && matching.all(|v| v.is_yanked()))
+    }
+
+    /// Concrete versions of all transitive dependencies, like in `Cargo.lock`. Uses only the local index.
+    ///
+    /// `version` is the latest non-yanked if `None`
+    pub fn resolve_dependencies(&self, name: &str, version: Option<&str>, query: &ResolveQuery) -> Result<Resolution, DepsErr> {
+        crate::resolve::Resolver::new(|name: &str| self.crates_io_crate_by_lowercase_name(name).ok(), query)
+            .resolve(&name.to_ascii_lowercase(), version)
     }
 
     pub fn crate_highest_version(&self, name: &str, stable_only: bool) -> Result<&Version, DepsErr> {
@@ -4,7 +4,13 @@ use rich_crate::Origin;
 use std::path::PathBuf;
 
 mod deps_stats;
+mod resolve;
+pub use resolve::{Resolution, ResolveQuery, ResolvedPackage};
 mod git_crates_index;
+mod registry;
+pub use registry::{AltRegistry, RegistryConfig, RegistrySource};
+mod sparse;
+pub use sparse::index_path;
 pub use crates_index::Crate as CratesIndexCrate;
 pub use crates_index::Version as CratesIndexVersion;
 pub use deps_stats::*;
@@ -32,4 +38,6 @@ pub enum DepsErr {
     GitIndexFile(PathBuf, String),
     #[error("Git crate {0:?} can't be indexed, because it's not on the list")]
     GitCrateNotAllowed(Origin),
+    #[error("Error in registries config {0:?}: {1}")]
+    RegistryConfig(PathBuf, String),
 }
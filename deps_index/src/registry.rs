@@ -0,0 +1,234 @@
+//! Alternative Cargo registries (other than crates.io), configured in `registries.toml` in the data dir:
+//!
+//! ```toml
+//! [registries.my-company]
+//! index = "sparse+https://cargo.example.com/index/"
+//! # sparse indexes can't be listed, so their crates must be named
+//! crates = ["foo", "bar"]
+//! # sent as the Authorization header, like Cargo's registry token
+//! token = "…"
+//! ```
+//!
+//! Git indexes (URLs without the `sparse+` prefix) are cloned, like the crates.io index.
+//!
+//! The crates.io index itself can be switched to the sparse protocol, which doesn't need a multi-GB git checkout:
+//!
+//! ```toml
+//! [crates-io]
+//! index = "sparse+https://index.crates.io/"
+//! ```
+
+use crate::sparse::{index_path, SparseIndex};
+use crate::DepsErr;
+use crates_index::Crate;
+use crates_index::Version;
+use fetcher::Fetcher;
+use log::{info, warn, error};
+use rich_crate::Origin;
+use serde_derive::*;
+use smartstring::alias::String as SmolStr;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use ahash::HashMap;
+
+/// Much more than crates.io allows, but private registries may have bigger crates
+const MAX_TARBALL_BYTES: usize = 100_000_000;
+
+#[derive(Debug, Default, Deserialize)]
+struct RegistriesFile {
+    #[serde(default, rename = "crates-io")]
+    crates_io: Option<RegistrySource>,
+    #[serde(default)]
+    registries: BTreeMap<String, RegistrySource>,
+}
+
+fn read_registries_file(data_dir: &Path) -> Result<RegistriesFile, DepsErr> {
+    let path = data_dir.join("registries.toml");
+    match fs::read_to_string(&path) {
+        Ok(s) => toml::from_str(&s).map_err(|e| DepsErr::RegistryConfig(path, e.to_string())),
+        Err(_) => Ok(RegistriesFile::default()),
+    }
+}
+
+/// `None` if crates.io should use the git index
+pub(crate) fn crates_io_sparse_index(data_dir: &Path) -> Result<Option<SparseIndex>, DepsErr> {
+    Ok(read_registries_file(data_dir)?.crates_io
+        .filter(|source| source.is_sparse())
+        .map(|source| source.sparse_index(&data_dir.join("index-sparse"))))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegistrySource {
+    /// `sparse+https://…` or a git URL
+    pub index: String,
+    #[serde(default)]
+    pub crates: Vec<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Cargo's `config.json` at the root of the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    pub dl: String,
+    #[serde(default)]
+    pub api: Option<String>,
+}
+
+impl RegistryConfig {
+    /// Expands the `dl` template the same way Cargo does
+    pub fn download_url(&self, version: &Version) -> String {
+        let name = version.name();
+        let ver = version.version();
+        const MARKERS: [&str; 5] = ["{crate}", "{version}", "{prefix}", "{lowerprefix}", "{sha256-checksum}"];
+        if !MARKERS.iter().any(|m| self.dl.contains(m)) {
+            return format!("{}/{name}/{ver}/download", self.dl.trim_end_matches('/'));
+        }
+        // {prefix} keeps the case of the name, only {lowerprefix} is lowercased
+        let prefix = index_path(name);
+        let prefix = prefix.rsplit_once('/').map_or("", |(p, _)| p);
+        let checksum: String = version.checksum().iter().map(|b| format!("{b:02x}")).collect();
+        self.dl.replace("{crate}", name)
+            .replace("{version}", ver)
+            .replace("{prefix}", prefix)
+            .replace("{lowerprefix}", &prefix.to_ascii_lowercase())
+            .replace("{sha256-checksum}", &checksum)
+    }
+}
+
+pub struct AltRegistry {
+    pub name: Box<str>,
+    source: RegistrySource,
+    cache_dir: PathBuf,
+    config: Option<RegistryConfig>,
+    crates: HashMap<SmolStr, Crate>,
+}
+
+/// Registries from `registries.toml`, with crates that are in the local copy of their index.
+///
+/// A registry that can't be loaded is logged and skipped, so that it doesn't take down the rest of the index.
+pub(crate) fn load_registries(data_dir: &Path) -> Result<Vec<AltRegistry>, DepsErr> {
+    Ok(read_registries_file(data_dir)?.registries.into_iter().filter_map(|(name, mut source)| {
+        if !Origin::is_valid_crate_name(&name) {
+            error!("registries.toml: bad registry name {name}");
+            return None;
+        }
+        // names are used in index file paths
+        source.crates.retain(|c| {
+            let ok = Origin::is_valid_crate_name(c);
+            if !ok {
+                warn!("registries.toml: bad crate name {c:?} in {name}");
+            }
+            ok
+        });
+        let cache_dir = data_dir.join("registries").join(&name);
+        AltRegistry::load(name.into(), source, cache_dir)
+            .map_err(|e| error!("registry skipped: {e}")).ok()
+    }).collect())
+}
+
+impl AltRegistry {
+    fn load(name: Box<str>, source: RegistrySource, cache_dir: PathBuf) -> Result<Self, DepsErr> {
+        let (config, crates) = if source.is_sparse() {
+            // only what has been fetched by update()
+            let index = source.sparse_index(&cache_dir.join("index"));
+            let config = index.cached("config.json").and_then(|c| serde_json::from_slice(&c).ok());
+            let crates = source.crates.iter().filter_map(|name| {
+                let name = name.to_ascii_lowercase();
+                let c = Crate::from_slice(&index.cached(&index_path(&name))?)
+                    .map_err(|e| warn!("bad index file of {name}: {e}")).ok()?;
+                Some((name.into(), c))
+            }).collect();
+            (config, crates)
+        } else if !cache_dir.exists() {
+            // cloning can take a long time, so it's left for update() instead of blocking startup
+            info!("Registry {name} hasn't been fetched yet");
+            (None, HashMap::default())
+        } else {
+            let index = crates_index::Index::with_path(&cache_dir, &source.index)
+                .map_err(|e| DepsErr::Crates(format!("{name}: {e}")))?;
+            let config = index.index_config().map_err(|e| error!("{name} config.json: {e}")).ok()
+                .map(|c| RegistryConfig { dl: c.dl, api: c.api });
+            let crates = index.crates().map(|c| (c.name().to_ascii_lowercase().into(), c)).collect();
+            (config, crates)
+        };
+        info!("Registry {name} has {} crates", crates.len());
+        Ok(Self { name, source, cache_dir, config, crates })
+    }
+
+    /// Fetches the index. The changes are visible after the `Index` is reloaded.
+    pub(crate) async fn update(&self) {
+        if self.source.is_sparse() {
+            let index = self.source.sparse_index(&self.cache_dir.join("index"));
+            let paths = std::iter::once("config.json".to_owned())
+                .chain(self.source.crates.iter().map(|name| index_path(&name.to_ascii_lowercase())));
+            for path in paths {
+                if let Err(e) = index.update(&path).await {
+                    error!("{} index update error: {e}", self.name);
+                }
+            }
+        } else {
+            let path = self.cache_dir.clone();
+            let url = self.source.index.clone();
+            let _ = tokio::task::spawn_blocking(move || {
+                let _ = crates_index::Index::with_path(path, url)
+                    .and_then(|mut idx| idx.update())
+                    .map_err(|e| error!("registry index update error: {}", e));
+            }).await;
+        }
+    }
+
+    pub fn crates(&self) -> &HashMap<SmolStr, Crate> {
+        &self.crates
+    }
+
+    pub fn crate_by_lowercase_name(&self, name: &str) -> Option<&Crate> {
+        self.crates.get(name)
+    }
+
+    pub fn config(&self) -> Option<&RegistryConfig> {
+        self.config.as_ref()
+    }
+
+    /// The `.crate` tarball, cached locally
+    pub async fn download(&self, fetcher: &Fetcher, version: &Version) -> Result<Vec<u8>, DepsErr> {
+        let path = self.cache_dir.join("tarballs").join(format!("{}-{}.crate", version.name().to_ascii_lowercase(), version.version()));
+        if let Ok(data) = fs::read(&path) {
+            return Ok(data);
+        }
+        let config = self.config.as_ref().ok_or_else(|| DepsErr::Crates(format!("{} has no config.json", self.name)))?;
+        let url = config.download_url(version);
+        let data = fetcher.fetch_limited_with_auth(&url, self.source.token.as_deref(), MAX_TARBALL_BYTES).await
+            .map_err(|e| DepsErr::Crates(format!("{url}: {e}")))?;
+        if data.len() < 10 || data[0] != 31 || data[1] != 139 {
+            return Err(DepsErr::Crates(format!("Not tarball: {url}")));
+        }
+        let _ = fs::create_dir_all(path.parent().unwrap());
+        let _ = fs::write(&path, &data);
+        Ok(data)
+    }
+}
+
+impl RegistrySource {
+    fn is_sparse(&self) -> bool {
+        self.index.starts_with("sparse+")
+    }
+
+    /// Files are stored directly in the `index_dir`
+    fn sparse_index(&self, index_dir: &Path) -> SparseIndex {
+        SparseIndex::new(&self.index, index_dir.to_owned(), self.token.clone())
+    }
+}
+
+#[test]
+fn dl_template() {
+    let c = Crate::from_slice(br#"{"name":"Foo-bar","vers":"1.0.0","deps":[],"cksum":"0000000000000000000000000000000000000000000000000000000000000000","features":{},"yanked":false}"#).unwrap();
+    let v = c.most_recent_version();
+    let plain = RegistryConfig { dl: "https://example.com/api/v1/crates/".into(), api: None };
+    assert_eq!("https://example.com/api/v1/crates/Foo-bar/1.0.0/download", plain.download_url(v));
+    let templated = RegistryConfig { dl: "https://example.com/{lowerprefix}/{crate}-{version}.crate".into(), api: None };
+    assert_eq!("https://example.com/fo/o-/Foo-bar-1.0.0.crate", templated.download_url(v));
+    let templated = RegistryConfig { dl: "https://example.com/{prefix}/{crate}/{version}".into(), api: None };
+    assert_eq!("https://example.com/Fo/o-/Foo-bar/1.0.0", templated.download_url(v));
+}
@@ -0,0 +1,378 @@
+//! Resolves a crate to a full set of concrete dependency versions, approximating what Cargo would put in `Cargo.lock`.
+//!
+//! It uses only the local copy of the index. Differences from Cargo: conflicting requirements
+//! are handled by retrying with all requirements seen so far rather than by backtracking,
+//! and features are unified across all dependency kinds (like resolver v1).
+
+use crate::DependencyKind;
+use crate::DepsErr;
+use crate::Origin;
+use crates_index::Crate;
+use crates_index::Version;
+use semver::Version as SemVer;
+use semver::VersionReq;
+use smartstring::alias::String as SmolStr;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Gives up on pathological dependency graphs
+const MAX_RESTARTS: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct ResolveQuery {
+    pub features: Vec<SmolStr>,
+    pub default_features: bool,
+    /// Include dev-dependencies of the root crate
+    pub dev: bool,
+    /// `Cargo.lock` has dependencies for all platforms, but a build on one platform needs fewer
+    pub all_targets: bool,
+}
+
+impl Default for ResolveQuery {
+    fn default() -> Self {
+        Self { features: Vec::new(), default_features: true, dev: false, all_targets: true }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    pub name: SmolStr,
+    pub version: SemVer,
+    /// Enabled features, including `default`
+    pub features: BTreeSet<SmolStr>,
+    /// name and version of other packages in the resolution
+    pub dependencies: Vec<(SmolStr, SemVer)>,
+    /// Compressed size, if known. Filled in by the caller, since the index doesn't have it.
+    pub tarball_size: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    /// The first one is the root crate
+    pub packages: Vec<ResolvedPackage>,
+    /// Dependencies that couldn't be resolved: dependent's name, dependency's name, requirement
+    pub unresolved: Vec<(SmolStr, SmolStr, String)>,
+}
+
+impl Resolution {
+    pub fn root(&self) -> &ResolvedPackage {
+        &self.packages[0]
+    }
+
+    /// Number of crates needed, excluding the root crate
+    pub fn crate_count(&self) -> usize {
+        self.packages.len() - 1
+    }
+
+    /// Crates that are in the tree in more than one semver-incompatible version
+    pub fn duplicates(&self) -> Vec<(&str, Vec<&SemVer>)> {
+        let mut by_name = BTreeMap::<&str, Vec<&SemVer>>::new();
+        for p in &self.packages {
+            by_name.entry(&p.name).or_default().push(&p.version);
+        }
+        by_name.into_iter().filter(|(_, v)| v.len() > 1).map(|(name, mut versions)| {
+            versions.sort_unstable();
+            (name, versions)
+        }).collect()
+    }
+
+    /// Sum of known tarball sizes (excluding the root crate), and number of crates with unknown size
+    pub fn total_tarball_size(&self) -> (u64, usize) {
+        self.packages[1..].iter().fold((0, 0), |(sum, unknown), p| match p.tarball_size {
+            Some(size) => (sum + size, unknown),
+            None => (sum, unknown + 1),
+        })
+    }
+
+    pub fn get(&self, name: &str, version: &SemVer) -> Option<&ResolvedPackage> {
+        self.packages.iter().find(|p| p.name == name && &p.version == version)
+    }
+
+    /// Depth-first tree, as `(depth, package)`. Packages that have already been listed aren't expanded again.
+    pub fn tree(&self) -> Vec<(usize, &ResolvedPackage)> {
+        let mut out = Vec::with_capacity(self.packages.len());
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![(0, self.root())];
+        while let Some((depth, p)) = stack.pop() {
+            out.push((depth, p));
+            if !seen.insert((&p.name, &p.version)) {
+                continue;
+            }
+            for (name, ver) in p.dependencies.iter().rev() {
+                if let Some(dep) = self.get(name, ver) {
+                    stack.push((depth + 1, dep));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Versions that Cargo considers compatible share the same key
+fn compat_key(v: &SemVer) -> (u64, u64, u64) {
+    match (v.major, v.minor) {
+        (0, 0) => (0, 0, v.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+type PkgKey = (SmolStr, (u64, u64, u64));
+
+struct Selected<'a> {
+    version: &'a Version,
+    semver: SemVer,
+    features: BTreeSet<SmolStr>,
+    /// Features for which dependencies have been added already
+    processed_features: Option<BTreeSet<SmolStr>>,
+    dependencies: BTreeSet<PkgKey>,
+}
+
+pub(crate) struct Resolver<'a, F> {
+    get_crate: F,
+    query: &'a ResolveQuery,
+    /// Requirements seen in previous attempts, to pick versions that satisfy all of them
+    constraints: BTreeMap<PkgKey, Vec<VersionReq>>,
+}
+
+impl<'a, F: Fn(&str) -> Option<&'a Crate>> Resolver<'a, F> {
+    pub(crate) fn new(get_crate: F, query: &'a ResolveQuery) -> Self {
+        Self { get_crate, query, constraints: BTreeMap::new() }
+    }
+
+    /// `version` is the latest non-yanked if `None`
+    pub(crate) fn resolve(mut self, name: &str, version: Option<&str>) -> Result<Resolution, DepsErr> {
+        let krate = (self.get_crate)(name).ok_or_else(|| DepsErr::CrateNotFound(Origin::from_crates_io_name(name)))?;
+        let root = match version {
+            Some(version) => krate.versions().iter().find(|v| v.version() == version),
+            None => krate.versions().iter().rev().filter(|v| !v.is_yanked()).filter_map(|v| Some((SemVer::parse(v.version()).ok()?, v))).max_by(|a, b| a.0.cmp(&b.0)).map(|(_, v)| v),
+        }.ok_or_else(|| DepsErr::Crates(format!("{name} has no usable version {}", version.unwrap_or(""))))?;
+
+        for _ in 0..MAX_RESTARTS {
+            if let Some(res) = self.attempt(root)? {
+                return Ok(res);
+            }
+        }
+        Err(DepsErr::Crates(format!("can't resolve dependencies of {name}")))
+    }
+
+    /// `None` if a requirement conflicted with an earlier selection, and it needs to start over
+    fn attempt(&mut self, root: &'a Version) -> Result<Option<Resolution>, DepsErr> {
+        let root_semver = SemVer::parse(root.version()).map_err(|_| DepsErr::SemverParsingError(format!("{} {}", root.name(), root.version())))?;
+        let root_key: PkgKey = (root.name().to_ascii_lowercase().into(), compat_key(&root_semver));
+        let mut selected = BTreeMap::new();
+        let mut features: BTreeSet<SmolStr> = self.query.features.iter().cloned().collect();
+        if self.query.default_features {
+            features.insert("default".into());
+        }
+        selected.insert(root_key.clone(), Selected { version: root, semver: root_semver, features, processed_features: None, dependencies: BTreeSet::new() });
+
+        let mut unresolved = Vec::new();
+        let mut queue = VecDeque::from([root_key.clone()]);
+        while let Some(key) = queue.pop_front() {
+            let sel = selected.get_mut(&key).expect("queued");
+            if sel.processed_features.as_ref() == Some(&sel.features) {
+                continue;
+            }
+            sel.processed_features = Some(sel.features.clone());
+            let version = sel.version;
+            let is_root = key == root_key;
+            let (enabled_deps, dep_features) = expand_features(version, &sel.features);
+
+            for dep in version.dependencies() {
+                match dep.kind() {
+                    DependencyKind::Normal | DependencyKind::Build => {},
+                    DependencyKind::Dev => if !is_root || !self.query.dev { continue },
+                }
+                if dep.target().is_some() && !self.query.all_targets {
+                    continue;
+                }
+                if dep.is_optional() && !enabled_deps.contains(dep.name()) {
+                    continue;
+                }
+                let req = match VersionReq::parse(dep.requirement()) {
+                    Ok(r) => r,
+                    Err(_) => {
+                        unresolved.push((version.name().into(), dep.crate_name().into(), dep.requirement().into()));
+                        continue;
+                    },
+                };
+                let dep_name: SmolStr = dep.crate_name().to_ascii_lowercase().into();
+
+                // reuse a compatible version that has been selected already, like Cargo
+                let existing = selected.iter()
+                    .find(|((name, _), s)| *name == dep_name && req.matches(&s.semver))
+                    .map(|(k, _)| k.clone());
+                let dep_key = match existing {
+                    Some(k) => k,
+                    None => {
+                        let dep_crate = match (self.get_crate)(&dep_name) {
+                            Some(c) => c,
+                            None => {
+                                unresolved.push((version.name().into(), dep_name, dep.requirement().into()));
+                                continue;
+                            },
+                        };
+                        let (candidate, semver) = match self.best_match(dep_crate, &dep_name, &req) {
+                            Some(c) => c,
+                            None => {
+                                unresolved.push((version.name().into(), dep_name, dep.requirement().into()));
+                                continue;
+                            },
+                        };
+                        let dep_key = (dep_name, compat_key(&semver));
+                        if selected.contains_key(&dep_key) {
+                            // a different version in the same semver range has been picked based on other requirements
+                            self.add_constraint(dep_key, req);
+                            return Ok(None);
+                        }
+                        selected.insert(dep_key.clone(), Selected { version: candidate, semver, features: BTreeSet::new(), processed_features: None, dependencies: BTreeSet::new() });
+                        dep_key
+                    },
+                };
+                self.add_constraint(dep_key.clone(), req);
+
+                let dep_sel = selected.get_mut(&dep_key).expect("inserted");
+                if dep.has_default_features() {
+                    dep_sel.features.insert("default".into());
+                }
+                dep_sel.features.extend(dep.features().iter().map(|f| SmolStr::from(f.as_str())));
+                if let Some(f) = dep_features.get(dep.name()) {
+                    dep_sel.features.extend(f.iter().cloned());
+                }
+                if dep_sel.processed_features.as_ref() != Some(&dep_sel.features) {
+                    queue.push_back(dep_key.clone());
+                }
+                selected.get_mut(&key).expect("queued").dependencies.insert(dep_key);
+            }
+        }
+
+        let mut root_package = None;
+        let mut packages = Vec::with_capacity(selected.len());
+        for (key, s) in &selected {
+            let p = ResolvedPackage {
+                name: key.0.clone(),
+                version: s.semver.clone(),
+                features: s.features.iter().filter(|f| s.version.features().contains_key(f.as_str())).cloned().collect(),
+                dependencies: s.dependencies.iter().filter_map(|k| Some((k.0.clone(), selected.get(k)?.semver.clone()))).collect(),
+                tarball_size: None,
+            };
+            if *key == root_key {
+                root_package = Some(p);
+            } else {
+                packages.push(p);
+            }
+        }
+        packages.insert(0, root_package.expect("root"));
+        Ok(Some(Resolution { packages, unresolved }))
+    }
+
+    fn add_constraint(&mut self, key: PkgKey, req: VersionReq) {
+        let reqs = self.constraints.entry(key).or_default();
+        if !reqs.contains(&req) {
+            reqs.push(req);
+        }
+    }
+
+    /// Highest non-yanked version matching the requirement and all previously seen requirements for its semver range
+    fn best_match(&self, krate: &'a Crate, name: &SmolStr, req: &VersionReq) -> Option<(&'a Version, SemVer)> {
+        krate.versions().iter()
+            .filter(|v| !v.is_yanked())
+            .filter_map(|v| Some((v, SemVer::parse(v.version()).ok()?)))
+            .filter(|(_, semver)| req.matches(semver))
+            .filter(|(_, semver)| {
+                self.constraints.get(&(name.clone(), compat_key(semver)))
+                    .map_or(true, |reqs| reqs.iter().all(|r| r.matches(semver)))
+            })
+            .max_by(|a, b| a.1.cmp(&b.1))
+    }
+}
+
+/// Follows feature definitions. Returns names of enabled optional dependencies, and features to enable in dependencies (by dependency name).
+fn expand_features(version: &Version, requested: &BTreeSet<SmolStr>) -> (BTreeSet<SmolStr>, BTreeMap<SmolStr, BTreeSet<SmolStr>>) {
+    let defined = version.features();
+    // optional dependencies are implicit features, unless some feature uses `dep:` syntax for them
+    let uses_dep_syntax = defined.values().flatten().any(|f| f.starts_with("dep:"));
+    let is_optional_dep = |name: &str| version.dependencies().iter().any(|d| d.is_optional() && d.name() == name);
+
+    let mut enabled_deps = BTreeSet::new();
+    let mut dep_features = BTreeMap::<SmolStr, BTreeSet<SmolStr>>::new();
+    let mut seen = BTreeSet::new();
+    let mut todo: Vec<SmolStr> = requested.iter().cloned().collect();
+    while let Some(feature) = todo.pop() {
+        if !seen.insert(feature.clone()) {
+            continue;
+        }
+        if let Some(dep) = feature.strip_prefix("dep:") {
+            enabled_deps.insert(dep.into());
+        } else if let Some((dep, dep_feature)) = feature.split_once('/') {
+            // `dep?/feat` doesn't enable the dependency by itself
+            let weak = dep.ends_with('?');
+            let dep = dep.trim_end_matches('?');
+            if !weak {
+                enabled_deps.insert(dep.into());
+                if !uses_dep_syntax && defined.contains_key(dep) {
+                    todo.push(dep.into());
+                }
+            }
+            dep_features.entry(dep.into()).or_default().insert(dep_feature.into());
+        } else if let Some(implies) = defined.get(feature.as_str()) {
+            todo.extend(implies.iter().map(|f| SmolStr::from(f.as_str())));
+            if !uses_dep_syntax && is_optional_dep(&feature) {
+                enabled_deps.insert(feature);
+            }
+        } else if is_optional_dep(&feature) {
+            enabled_deps.insert(feature);
+        }
+    }
+    // weak features apply only to dependencies enabled otherwise
+    dep_features.retain(|dep, _| enabled_deps.contains(dep) || !is_optional_dep(dep));
+    (enabled_deps, dep_features)
+}
+
+#[test]
+fn resolves_like_cargo() {
+    let index = [
+        r#"{"name":"app","vers":"1.0.0","deps":[
+            {"name":"log","req":"^0.4.1","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"},
+            {"name":"serde","req":"^1","features":["derive"],"optional":true,"default_features":true,"target":null,"kind":"normal"},
+            {"name":"old-log","package":"log","req":"^0.3","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"},
+            {"name":"tester","req":"^1","features":[],"optional":false,"default_features":true,"target":null,"kind":"dev"}
+        ],"cksum":"00","features":{"default":["json"],"json":["serde/std"]},"yanked":false}"#,
+        r#"{"name":"log","vers":"0.3.9","deps":[
+            {"name":"log","req":"^0.4.2","features":[],"optional":false,"default_features":true,"target":null,"kind":"normal"}
+        ],"cksum":"00","features":{},"yanked":false}"#,
+        r#"{"name":"log","vers":"0.4.1","deps":[],"cksum":"00","features":{},"yanked":false}"#,
+        r#"{"name":"log","vers":"0.4.2","deps":[],"cksum":"00","features":{},"yanked":false}"#,
+        r#"{"name":"log","vers":"0.4.3","deps":[],"cksum":"00","features":{},"yanked":true}"#,
+        r#"{"name":"serde","vers":"1.0.0","deps":[
+            {"name":"serde_derive","req":"^1","features":[],"optional":true,"default_features":true,"target":null,"kind":"normal"}
+        ],"cksum":"00","features":{"default":["std"],"std":[],"derive":["serde_derive"]},"yanked":false}"#,
+        r#"{"name":"serde_derive","vers":"1.0.0","deps":[],"cksum":"00","features":{},"yanked":false}"#,
+        r#"{"name":"tester","vers":"1.0.0","deps":[],"cksum":"00","features":{},"yanked":false}"#,
+    ];
+    let mut crates: BTreeMap<String, String> = BTreeMap::new();
+    for line in index {
+        let name = line.split('"').nth(3).unwrap();
+        let c = crates.entry(name.into()).or_default();
+        c.push_str(&line.replace('\n', ""));
+        c.push('\n');
+    }
+    let crates: BTreeMap<_, _> = crates.into_iter().map(|(k, v)| (k, Crate::from_slice(v.as_bytes()).unwrap())).collect();
+
+    let query = ResolveQuery::default();
+    let res = Resolver::new(|name: &str| crates.get(name), &query).resolve("app", None).unwrap();
+    assert_eq!(res.root().name, "app");
+    let names: Vec<_> = res.packages.iter().map(|p| format!("{} {}", p.name, p.version)).collect();
+    // log 0.4.3 is yanked, and log 0.3 reuses 0.4.2 instead of adding another copy
+    assert_eq!(names, ["app 1.0.0", "log 0.3.9", "log 0.4.2", "serde 1.0.0", "serde_derive 1.0.0"]);
+    assert_eq!(res.crate_count(), 4);
+    assert_eq!(res.duplicates().len(), 1);
+    assert!(res.get("serde", &"1.0.0".parse().unwrap()).unwrap().features.contains("derive"));
+    assert!(res.unresolved.is_empty());
+    assert_eq!(res.tree().len(), 6);
+
+    let query = ResolveQuery { default_features: false, dev: true, ..Default::default() };
+    let res = Resolver::new(|name: &str| crates.get(name), &query).resolve("app", Some("1.0.0")).unwrap();
+    let names: Vec<_> = res.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["app", "log", "log", "tester"]);
+}
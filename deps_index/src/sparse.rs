@@ -0,0 +1,226 @@
+use crate::DepsErr;
+use crates_index::Crate;
+use futures::stream::StreamExt;
+use log::{debug, info, warn};
+use reqwest::header;
+use reqwest::StatusCode;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Client for Cargo's sparse index protocol, which keeps a local copy of the index files.
+///
+/// Files are revalidated with ETag/Last-Modified, so that unchanged crates cost only a 304 response.
+pub(crate) struct SparseIndex {
+    /// Without the `sparse+` prefix, always ends with `/`
+    url: String,
+    cache_dir: PathBuf,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl SparseIndex {
+    pub fn new(url: &str, cache_dir: PathBuf, token: Option<String>) -> Self {
+        let mut url = url.trim_start_matches("sparse+").to_owned();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        Self {
+            url,
+            cache_dir,
+            token,
+            client: reqwest::Client::builder().build().unwrap(),
+        }
+    }
+
+    /// Local copy of the file, if it has been fetched before
+    pub fn cached(&self, rel_path: &str) -> Option<Vec<u8>> {
+        fs::read(self.cache_dir.join(rel_path)).ok()
+    }
+
+    /// All crates that have been fetched before. The protocol can't list crates, so that's all we know about.
+    pub fn cached_crates(&self) -> Vec<Crate> {
+        let mut files = Vec::new();
+        collect_index_files(&self.cache_dir, 0, &mut files);
+        files.into_iter().filter_map(|path| {
+            let data = fs::read(&path).ok()?;
+            Crate::from_slice(&data).map_err(|e| warn!("bad index file {}: {e}", path.display())).ok()
+        }).collect()
+    }
+
+    /// Updates many files concurrently. Returns paths of the files that have changed.
+    pub async fn update_many(&self, rel_paths: impl Iterator<Item = String>) -> Vec<String> {
+        futures::stream::iter(rel_paths)
+            .map(|rel_path| async move {
+                match self.update(&rel_path).await {
+                    Ok(changed) => changed.then_some(rel_path),
+                    Err(e) => {
+                        warn!("sparse index: {e}");
+                        None
+                    },
+                }
+            })
+            .buffer_unordered(16)
+            .filter_map(|res| async move { res })
+            .collect().await
+    }
+
+    /// Fetches the file if it has changed since the last time. Returns true if the local copy has changed.
+    pub async fn update(&self, rel_path: &str) -> Result<bool, DepsErr> {
+        let path = self.cache_dir.join(rel_path);
+        let validators_path = validators_path(&path);
+        let mut req = self.client.get(format!("{}{rel_path}", self.url))
+            .header(header::USER_AGENT, "lib.rs/1.1");
+        if let Some(token) = &self.token {
+            req = req.header(header::AUTHORIZATION, token);
+        }
+        // validators are useless if the file is gone
+        if path.exists() {
+            if let Ok(validators) = fs::read_to_string(&validators_path) {
+                let mut lines = validators.lines();
+                if let Some(etag) = lines.next().filter(|l| !l.is_empty()) {
+                    req = req.header(header::IF_NONE_MATCH, etag);
+                }
+                if let Some(modified) = lines.next().filter(|l| !l.is_empty()) {
+                    req = req.header(header::IF_MODIFIED_SINCE, modified);
+                }
+            }
+        }
+
+        let res = req.send().await.map_err(|e| DepsErr::Crates(format!("{rel_path}: {e}")))?;
+        match res.status() {
+            StatusCode::NOT_MODIFIED => {
+                debug!("{rel_path} not modified");
+                Ok(false)
+            },
+            StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => {
+                info!("{rel_path} is not in the index any more");
+                let existed = path.exists();
+                let _ = fs::remove_file(&path);
+                let _ = fs::remove_file(&validators_path);
+                Ok(existed)
+            },
+            status if status.is_success() => {
+                let header_str = |name: header::HeaderName| res.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_owned();
+                let validators = format!("{}\n{}\n", header_str(header::ETAG), header_str(header::LAST_MODIFIED));
+                let body = res.bytes().await.map_err(|e| DepsErr::Crates(format!("{rel_path}: {e}")))?;
+                let changed = fs::read(&path).map_or(true, |old| old[..] != body[..]);
+                if changed {
+                    write_atomically(&path, &body).map_err(|e| DepsErr::Crates(format!("{}: {e}", path.display())))?;
+                }
+                let _ = fs::write(&validators_path, validators);
+                Ok(changed)
+            },
+            status => Err(DepsErr::Crates(format!("{rel_path}: HTTP {status}"))),
+        }
+    }
+}
+
+/// Index files are at most 3 dirs deep, e.g. `se/rd/serde`
+fn collect_index_files(dir: &Path, depth: u8, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for e in entries.filter_map(|e| e.ok()) {
+        let path = e.path();
+        let is_dir = e.file_type().map_or(false, |t| t.is_dir());
+        if is_dir {
+            if depth < 2 {
+                collect_index_files(&path, depth + 1, out);
+            }
+            continue;
+        }
+        // crate names can't have dots, so that skips config.json and our own files
+        if depth > 0 && !e.file_name().to_string_lossy().contains('.') {
+            out.push(path);
+        }
+    }
+}
+
+fn validators_path(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".etag");
+    p.into()
+}
+
+fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)
+}
+
+/// Path of the crate's file in the index, e.g. `se/rd/serde`
+///
+/// The index uses lowercase names. The case is kept as-is, because Cargo's `{prefix}` isn't lowercased.
+pub fn index_path(name: &str) -> String {
+    match name.len() {
+        0 => String::new(),
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+#[test]
+fn index_paths() {
+    assert_eq!("1/a", index_path("a"));
+    assert_eq!("3/f/foo", index_path("foo"));
+    assert_eq!("se/rd/serde", index_path("serde"));
+}
+
+/// Serves files from a dir, with ETags, like a static file server would
+#[cfg(test)]
+fn serve_dir(root: PathBuf) -> String {
+    use std::io::{BufRead, BufReader, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("sparse+http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().filter_map(|s| s.ok()) {
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines().map_while(|l| l.ok());
+            let path = lines.next().unwrap_or_default().split(' ').nth(1).unwrap_or("/").trim_start_matches('/').to_owned();
+            let if_none_match = lines.take_while(|l| !l.is_empty())
+                .find_map(|l| l.to_ascii_lowercase().strip_prefix("if-none-match: ").map(|v| v.to_owned()));
+            let response = match fs::read(root.join(path)) {
+                Ok(data) => {
+                    let etag = format!("\"{}\"", data.len());
+                    if if_none_match.as_ref() == Some(&etag) {
+                        b"HTTP/1.1 304 Not Modified\r\nconnection: close\r\n\r\n".to_vec()
+                    } else {
+                        let mut res = format!("HTTP/1.1 200 OK\r\netag: {etag}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n", data.len()).into_bytes();
+                        res.extend(data);
+                        res
+                    }
+                },
+                Err(_) => b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_vec(),
+            };
+            let _ = stream.write_all(&response);
+        }
+    });
+    url
+}
+
+#[tokio::test]
+async fn sparse_update() {
+    let tmp = tempfile::tempdir().unwrap();
+    let remote = tmp.path().join("remote");
+    fs::create_dir_all(remote.join("3/f")).unwrap();
+    let foo = br#"{"name":"foo","vers":"1.0.0","deps":[],"cksum":"0000000000000000000000000000000000000000000000000000000000000000","features":{},"yanked":false}"#;
+    fs::write(remote.join("3/f/foo"), &foo[..]).unwrap();
+    fs::write(remote.join("config.json"), br#"{"dl":"https://example.com/dl"}"#).unwrap();
+
+    let index = SparseIndex::new(&serve_dir(remote.clone()), tmp.path().join("local"), None);
+    assert_eq!(2, index.update_many(["config.json".to_owned(), index_path("foo"), index_path("bar")].into_iter()).await.len());
+    assert!(!index.update("3/f/foo").await.unwrap(), "should be 304");
+    let cached = index.cached_crates();
+    assert_eq!(1, cached.len());
+    assert_eq!("foo", cached[0].name());
+
+    fs::remove_file(remote.join("3/f/foo")).unwrap();
+    assert!(index.update("3/f/foo").await.unwrap());
+    assert!(index.cached_crates().is_empty());
+}
@@ -19,8 +19,13 @@ pub struct BuildStatus {
 
 impl DocsRsClient {
     pub fn new(cache_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new_with_fetcher(cache_path, Arc::new(Fetcher::new(8)))
+    }
+
+    /// The fetcher can use a cassette to replay responses in tests
+    pub fn new_with_fetcher(cache_path: impl AsRef<Path>, fetcher: Arc<Fetcher>) -> Result<Self, Error> {
         Ok(Self {
-            cache: TempCacheJson::new(cache_path.as_ref(), Arc::new(Fetcher::new(8)), Duration::from_secs(3600*24*31*3))?,
+            cache: TempCacheJson::new(cache_path.as_ref(), fetcher, Duration::from_secs(3600*24*31*3))?,
         })
     }
 
@@ -1,12 +1,19 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::error::Elapsed;
 use tokio::time::timeout;
+pub use http_cassette::{Cassette, Mode as CassetteMode};
 
 #[derive(Debug)]
 pub struct Fetcher {
     client: reqwest::Client,
     sem: tokio::sync::Semaphore,
     sem_timeout: u16,
+    /// For tests: record or replay responses instead of always using the network
+    cassette: Option<Cassette>,
+    /// Set by `new_public_only`
+    public_only: bool,
 }
 
 use quick_error::quick_error;
@@ -22,6 +29,19 @@ quick_error! {
             display("Request timed out")
             from(Elapsed)
         }
+        Status(status: u16, url: String) {
+            display("HTTP {} for {}", status, url)
+        }
+        TooLarge {
+            display("Response is too large")
+        }
+        NotPublic(url: String) {
+            display("{} is not a public address", url)
+        }
+        Cassette(err: http_cassette::Error) {
+            display("{}", err)
+            from()
+        }
     }
 }
 
@@ -32,10 +52,51 @@ impl Fetcher {
             client,
             sem_timeout: (max_concurrent + 3).max(5),
             sem: tokio::sync::Semaphore::new(max_concurrent.into()),
+            cassette: None,
+            public_only: false,
+        }
+    }
+
+    /// For fetching user-supplied URLs. Refuses to connect to private, loopback, and link-local addresses, including via DNS and redirects.
+    pub fn new_public_only(max_concurrent: u16) -> Self {
+        let client = reqwest::Client::builder()
+            .dns_resolver(Arc::new(PublicOnlyResolver))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() > 5 {
+                    attempt.error("too many redirects")
+                } else if !is_public_url(attempt.url()) {
+                    attempt.error("redirect to a non-public address")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build().unwrap();
+        Self {
+            client,
+            public_only: true,
+            ..Self::new(max_concurrent)
+        }
+    }
+
+    /// Responses are recorded to, or replayed from, the cassette's directory
+    pub fn new_with_cassette(max_concurrent: u16, cassette: Cassette) -> Self {
+        Self {
+            cassette: Some(cassette),
+            ..Self::new(max_concurrent)
         }
     }
 
     pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        self.fetch_limited(url, usize::MAX).await
+    }
+
+    /// Like `fetch`, but gives up as soon as the body is longer than `max_bytes`
+    pub async fn fetch_limited(&self, url: &str, max_bytes: usize) -> Result<Vec<u8>, Error> {
+        self.fetch_limited_with_auth(url, None, max_bytes).await
+    }
+
+    /// Like `fetch_limited`, with an `Authorization` header, e.g. a private registry's token
+    pub async fn fetch_limited_with_auth(&self, url: &str, authorization: Option<&str>, max_bytes: usize) -> Result<Vec<u8>, Error> {
         let _s = match self.sem.try_acquire() {
             Ok(s) => {
                 log::info!("REQ {}", url);
@@ -49,10 +110,117 @@ impl Fetcher {
             },
         };
 
-        let res = timeout(Duration::from_secs(21), self.client.get(url)
-            .header(reqwest::header::USER_AGENT, "lib.rs/1.1")
-            .send()).await??
+        let mut req = self.client.get(url)
+            .header(reqwest::header::USER_AGENT, "lib.rs/1.1");
+        if let Some(authorization) = authorization {
+            req = req.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+        let req = req.build()?;
+        // IP addresses in URLs don't go through the resolver
+        if self.public_only && !is_public_url(req.url()) {
+            return Err(Error::NotPublic(url.into()));
+        }
+
+        if let Some(cassette) = &self.cassette {
+            let rec = timeout(Duration::from_secs(61), cassette.send(&self.client, req)).await??;
+            if !rec.is_success() {
+                return Err(Error::Status(rec.status, rec.url));
+            }
+            let body = rec.body();
+            if body.len() > max_bytes {
+                return Err(Error::TooLarge);
+            }
+            return Ok(body);
+        }
+
+        let mut res = timeout(Duration::from_secs(21), self.client.execute(req)).await??
             .error_for_status()?;
-        Ok(timeout(Duration::from_secs(61), res.bytes()).await??.to_vec())
+        if res.content_length().map_or(false, |len| len > max_bytes as u64) {
+            return Err(Error::TooLarge);
+        }
+        timeout(Duration::from_secs(61), async move {
+            let mut body = Vec::new();
+            while let Some(chunk) = res.chunk().await? {
+                if body.len() + chunk.len() > max_bytes {
+                    return Err(Error::TooLarge);
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(body)
+        }).await?
+    }
+}
+
+/// Only literal IPs are checked here. Host names are checked by `PublicOnlyResolver`.
+fn is_public_url(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host_str().map(|h| h.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>()) {
+        Some(Ok(ip)) => is_public_ip(ip),
+        Some(Err(_)) => true,
+        None => false,
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast() || ip.is_documentation() ||
+                a == 0 || (a == 100 && (b & 0xC0) == 64)) // 100.64/10 is carrier-grade NAT
+        },
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4() {
+                return !ip.is_loopback() && is_public_ip(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback() || ip.is_unspecified() ||
+                (first & 0xfe00) == 0xfc00 || // unique local
+                (first & 0xffc0) == 0xfe80) // link-local
+        },
+    }
+}
+
+struct PublicOnlyResolver;
+
+impl reqwest::dns::Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public addresses", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
     }
 }
+
+#[test]
+fn public_addresses() {
+    let public = |url: &str| is_public_url(&url.parse().unwrap());
+    assert!(public("https://example.com/img.png"));
+    assert!(public("http://93.184.216.34/"));
+    assert!(!public("http://127.0.0.1:8080/"));
+    assert!(!public("http://10.1.2.3/"));
+    assert!(!public("http://169.254.169.254/latest/meta-data/"));
+    assert!(!public("http://[::1]/"));
+    assert!(!public("http://[fe80::1]/"));
+    assert!(!public("http://[::ffff:192.168.0.1]/"));
+    assert!(!public("file:///etc/passwd"));
+}
+
+#[tokio::test]
+async fn replay_fetch() {
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = Cassette::new(dir.path(), CassetteMode::Replay);
+    cassette.save(&http_cassette::Recording::new("https://example.com/ok", 200, vec![], b"hello".to_vec())).unwrap();
+    cassette.save(&http_cassette::Recording::new("https://example.com/404", 404, vec![], vec![])).unwrap();
+
+    let f = Fetcher::new_with_cassette(1, cassette);
+    assert_eq!(b"hello", &f.fetch("https://example.com/ok").await.unwrap()[..]);
+    assert!(matches!(f.fetch("https://example.com/404").await, Err(Error::Status(404, _))));
+    assert!(matches!(f.fetch("https://example.com/new").await, Err(Error::Cassette(_))));
+}
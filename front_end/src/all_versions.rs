@@ -6,6 +6,7 @@ use kitchen_sink::CrateOwners;
 use kitchen_sink::KitchenSink;
 use kitchen_sink::KitchenSinkErr;
 use kitchen_sink::Origin;
+use kitchen_sink::SemverViolation;
 use kitchen_sink::Severity;
 use ahash::HashMap;
 use ahash::HashSet;
@@ -14,6 +15,8 @@ use smartstring::alias::String as SmolStr;
 use rich_crate::{RichCrate, RichCrateVersion};
 use semver::Version as SemVer;
 use std::mem;
+use std::time::Duration;
+use tokio::time::timeout;
 
 pub struct AllVersions {
     pub(crate) origin: Origin,
@@ -24,6 +27,20 @@ pub struct AllVersions {
     pub(crate) has_authors: bool,
     pub(crate) has_feat_changes: bool,
     pub(crate) has_deps_changes: bool,
+    /// Newest first
+    pub(crate) semver_violations: Vec<SemverViolation>,
+    /// Newest first
+    pub(crate) yank_history: Vec<YankEvent>,
+}
+
+/// Yanking or un-yanking of a release
+#[derive(Debug)]
+pub(crate) struct YankEvent {
+    pub version: SmolStr,
+    /// false if un-yanked
+    pub yanked: bool,
+    pub date: String,
+    pub by: (SmolStr, Option<SmolStr>),
 }
 
 #[derive(Debug)]
@@ -75,6 +92,18 @@ impl AllVersions {
             .map(|v| (v.num, v.audit_actions))
             .collect();
         let downloads = downloads.map_err(|e| log::error!("d/l: {}", e)).unwrap_or_default();
+
+        let mut yank_actions: Vec<_> = release_meta.iter()
+            .flat_map(|(num, audit)| audit.iter().map(move |a| (num, a)))
+            .filter(|(_, a)| a.action == "yank" || a.action == "unyank")
+            .collect();
+        yank_actions.sort_unstable_by(|(_, a), (_, b)| b.time.cmp(&a.time));
+        let yank_history = yank_actions.into_iter().map(|(num, a)| YankEvent {
+            version: num.clone(),
+            yanked: a.action == "yank",
+            date: a.time.format("%b %e, %Y").to_string(),
+            by: (a.user.login.clone(), a.user.name.clone()),
+        }).collect();
         let capitalized_name = krate.capitalized_name().to_string();
 
         let ver_dates = all.versions();
@@ -90,7 +119,7 @@ impl AllVersions {
             let num = version_meta.version();
             let sem: SemVer = num.parse().ok()?;
             let audit = release_meta.remove(num)?;
-            let release_date = &ver_dates.get(num)?.created_at;
+            let release_date = ver_dates.get(num)?.created_at;
 
             let mut required_deps = HashMap::with_capacity(version_meta.dependencies().len());
 
@@ -132,6 +161,13 @@ impl AllVersions {
 
         let compat = kitchen_sink.rustc_compatibility(&all).await?;
 
+        // needs tarballs of versions that may not have been analyzed yet
+        let semver_violations = match timeout(Duration::from_secs(10), kitchen_sink.semver_violations(&all, 5)).await {
+            Ok(Ok(v)) => v,
+            Ok(Err(e)) => { log::warn!("semver check: {e}"); Vec::new() },
+            Err(_) => Vec::new(),
+        };
+
         let mut prev_required_deps = None::<HashMap<String, HashMap<_, _>>>;
         let mut prev_features = None::<HashSet<_>>;
         let mut prev_semver = None::<SemVer>;
@@ -142,7 +178,7 @@ impl AllVersions {
             let security_advisory_url = advisory.and_then(|a| a.id().url());
 
             let yanked = version_meta.is_yanked();
-            let release_date = release_date.format("%b %e, %Y").to_string();
+            let release_date = release_date.map(|d| d.format("%b %e, %Y").to_string()).unwrap_or_default();
 
             let dl = {
                 let num = downloads.get(&version_meta.version().try_into().unwrap()).copied().unwrap_or(0);
@@ -233,7 +269,7 @@ impl AllVersions {
             let msrv = compat.get(&version).and_then(|c| {
                 c.oldest_ok().map(|oldest_ok| {
                     let exact = c.newest_bad().is_some();
-                    (c.newest_bad().map(|n| n + 1).unwrap_or(oldest_ok), oldest_ok, exact)
+                    (c.msrv().unwrap_or(oldest_ok), oldest_ok, exact)
                 })
             });
 
@@ -292,6 +328,8 @@ impl AllVersions {
             has_feat_changes: version_history.iter().any(|v| !v.feat_added.is_empty() || !v.feat_removed.is_empty()),
             has_deps_changes: version_history.iter().any(|v| !v.deps_added.is_empty() || !v.deps_removed.is_empty() || !v.deps_upgraded.is_empty()),
             changelog_url,
+            semver_violations,
+            yank_history,
             version_history,
             capitalized_name,
             origin,
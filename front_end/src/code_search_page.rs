@@ -0,0 +1,56 @@
+use crate::templates;
+use crate::Page;
+use crate::Urler;
+use search_index::CodeCrateFound;
+use search_index::CodeQuery;
+use std::io::Write;
+
+/// Results of searching for names of public items, like `trait Service`
+pub struct CodeSearchPage<'a> {
+    pub query: &'a str,
+    pub parsed_query: Option<CodeQuery>,
+    pub results: &'a [CodeCrateFound],
+    /// The code index is optional, and may not have been built
+    pub is_available: bool,
+}
+
+impl CodeSearchPage<'_> {
+    pub fn page(&self) -> Page {
+        let mut desc = String::with_capacity(300);
+        desc.push_str("Rust crates defining ");
+        desc.push_str(self.query);
+        desc.push_str(": ");
+        for r in self.results.iter().take(10) {
+            desc.push_str(&r.crate_name);
+            desc.push_str(", ");
+        }
+        desc.push_str("etc.");
+        Page {
+            title: format!("‘{}’ code search", self.query),
+            description: Some(desc),
+            noindex: true,
+            search_meta: true,
+            critical_css_data: Some(include_str!("../../style/public/search.css")),
+            critical_css_dev_url: Some("/search.css"),
+            ..Default::default()
+        }
+    }
+
+    /// docs.rs searches within the crate's docs
+    pub fn docs_url(&self, found: &CodeCrateFound, item_name: &str) -> String {
+        format!("https://docs.rs/{0}/{1}/{2}/?search={item_name}", found.crate_name, found.version, found.crate_name.replace('-', "_"))
+    }
+}
+
+/// `results` is `None` if the code index hasn't been built
+pub fn render_code_search_page(out: &mut dyn Write, query: &str, results: Option<&[CodeCrateFound]>) -> Result<(), anyhow::Error> {
+    let urler = Urler::new(None);
+    let page = CodeSearchPage {
+        query,
+        parsed_query: CodeQuery::parse(query),
+        results: results.unwrap_or_default(),
+        is_available: results.is_some(),
+    };
+    templates::code_search(out, &page, &urler)?;
+    Ok(())
+}
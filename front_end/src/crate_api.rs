@@ -0,0 +1,347 @@
+//! JSON representation of the crate pages, served under `/api/v1/crates/…`, and search results under `/api/v1/search`
+//!
+//! These structs are a stable, versioned contract for external tools,
+//! so fields should only be added, never renamed or removed.
+//! Bump `API_VERSION` (and the URL prefix) for incompatible changes.
+
+use crate::all_versions::AllVersions;
+use crate::crate_page::CratePage;
+use crate::reverse_dependencies::CratePageRevDeps;
+use kitchen_sink::CrateOwners;
+use kitchen_sink::DependencyKind;
+use kitchen_sink::KitchenSink;
+use kitchen_sink::OwnerKind;
+use rich_crate::RichCrate;
+use rich_crate::RichCrateVersion;
+use search_index::FilteredSearchResults;
+use semver::Version as SemVer;
+use serde_derive::Serialize;
+
+pub const API_VERSION: u16 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct CrateApi {
+    pub api_version: u16,
+    pub name: String,
+    pub origin: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub yanked: bool,
+    pub deprecated: bool,
+    /// Ranking used for sorting, 0..1
+    pub score: f64,
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    /// Rust 1.x minor version. `None` if unknown (not built yet)
+    pub msrv: Option<u16>,
+    /// Set explicitly via `rust-version`
+    pub explicit_msrv: Option<String>,
+    pub downloads: DownloadsApi,
+    pub dependents: Option<DependentsApi>,
+    pub size: Option<SizeApi>,
+    pub releases_summary: Option<String>,
+    /// RFC 3339
+    pub created_at: Option<String>,
+    /// RFC 3339
+    pub updated_at: Option<String>,
+    pub owners: Vec<OwnerApi>,
+    pub warnings: Vec<WarningApi>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadsApi {
+    pub per_month: Option<usize>,
+    pub weekly: Vec<WeekApi>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekApi {
+    pub date: String,
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependentsApi {
+    pub all: u32,
+    pub direct: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizeApi {
+    pub tarball: u64,
+    pub uncompressed: u64,
+    pub deps_tarball_typical: u64,
+    pub deps_tarball_minimal: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnerApi {
+    pub login: String,
+    pub kind: &'static str,
+    pub name: Option<String>,
+    pub github_login: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarningApi {
+    /// Machine-readable variant name of `kitchen_sink::Warning`
+    pub kind: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AllVersionsApi {
+    pub api_version: u16,
+    pub name: String,
+    pub origin: String,
+    pub yanked: bool,
+    pub changelog_url: Option<String>,
+    pub versions: Vec<VersionApi>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionApi {
+    pub version: String,
+    pub release_date: String,
+    pub yanked: bool,
+    pub semver_major_change: bool,
+    pub security_advisory_url: Option<String>,
+    /// Recent downloads per month of this version
+    pub downloads: u32,
+    /// Oldest rustc minor version that may work
+    pub msrv: Option<u16>,
+    /// `msrv` has been verified by a failed build of an older rustc
+    pub msrv_certain: bool,
+    pub published_by: Option<String>,
+    pub yanked_by: Option<String>,
+    pub deps_added: Vec<String>,
+    pub deps_removed: Vec<String>,
+    pub deps_upgraded: Vec<(String, String)>,
+    pub features_added: Vec<String>,
+    pub features_removed: Vec<String>,
+    pub source_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevDepsApi {
+    pub api_version: u16,
+    pub name: String,
+    pub version: String,
+    pub dependents: Option<DependentsApi>,
+    pub reverse_dependencies: Vec<RevDepApi>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevDepApi {
+    pub name: String,
+    pub version: String,
+    pub requirement: String,
+    pub kind: &'static str,
+    pub optional: bool,
+    pub matches_latest: bool,
+    pub downloads_per_month: u32,
+    pub direct_dependents: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchApi {
+    pub api_version: u16,
+    pub query: String,
+    /// Number of all matching crates, not just this page
+    pub total: usize,
+    /// 0-based
+    pub page: usize,
+    pub per_page: usize,
+    pub crates: Vec<SearchResultApi>,
+    pub facets: SearchFacetsApi,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResultApi {
+    pub name: String,
+    pub origin: String,
+    pub version: String,
+    pub description: String,
+    pub keywords: Vec<String>,
+    pub downloads_per_month: u64,
+    /// Combined relevance and crate score, only meaningful for sorting
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchFacetsApi {
+    pub categories: Vec<FacetCountApi>,
+    pub licenses: Vec<FacetCountApi>,
+    pub origins: Vec<FacetCountApi>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FacetCountApi {
+    /// Value usable in the corresponding filter parameter
+    pub value: String,
+    pub count: u64,
+}
+
+impl CrateApi {
+    pub(crate) async fn new(page: &CratePage<'_>, all: &RichCrate, ver: &RichCrateVersion, kitchen_sink: &KitchenSink) -> Result<Self, anyhow::Error> {
+        let origin = ver.origin();
+        let (score, owners, warnings, compat) = futures::join!(
+            kitchen_sink.crate_db.crate_rank(origin),
+            kitchen_sink.crate_owners(origin, CrateOwners::All),
+            validator::warnings_for_crate(kitchen_sink, ver, all),
+            kitchen_sink.rustc_compatibility(all),
+        );
+
+        let semver: Option<SemVer> = ver.version().parse().ok();
+        let msrv = compat.ok().and_then(|compat| {
+            let c = compat.get(semver.as_ref()?)?;
+            c.msrv()
+        });
+
+        let mut warnings: Vec<_> = warnings.map_err(|e| log::warn!("api warnings: {}", e)).unwrap_or_default().into_iter().map(|w| WarningApi {
+            kind: format!("{w:?}").split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_owned(),
+            message: w.to_string(),
+        }).collect();
+        warnings.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.message.cmp(&b.message)));
+
+        let owners = owners.map_err(|e| log::warn!("api owners: {}", e)).unwrap_or_default().into_iter().map(|o| OwnerApi {
+            kind: match o.kind { OwnerKind::User => "user", OwnerKind::Team => "team" },
+            github_login: o.github_login().map(From::from),
+            name: o.name.as_deref().map(From::from),
+            url: o.url.as_deref().map(From::from),
+            login: o.crates_io_login.to_string(),
+        }).collect();
+
+        let weekly = kitchen_sink.weekly_downloads(all, 16).unwrap_or_default().into_iter().map(|w| WeekApi {
+            date: w.date.format("%Y-%m-%d").to_string(),
+            total: w.total,
+        }).collect();
+
+        Ok(Self {
+            api_version: API_VERSION,
+            name: ver.short_name().into(),
+            origin: origin.to_str(),
+            version: ver.version().into(),
+            description: ver.description().map(From::from),
+            license: ver.license().map(From::from),
+            repository: ver.repository_http_url().map(|(_, url)| url),
+            homepage: ver.homepage().map(From::from),
+            documentation: ver.documentation().map(From::from),
+            yanked: ver.is_yanked(),
+            deprecated: kitchen_sink.is_marked_deprecated(origin),
+            score: score.unwrap_or(0.),
+            categories: ver.category_slugs().iter().map(|s| s.to_string()).collect(),
+            keywords: ver.keywords().to_vec(),
+            msrv,
+            explicit_msrv: ver.explicit_msrv().map(From::from),
+            downloads: DownloadsApi {
+                per_month: page.downloads_per_month(),
+                weekly,
+            },
+            dependents: page.dependents_stats().map(|d| DependentsApi { all: d.deps, direct: d.direct }),
+            size: page.sizes.as_ref().map(|s| SizeApi {
+                tarball: s.tarball,
+                uncompressed: s.uncompressed,
+                deps_tarball_typical: s.typical.tarball,
+                deps_tarball_minimal: s.minimal.tarball,
+            }),
+            releases_summary: page.version_stats_summary().map(|(s, extra)| match extra {
+                Some(extra) => format!("{s} {extra}"),
+                None => s,
+            }),
+            created_at: page.published_date().map(|d| d.to_rfc3339()),
+            updated_at: page.date_created().map(|d| d.to_rfc3339()),
+            owners,
+            warnings,
+        })
+    }
+}
+
+impl AllVersionsApi {
+    pub(crate) fn new(all: &AllVersions) -> Self {
+        Self {
+            api_version: API_VERSION,
+            name: all.origin.short_crate_name().into(),
+            origin: all.origin.to_str(),
+            yanked: all.is_yanked,
+            changelog_url: all.changelog_url.clone(),
+            versions: all.version_history.iter().rev().map(|v| VersionApi {
+                version: v.version.to_string(),
+                release_date: v.release_date.clone(),
+                yanked: v.yanked,
+                semver_major_change: v.is_semver_major_change,
+                security_advisory_url: v.security_advisory_url.clone(),
+                downloads: v.dl.num,
+                msrv: v.msrv.map(|(min, _, _)| min),
+                msrv_certain: v.msrv.map_or(false, |(_, _, certain)| certain),
+                published_by: v.published_by.as_ref().map(|(login, _)| login.to_string()),
+                yanked_by: v.yanked_by.as_ref().map(|(login, _)| login.to_string()),
+                deps_added: v.deps_added.clone(),
+                deps_removed: v.deps_removed.clone(),
+                deps_upgraded: v.deps_upgraded.clone(),
+                features_added: v.feat_added.clone(),
+                features_removed: v.feat_removed.clone(),
+                source_url: v.version_url.clone(),
+            }).collect(),
+        }
+    }
+}
+
+impl RevDepsApi {
+    pub(crate) fn new(revdeps: &CratePageRevDeps<'_>) -> Self {
+        Self {
+            api_version: API_VERSION,
+            name: revdeps.ver.short_name().into(),
+            version: revdeps.ver.version().into(),
+            dependents: revdeps.stats.map(|d| DependentsApi {
+                all: d.runtime.def + d.runtime.opt + d.build.def + d.build.opt + d.dev as u32,
+                direct: d.direct.all(),
+            }),
+            reverse_dependencies: revdeps.deps.iter().map(|d| RevDepApi {
+                name: d.depender.name().into(),
+                version: d.depender.version().into(),
+                requirement: d.req.to_string(),
+                kind: match d.kind {
+                    DependencyKind::Normal => "normal",
+                    DependencyKind::Dev => "dev",
+                    DependencyKind::Build => "build",
+                },
+                optional: d.is_optional,
+                matches_latest: d.matches_latest,
+                downloads_per_month: d.downloads,
+                direct_dependents: d.rev_dep_count,
+            }).collect(),
+        }
+    }
+}
+
+impl SearchApi {
+    pub(crate) fn new(query: &str, results: &FilteredSearchResults) -> Self {
+        Self {
+            api_version: API_VERSION,
+            query: query.into(),
+            total: results.total,
+            page: results.page,
+            per_page: results.per_page,
+            crates: results.crates.iter().map(|c| SearchResultApi {
+                name: c.crate_name.clone(),
+                origin: c.origin.to_str(),
+                version: c.version.clone(),
+                description: c.description.clone(),
+                keywords: c.keywords.iter().map(|k| k.to_string()).collect(),
+                downloads_per_month: c.monthly_downloads,
+                score: c.score,
+            }).collect(),
+            facets: SearchFacetsApi {
+                categories: results.facets.categories.iter().map(|(slug, count)| FacetCountApi { value: slug.clone(), count: *count }).collect(),
+                licenses: results.facets.license_families.iter().map(|(l, count)| FacetCountApi { value: l.as_str().into(), count: *count }).collect(),
+                origins: results.facets.origins.iter().map(|(o, count)| FacetCountApi { value: o.as_str().into(), count: *count }).collect(),
+            },
+        }
+    }
+}
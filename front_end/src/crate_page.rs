@@ -17,6 +17,8 @@ use kitchen_sink::ArcRichCrateVersion;
 use kitchen_sink::CResult;
 use kitchen_sink::CrateAuthor;
 use kitchen_sink::DepInfMap;
+use kitchen_sink::FeatureCompatInfo;
+use kitchen_sink::FeatureSet;
 use kitchen_sink::RevDependencies;
 use kitchen_sink::Severity;
 use kitchen_sink::{DepTy, KitchenSink, Origin};
@@ -87,6 +89,7 @@ pub struct CratePage<'a> {
     downloads_per_month_cached: Option<usize>,
     github_stargazers_and_watchers: Option<(u32, u32)>,
     direct_dependencies: (Vec<RichDep>, Vec<RichDep>, Vec<RichDep>),
+    feature_set_build_failures: Vec<FeatureCompatInfo>,
     up_to_date_class_cache: HashMap<String, &'static str>,
 }
 
@@ -102,7 +105,8 @@ pub struct Version<'a> {
     pub num: &'a str,
     pub semver: SemVer,
     pub yanked: bool,
-    pub created_at: DateTime<Utc>,
+    /// `None` if the version hasn't been indexed yet
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 ///
@@ -147,6 +151,7 @@ impl<'a> CratePage<'a> {
             .filter(|a| a.versions.is_vulnerable(&semver) && !a.withdrawn() && a.severity().is_some())
             .max_by_key(|a| a.severity().unwrap_or(Severity::None));
         let security_advisory_url = advisory.and_then(|a| a.id().url());
+        let feature_set_build_failures = kitchen_sink.feature_set_build_failures(origin, &semver).unwrap_or_default();
 
         let top_category = top_category
             .and_then(|(top, slug)| CATEGORIES.from_slug(slug).0.last().map(|&c| (top, c)));
@@ -180,6 +185,7 @@ impl<'a> CratePage<'a> {
         let mut page = Self {
             up_to_date_class_cache: HashMap::new(),
             direct_dependencies: ver.direct_dependencies(),
+            feature_set_build_failures,
             downloads_per_month_cached,
             github_stargazers_and_watchers,
             security_advisory_url,
@@ -457,6 +463,19 @@ impl<'a> CratePage<'a> {
         }
     }
 
+    /// Non-default ways of building this version that the builder found broken
+    pub fn feature_set_build_failures(&self) -> Option<Vec<Cow<'static, str>>> {
+        if self.feature_set_build_failures.is_empty() {
+            return None;
+        }
+        Some(self.feature_set_build_failures.iter().map(|f| match &f.feature_set {
+            FeatureSet::Feature(name) => format!("with only the {name} feature").into(),
+            FeatureSet::NoDefaultFeatures => "without default features".into(),
+            FeatureSet::AllFeatures => "with all features".into(),
+            FeatureSet::MinimalVersions => "with minimal versions of dependencies".into(),
+        }).collect())
+    }
+
     pub fn direct_dependencies(&self) -> Option<(&[RichDep], &[RichDep], &[RichDep])> {
         Some((
             &self.direct_dependencies.0,
@@ -483,6 +502,7 @@ impl<'a> CratePage<'a> {
                 let origin = Origin::from_crates_io_name(&richdep.package);
                 if let Ok(Some(pop)) = self.kitchen_sink.version_popularity(&origin, &req).await {
                     let res = match pop.pop {
+                        _ if pop.only_yanked => "obsolete",
                         x if x >= 0.75 && !pop.lost_popularity && !pop.deprecated => "common", // hide the version completely
                         _ if pop.matches_latest && !pop.lost_popularity && !pop.deprecated => "verynew", // display version in black
                         x if x >= 0.33 => "outdated", // orange
@@ -518,7 +538,7 @@ impl<'a> CratePage<'a> {
     }
 
     pub fn is_version_new(&self, ver: &Version<'_>, nth: usize) -> bool {
-        nth == 0 /*latest*/ && ver.created_at > Utc::now() - Duration::weeks(1)
+        nth == 0 /*latest*/ && ver.created_at.map_or(true, |d| d > Utc::now() - Duration::weeks(1))
     }
 
     pub fn has_runtime_deps(&self) -> bool {
@@ -557,10 +577,11 @@ impl<'a> CratePage<'a> {
         let grouped1 = Self::group_versions(
             0,
             all.map(|ver| {
+                let (year, month, day) = ver.created_at.map_or((0, 0, 0), |d| (d.year(), d.month(), d.day()));
                 let key = (
-                    ver.created_at.year(),
-                    ver.created_at.month(),
-                    ver.created_at.day(),
+                    year,
+                    month,
+                    day,
                     // semver exposes major bumps, specially for 0.x
                     if ver.semver.major == 0 { ver.semver.minor + 1 } else { 0 },
                     ver.semver.major,
@@ -575,10 +596,11 @@ impl<'a> CratePage<'a> {
             Self::group_versions(
                 1,
                 grouped1.into_iter().map(|v| {
+                    let (year, month) = v.ver.created_at.map_or((0, 0), |d| (d.year(), d.month()));
                     (
                         (
-                            v.ver.created_at.year(),
-                            v.ver.created_at.month(),
+                            year,
+                            month,
                             // semver exposes major bumps, specially for 0.x
                             if v.ver.semver.major == 0 { v.ver.semver.minor + 1 } else { 0 },
                             v.ver.semver.major,
@@ -592,7 +614,10 @@ impl<'a> CratePage<'a> {
         };
 
         let mut top = if grouped2.len() > 8 {
-            Self::group_versions(2, grouped2.into_iter().map(|v| ((v.ver.created_at.year(), v.ver.created_at.month() / 4, v.ver.semver.major), v)))
+            Self::group_versions(2, grouped2.into_iter().map(|v| {
+                let (year, month) = v.ver.created_at.map_or((0, 0), |d| (d.year(), d.month()));
+                ((year, month / 4, v.ver.semver.major), v)
+            }))
         } else {
             grouped2
         };
@@ -615,14 +640,16 @@ impl<'a> CratePage<'a> {
         let mut all: Vec<_> = self.all_versions().filter(|v| !v.yanked).collect();
         all.sort_by(|a, b| a.semver.cmp(&b.semver));
         cnt.total = all.len() as u32;
-        let recent = *all.iter().map(|d| &d.created_at).max()? - Duration::weeks(40);
+        let recent = all.iter().filter_map(|d| d.created_at).max()? - Duration::weeks(40);
+        // versions without a date haven't been indexed yet, so they're new
+        let is_recent = |v: &Version<'_>| v.created_at.map_or(true, |d| d >= recent);
         for v in all {
             if v.semver.major == 0 {
                 cnt.unstable += 1;
                 if let Some(ref prev) = prev {
                     if v.semver.minor != prev.semver.minor {
                         cnt.breaking += 1;
-                        if v.created_at >= recent {
+                        if is_recent(&v) {
                             cnt.breaking_recent += 1;
                         }
                     }
@@ -634,7 +661,7 @@ impl<'a> CratePage<'a> {
                 if let Some(ref prev) = prev {
                     if v.semver.major != prev.semver.major {
                         cnt.major += 1;
-                        if v.created_at >= recent {
+                        if is_recent(&v) {
                             cnt.major_recent += 1;
                         }
                     }
@@ -744,7 +771,7 @@ impl<'a> CratePage<'a> {
     }
 
     pub fn date_created(&self) -> Option<DateTime<Utc>> {
-        self.most_recent_version().map(|v| v.created_at)
+        self.most_recent_version().and_then(|v| v.created_at)
     }
 
     pub fn date_created_string(&self) -> Option<String> {
@@ -752,7 +779,8 @@ impl<'a> CratePage<'a> {
     }
 
     fn most_recent_version(&self) -> Option<Version<'a>> {
-        self.all_versions().max_by(|a, b| a.created_at.cmp(&b.created_at))
+        // undated versions haven't been indexed yet, so they're the newest
+        self.all_versions().max_by_key(|v| (v.created_at.is_none(), v.created_at))
     }
 
     pub fn all_versions(&self) -> impl Iterator<Item = Version<'a>> {
@@ -764,9 +792,9 @@ impl<'a> CratePage<'a> {
         }))
     }
 
-    pub fn published_date(&self) -> DateTime<Utc> {
-        let min_iso_date = self.all.versions().iter().map(|v| &v.created_at).min().expect("any version in the crate");
-        *min_iso_date
+    /// `None` if no version has a known date
+    pub fn published_date(&self) -> Option<DateTime<Utc>> {
+        self.all.versions().iter().filter_map(|v| v.created_at).min()
     }
 
     /// Data for weekly breakdown of recent downloads
@@ -10,6 +10,8 @@ use ahash::HashSetExt;
 mod all_versions;
 mod author_page;
 mod cat_page;
+mod code_search_page;
+mod crate_api;
 mod crate_page;
 mod crev;
 mod download_graph;
@@ -22,9 +24,11 @@ mod reverse_dependencies;
 mod search_page;
 mod urler;
 mod global_stats;
+pub use crate::code_search_page::*;
 pub use crate::not_found_page::*;
 pub use crate::search_page::*;
 pub use crate::global_stats::*;
+pub use crate::crate_api::*;
 use futures::future::try_join_all;
 use kitchen_sink::CrateOwnerRow;
 use kitchen_sink::CrateOwners;
@@ -220,6 +224,49 @@ pub async fn render_all_versions_page(out: &mut impl Write, all: RichCrate, ver:
     Ok(())
 }
 
+/// JSON version of the crate page for `/api/v1/crates/{crate}`
+pub async fn render_crate_api_json(out: &mut impl Write, all: &RichCrate, ver: &RichCrateVersion, kitchen_sink: &KitchenSink, renderer: &Renderer) -> Result<(), anyhow::Error> {
+    if stopped() {
+        return Err(KitchenSinkErr::Stopped.into());
+    }
+    let c = CratePage::new(all, ver, kitchen_sink, renderer).await.context("Can't load data for crate api")?;
+    let api = CrateApi::new(&c, all, ver, kitchen_sink).await?;
+    serde_json::to_writer(out, &api).context("crate api io")?;
+    Ok(())
+}
+
+/// JSON version of `all_versions.rs.html`
+pub async fn render_all_versions_api_json(out: &mut impl Write, all: RichCrate, ver: &RichCrateVersion, kitchen_sink: &KitchenSink) -> Result<(), anyhow::Error> {
+    if stopped() {
+        return Err(KitchenSinkErr::Stopped.into());
+    }
+    let urler = Urler::new(None);
+    let c = crate::all_versions::AllVersions::new(all, ver, kitchen_sink, &urler).await?;
+    serde_json::to_writer(out, &AllVersionsApi::new(&c)).context("all_versions api io")?;
+    Ok(())
+}
+
+/// JSON version of `reverse_dependencies.rs.html`
+pub async fn render_crate_reverse_dependencies_api_json(out: &mut impl Write, ver: &RichCrateVersion, kitchen_sink: &KitchenSink, renderer: &Renderer) -> Result<(), anyhow::Error> {
+    if stopped() {
+        return Err(KitchenSinkErr::Stopped.into());
+    }
+    let c = reverse_dependencies::CratePageRevDeps::new(ver, kitchen_sink, renderer).await?;
+    serde_json::to_writer(out, &RevDepsApi::new(&c)).context("rev deps api io")?;
+    Ok(())
+}
+
+/// JSON for `/api/v1/search`
+pub fn render_search_api_json(out: &mut impl Write, query: &str, results: &search_index::FilteredSearchResults) -> Result<(), anyhow::Error> {
+    serde_json::to_writer(out, &SearchApi::new(query, results)).context("search api io")?;
+    Ok(())
+}
+
+pub fn render_msrv_pins_json(out: &mut impl Write, pins: &kitchen_sink::MsrvPins) -> Result<(), anyhow::Error> {
+    serde_json::to_writer(out, pins).context("msrv pins io")?;
+    Ok(())
+}
+
 /// See `crev.rs.html`
 pub async fn render_crate_reviews(out: &mut impl Write, reviews: &[Review], ver: &RichCrateVersion, kitchen_sink: &KitchenSink, renderer: &Renderer) -> Result<(), anyhow::Error> {
     if stopped() {
@@ -260,7 +307,7 @@ pub async fn render_trending_crates(out: &mut impl Write, kitchen_sink: &Kitchen
 
     // updated were sorted by rank…
     updated.sort_by_cached_key(|(_, all)| {
-        std::cmp::Reverse(all.versions().iter().map(|v| &v.created_at).max().map(|v| v.to_string()))
+        std::cmp::Reverse(all.most_recent_release().map(|v| v.to_string()))
     });
 
     let urler = Urler::new(None);
@@ -296,7 +343,8 @@ dl: {dl}
     Ok(())
 }
 
-pub async fn render_compat_page(out: &mut impl Write, all: RichCrate, kitchen_sink: &KitchenSink) -> Result<(), anyhow::Error> {
+/// `target_rustc` is the minor version of Rust to suggest compatible dependencies for
+pub async fn render_compat_page(out: &mut impl Write, all: RichCrate, kitchen_sink: &KitchenSink, target_rustc: Option<u16>) -> Result<(), anyhow::Error> {
     let mut rustc_versions = HashSet::new();
     rustc_versions.insert(60);
     rustc_versions.insert(55);
@@ -314,7 +362,18 @@ pub async fn render_compat_page(out: &mut impl Write, all: RichCrate, kitchen_si
 
     let mut rustc_versions = rustc_versions.into_iter().collect::<Vec<u16>>();
     rustc_versions.sort_unstable();
-    templates::compat(out, (rustc_versions, by_crate_ver))?;
+
+    let msrv_features = by_crate_ver.keys().rev().filter_map(|ver| {
+        let features = kitchen_sink.rustc_features_setting_msrv(all.origin(), ver).ok()?;
+        if features.is_empty() { None } else { Some((ver.clone(), features)) }
+    }).collect::<Vec<_>>();
+    // blame is for the version that users get by default
+    let latest_stable = by_crate_ver.keys().rev().find(|v| v.pre.is_empty()).or_else(|| by_crate_ver.keys().next_back());
+    let blame = match latest_stable {
+        Some(ver) => Some((ver.clone(), kitchen_sink.msrv_blame(&all, ver, target_rustc).await?)),
+        None => None,
+    };
+    templates::compat(out, (rustc_versions, by_crate_ver, msrv_features, blame))?;
     Ok(())
 }
 
@@ -237,7 +237,7 @@ impl<'a> HomePage<'a> {
     }
 
     pub fn last_modified(&self, allver: &RichCrate) -> DateTime<Utc> {
-        *allver.versions().iter().map(|a| &a.created_at).max().expect("no versions?")
+        allver.most_recent_release().unwrap_or_else(Utc::now)
     }
 
     /// for the feed
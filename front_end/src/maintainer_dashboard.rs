@@ -10,6 +10,7 @@ use kitchen_sink::CError;
 use kitchen_sink::CrateOwnerRow;
 use kitchen_sink::CResult;
 use kitchen_sink::Edition;
+use kitchen_sink::FeatureSet;
 use kitchen_sink::KitchenSink;
 use kitchen_sink::MaintenanceStatus;
 use kitchen_sink::Origin;
@@ -290,6 +291,12 @@ async fn elaborate_warnings(origin: Origin, mut crate_ranking: f32, res: CResult
                     (3, format!("Dependency {name} {req} is deprecated").into(), "Please remove the dependency or replace it with a different crate.".into(),
                         Some((format!("{name} crate").into(), urler.crate_by_origin(&origin).into())))
                 },
+                Warning::YankedDependency(name, req) => {
+                    let origin = Origin::from_crates_io_name(&name);
+                    extended_desc = Some("Yanked versions are still used by existing lockfiles, but Cargo won't pick them for new projects or after cargo update, so builds of this crate without a lockfile will fail.");
+                    (3, format!("Dependency {name} {req} requires a yanked version").into(), "All versions matching this requirement have been yanked. Please upgrade to a version that is still available.".into(),
+                        Some((format!("{name} versions").into(), urler.all_versions(&origin).unwrap_or_else(|| urler.crate_by_origin(&origin)).into())))
+                },
                 Warning::OutdatedDependency(name, req, severity) => {
                     let origin = Origin::from_crates_io_name(&name);
                     let mut upgrade_version = Cow::Borrowed("the latest version");
@@ -366,6 +373,19 @@ async fn elaborate_warnings(origin: Origin, mut crate_ranking: f32, res: CResult
                     (1, "Crate is 'reserved'".into(), "Please be respectful of crates.io and don't squat crate names. You can ensure the crate can be given to someone else by co-owners, e.g. rust-bus org maintainers: cargo owner --add rust-bus-owner".to_string().into(),
                     Some(("Rust-bus maintainers".into(), "https://users.rust-lang.org/t/bus-factor-1-for-crates/17046".into())))
                 },
+                Warning::ConfusableName(similar_to, kind) => {
+                    extended_desc = Some("Names that look like names of popular crates are used for typosquatting attacks, so users may be wary of this crate, and it may be flagged for moderation. If this is an official companion crate, add the popular crate's owners as co-owners.");
+                    let origin = Origin::from_crates_io_name(&similar_to);
+                    (2, format!("Name could be confused with {similar_to}").into(),
+                    format!("The name {kind} of a popular crate. Please consider picking a more distinct name, so that users don't add it by mistake.").into(),
+                    Some((similar_to.to_string().into(), urler.crate_by_origin(&origin).into())))
+                },
+                Warning::SemverBreak(version, prev, changes) => {
+                    extended_desc = Some("This is based on comparing signatures of public items in the crate's sources, and may be a false positive if items were moved around or are generated by macros. Cargo upgrades semver-compatible versions automatically, so breaking changes can break builds of your users. If the change was unintended, consider yanking the release and publishing a fix.");
+                    (2, format!("Version {version} may be semver-incompatible").into(),
+                    format!("{changes} public item{} changed or removed since {prev}, without bumping the major version.", if changes == 1 {" was"} else {"s were"}).into(),
+                    urler.all_versions(k.origin()).map(|url| ("Details".into(), url.into())))
+                },
                 Warning::StaleRelease(days, is_stable, severity) => {
                     if k.is_nightly() {
                         extended_desc = Some("Nightly crates tend to have a short lifespan. We're delisting them if they're not updated frequently.");
@@ -390,6 +410,26 @@ async fn elaborate_warnings(origin: Origin, mut crate_ranking: f32, res: CResult
                     extended_desc = Some("If it's a newly released crate, it's possible we haven't finished indexing the repository yet.");
                     (1, "Could not find the crate in the repository".into(), format!("Make sure the main branch of {repo_url} contains the Cargo.toml for the crate. If you have forked the crate, change the repository property in Cargo.toml to your fork's URL.").into(), None)
                 },
+                Warning::FeatureSetBuildFails(set, rustc) => {
+                    extended_desc = Some("Cargo unifies features across the whole dependency tree, so users may end up with any combination of features. Each feature should enable everything it needs, including other features and optional dependencies.");
+                    let (title, how) = match set.parse() {
+                        Ok(FeatureSet::Feature(f)) => (format!("Feature {f} fails to build"), format!("with default-features = false, features = [\"{f}\"]")),
+                        Ok(FeatureSet::NoDefaultFeatures) => ("Fails to build without default features".into(), "with default-features = false".into()),
+                        Ok(FeatureSet::AllFeatures) => ("Fails to build with all features".into(), "with all features enabled".into()),
+                        _ => (format!("Fails to build with {set}"), format!("with {set}")),
+                    };
+                    (2, title.into(), format!("We've checked the latest release with Rust 1.{rustc} {how}, and it didn't compile, even though the default features did. You can test all features one by one with cargo hack check --each-feature.").into(),
+                        Some(("Feature unification".into(), "https://doc.rust-lang.org/cargo/reference/features.html#feature-unification".into())))
+                },
+                Warning::MinimalVersionsBuildFails(dep, rustc) => {
+                    extended_desc = Some("Cargo is allowed to pick any version matching a requirement, e.g. when another crate or a lockfile needs an older one. Raise the requirements to versions that have everything the crate uses.");
+                    (1, match &dep {
+                        Some(dep) => format!("Lower bound of dependency {dep} is wrong"),
+                        None => "Lower bounds of dependencies are wrong".into(),
+                    }.into(),
+                    format!("The latest release didn't compile with Rust 1.{rustc} when{} dependencies were resolved to the oldest versions allowed by Cargo.toml. Check it with cargo +nightly update -Z minimal-versions.", if dep.is_some() {" its"} else {""}).into(),
+                        Some(("Minimal versions".into(), "https://doc.rust-lang.org/cargo/reference/unstable.html#minimal-versions".into())))
+                },
                 Warning::LicenseSpdxSyntax => {
                     (1, format!("License {} is not in SPDX syntax", k.license().unwrap_or("")).into(), "Use \"OR\" instead of \"/\".".into(), Some(("SPDX license list".into(), "https://spdx.org/licenses/".into())))
                 }
@@ -1,7 +1,9 @@
 use crate::templates;
 use crate::Page;
 use crate::Urler;
+use kitchen_sink::SimilarCrateName;
 use render_readme::Renderer;
+use rich_crate::Origin;
 use std::io::Write;
 
 pub struct NotFoundPage<'a> {
@@ -9,11 +11,14 @@ pub struct NotFoundPage<'a> {
     pub results: &'a [search_index::CrateFound],
     pub query: &'a str,
     pub item_name: &'a str,
+    /// Crates with names like the one that wasn't found
+    pub similar_crates: Vec<Origin>,
 }
 
 impl NotFoundPage<'_> {
-    pub fn new<'a>(query: &'a str, item_name: &'a str, results: &'a [search_index::CrateFound], markup: &'a Renderer) -> NotFoundPage<'a> {
-        NotFoundPage { query, markup, results, item_name }
+    pub fn new<'a>(query: &'a str, item_name: &'a str, results: &'a [search_index::CrateFound], similar_names: &[SimilarCrateName], markup: &'a Renderer) -> NotFoundPage<'a> {
+        let similar_crates = similar_names.iter().filter_map(|s| Origin::try_from_crates_io_name(&s.name)).collect();
+        NotFoundPage { query, markup, results, item_name, similar_crates }
     }
 
     pub fn page(&self) -> Page {
@@ -64,9 +69,9 @@ impl NotFoundPage<'_> {
     }
 }
 
-pub fn render_404_page(out: &mut dyn Write, query: &str, item_name: &str, results: &[search_index::CrateFound], markup: &Renderer) -> Result<(), anyhow::Error> {
+pub fn render_404_page(out: &mut dyn Write, query: &str, item_name: &str, results: &[search_index::CrateFound], similar_names: &[SimilarCrateName], markup: &Renderer) -> Result<(), anyhow::Error> {
     let urler = Urler::new(None);
-    let page = NotFoundPage::new(query, item_name, results, markup);
+    let page = NotFoundPage::new(query, item_name, results, similar_names, markup);
     templates::not_found(out, &page, &urler)?;
     Ok(())
 }
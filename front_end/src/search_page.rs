@@ -2,7 +2,12 @@ use ahash::HashMapExt;
 use crate::templates;
 use crate::Page;
 use crate::Urler;
+use categories::CATEGORIES;
+use kitchen_sink::SimilarCrateName;
 use render_readme::Renderer;
+use rich_crate::Origin;
+use search_index::QueryTerm;
+use search_index::SearchQuery;
 use search_index::SearchResults;
 use ahash::HashMap;
 use std::io::Write;
@@ -12,6 +17,15 @@ pub enum SearchKind<'a> {
     Keyword(&'a str),
 }
 
+/// One clause of the query, as understood by the search
+pub struct QueryPart {
+    pub exclude: bool,
+    /// Human-readable field name
+    pub kind: &'static str,
+    pub label: String,
+    pub url: Option<String>,
+}
+
 pub struct SearchPage<'a> {
     markup: &'a Renderer,
     pub good_results: &'a [search_index::CrateFound],
@@ -19,10 +33,13 @@ pub struct SearchPage<'a> {
     pub query: SearchKind<'a>,
     dividing_keywords: &'a [String],
     pub normalized_query: Option<&'a str>,
+    parsed_query: Option<&'a SearchQuery>,
+    /// When the query looks like a misspelled crate name
+    pub similar_crates: Vec<Origin>,
 }
 
 impl SearchPage<'_> {
-    pub fn new<'a>(query: &'a str, results: &'a SearchResults, markup: &'a Renderer) -> SearchPage<'a> {
+    pub fn new<'a>(query: &'a str, results: &'a SearchResults, similar_names: &[SimilarCrateName], markup: &'a Renderer) -> SearchPage<'a> {
         let half_score = results.crates.get(0).map_or(0., |r| r.score) * 0.33;
         let num = results.crates.iter().take_while(|r| r.score >= half_score).count();
         let (good_results, mut bad_results) = results.crates.split_at(num);
@@ -38,6 +55,8 @@ impl SearchPage<'_> {
             bad_results,
             dividing_keywords: &results.keywords,
             normalized_query: results.normalized_query.as_deref(),
+            parsed_query: Some(&results.parsed_query),
+            similar_crates: similar_names.iter().filter_map(|s| Origin::try_from_crates_io_name(&s.name)).collect(),
         }
     }
 
@@ -49,9 +68,30 @@ impl SearchPage<'_> {
             bad_results: &[],
             dividing_keywords: &results.keywords,
             normalized_query: results.normalized_query.as_deref(),
+            parsed_query: None,
+            similar_crates: Vec::new(),
         }
     }
 
+    /// Shows how the query syntax has been understood. `None` for plain queries.
+    pub fn query_interpretation(&self, url: &Urler) -> Option<Vec<QueryPart>> {
+        let parsed = self.parsed_query.filter(|q| q.has_syntax())?;
+        Some(parsed.clauses.iter().map(|c| {
+            let (kind, label, url) = match &c.term {
+                QueryTerm::Word(w) => ("text", w.clone(), None),
+                QueryTerm::Phrase(p) => ("phrase", p.clone(), None),
+                QueryTerm::Keyword(k) => ("keyword", format!("#{k}"), Some(url.keyword(k))),
+                QueryTerm::Category(slug) => match CATEGORIES.from_slug(slug) {
+                    (cats, true) if !cats.is_empty() => ("category", cats[cats.len() - 1].name.clone(), Some(url.category(cats[cats.len() - 1]))),
+                    _ => ("unknown category", slug.clone(), None),
+                },
+                QueryTerm::Name(n) => ("crate name", n.clone(), None),
+                QueryTerm::NamePrefix(n) => ("crate name starting with", n.clone(), None),
+            };
+            QueryPart { exclude: c.exclude, kind, label, url }
+        }).collect())
+    }
+
     pub fn search_also(&self) -> Option<impl Iterator<Item=(String, &str)>> {
         let query = match self.query {
             SearchKind::Query(s) | SearchKind::Keyword(s) => s,
@@ -181,9 +221,9 @@ impl SearchPage<'_> {
     }
 }
 
-pub fn render_serp_page(out: &mut dyn Write, query: &str, results: &SearchResults, markup: &Renderer) -> Result<(), anyhow::Error> {
+pub fn render_serp_page(out: &mut dyn Write, query: &str, results: &SearchResults, similar_names: &[SimilarCrateName], markup: &Renderer) -> Result<(), anyhow::Error> {
     let urler = Urler::new(None);
-    let page = SearchPage::new(query, results, markup);
+    let page = SearchPage::new(query, results, similar_names, markup);
     templates::serp(out, &page, &urler)?;
     Ok(())
 }
@@ -43,7 +43,7 @@ impl Urler {
     /// Summary of all dependencies
     pub fn deps(&self, krate: &RichCrateVersion) -> Option<String> {
         match krate.origin() {
-            Origin::CratesIo(_) => None,
+            Origin::CratesIo(_) | Origin::Registry { .. } => None,
             Origin::GitHub { repo, .. } => Some(format!("https://deps.rs/repo/github/{}/{}", Encoded(&*repo.owner), Encoded(&*repo.repo))),
             Origin::GitLab { repo, .. } => Some(format!("https://deps.rs/repo/gitlab/{}/{}", Encoded(&*repo.owner), Encoded(&*repo.repo))),
         }
@@ -58,6 +58,9 @@ impl Urler {
                 let host = if let Origin::GitHub { .. } = origin { "gh" } else { "lab" };
                 format!("/install/{host}/{}/{}/{}", Encoded(&*repo.owner), Encoded(&*repo.repo), Encoded::str(package))
             }
+            Origin::Registry { registry, package } => {
+                format!("/install/reg/{}/{}", Encoded(&**registry), Encoded(&**package))
+            }
         }
     }
 
@@ -71,6 +74,7 @@ impl Urler {
                 // format!("/{}/{}/{}/{}/versions", host, Encoded(&*repo.owner), Encoded(&*repo.repo), Encoded::str(&package))
                 None
             }
+            Origin::Registry { .. } => None,
         }
     }
 
@@ -86,7 +90,7 @@ impl Urler {
     pub fn reverse_deps(&self, origin: &Origin) -> Option<String> {
         match origin {
             Origin::CratesIo(lowercase_name) => Some(format!("/crates/{}/rev", Encoded::str(lowercase_name))),
-            Origin::GitHub { .. } | Origin::GitLab { .. } => None,
+            Origin::GitHub { .. } | Origin::GitLab { .. } | Origin::Registry { .. } => None,
         }
     }
 
@@ -143,6 +147,9 @@ impl Urler {
             Origin::GitLab { repo, package } => {
                 format!("/lab/{}/{}/{}", Encoded(&*repo.owner), Encoded(&*repo.repo), Encoded::str(package))
             }
+            Origin::Registry { registry, package } => {
+                format!("/reg/{}/{}", Encoded(&**registry), Encoded(&**package))
+            }
         }
     }
 
@@ -209,6 +216,11 @@ impl Urler {
         format!("/search?q={}", Encoded(query))
     }
 
+    /// Names of public items in crates' code
+    pub fn search_code(&self, query: &str) -> String {
+        format!("/search?mode=code&q={}", Encoded(query))
+    }
+
     pub fn search_ddg(&self, query: &str) -> String {
         format!("https://duckduckgo.com/?q=site%3Alib.rs+{}", Encoded(query))
     }
@@ -1,10 +1,10 @@
 use std::time::Duration;
 use repo_url::SimpleRepo;
 use simple_cache::TempCache;
-use std::future::Future;
 use std::path::Path;
 
 use github_v3::StatusCode;
+pub use github_v3::Priority;
 use serde::{Deserialize, Serialize};
 
 mod model;
@@ -63,12 +63,26 @@ pub struct GitHub {
     contribs: TempCache<(String, Option<Vec<UserContrib>>)>,
     repos: TempCache<(String, Option<GitHubRepo>)>,
     emails: TempCache<(String, Option<Vec<User>>)>,
+    /// For revalidating outdated cache entries with conditional requests
+    etags: TempCache<String>,
+    /// Never use the network, and treat everything not in the cache as not found
+    cache_only: bool,
 }
 
 impl GitHub {
     pub fn new(cache_path: impl AsRef<Path>, token: &str) -> CResult<Self> {
+        Self::new_with_client(cache_path, github_v3::Client::new(Some(token)))
+    }
+
+    /// Requests are spread across the tokens
+    pub fn new_with_tokens(cache_path: impl AsRef<Path>, tokens: &[&str]) -> CResult<Self> {
+        Self::new_with_client(cache_path, github_v3::Client::new_with_tokens(tokens))
+    }
+
+    /// The client can use a cassette to replay responses in tests
+    pub fn new_with_client(cache_path: impl AsRef<Path>, client: github_v3::Client) -> CResult<Self> {
         Ok(Self {
-            client: github_v3::Client::new(Some(token)),
+            client,
             user_orgs: TempCache::new(&cache_path.as_ref().with_file_name("github_user_orgs.bin"), Duration::from_secs(3600*24*14))?,
             orgs: TempCache::new(&cache_path.as_ref().with_file_name("github_orgs2.bin"), Duration::from_secs(3600*24*31))?,
             users: TempCache::new(&cache_path.as_ref().with_file_name("github_users3.bin"), Duration::from_secs(3600*24*31*2))?,
@@ -77,9 +91,21 @@ impl GitHub {
             contribs: TempCache::new(&cache_path.as_ref().with_file_name("github_contribs.bin"), Duration::from_secs(3600*24*31*2))?,
             repos: TempCache::new(&cache_path.as_ref().with_file_name("github_repos2.bin"), Duration::from_secs(3600*24*15))?,
             emails: TempCache::new(&cache_path.as_ref().with_file_name("github_emails.bin"), Duration::from_secs(3600*24*31*3))?,
+            etags: TempCache::new(&cache_path.as_ref().with_file_name("github_etags.bin"), Duration::from_secs(3600*24*31*6))?,
+            cache_only: false,
         })
     }
 
+    pub fn cache_only(&mut self, no_net: bool) -> &mut Self {
+        self.cache_only = no_net;
+        self
+    }
+
+    /// Bulk jobs should use `Background`, so that they don't use up the rate limit needed by the site
+    pub fn set_priority(&self, priority: Priority) {
+        self.client.set_default_priority(priority);
+    }
+
     pub async fn user_by_email(&self, email: &str) -> CResult<Option<Vec<User>>> {
         let std_suffix = "@users.noreply.github.com";
         if let Some(rest) = email.strip_suffix(std_suffix) {
@@ -88,10 +114,9 @@ impl GitHub {
                 return Ok(Some(vec![user]));
             }
         }
-        self.get_cached(&self.emails, (email, ""), |client| client.get()
+        self.get_cached("emails", &self.emails, (email, ""), |client| client.get()
                        .path("search/users")
-                       .query("q=in:email%20").arg(email)
-                       .send(), |res: SearchResults<User>| {
+                       .query("q=in:email%20").arg(email), |res: SearchResults<User>| {
                         println!("Found {email} = {:#?}", res.items);
                         res.items
                     }).await
@@ -103,38 +128,33 @@ impl GitHub {
         }
 
         let key = login.to_ascii_lowercase();
-        self.get_cached(&self.users, (&key, ""), |client| client.get()
-                       .path("users").arg(login)
-                       .send(), id).await.map_err(|e| e.context("user_by_login"))
+        self.get_cached("users", &self.users, (&key, ""), |client| client.get()
+                       .path("users").arg(login), id).await.map_err(|e| e.context("user_by_login"))
     }
 
     pub async fn user_orgs(&self, login: &str) -> CResult<Option<Vec<UserOrg>>> {
         let key = login.to_ascii_lowercase();
-        self.get_cached(&self.user_orgs, (&key, ""), |client| client.get()
-                       .path("users").arg(login).path("orgs")
-                       .send(), id).await.map_err(|e| e.context("user_orgs"))
+        self.get_cached("user_orgs", &self.user_orgs, (&key, ""), |client| client.get()
+                       .path("users").arg(login).path("orgs"), id).await.map_err(|e| e.context("user_orgs"))
     }
 
     pub async fn org(&self, login: &str) -> CResult<Option<Org>> {
         let key = login.to_ascii_lowercase();
-        self.get_cached(&self.orgs, (&key, ""), |client| client.get()
-                       .path("orgs").arg(login)
-                       .send(), id).await.map_err(|e| e.context("user_orgs"))
+        self.get_cached("orgs", &self.orgs, (&key, ""), |client| client.get()
+                       .path("orgs").arg(login), id).await.map_err(|e| e.context("user_orgs"))
     }
 
     pub async fn commits(&self, repo: &SimpleRepo, as_of_version: &str) -> CResult<Option<Vec<CommitMeta>>> {
         let key = format!("commits/{}/{}", repo.owner, repo.repo);
-        self.get_cached(&self.commits, (&key, as_of_version), |client| client.get()
+        self.get_cached("commits", &self.commits, (&key, as_of_version), |client| client.get()
                            .path("repos").arg(&repo.owner).arg(&repo.repo)
-                           .path("commits")
-                           .send(), id).await.map_err(|e| e.context("commits"))
+                           .path("commits"), id).await.map_err(|e| e.context("commits"))
     }
 
     pub async fn releases(&self, repo: &SimpleRepo, as_of_version: &str) -> CResult<Option<Vec<GitHubRelease>>> {
         let key = format!("release/{}/{}", repo.owner, repo.repo);
-        self.get_cached(&self.releases, (&key, as_of_version), |client| client.get()
-                           .path("repos").arg(&repo.owner).arg(&repo.repo).path("releases")
-                           .send(), id).await.map_err(|e| e.context("releases"))
+        self.get_cached("releases", &self.releases, (&key, as_of_version), |client| client.get()
+                           .path("repos").arg(&repo.owner).arg(&repo.repo).path("releases"), id).await.map_err(|e| e.context("releases"))
     }
 
     pub async fn topics(&self, repo: &SimpleRepo, as_of_version: &str) -> CResult<Option<Vec<String>>> {
@@ -144,9 +164,8 @@ impl GitHub {
 
     pub async fn repo(&self, repo: &SimpleRepo, as_of_version: &str) -> CResult<Option<GitHubRepo>> {
         let key = format!("{}/{}", repo.owner, repo.repo);
-        self.get_cached(&self.repos, (&key, as_of_version), |client| client.get()
-                .path("repos").arg(&repo.owner).arg(&repo.repo)
-                .send(), |mut ghdata: GitHubRepo| {
+        self.get_cached("repos", &self.repos, (&key, as_of_version), |client| client.get()
+                .path("repos").arg(&repo.owner).arg(&repo.repo), |mut ghdata: GitHubRepo| {
                     // Keep GH-specific logic in here
                     if ghdata.has_pages {
                         // Name is case-sensitive
@@ -167,28 +186,50 @@ impl GitHub {
     pub async fn contributors(&self, repo: &SimpleRepo, as_of_version: &str) -> CResult<Option<Vec<UserContrib>>> {
         let path = format!("repos/{}/{}/stats/contributors", repo.owner, repo.repo);
         let key = (path.as_str(), as_of_version);
-        self.get_cached(&self.contribs, key, |client: &github_v3::Client| {
-            client.get().path("repos").arg(&repo.owner).arg(&repo.repo).path("stats/contributors").send()
+        self.get_cached("contribs", &self.contribs, key, |client: &github_v3::Client| {
+            client.get().path("repos").arg(&repo.owner).arg(&repo.repo).path("stats/contributors")
         }, id).await
     }
 
-    async fn get_cached<F, P, B, R, A>(&self, cache: &TempCache<(String, Option<R>)>, key: (&str, &str), cb: F, postproc: P) -> CResult<Option<R>>
+    /// `kind` namespaces keys of the ETags cache, which is shared by all the caches
+    async fn get_cached<F, P, B, R>(&self, kind: &str, cache: &TempCache<(String, Option<R>)>, key: (&str, &str), cb: F, postproc: P) -> CResult<Option<R>>
     where
         P: FnOnce(B) -> R,
-        F: FnOnce(&github_v3::Client) -> A,
-        A: Future<Output = Result<github_v3::Response, github_v3::GHError>>,
+        F: FnOnce(&github_v3::Client) -> github_v3::Builder,
         B: for<'de> serde::Deserialize<'de> + serde::Serialize + Clone + Send + 'static,
         R: for<'de> serde::Deserialize<'de> + serde::Serialize + Clone + Send + 'static,
     {
+        let mut outdated = None;
         if let Some((ver, payload)) = cache.get(key.0)? {
-            if ver == key.1 {
+            if ver == key.1 || self.cache_only {
                 return Ok(payload);
             }
             eprintln!("Cache near miss {}@{ver} vs {}", key.0, key.1);
+            outdated = Some(payload);
+        }
+
+        if self.cache_only {
+            return Ok(None);
+        }
+
+        let etag_key = format!("{kind}/{}", key.0);
+        let mut req = cb(&self.client);
+        if outdated.is_some() {
+            if let Some(etag) = self.etags.get(etag_key.as_str())? {
+                req = req.if_none_match(&etag);
+            }
         }
 
-        let (status, res) = match Box::pin(cb(&self.client)).await {
+        let mut etag = None;
+        let (status, res) = match Box::pin(req.send()).await {
+            Ok(res) if res.is_not_modified() => {
+                // revalidated, and it didn't cost any rate limit
+                let payload = outdated.unwrap_or(None);
+                cache.set(key.0, (key.1.to_string(), payload.clone()))?;
+                return Ok(payload);
+            },
             Ok(res) => {
+                etag = res.etag().map(String::from);
                 let status = res.status();
                 let headers = res.headers();
                 eprintln!("Recvd {}@{} {status:?} {headers:?}", key.0, key.1);
@@ -230,6 +271,9 @@ impl GitHub {
                 let res = (key.1.to_string(), Some(val));
                 if keep_cached {
                     cache.set(key.0, &res)?;
+                    if let Some(etag) = etag {
+                        self.etags.set(etag_key, etag)?;
+                    }
                 }
                 Ok(res.1)
             },
@@ -340,6 +384,43 @@ async fn github_releases() {
     assert!(releases.len() > 4, "{releases:?}");
 }
 
+#[cfg(test)]
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_user_and_not_found() {
+    use github_v3::{Cassette, CassetteMode, Recording};
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = Cassette::new(dir.path().join("cassette"), CassetteMode::Replay);
+    let json = || vec![("content-type".to_owned(), "application/json".to_owned())];
+    cassette.save(&Recording::new("https://api.github.com/users/octocat", 200, json(),
+        br#"{"id":583231,"login":"octocat","html_url":"https://github.com/octocat","type":"User"}"#.to_vec())).unwrap();
+    cassette.save(&Recording::new("https://api.github.com/users/ghost404", 404, json(), br#"{"message":"Not Found"}"#.to_vec())).unwrap();
+
+    let gh = GitHub::new_with_client(dir.path().join("github.db"), github_v3::Client::new_with_cassette(None, cassette)).unwrap();
+    assert_eq!(583231, gh.user_by_login("octocat").await.unwrap().unwrap().id);
+    assert!(gh.user_by_login("ghost404").await.unwrap().is_none());
+    // 404 is cached
+    assert!(gh.user_by_login("ghost404").await.unwrap().is_none());
+}
+
+#[cfg(test)]
+#[tokio::test(flavor = "multi_thread")]
+async fn revalidate_with_etag() {
+    use github_v3::{Cassette, CassetteMode, Recording};
+    let dir = tempfile::tempdir().unwrap();
+    let repo = SimpleRepo { owner: "o".into(), repo: "r".into() };
+    let url = "https://api.github.com/repos/o/r/releases";
+    let release = br#"[{"tag_name":"v1.0","name":"One","created_at":"2020-01-01T00:00:00Z","published_at":"2020-01-01T00:00:00Z"}]"#;
+    let cassette = Cassette::new(dir.path().join("cassette"), CassetteMode::Replay);
+    cassette.save(&Recording::new(url, 200, vec![("content-type".into(), "application/json".into()), ("etag".into(), "W/\"1\"".into())], release.to_vec())).unwrap();
+    let gh = GitHub::new_with_client(dir.path().join("github.db"), github_v3::Client::new_with_cassette(None, cassette.clone())).unwrap();
+    assert_eq!(1, gh.releases(&repo, "1.0.0").await.unwrap().unwrap().len());
+
+    // new crate version makes the cache outdated, but GitHub says it hasn't changed
+    cassette.save(&Recording::new(url, 304, vec![("etag".into(), "W/\"1\"".into())], vec![])).unwrap();
+    assert_eq!(1, gh.releases(&repo, "1.0.1").await.unwrap().unwrap().len());
+    assert_eq!("1.0.1", gh.releases.get(&*format!("release/{}/{}", repo.owner, repo.repo)).unwrap().unwrap().0);
+}
+
 #[cfg(test)]
 #[tokio::test(flavor = "multi_thread")]
 async fn test_user_by_email() {
@@ -0,0 +1,133 @@
+use reqwest::header::HeaderValue;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicU32, AtomicU64};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Which requests may use up the rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Someone is waiting for the response, e.g. a page is being rendered.
+    /// Can use the whole rate limit, and isn't paced. Fails with `GHError::RateLimited` when there's nothing left.
+    Foreground,
+    /// Bulk jobs. They run a few at a time, are paced to spread the rate limit until its reset,
+    /// and wait instead of using the reserve left for foreground requests.
+    Background,
+}
+
+/// Rate limit of a token, as last reported by GitHub
+struct Token {
+    auth: Option<HeaderValue>,
+    /// `u32::MAX` if unknown
+    remaining: AtomicU32,
+    /// Requests per hour, 0 if unknown
+    limit: AtomicU32,
+    /// Unix timestamp
+    reset: AtomicU64,
+    /// Pause between background requests, to spread what's left until the reset
+    wait_sec: AtomicU32,
+}
+
+/// Rate limits of all tokens the client can rotate between.
+///
+/// GitHub reports the same limits to every process using the token, so the reserve works even if
+/// bulk jobs and the site run in separate processes.
+pub(crate) struct Budget {
+    tokens: Vec<Token>,
+    /// Percentage of each token's limit left only for `Foreground`
+    pub background_reserve_percent: AtomicU32,
+}
+
+impl Budget {
+    /// Without tokens the requests are anonymous
+    pub fn new(tokens: &[&str]) -> Self {
+        let auth = if tokens.is_empty() {
+            vec![None]
+        } else {
+            tokens.iter().map(|t| Some(HeaderValue::from_str(&format!("token {t}")).expect("token"))).collect()
+        };
+        Self {
+            tokens: auth.into_iter().map(|auth| Token {
+                auth,
+                remaining: AtomicU32::new(u32::MAX),
+                limit: AtomicU32::new(0),
+                reset: AtomicU64::new(0),
+                wait_sec: AtomicU32::new(0),
+            }).collect(),
+            background_reserve_percent: AtomicU32::new(20),
+        }
+    }
+
+    pub fn auth(&self, token: usize) -> Option<&HeaderValue> {
+        self.tokens[token].auth.as_ref()
+    }
+
+    /// Index of the token with most requests left, or time until one of them resets
+    pub fn pick(&self, priority: Priority, now: SystemTime) -> Result<usize, Duration> {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let reserve_percent = match priority {
+            Priority::Foreground => 0,
+            Priority::Background => self.background_reserve_percent.load(Relaxed).min(100),
+        };
+        let mut best: Option<(usize, u32)> = None;
+        let mut earliest_reset = u64::MAX;
+        for (i, t) in self.tokens.iter().enumerate() {
+            let reset = t.reset.load(Relaxed);
+            let remaining = if reset <= now { u32::MAX } else { t.remaining.load(Relaxed) };
+            let reserve = (u64::from(t.limit.load(Relaxed)) * u64::from(reserve_percent) / 100) as u32;
+            if remaining > reserve && best.map_or(true, |(_, r)| remaining > r) {
+                best = Some((i, remaining));
+            }
+            earliest_reset = earliest_reset.min(reset);
+        }
+        match best {
+            Some((i, _)) => Ok(i),
+            None => Err(Duration::from_secs(earliest_reset.saturating_sub(now).max(1))),
+        }
+    }
+
+    /// `limit` is `None` if GitHub didn't report it, and then the last known one is kept
+    pub fn update(&self, token: usize, remaining: u32, limit: Option<u32>, reset: SystemTime) {
+        let t = &self.tokens[token];
+        t.remaining.store(remaining, Relaxed);
+        if let Some(limit) = limit {
+            t.limit.store(limit, Relaxed);
+        }
+        t.reset.store(reset.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(), Relaxed);
+    }
+
+    pub fn wait_sec(&self, token: usize) -> u32 {
+        self.tokens[token].wait_sec.load(Relaxed)
+    }
+
+    pub fn set_wait_sec(&self, token: usize, wait_sec: u32) {
+        self.tokens[token].wait_sec.store(wait_sec, Relaxed);
+    }
+}
+
+#[test]
+fn picks_tokens() {
+    let now = SystemTime::now();
+    let in_an_hour = now + Duration::from_secs(3600);
+    let b = Budget::new(&["a", "b"]);
+    assert_eq!(Ok(0), b.pick(Priority::Background, now));
+
+    b.update(0, 10, Some(5000), in_an_hour);
+    assert_eq!(Ok(1), b.pick(Priority::Background, now));
+    b.update(1, 500, Some(5000), in_an_hour);
+    // both are below the reserve of 20%
+    assert!(b.pick(Priority::Background, now).unwrap_err() > Duration::from_secs(3500));
+    assert_eq!(Ok(1), b.pick(Priority::Foreground, now));
+    b.update(1, 0, None, in_an_hour);
+    assert_eq!(Ok(0), b.pick(Priority::Foreground, now));
+    b.update(0, 0, None, in_an_hour);
+    assert!(b.pick(Priority::Foreground, now).is_err());
+    // after the reset
+    assert_eq!(Ok(0), b.pick(Priority::Background, in_an_hour + Duration::from_secs(1)));
+
+    // the reserve scales with the limit
+    let b = Budget::new(&["a"]);
+    b.update(0, 500, Some(1000), in_an_hour);
+    assert_eq!(Ok(0), b.pick(Priority::Background, now));
+    b.update(0, 150, None, in_an_hour);
+    assert!(b.pick(Priority::Background, now).is_err());
+}
@@ -1,43 +1,57 @@
 use futures::Stream;
 pub use futures::StreamExt;
+pub use http_cassette::{Cassette, Mode as CassetteMode, Recording};
 pub use reqwest::header::HeaderMap;
 pub use reqwest::header::HeaderValue;
 pub use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
-use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+mod budget;
+use budget::Budget;
+pub use budget::Priority;
+
 /// Response from the API
 pub struct Response {
-    res: reqwest::Response,
+    res: RawResponse,
     client: Arc<ClientInner>,
+    priority: Priority,
+}
+
+/// The body is read eagerly, so that it's the same for live and replayed responses
+struct RawResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
 }
 
 impl Response {
     /// Fetch a single JSON object from the API
     pub async fn obj<T: DeserializeOwned>(self) -> Result<T, GHError> {
-        Ok(self.res.json().await?)
+        Ok(serde_json::from_slice(&self.res.body)?)
     }
 
     /// Stream an array of objects from the API
     pub fn array<T: DeserializeOwned + std::marker::Unpin + 'static>(self) -> impl Stream<Item = Result<T, GHError>> {
         let mut res = self.res;
         let client = self.client;
+        let priority = self.priority;
 
         // Pin is required for easy iteration, otherwise the caller would have to pin it
         Box::pin(async_stream::try_stream! {
             loop {
-                let next_link = res.headers().get("link")
+                let next_link = res.headers.get("link")
                     .and_then(|h| h.to_str().ok())
                     .and_then(parse_next_link);
-                let items = res.json::<Vec<T>>().await?;
+                let items = serde_json::from_slice::<Vec<T>>(&res.body)?;
                 for item in items {
                     yield item;
                 }
                 match next_link {
-                    Some(url) => res = client.raw_get(&url).await?,
+                    Some(url) => res = client.raw_get(&url, priority, None).await?,
                     None => break,
                 }
             }
@@ -46,12 +60,22 @@ impl Response {
 
     /// Response headers
     pub fn headers(&self) -> &HeaderMap {
-        self.res.headers()
+        &self.res.headers
     }
 
     /// Response status
     pub fn status(&self) -> StatusCode {
-        self.res.status()
+        self.res.status
+    }
+
+    /// For `Builder::if_none_match()` in a later request
+    pub fn etag(&self) -> Option<&str> {
+        self.res.headers.get("etag").and_then(|h| h.to_str().ok())
+    }
+
+    /// The response to `Builder::if_none_match()` has no body, because the previous one is still valid
+    pub fn is_not_modified(&self) -> bool {
+        self.res.status == StatusCode::NOT_MODIFIED
     }
 }
 
@@ -62,6 +86,8 @@ pub struct Builder {
     client: Arc<ClientInner>,
     url: String,
     query_string_started: bool,
+    priority: Option<Priority>,
+    etag: Option<String>,
 }
 
 impl Builder {
@@ -109,20 +135,38 @@ impl Builder {
         self
     }
 
+    /// Overrides the client's default priority (see `Client::set_default_priority()`)
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Conditional request. If the data hasn't changed since the response with this ETag,
+    /// the response is `304 Not Modified` without a body. These don't count against the rate limit.
+    pub fn if_none_match(mut self, etag: &str) -> Self {
+        self.etag = Some(etag.to_owned());
+        self
+    }
+
     /// Make the request
     pub async fn send(self) -> Result<Response, GHError> {
-        let res = self.client.raw_get(&self.url).await?;
+        let priority = self.priority.unwrap_or_else(|| self.client.default_priority());
+        let res = self.client.raw_get(&self.url, priority, self.etag.as_deref()).await?;
         Ok(Response {
             client: self.client,
-            res
+            res,
+            priority,
         })
     }
 }
 
 struct ClientInner {
     client: reqwest::Client,
-    // FIXME: this should be per endpoint, because search and others have different throttling
-    wait_sec: AtomicU32,
+    cassette: Option<Cassette>,
+    budget: Budget,
+    background_default: AtomicBool,
+    /// Limits concurrency of background requests, so that they don't hog the connection pool
+    background_slots: tokio::sync::Semaphore,
 }
 
 /// API Client. Start here.
@@ -138,11 +182,24 @@ impl Client {
 
     /// Takes API token for authenticated requests (make the token in GitHub settings)
     pub fn new(token: Option<&str>) -> Self {
-        let mut default_headers = HeaderMap::with_capacity(2);
+        Self::new_inner(&token.into_iter().collect::<Vec<_>>(), None)
+    }
+
+    /// Spreads requests across multiple tokens, using the one with the most requests left
+    pub fn new_with_tokens(tokens: &[&str]) -> Self {
+        Self::new_inner(tokens, None)
+    }
+
+    /// For tests. Responses are recorded to, or replayed from, the cassette's directory.
+    ///
+    /// The token is only needed for recording.
+    pub fn new_with_cassette(token: Option<&str>, cassette: Cassette) -> Self {
+        Self::new_inner(&token.into_iter().collect::<Vec<_>>(), Some(cassette))
+    }
+
+    fn new_inner(tokens: &[&str], cassette: Option<Cassette>) -> Self {
+        let mut default_headers = HeaderMap::with_capacity(1);
         default_headers.insert("Accept", HeaderValue::from_static("application/vnd.github.v3+json"));
-        if let Some(token) = token {
-            default_headers.insert("Authorization", HeaderValue::from_str(&format!("token {token}")).unwrap());
-        }
 
         Self {
             inner: Arc::new(ClientInner {
@@ -153,11 +210,24 @@ impl Client {
                     .timeout(Duration::from_secs(20))
                     .build()
                     .unwrap(),
-                wait_sec: AtomicU32::new(0),
+                cassette,
+                budget: Budget::new(tokens),
+                background_default: AtomicBool::new(false),
+                background_slots: tokio::sync::Semaphore::new(4),
             }),
         }
     }
 
+    /// Priority of requests that don't set it. `Foreground` by default.
+    pub fn set_default_priority(&self, priority: Priority) {
+        self.inner.background_default.store(priority == Priority::Background, SeqCst);
+    }
+
+    /// Background requests wait when less than this percentage of a token's hourly limit is left. 20% by default.
+    pub fn set_background_reserve_percent(&self, percent: u8) {
+        self.inner.budget.background_reserve_percent.store(percent.into(), SeqCst);
+    }
+
     /// Make a new request to the API.
     pub fn get(&self) -> Builder {
         let mut url = String::with_capacity(100);
@@ -166,31 +236,53 @@ impl Client {
             client: self.inner.clone(),
             url,
             query_string_started: false,
+            priority: None,
+            etag: None,
         }
     }
 }
 
 impl ClientInner {
+    fn default_priority(&self) -> Priority {
+        if self.background_default.load(SeqCst) { Priority::Background } else { Priority::Foreground }
+    }
+
     // Get a single response
-    async fn raw_get(&self, url: &str) -> Result<reqwest::Response, GHError> {
+    async fn raw_get(&self, url: &str, priority: Priority, if_none_match: Option<&str>) -> Result<RawResponse, GHError> {
         debug_assert!(url.starts_with("https://api.github.com/"));
 
+        let _slot = match priority {
+            Priority::Background => Some(self.background_slots.acquire().await.map_err(|_| GHError::Internal)?),
+            Priority::Foreground => None,
+        };
+
         let mut retries = 5u8;
         let mut retry_delay = 1;
         loop {
-            let wait_sec = self.wait_sec.load(SeqCst);
-            if wait_sec > 0 {
+            let token = match self.budget.pick(priority, SystemTime::now()) {
+                Ok(token) => token,
+                Err(_) if priority == Priority::Foreground => return Err(GHError::RateLimited),
+                Err(until_reset) => {
+                    tokio::time::sleep(until_reset).await;
+                    continue;
+                },
+            };
+
+            // FIXME: this should be per endpoint, because search and others have different throttling
+            let wait_sec = self.budget.wait_sec(token);
+            if wait_sec > 0 && priority == Priority::Background {
                 // This has poor behavior with concurrency. It should be pacing all requests.
                 tokio::time::sleep(Duration::from_secs(wait_sec.into())).await;
             }
 
-            let res = self.client.get(url).send().await?;
+            let res = self.fetch(url, token, if_none_match).await?;
 
-            let headers = res.headers();
-            let status = res.status();
+            let headers = &res.headers;
+            let status = res.status;
 
             let wait_sec = match (Self::rate_limit_remaining(headers), Self::rate_limit_reset(headers)) {
                 (Some(rl), Some(rs)) => {
+                    self.budget.update(token, rl, Self::rate_limit_limit(headers), rs);
                     rs.duration_since(SystemTime::now()).ok()
                         .and_then(|d| d.checked_div(rl + 2))
                         .map(|d| d.as_secs() as u32)
@@ -198,7 +290,7 @@ impl ClientInner {
                 }
                 _ => if status == StatusCode::TOO_MANY_REQUESTS {3} else {0},
             };
-            self.wait_sec.store(wait_sec, SeqCst);
+            self.budget.set_wait_sec(token, wait_sec);
 
             let should_wait_for_content = status == StatusCode::ACCEPTED;
             if should_wait_for_content && retries > 0 {
@@ -208,14 +300,38 @@ impl ClientInner {
                 continue;
             }
 
-            return if status.is_success() && !should_wait_for_content {
+            return if (status.is_success() && !should_wait_for_content) || status == StatusCode::NOT_MODIFIED {
                 Ok(res)
             } else {
-                Err(error_for_response(res).await)
+                Err(error_for_response(res))
             };
         }
     }
 
+    async fn fetch(&self, url: &str, token: usize, if_none_match: Option<&str>) -> Result<RawResponse, GHError> {
+        let mut req = self.client.get(url);
+        if let Some(auth) = self.budget.auth(token) {
+            req = req.header("Authorization", auth.clone());
+        }
+        if let Some(etag) = if_none_match {
+            req = req.header("If-None-Match", etag);
+        }
+        if let Some(cassette) = &self.cassette {
+            let rec = cassette.send(&self.client, req.build()?).await?;
+            return Ok(RawResponse {
+                status: StatusCode::from_u16(rec.status).map_err(|_| GHError::Internal)?,
+                headers: rec.header_map(),
+                body: rec.body(),
+            });
+        }
+        let res = req.send().await?;
+        Ok(RawResponse {
+            status: res.status(),
+            headers: res.headers().clone(),
+            body: res.bytes().await?.to_vec(),
+        })
+    }
+
     /// GitHub's `x-ratelimit-remaining` header
     pub fn rate_limit_remaining(headers: &HeaderMap) -> Option<u32> {
         headers.get("x-ratelimit-remaining")
@@ -223,6 +339,13 @@ impl ClientInner {
             .and_then(|s| s.parse().ok())
     }
 
+    /// GitHub's `x-ratelimit-limit` header
+    pub fn rate_limit_limit(headers: &HeaderMap) -> Option<u32> {
+        headers.get("x-ratelimit-limit")
+            .and_then(|s| s.to_str().ok())
+            .and_then(|s| s.parse().ok())
+    }
+
     /// GitHub's `x-ratelimit-reset` header
     pub fn rate_limit_reset(headers: &HeaderMap) -> Option<SystemTime> {
         headers.get("x-ratelimit-reset")
@@ -232,13 +355,12 @@ impl ClientInner {
     }
 }
 
-async fn error_for_response(res: reqwest::Response) -> GHError {
-    let status = res.status();
-    let mime = res.headers().get("content-type").and_then(|h| h.to_str().ok()).unwrap_or("");
+fn error_for_response(res: RawResponse) -> GHError {
+    let mime = res.headers.get("content-type").and_then(|h| h.to_str().ok()).unwrap_or("");
     GHError::Response {
-        status,
+        status: res.status,
         message: if mime.starts_with("application/json") {
-            res.json::<GitHubErrorResponse>().await.ok().map(|res| res.message)
+            serde_json::from_slice::<GitHubErrorResponse>(&res.body).ok().map(|res| res.message)
         } else {
             None
         },
@@ -276,6 +398,19 @@ pub enum GHError {
     Response { status: StatusCode, message: Option<String> },
     #[error("Internal error")]
     Internal,
+    #[error("GitHub API rate limit exhausted")]
+    RateLimited,
+    #[error("JSON error: {}", _0)]
+    Json(#[from] serde_json::Error),
+}
+
+impl From<http_cassette::Error> for GHError {
+    fn from(e: http_cassette::Error) -> Self {
+        match e {
+            http_cassette::Error::Http(e) => e.into(),
+            e => Self::Request(e.to_string()),
+        }
+    }
 }
 
 impl From<reqwest::Error> for GHError {
@@ -304,6 +439,55 @@ mod test {
         gh.get().path("users/octocat/orgs").send().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn replay_pagination() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = Cassette::new(dir.path(), CassetteMode::Replay);
+        let json = || ("content-type".to_owned(), "application/json".to_owned());
+        let next = "<https://api.github.com/users/octocat/repos?page=2>; rel=\"next\"";
+        cassette.save(&Recording::new("https://api.github.com/users/octocat/repos", 200, vec![json(), ("link".into(), next.into())], b"[1,2]".to_vec())).unwrap();
+        cassette.save(&Recording::new("https://api.github.com/users/octocat/repos?page=2", 200, vec![json()], b"[3]".to_vec())).unwrap();
+
+        let gh = Client::new_with_cassette(None, cassette);
+        let all: Vec<u32> = gh.get().path("users/octocat/repos").send().await.unwrap().array().map(|r| r.unwrap()).collect().await;
+        assert_eq!(all, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn replay_rate_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = Cassette::new(dir.path(), CassetteMode::Replay);
+        cassette.save(&Recording::new("https://api.github.com/users/octocat", 403, vec![
+            ("content-type".into(), "application/json; charset=utf-8".into()),
+            ("x-ratelimit-remaining".into(), "0".into()),
+            ("x-ratelimit-reset".into(), "1600000000".into()),
+        ], br#"{"message":"API rate limit exceeded"}"#.to_vec())).unwrap();
+
+        let gh = Client::new_with_cassette(None, cassette);
+        match gh.get().path("users").arg("octocat").send().await {
+            Err(GHError::Response { status, message }) => {
+                assert_eq!(StatusCode::FORBIDDEN, status);
+                assert_eq!("API rate limit exceeded", message.unwrap());
+            },
+            _ => panic!("should fail"),
+        }
+        // the reset time has passed, so there's no need to wait
+        assert_eq!(0, gh.inner.budget.wait_sec(0));
+    }
+
+    #[tokio::test]
+    async fn replay_not_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let cassette = Cassette::new(dir.path(), CassetteMode::Replay);
+        cassette.save(&Recording::new("https://api.github.com/repos/a/b", 304, vec![("etag".into(), "\"abc\"".into())], vec![])).unwrap();
+
+        let gh = Client::new_with_cassette(None, cassette);
+        gh.set_default_priority(Priority::Background);
+        let res = gh.get().path("repos").arg("a").arg("b").if_none_match("\"abc\"").send().await.unwrap();
+        assert!(res.is_not_modified());
+        assert_eq!(Some("\"abc\""), res.etag());
+    }
+
     #[test]
     fn parse_next_link_test() {
         let example = "\"<https://api.github.com/organizations/fakeid/repos?page=1>; rel=\"prev\", <https://api.github.com/organizations/fakeid/repos?page=3>; rel=\"next\", <https://api.github.com/organizations/fakeid/repos?page=38>; rel=\"last\", <https://api.github.com/organizations/fakeid/repos?page=1>; rel=\"first\"";
@@ -0,0 +1,158 @@
+//! Records HTTP responses to a directory ("cassette"), and serves them back later,
+//! so that tests of HTTP clients don't need network access and always see the same data.
+//!
+//! Each response is saved as a JSON file named after the request URL.
+//! Request headers (such as auth tokens) are never saved.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Make real requests, and save all responses (overwriting old ones)
+    Record,
+    /// Never use the network. Requests that haven't been recorded fail with `Error::NotRecorded`.
+    Replay,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No recording for {0} in the cassette")]
+    NotRecorded(String),
+    #[error("Cassette I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Bad recording: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    dir: PathBuf,
+    mode: Mode,
+}
+
+/// A saved response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// UTF-8 bodies are stored as-is to keep recordings readable and editable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_hex: Option<String>,
+}
+
+impl Recording {
+    pub fn new(url: impl Into<String>, status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> Self {
+        let (body, body_hex) = match String::from_utf8(body) {
+            Ok(s) => (Some(s), None),
+            Err(e) => (None, Some(hex::encode(e.as_bytes()))),
+        };
+        Self { url: url.into(), status, headers, body, body_hex }
+    }
+
+    pub fn body(&self) -> Vec<u8> {
+        match (&self.body, &self.body_hex) {
+            (Some(s), _) => s.as_bytes().to_vec(),
+            (None, Some(h)) => hex::decode(h).unwrap_or_default(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Headers that can't be represented in HTTP are skipped
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers.iter().filter_map(|(k, v)| {
+            Some((HeaderName::from_bytes(k.as_bytes()).ok()?, HeaderValue::from_str(v).ok()?))
+        }).collect()
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+impl Cassette {
+    pub fn new(dir: impl Into<PathBuf>, mode: Mode) -> Self {
+        Self { dir: dir.into(), mode }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Sends the request, or returns its recording in the replay mode.
+    ///
+    /// Error statuses are recorded too, so it's up to the caller to check `status`.
+    pub async fn send(&self, client: &reqwest::Client, req: reqwest::Request) -> Result<Recording, Error> {
+        let url = req.url().to_string();
+        match self.mode {
+            Mode::Replay => self.load(&url),
+            Mode::Record => {
+                let res = client.execute(req).await?;
+                let status = res.status().as_u16();
+                let headers = res.headers().iter()
+                    .filter(|(k, _)| *k != reqwest::header::SET_COOKIE)
+                    .filter_map(|(k, v)| Some((k.as_str().to_owned(), v.to_str().ok()?.to_owned())))
+                    .collect();
+                let rec = Recording::new(url, status, headers, res.bytes().await?.to_vec());
+                self.save(&rec)?;
+                Ok(rec)
+            },
+        }
+    }
+
+    pub fn load(&self, url: &str) -> Result<Recording, Error> {
+        let data = match fs::read(self.path_for_url(url)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NotRecorded(url.into())),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Can be used to write recordings by hand, e.g. for responses that are hard to get from a real server
+    pub fn save(&self, rec: &Recording) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path_for_url(&rec.url), serde_json::to_vec_pretty(rec)?)?;
+        Ok(())
+    }
+
+    /// Readable name with a hash, because URLs may be too long or differ only in characters that aren't allowed in file names
+    fn path_for_url(&self, url: &str) -> PathBuf {
+        let readable: String = url.split_once("://").map_or(url, |(_, rest)| rest)
+            .chars().take(100)
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{readable}-{:016x}.json", fnv1a(url.as_bytes())))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Stable across Rust versions, unlike `DefaultHasher`
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x100000001b3))
+}
+
+#[test]
+fn replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let c = Cassette::new(dir.path(), Mode::Replay);
+    c.save(&Recording::new("https://example.com/a?b=c", 404, vec![("x-test".into(), "1".into())], b"gone".to_vec())).unwrap();
+    c.save(&Recording::new("https://example.com/bin", 200, vec![], vec![0xFF, 0])).unwrap();
+
+    let rec = c.load("https://example.com/a?b=c").unwrap();
+    assert_eq!(404, rec.status);
+    assert_eq!(b"gone", &rec.body()[..]);
+    assert_eq!("1", rec.header_map()["x-test"]);
+    assert_eq!(vec![0xFF, 0], c.load("https://example.com/bin").unwrap().body());
+    assert!(matches!(c.load("https://example.com/a?b=d"), Err(Error::NotRecorded(_))));
+}
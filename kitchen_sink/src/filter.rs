@@ -1,9 +1,16 @@
+use ahash::HashSet;
 use fetcher::Fetcher;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::{AnimationDecoder, DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageOutputFormat};
+use log::{debug, warn};
+use parking_lot::Mutex;
 use render_readme::ImageFilter;
-use simple_cache::{Error, TempCacheJson};
+use simple_cache::{Error, TempCache, TempCacheJson};
 use tokio::time::timeout;
 use std::borrow::Cow;
-use std::path::Path;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -75,3 +82,272 @@ impl ImageFilter for ImageOptimAPIFilter {
         Some((width, height))
     }
 }
+
+/// Links images directly, for sites that can't use an external image proxy
+pub struct NoImageFilter;
+
+impl ImageFilter for NoImageFilter {
+    fn filter_url<'a>(&self, url: &'a str) -> (Cow<'a, str>, Option<Cow<'a, str>>) {
+        (url.into(), None)
+    }
+
+    fn image_size(&self, _image_url: &str) -> Option<(u32, u32)> {
+        None
+    }
+}
+
+/// Bigger downloads are rejected
+const MAX_IMAGE_BYTES: usize = 8 << 20;
+/// Bigger images are rejected without decoding them
+const MAX_IMAGE_DIMENSION: u32 = 8000;
+/// Width × height × frames of animations, since all frames are decoded at once
+const MAX_GIF_PIXELS: u64 = 50_000_000;
+/// Wider images are assumed to be for high-DPI displays, and get a half-size 1x variant
+const MAX_1X_WIDTH: u32 = 800;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImageProxyError {
+    #[error("image fetch failed: {0}")]
+    Fetch(#[from] fetcher::Error),
+    #[error("bad image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("image is too large")]
+    TooLarge,
+    #[error("not a supported image type")]
+    Unsupported,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Cache(#[from] Error),
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug)]
+struct ProxiedImage {
+    /// Of the 1x variant
+    width: u32,
+    height: u32,
+    has_2x: bool,
+}
+
+/// Fetches images and serves them from our own server, without any external image service.
+///
+/// Images are re-encoded, which strips all metadata. Served from `url_prefix` + name returned by `filter_url`.
+/// Images on private networks are not fetched.
+pub struct LocalImageProxy {
+    url_prefix: String,
+    /// key -> original URL
+    urls: TempCache<String>,
+    images: Arc<ImageStore>,
+    handle: tokio::runtime::Handle,
+}
+
+/// Re-encoded images, shared with fetches started in the background by `image_size`
+struct ImageStore {
+    images_dir: PathBuf,
+    /// key -> dimensions, `None` if it's not an image we can use
+    processed: TempCache<Option<ProxiedImage>>,
+    fetcher: Arc<Fetcher>,
+    /// keys of images being fetched in the background
+    in_progress: Mutex<HashSet<String>>,
+}
+
+impl LocalImageProxy {
+    pub async fn new(url_prefix: &str, images_dir: PathBuf) -> Result<Self, ImageProxyError> {
+        fs::create_dir_all(&images_dir)?;
+        Ok(Self {
+            url_prefix: url_prefix.into(),
+            urls: TempCache::new(images_dir.join("urls"), Duration::ZERO)?,
+            images: Arc::new(ImageStore {
+                processed: TempCache::new(images_dir.join("processed"), Duration::from_secs(3600*24*31))?,
+                images_dir,
+                fetcher: Arc::new(Fetcher::new_public_only(8)),
+                in_progress: Mutex::default(),
+            }),
+            handle: tokio::runtime::Handle::current(),
+        })
+    }
+
+    fn key_for_url(url: &str) -> String {
+        blake3::hash(url.as_bytes()).to_hex()[..32].to_owned()
+    }
+
+    /// File name to serve (e.g. `abc` or `abc@2x`), or `None` if the image can't be proxied
+    pub async fn image_file(&self, name: &str) -> Option<(Vec<u8>, &'static str)> {
+        let key = name.strip_suffix("@2x").unwrap_or(name);
+        if key.len() != 32 || !key.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        if self.images.processed.get(key).ok()?.is_none() {
+            let url = self.urls.get(key).ok()??;
+            self.images.process(key, &url).await.map_err(|e| warn!("{url}: {e}")).ok()??;
+        }
+        // not all images are large enough for 2x
+        [name, key].iter().find_map(|name| {
+            [("png", "image/png"), ("jpg", "image/jpeg"), ("gif", "image/gif")].iter()
+                .find_map(|&(ext, mime)| Some((fs::read(self.images.images_dir.join(format!("{name}.{ext}"))).ok()?, mime)))
+        })
+    }
+}
+
+impl ImageStore {
+
+    async fn process(&self, key: &str, url: &str) -> Result<Option<ProxiedImage>, ImageProxyError> {
+        let data = match self.fetcher.fetch_limited(url, MAX_IMAGE_BYTES).await {
+            Ok(data) => data,
+            Err(fetcher::Error::TooLarge) => Err(ImageProxyError::TooLarge),
+            Err(fetcher::Error::NotPublic(_)) => Err(ImageProxyError::Unsupported),
+            Err(e) => return Err(e.into()),
+        };
+        let img = match data.and_then(|data| self.process_data(key, &data)) {
+            Ok(img) => Some(img),
+            Err(e @ (ImageProxyError::Image(_) | ImageProxyError::TooLarge | ImageProxyError::Unsupported)) => {
+                debug!("can't proxy {url}: {e}");
+                None
+            },
+            Err(e) => return Err(e),
+        };
+        self.processed.set(key, img)?;
+        Ok(img)
+    }
+
+    fn process_data(&self, key: &str, data: &[u8]) -> Result<ProxiedImage, ImageProxyError> {
+        if data.len() > MAX_IMAGE_BYTES {
+            return Err(ImageProxyError::TooLarge);
+        }
+        let format = image::guess_format(data).map_err(|_| ImageProxyError::Unsupported)?;
+        let mut reader = image::io::Reader::with_format(Cursor::new(data), format);
+        let mut limits = image::io::Limits::default();
+        limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+        limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+        reader.limits(limits);
+
+        match format {
+            // re-encoded frame by frame to keep the animation, but drop comments and application extensions
+            ImageFormat::Gif => {
+                let decoder = GifDecoder::new(Cursor::new(data))?;
+                let (width, height) = decoder.dimensions();
+                if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+                    return Err(ImageProxyError::TooLarge);
+                }
+                let frame_pixels = u64::from(width) * u64::from(height);
+                let mut frames = Vec::new();
+                for frame in decoder.into_frames() {
+                    if (frames.len() as u64 + 1) * frame_pixels > MAX_GIF_PIXELS {
+                        return Err(ImageProxyError::TooLarge);
+                    }
+                    frames.push(frame?);
+                }
+                self.write_file(&format!("{key}.gif"), |w| {
+                    let mut encoder = GifEncoder::new(w);
+                    encoder.set_repeat(Repeat::Infinite)?;
+                    encoder.encode_frames(frames)
+                })?;
+                return Ok(ProxiedImage { width, height, has_2x: false });
+            },
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP => {},
+            _ => return Err(ImageProxyError::Unsupported),
+        }
+
+        let img = reader.decode()?;
+        let jpeg = format == ImageFormat::Jpeg && !img.color().has_alpha();
+        let has_2x = img.width() > MAX_1X_WIDTH;
+        let img = if img.width() > MAX_1X_WIDTH * 2 {
+            img.resize(MAX_1X_WIDTH * 2, MAX_IMAGE_DIMENSION, image::imageops::FilterType::Lanczos3)
+        } else { img };
+        let img1x = if has_2x {
+            self.write_image(&format!("{key}@2x"), &img, jpeg)?;
+            img.resize(img.width() / 2, img.height(), image::imageops::FilterType::Lanczos3)
+        } else { img };
+        self.write_image(key, &img1x, jpeg)?;
+        let (width, height) = img1x.dimensions();
+        Ok(ProxiedImage { width, height, has_2x })
+    }
+
+    fn write_image(&self, name: &str, img: &DynamicImage, jpeg: bool) -> Result<(), ImageProxyError> {
+        let (ext, format) = if jpeg { ("jpg", ImageOutputFormat::Jpeg(85)) } else { ("png", ImageOutputFormat::Png) };
+        self.write_file(&format!("{name}.{ext}"), |w| img.write_to(&mut Cursor::new(w), format))
+    }
+
+    fn write_file(&self, file_name: &str, write: impl FnOnce(&mut Vec<u8>) -> Result<(), image::ImageError>) -> Result<(), ImageProxyError> {
+        let mut out = Vec::new();
+        write(&mut out)?;
+        let path = self.images_dir.join(file_name);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+impl ImageFilter for LocalImageProxy {
+    fn filter_url<'a>(&self, url: &'a str) -> (Cow<'a, str>, Option<Cow<'a, str>>) {
+        // SVG can't be re-encoded, and badges should stay up to date
+        let is_svg = url.starts_with("https://img.shields.io/") || url.split(['?', '#']).next().unwrap_or("").ends_with(".svg");
+        if is_svg || !(url.starts_with("https://") || url.starts_with("http://")) {
+            return (url.into(), None);
+        }
+        let key = Self::key_for_url(url);
+        let has_2x = match self.images.processed.get(key.as_str()) {
+            Ok(Some(Some(img))) => img.has_2x,
+            // it's not usable, so at least don't pretend it's ours
+            Ok(Some(None)) => return (url.into(), None),
+            _ => {
+                if let Err(e) = self.urls.set(key.as_str(), url.to_owned()) {
+                    warn!("image url cache: {e}");
+                }
+                true
+            },
+        };
+        (
+            format!("{}{key}", self.url_prefix).into(),
+            has_2x.then(|| format!("{}{key}@2x 2x", self.url_prefix).into()),
+        )
+    }
+
+    fn image_size(&self, image_url: &str) -> Option<(u32, u32)> {
+        let key = match image_url.strip_prefix(&self.url_prefix) {
+            Some(key) => key.trim_end_matches("@2x").to_owned(),
+            None => Self::key_for_url(image_url),
+        };
+        if let Some(img) = self.images.processed.get(key.as_str()).ok()? {
+            return img.map(|img| (img.width, img.height));
+        }
+        // rendering can't wait for the download, so the size will be known the next time the page is rendered
+        let url = self.urls.get(key.as_str()).ok()??;
+        if self.images.in_progress.lock().insert(key.clone()) {
+            let images = self.images.clone();
+            self.handle.spawn(async move {
+                if let Err(e) = images.process(&key, &url).await {
+                    warn!("image req of {url} failed: {e}");
+                }
+                images.in_progress.lock().remove(&key);
+            });
+        }
+        None
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn local_proxy_variants() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let proxy = LocalImageProxy::new("/img/", dir.to_owned()).await.unwrap();
+    let mut png = Vec::new();
+    DynamicImage::new_rgb8(1000, 500).write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png).unwrap();
+    let img = proxy.images.process_data("0123456789abcdef0123456789abcdef", &png).unwrap();
+    assert!(img.has_2x);
+    assert_eq!((500, 250), (img.width, img.height));
+    assert!(dir.join("0123456789abcdef0123456789abcdef@2x.png").exists());
+    assert!(matches!(proxy.images.process_data("x", b"<svg></svg>"), Err(ImageProxyError::Unsupported)));
+
+    let mut gif = Vec::new();
+    let frames = (0..2).map(|_| image::Frame::new(image::RgbaImage::new(20, 10)));
+    GifEncoder::new(&mut gif).encode_frames(frames).unwrap();
+    let trailer = gif.pop().unwrap();
+    gif.extend_from_slice(b"\x21\xfe\x07comment\x00");
+    gif.push(trailer);
+    let img = proxy.images.process_data("fedcba9876543210fedcba9876543210", &gif).unwrap();
+    assert_eq!((20, 10), (img.width, img.height));
+    let saved = fs::read(dir.join("fedcba9876543210fedcba9876543210.gif")).unwrap();
+    assert!(!saved.windows(7).any(|w| w == b"comment"));
+}
@@ -5,7 +5,11 @@ extern crate serde_derive;
 extern crate log;
 
 mod ablocklist;
+mod name_index;
+mod typosquat;
 mod yearly;
+mod msrv_blame;
+mod msrv_pins;
 use crate_db::builddb::RustcMinorVersion;
 use crate_git_checkout::FoundManifest;
 use event_log::EventLog;
@@ -16,7 +20,12 @@ use tokio::task::block_in_place;
 use tokio::time::Instant;
 
 pub use crate::ablocklist::*;
+pub use crate::name_index::*;
+pub use crate::typosquat::*;
 pub use crate::yearly::*;
+pub use crate::msrv_blame::{MsrvBlame, MsrvBlameDep};
+use crate::msrv_blame::{BlameCrateData, MsrvBlameMemo};
+pub use crate::msrv_pins::{MsrvPin, MsrvPins, ProjectFile, UnsatisfiableDep};
 pub use deps_index::*;
 use futures::future::BoxFuture;
 use futures::FutureExt;
@@ -27,10 +36,15 @@ mod ctrlcbreak;
 pub use crate::ctrlcbreak::*;
 mod nonblock;
 pub use crate::nonblock::*;
+mod site_config;
+pub use crate::site_config::*;
 
 pub use crate_db::builddb::Compat;
 pub use crate_db::builddb::CompatByCrateVersion;
 pub use crate_db::builddb::CompatRanges;
+pub use crate_db::builddb::FeatureCompatInfo;
+pub use crate_db::builddb::FeatureSet;
+pub use crate_db::CrateDerivedData;
 pub use crate_db::CrateOwnerRow;
 pub use crates_io_client::CrateDepKind;
 pub use crates_io_client::CrateDependency;
@@ -43,6 +57,7 @@ pub use creviews::Rating;
 pub use creviews::Review;
 pub use creviews::security::Advisory;
 pub use creviews::security::Severity;
+pub use github_info::Priority as GitHubPriority;
 pub use github_info::Org;
 pub use github_info::User;
 pub use github_info::UserOrg;
@@ -62,6 +77,8 @@ pub use rich_crate::{Cfg, Target};
 pub use semver::Version as SemVer;
 
 use tarball::CrateFilesSummary;
+use tarball::ApiSurface;
+pub use tarball::{ApiChange, SemverViolation};
 use cargo_toml::Manifest;
 use cargo_toml::Package;
 use categories::Category;
@@ -168,11 +185,24 @@ pub enum Warning {
     SysNoLinks,
     #[error("Squatted name")]
     Reserved,
+    #[error("Name could be confused with {} ({})", _0, _1)]
+    ConfusableName(Box<str>, ConfusionKind),
+    /// version, previous version, number of changes
+    #[error("Version {} has breaking API changes since {}", _0, _1)]
+    SemverBreak(Box<str>, Box<str>, u32),
+    #[error("Dependency {} {} matches only yanked versions", _0, _1)]
+    YankedDependency(Box<str>, Box<str>),
     #[error("License is not an SPDX expression")]
     LicenseSpdxSyntax,
     /// last arg is severity 1-n
     #[error("It's been {} days since the last {}release", _0, if *_1 {"stable "} else {"pre"})]
-    StaleRelease(u32, bool, u8)
+    StaleRelease(u32, bool, u8),
+    /// feature set, rustc version
+    #[error("Build with {} fails with Rust 1.{}", _0, _1)]
+    FeatureSetBuildFails(Box<str>, u16),
+    /// dependency with wrong lower bound (if known), rustc version
+    #[error("Build with minimal versions of dependencies fails with Rust 1.{}{}", _1, _0.as_ref().map(|d| format!(" ({d})")).unwrap_or_default())]
+    MinimalVersionsBuildFails(Option<Box<str>>, u16),
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -205,6 +235,8 @@ pub enum KitchenSinkErr {
     CratesDataDirEnvVarMissing,
     #[error("{} does not exist\nPlease get data files from https://lib.rs/data and put them in that directory, or set CRATES_DATA_DIR to their location.", _0)]
     CacheDbMissing(String),
+    #[error("Bad site config {0}")]
+    BadSiteConfig(String),
     #[error("Error when parsing verison")]
     SemverParsingError,
     #[error("Db error {}", _0)]
@@ -265,6 +297,8 @@ pub type StatsHistogram = HashMap<u32, (u32, Vec<String>)>;
 pub struct KitchenSink {
     pub index: Arc<Index>,
     crates_io: crates_io_client::CratesIoClient,
+    /// Shared with `crates_io`, for downloads from other registries
+    fetcher: std::sync::Arc<fetcher::Fetcher>,
     docs_rs: docs_rs_client::DocsRsClient,
     url_check_cache: TempCache<(bool, u8)>,
     readme_check_cache: TempCache<()>,
@@ -275,6 +309,8 @@ pub struct KitchenSink {
     gh: github_info::GitHub,
     loaded_rich_crate_version_cache: RwLock<FxHashMap<Origin, ArcRichCrateVersion>>,
     category_crate_counts: DoubleCheckedCell<Option<HashMap<String, (u32, f64)>>>,
+    crate_name_index: DoubleCheckedCell<CrateNameIndex>,
+    name_confusion_detector: DoubleCheckedCell<NameConfusionDetector>,
     top_crates_cached: Mutex<FxHashMap<String, Arc<DoubleCheckedCell<Arc<Vec<Origin>>>>>>,
     git_checkout_path: PathBuf,
     yearly: AllDownloads,
@@ -288,13 +324,22 @@ pub struct KitchenSink {
     rustsec: Arc<Mutex<creviews::security::RustSec>>,
     crate_rustc_compat_cache: RwLock<HashMap<Origin, CompatByCrateVersion>>,
     crate_rustc_compat_db: OnceCell<BuildDb>,
+    /// `all_crate_compat` is slow to load
+    all_crate_compat_cache: RwLock<Option<(Instant, Arc<HashMap<Origin, CompatByCrateVersion>>)>>,
+    /// Held while `all_crate_compat_cache` is being reloaded, so that it's loaded only once
+    all_crate_compat_loading: parking_lot::Mutex<()>,
     data_path: PathBuf,
     /// login -> reason
     ablocklist: ABlockList,
     event_log: EventLog<SharedEvent>,
     is_deprecated_crate: TempCache<()>,
+    /// name-version
+    api_surfaces: TempCache<Option<ApiSurface>>,
+    config: SiteConfig,
 }
 
+/// Persisted in the event log and read by older builds too, so new variants may only be appended,
+/// and payloads must stay small.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SharedEvent {
     // Origin serialized
@@ -303,24 +348,38 @@ pub enum SharedEvent {
     CrateNeedsReindexing(String),
     /// Newer crates.io release found
     CrateUpdated(String),
-    DailyStatsUpdated,
+    /// New crates.io data dump has been ingested. Timestamp of the run, see `daily_stats_changes()` for the crates.
+    DailyStatsUpdated(String),
+}
+
+/// Crates with changed downloads, owners, or dependers in the last data dump run
+#[derive(Serialize, Deserialize)]
+struct DailyStatsChanges {
+    updated_at: String,
+    origins: Vec<String>,
 }
 
 impl KitchenSink {
     /// Use env vars to find data directory and config
     pub async fn new_default() -> CResult<Self> {
-        let github_token = match env::var("GITHUB_TOKEN") {
-            Ok(t) => t,
-            Err(_) => {
-                warn!("warning: Environment variable GITHUB_TOKEN is not set.\nGet token from https://github.com/settings/tokens and export GITHUB_TOKEN=…\nWithout it some requests will fail and new crates won't be analyzed properly.");
-                "".to_owned()
-            },
-        };
         let data_path = Self::data_path().context("can't get data path")?;
-        Self::new(&data_path, &github_token).await
+        let config = SiteConfig::load(&data_path)?;
+        if config.github.enabled && config.github_token().is_none() {
+            warn!("warning: Environment variable GITHUB_TOKEN is not set.\nGet token from https://github.com/settings/tokens and export GITHUB_TOKEN=…\nWithout it some requests will fail and new crates won't be analyzed properly.\nSet `enabled = false` in the [github] section of site.toml to run without GitHub.");
+        }
+        Self::new_with_config(&data_path, config).await
     }
 
+    /// Uses `site.toml` from the `data_path`, if any, with the given token unless the config has one
     pub async fn new(data_path: &Path, github_token: &str) -> CResult<Self> {
+        let mut config = SiteConfig::load(data_path)?;
+        if config.github.token.is_none() && !github_token.is_empty() {
+            config.github.token = Some(github_token.to_owned());
+        }
+        Self::new_with_config(data_path, config).await
+    }
+
+    pub async fn new_with_config(data_path: &Path, config: SiteConfig) -> CResult<Self> {
         let _ = env_logger::Builder::from_default_env()
             .filter(Some("html5ever"), log::LevelFilter::Error)
             .filter(Some("html5ever::tree_builder"), log::LevelFilter::Error)
@@ -328,15 +387,27 @@ impl KitchenSink {
             .filter(Some("tantivy"), log::LevelFilter::Error)
             .try_init();
 
+        let fetcher = std::sync::Arc::new(fetcher::Fetcher::new(4));
         let ((crates_io, gh), (index, (crev, rustsec))) = tokio::task::spawn_blocking({
             let data_path = data_path.to_owned();
-            let github_token = github_token.to_owned();
+            let fetcher = fetcher.clone();
+            let github_tokens = config.github_tokens();
+            let github_enabled = config.github.enabled;
+            let crates_io_enabled = config.crates_io.enabled;
+            let offline = config.offline;
             let ghdb = data_path.join("github.db");
             move || {
                 rayon::join(|| rayon::join(
-                    || crates_io_client::CratesIoClient::new(&data_path),
-                    move || github_info::GitHub::new(&ghdb, &github_token)),
-                || rayon::join(|| Index::new(&data_path), || rayon::join(Creviews::new, || creviews::security::RustSec::new(&data_path))))
+                    || crates_io_client::CratesIoClient::new_with_fetcher(&data_path, fetcher).map(|mut c| { c.cache_only(!crates_io_enabled || offline); c }),
+                    move || {
+                        let tokens: Vec<_> = github_tokens.iter().map(|t| t.as_str()).collect();
+                        github_info::GitHub::new_with_tokens(&ghdb, &tokens).map(|mut gh| { gh.cache_only(!github_enabled); gh })
+                    }),
+                || rayon::join(|| Index::new_with_crates_io(&data_path, crates_io_enabled), || rayon::join(Creviews::new, || if offline {
+                    creviews::security::RustSec::new_local(&data_path)
+                } else {
+                    creviews::security::RustSec::new(&data_path)
+                })))
             }
         }).await?;
 
@@ -346,6 +417,7 @@ impl KitchenSink {
             crev: Arc::new(crev.context("crev")?),
             rustsec: Arc::new(Mutex::new(rustsec.map_err(std::sync::Arc::new)?)),
             crates_io: crates_io.context("crates_io")?,
+            fetcher,
             index: Arc::new(index?),
             url_check_cache: TempCache::new(&data_path.join("url_check2.db"), Duration::from_secs(3600*24*11)).context("urlcheck")?,
             readme_check_cache: TempCache::new(&data_path.join("readme_check.db"), Duration::from_secs(3600*24*31*6)).context("readmecheck")?,
@@ -358,6 +430,8 @@ impl KitchenSink {
             loaded_rich_crate_version_cache: RwLock::new(FxHashMap::default()),
             git_checkout_path: data_path.join("git"),
             category_crate_counts: DoubleCheckedCell::new(),
+            crate_name_index: DoubleCheckedCell::new(),
+            name_confusion_detector: DoubleCheckedCell::new(),
             top_crates_cached: Mutex::new(FxHashMap::default()),
             yearly: AllDownloads::new(data_path),
             category_overrides: Self::load_category_overrides(&data_path.join("category_overrides.txt")).context("cat")?,
@@ -366,15 +440,28 @@ impl KitchenSink {
             depender_changes: TempCache::new(&data_path.join("deps-changes3.tmp"), Duration::ZERO).context("tmp2")?,
             stats_histograms: TempCache::new(&data_path.join("stats-histograms.tmp"), Duration::from_secs(3600*24*31*3)).context("tmp3")?,
             is_deprecated_crate: TempCache::new(&data_path.join("deprecated.tmp"), Duration::ZERO).context("tmpdp")?,
+            api_surfaces: TempCache::new(&data_path.join("api-surfaces.tmp"), Duration::ZERO).context("tmpapi")?,
             throttle: tokio::sync::Semaphore::new(40),
             auto_indexing_throttle: tokio::sync::Semaphore::new(4),
             crate_rustc_compat_cache: RwLock::default(),
             crate_rustc_compat_db: OnceCell::new(),
+            all_crate_compat_cache: RwLock::default(),
+            all_crate_compat_loading: parking_lot::Mutex::new(()),
             event_log: EventLog::new(data_path.join("event_log.db")).context("events")?,
             data_path: data_path.into(),
+            config,
         }))
     }
 
+    pub fn site_config(&self) -> &SiteConfig {
+        &self.config
+    }
+
+    /// Bulk jobs should set `Background`, so that they leave enough of the GitHub rate limit for the site
+    pub fn set_github_priority(&self, priority: GitHubPriority) {
+        self.gh.set_priority(priority);
+    }
+
     fn assert_exists(path: PathBuf) -> Result<PathBuf, KitchenSinkErr> {
         if !path.exists() {
             Err(KitchenSinkErr::CacheDbMissing(path.display().to_string()))
@@ -457,6 +544,70 @@ impl KitchenSink {
         (all, out)
     }
 
+    /// Popular crates that this crate's name could be mistaken for, riskiest first.
+    ///
+    /// Crates co-owned by owners of the popular crate (like `tokio-macros`) are not reported.
+    pub async fn name_confusions(&self, origin: &Origin) -> CResult<Vec<NameConfusion>> {
+        let name = match origin {
+            Origin::CratesIo(name) => name,
+            _ => return Ok(vec![]),
+        };
+        let detector = self.name_confusion_detector.get_or_try_init(async {
+            block_in_place(|| {
+                let popular = self.most_popular_crates(5000)?;
+                Ok::<_, CError>(NameConfusionDetector::new(popular.iter().map(|(o, score)| (o.short_crate_name(), *score))))
+            })
+        }).await?;
+
+        let found = detector.check(name);
+        if found.is_empty() {
+            return Ok(found);
+        }
+        // the popular crate is established too, so it's a coincidence, not imitation
+        if self.downloads_per_month(origin).await?.map_or(false, |dl| dl >= typosquat::ESTABLISHED_MONTHLY_DOWNLOADS) {
+            return Ok(vec![]);
+        }
+
+        let mut out = Vec::new();
+        for c in found {
+            let popular = match Origin::try_from_crates_io_name(&c.similar_to) {
+                Some(o) => o,
+                None => continue,
+            };
+            let (common_owners, _) = self.common_real_owners(&popular, origin).await?;
+            if common_owners > 0 {
+                continue;
+            }
+            // companion crates are often owned via a team, or by owners without a known GitHub account
+            if typosquat::is_companion_crate(name, &c.similar_to) && self.have_common_owner_login(&popular, origin).await? {
+                continue;
+            }
+            out.push(c);
+        }
+        Ok(out)
+    }
+
+    /// Recently published crates with names imitating popular crates, for moderation.
+    /// Riskiest first, with their owners (to add to the `ablocklist.csv` if needed).
+    pub async fn name_confusion_report(&self, days: u32, min_risk: f64) -> CResult<Vec<(Origin, NameConfusion, Vec<CrateOwner>)>> {
+        let new_crates = self.crate_db.newly_added_crates(days, 10000).await?;
+        let mut out = Vec::new();
+        for (origin, _) in new_crates {
+            let top = match self.name_confusions(&origin).await?.into_iter().next() {
+                Some(c) if c.risk >= min_risk => c,
+                _ => continue,
+            };
+            let owners = self.crate_owners(&origin, CrateOwners::Strict).await?;
+            // already dealt with
+            if !owners.is_empty() && owners.iter().all(|o| self.crates_io_login_on_blocklist(&o.crates_io_login).is_some()) {
+                continue;
+            }
+            out.push((origin, top, owners));
+        }
+        out.sort_unstable_by(|a, b| b.1.risk.total_cmp(&a.1.risk));
+        Ok(out)
+    }
+
     /// Don't make requests to crates.io
     pub fn cache_only(&mut self, no_net: bool) -> &mut Self {
         self.crates_io.cache_only(no_net);
@@ -479,6 +630,20 @@ impl KitchenSink {
         self.index.crates_io_crates()
     }
 
+    /// Crates-io crates with names that differ only by a typo, `-`/`_`, or affixes like `-rs`.
+    /// Most similar and popular first.
+    pub async fn similar_crate_names(&self, name: &str, limit: usize) -> CResult<Vec<SimilarCrateName>> {
+        let index = self.crate_name_index.get_or_try_init(async {
+            let stats = self.index.deps_stats().await.map_err(KitchenSinkErr::Deps)?;
+            Ok::<_, KitchenSinkErr>(block_in_place(|| {
+                CrateNameIndex::new(self.all_crates_io_crates().keys().map(|name| {
+                    (name.as_str(), stats.counts.get(name).map_or(0, |r| r.direct.all()))
+                }))
+            }))
+        }).await?;
+        Ok(block_in_place(|| index.similar(name, limit)))
+    }
+
     pub fn total_year_downloads(&self, year: u16) -> KResult<[u64; 366]> {
         Ok(self.yearly.total_year_downloads(year)?)
     }
@@ -715,7 +880,7 @@ impl KitchenSink {
             .buffer_unordered(8)
             .filter_map(|x| async {x});
         let mut crates = stream.filter(move |k| {
-            let latest = k.versions().iter().map(|v| &v.created_at).max();
+            let latest = k.most_recent_release();
             let res = if let Some(timestamp) = latest {
                 timestamp.timestamp() >= min_timestamp as i64
             } else {
@@ -757,8 +922,8 @@ impl KitchenSink {
                 let meta = self.crates_io_meta(name).await?;
                 let versions = meta.versions().map(|c| Ok(CrateVersion {
                     num: c.num,
-                    updated_at: DateTime::parse_from_rfc3339(&c.updated_at)?.with_timezone(&Utc),
-                    created_at: DateTime::parse_from_rfc3339(&c.created_at)?.with_timezone(&Utc),
+                    updated_at: Some(DateTime::parse_from_rfc3339(&c.updated_at)?.with_timezone(&Utc)),
+                    created_at: Some(DateTime::parse_from_rfc3339(&c.created_at)?.with_timezone(&Utc)),
                     yanked: c.yanked,
                 })).collect::<Result<_,chrono::ParseError>>()?;
                 Ok(RichCrate::new(origin.clone(), meta.krate.name, versions))
@@ -769,9 +934,29 @@ impl KitchenSink {
             Origin::GitLab { repo, package } => {
                 watch("repocrate2", self.rich_crate_gitlab(origin, repo, package)).await
             },
+            Origin::Registry { registry, package } => {
+                self.rich_crate_registry(origin, registry, package).await
+            },
         }
     }
 
+    async fn rich_crate_registry(&self, origin: &Origin, registry: &str, package: &str) -> CResult<RichCrate> {
+        let krate = self.index.registry_crate(origin).map_err(KitchenSinkErr::Deps)?;
+        // the index has no dates, so they're from when we've first seen the version.
+        // Versions that haven't been indexed yet are undated.
+        let first_seen: HashMap<_, _> = self.crate_db.crate_versions(origin).await?.into_iter().collect();
+        let versions = krate.versions().iter().map(|v| {
+            let date = first_seen.get(v.version()).map(|&timestamp| Utc.timestamp(timestamp as _, 0));
+            CrateVersion {
+                num: v.version().into(),
+                yanked: v.is_yanked(),
+                updated_at: date,
+                created_at: date,
+            }
+        }).collect();
+        Ok(RichCrate::new(origin.clone(), format!("{registry}/{package}").into(), versions))
+    }
+
     async fn rich_crate_gh(&self, origin: &Origin, repo: &SimpleRepo, package: &str) -> CResult<RichCrate> {
         let host = Repo::GitHub(repo.clone()).try_into().map_err(|_| KitchenSinkErr::CrateNotFound(origin.clone())).context("ghrepo host bad")?;
         let cachebust = self.cachebust_string_for_repo(&host).await.context("ghrepo")?;
@@ -803,8 +988,8 @@ impl KitchenSink {
                     Some(CrateVersion {
                         num: num.into(),
                         yanked: r.draft.unwrap_or(false),
-                        updated_at: date,
-                        created_at: date,
+                        updated_at: Some(date),
+                        created_at: Some(date),
                     })
                 }).collect();
                 if !versions.is_empty() {
@@ -816,7 +1001,7 @@ impl KitchenSink {
         };
 
         let versions: Vec<_> = self.crate_db.crate_versions(origin).await?.into_iter().map(|(num, timestamp)| {
-            let date = Utc.timestamp(timestamp as _, 0);
+            let date = Some(Utc.timestamp(timestamp as _, 0));
             CrateVersion {
                 num,
                 yanked: false,
@@ -837,7 +1022,7 @@ impl KitchenSink {
             let mut pkg_ver = crate_git_checkout::find_versions(&checkout)?;
             if let Some(v) = pkg_ver.remove(&*package) {
                 let versions: Vec<_> = v.into_iter().map(|(num, timestamp)| {
-                    let date = Utc.timestamp(timestamp, 0);
+                    let date = Some(Utc.timestamp(timestamp, 0));
                     CrateVersion {
                         num: num.into(),
                         yanked: false,
@@ -997,6 +1182,15 @@ impl KitchenSink {
                         }
                     }
                 },
+                Origin::Registry { .. } => {
+                    if !allow_stale {
+                        let expected_cache_key = self.index.cache_key_for_origin(origin).context("error finding registry index data")?;
+                        if expected_cache_key != cached.cache_key {
+                            info!("Ignoring derived cache of {:?}, because it changed", origin);
+                            maybe_data = None;
+                        }
+                    }
+                },
                 _ => {}, // TODO: figure out when to invalidate cache of git-repo crates
             }
         }
@@ -1124,6 +1318,62 @@ impl KitchenSink {
         Ok(meta)
     }
 
+    /// Public API of the crate's library, `None` if it has no lib or it couldn't be parsed
+    async fn api_surface(&self, name: &str, ver: &str) -> CResult<Option<ApiSurface>> {
+        let key = format!("{name}-{ver}");
+        if let Some(surface) = self.api_surfaces.get(key.as_str())? {
+            return Ok(surface);
+        }
+        let files = self.crate_files_summary_from_crates_io_tarball(name, ver).await?;
+        let surface = spawn_blocking(move || files.api_surface()).await?;
+        self.api_surfaces.set(key, &surface)?;
+        Ok(surface)
+    }
+
+    /// Releases that have been published as semver-compatible with the previous release, but changed or removed public items.
+    ///
+    /// Compares up to `max_pairs` most recent pairs of consecutive non-yanked releases. Newest first.
+    pub async fn semver_violations(&self, all: &RichCrate, max_pairs: usize) -> CResult<Vec<SemverViolation>> {
+        if !matches!(all.origin(), Origin::CratesIo(_)) {
+            return Ok(vec![]);
+        }
+        // keeps the original strings, because they're the tarballs' names
+        let mut versions: Vec<_> = all.versions().iter()
+            .filter(|v| !v.yanked)
+            .filter_map(|v| Some((SemVer::parse(&v.num).ok()?, v.num.as_str())))
+            .filter(|(v, _)| v.pre.is_empty())
+            .collect();
+        versions.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let pairs: Vec<_> = versions.windows(2).rev().take(max_pairs)
+            .filter(|pair| tarball::is_compatible_upgrade(&pair[0].0, &pair[1].0))
+            .map(|pair| (pair[0].1, pair[1].1))
+            .collect();
+
+        // consecutive pairs share versions, and each surface is cached, so fetch every version once, in parallel
+        let mut to_fetch: Vec<_> = pairs.iter().flat_map(|&(older, newer)| [older, newer]).collect();
+        to_fetch.sort_unstable();
+        to_fetch.dedup();
+        let surfaces: HashMap<&str, ApiSurface> = join_all(to_fetch.into_iter().map(|ver| async move {
+            let surface = timeout("api surface", 20, self.api_surface(all.name(), ver)).await
+                .map_err(|e| debug!("api surface of {} {ver}: {e}", all.name())).ok().flatten()?;
+            Some((ver, surface))
+        })).await.into_iter().flatten().collect();
+
+        let mut out = Vec::new();
+        for (older_str, newer_str) in pairs {
+            let (older_api, newer_api) = match (surfaces.get(older_str), surfaces.get(newer_str)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            let changes = older_api.breaking_changes(newer_api);
+            if !changes.is_empty() {
+                out.push(SemverViolation { prev_version: older_str.to_string(), version: newer_str.to_string(), changes });
+            }
+        }
+        Ok(out)
+    }
+
     async fn rich_crate_version_data_from_crates_io(&self, latest: &CratesIndexVersion) -> CResult<(CrateVersionSourceData, Manifest, Warnings)> {
         debug!("Building whole crate {}", latest.name());
 
@@ -1201,6 +1451,45 @@ impl KitchenSink {
         watch("data-common", self.rich_crate_version_data_common(origin, meta, latest.is_yanked(), warnings)).await
     }
 
+    async fn rich_crate_version_data_from_registry(&self, origin: &Origin) -> CResult<(CrateVersionSourceData, Manifest, Warnings)> {
+        let registry = origin.registry_name().and_then(|r| self.index.registry(r)).ok_or_else(|| KitchenSinkErr::CrateNotFound(origin.clone()))?;
+        let latest = self.index.registry_crate_highest_version(origin, false).map_err(KitchenSinkErr::Deps)?;
+        debug!("Building whole crate {:?}", origin);
+
+        let _f = timeout("data-throttle", 28, self.throttle.acquire().map_err(|_| KitchenSinkErr::DataTimedOut)).await;
+        if stopped() {return Err(KitchenSinkErr::Stopped.into());}
+
+        let name = latest.name().to_owned();
+        let ver = latest.version().to_owned();
+        let tarball = timeout("tarball fetch", 16, registry.download(&self.fetcher, latest)
+            .map_err(|e| KitchenSinkErr::DataNotFound(format!("{name}-{ver}: {e}")))).await?;
+        let mut meta = timeout("untar1", 40, spawn_blocking({
+                let name = name.clone();
+                let ver = ver.clone();
+                move || tarball::read_archive(&tarball[..], &name, &ver)
+            })
+            .map_err(|e| KitchenSinkErr::Internal(std::sync::Arc::new(e)))).await?
+            .map_err(|e| KitchenSinkErr::UnarchiverError(format!("{name}-{ver}"), Arc::new(e)))?;
+
+        let package = meta.manifest.package.as_ref().ok_or_else(|| KitchenSinkErr::NotAPackage(origin.clone()))?;
+        let mut warnings = HashSet::new();
+        if package.repository().is_none() {
+            warnings.insert(Warning::NoRepositoryProperty);
+        }
+        let maybe_repo = package.repository().and_then(|r| Repo::new(r).ok());
+        if meta.readme.is_none() {
+            warnings.insert(if package.readme().is_some() { Warning::NoReadmePackaged } else { Warning::NoReadmeProperty });
+            warnings.extend(Box::pin(self.add_readme_from_repo(&mut meta, maybe_repo.as_ref())).await);
+        }
+        if meta.path_in_repo.is_none() {
+            if let Some(r) = maybe_repo.as_ref() {
+                meta.path_in_repo = self.crate_db.path_in_repo(r, &name).await?;
+            }
+        }
+
+        watch("data-common", self.rich_crate_version_data_common(origin.clone(), meta, latest.is_yanked(), warnings)).await
+    }
+
     ///// Fixing and faking the data
     async fn rich_crate_version_data_common(&self, origin: Origin, mut meta: CrateFilesSummary, is_yanked: bool, mut warnings: Warnings) -> CResult<(CrateVersionSourceData, Manifest, Warnings)> {
         if stopped() {return Err(KitchenSinkErr::Stopped.into());}
@@ -1665,7 +1954,7 @@ impl KitchenSink {
 
     /// name is case-sensitive!
     pub async fn has_docs_rs(&self, origin: &Origin, name: &str, ver: &str) -> bool {
-        if !origin.is_crates_io() {
+        if !origin.is_crates_io() || !self.config.docs_rs.enabled {
             return false;
         }
         watch("builds", self.docs_rs.builds(name, ver)).await.unwrap_or(true) // fail open
@@ -1701,6 +1990,27 @@ impl KitchenSink {
         }
     }
 
+    /// Resolves all transitive dependencies to concrete versions from the local index, like Cargo would for a new project.
+    ///
+    /// Tarball sizes are filled in only for crates whose latest version has been indexed already, since it doesn't fetch anything.
+    pub async fn resolve_dependencies(&self, origin: &Origin, version: Option<&str>, query: &ResolveQuery) -> CResult<Resolution> {
+        let name = match origin {
+            Origin::CratesIo(name) => name,
+            _ => return Err(KitchenSinkErr::NoVersions.into()),
+        };
+        let mut res = block_in_place(|| self.index.resolve_dependencies(name, version, query)).map_err(KitchenSinkErr::Deps)?;
+        for p in &mut res.packages {
+            let origin = Origin::from_crates_io_name(&p.name);
+            if let Ok(Some(cached)) = self.rich_crate_version_data_derived(&origin).await {
+                let cached_version = cached.manifest.package.as_ref().map(|p| p.version());
+                if cached_version.and_then(|v| SemVer::parse(v).ok()).as_ref() == Some(&p.version) {
+                    p.tarball_size = Some(cached.derived.crate_compressed_size.into());
+                }
+            }
+        }
+        Ok(res)
+    }
+
     #[inline]
     fn iter_crates_io_version_matching_requirement_by_lowercase_name(&self, crate_name: &str, req: &str) -> Result<impl Iterator<Item=(SemVer, &CratesIndexVersion)> + '_, KitchenSinkErr> {
         assert!(crate_name.as_bytes().iter().all(|c| !c.is_ascii_uppercase()));
@@ -1753,15 +2063,50 @@ impl KitchenSink {
     }
 
     pub async fn update(&self) {
+        if self.config.offline {
+            info!("offline, not updating indexes");
+            return;
+        }
         let crev = self.crev.clone();
         let rustsec = self.rustsec.clone();
         rayon::spawn(move || {
             let _ = rustsec.lock().unwrap().update().map_err(|e| error!("crev update: {e}"));
             let _ = crev.update().map_err(|e| error!("crev update: {e}"));
         });
+        // the sparse index can't be listed, so on first start it's seeded with crates we've seen before
+        if self.crates_io_index_needs_seeding() {
+            match self.crate_db.crates_io_crate_names().await {
+                Ok(names) => {
+                    info!("Seeding the sparse crates index with {} crates", names.len());
+                    self.index.update_crates_io_crates(names.iter().map(|n| n.as_str())).await;
+                },
+                Err(e) => error!("can't seed the sparse index: {e}"),
+            }
+        }
         self.index.update().await;
     }
 
+    /// The sparse crates.io index is empty until `update()` fetches crates known from the crate db
+    pub fn crates_io_index_needs_seeding(&self) -> bool {
+        !self.config.offline && self.index.is_crates_io_sparse() && self.index.crates_io_crates().is_empty()
+    }
+
+    /// Needed only when using the sparse crates.io index, which can't discover new or changed crates by itself
+    pub async fn update_crates_io_crates(&self, names: impl Iterator<Item = &str>) {
+        if self.config.offline {
+            return;
+        }
+        self.index.update_crates_io_crates(names).await;
+    }
+
+    /// Rechecks every crate in the sparse crates.io index, in addition to the named ones. Slow.
+    pub async fn update_all_crates_io_crates<'a>(&'a self, names: impl Iterator<Item = &'a str>) {
+        if self.config.offline {
+            return;
+        }
+        self.index.update_all_crates_io_crates(names).await;
+    }
+
     pub async fn crates_io_all_rev_deps_counts(&self) -> Result<StatsHistogram, KitchenSinkErr> {
         let stats = self.index.deps_stats().await.map_err(KitchenSinkErr::Deps)?;
         let mut tmp = HashMap::new();
@@ -1827,8 +2172,12 @@ impl KitchenSink {
         } else {
             false
         };
+        let only_yanked = self.index.requirement_matches_only_yanked(&origin.short_crate_name().to_ascii_lowercase(), requirement).unwrap_or(false);
+        if only_yanked {
+            pop = 0.;
+        }
         Ok(Some(VersionPopularity {
-            lost_popularity, pop, matches_latest, deprecated
+            lost_popularity, pop, matches_latest, deprecated, only_yanked,
         }))
     }
 
@@ -2197,6 +2546,11 @@ impl KitchenSink {
                 let res = watch("reindexing-repodata", self.rich_crate_version_from_repo(origin)).await?;
                 (res, 0)
             },
+            Origin::Registry { .. } => {
+                let cache_key = self.index.cache_key_for_origin(origin)?;
+                let res = watch("reindexing-registry-data", self.rich_crate_version_data_from_registry(origin)).await.context("rich_crate_version_data_from_registry")?;
+                (res, cache_key)
+            },
         };
         Ok((source_data, manifest, warnings, cache_key))
     }
@@ -2408,6 +2762,23 @@ impl KitchenSink {
         Ok(u)
     }
 
+    /// Features that builds of this crate version failed on, with Rust versions that stabilized them (if known), newest first
+    pub fn rustc_features_setting_msrv(&self, origin: &Origin, ver: &SemVer) -> Result<Vec<(String, Option<RustcMinorVersion>)>, KitchenSinkErr> {
+        self.build_db()?.features_setting_msrv(origin, ver)
+            .map_err(|_| KitchenSinkErr::BadRustcCompatData)
+    }
+
+    /// Feature sets (or minimal versions of dependencies) that don't build, as checked with the newest rustc
+    pub fn feature_set_build_failures(&self, origin: &Origin, ver: &SemVer) -> Result<Vec<FeatureCompatInfo>, KitchenSinkErr> {
+        let mut res = self.build_db()?.get_feature_compat(origin)
+            .map_err(|_| KitchenSinkErr::BadRustcCompatData)?;
+        res.retain(|r| r.crate_version == *ver);
+        // sorted by newest rustc first
+        res.dedup_by(|b, a| a.feature_set == b.feature_set);
+        res.retain(|r| !r.compat.successful());
+        Ok(res)
+    }
+
     pub fn rustc_compatibility_no_deps(&self, all: &RichCrate) -> Result<CompatByCrateVersion, KitchenSinkErr> {
         let db = self.build_db()?;
         self.rustc_compatibility_inner_non_recursive(all, db)
@@ -2430,8 +2801,7 @@ impl KitchenSink {
             };
             let c = c.entry(semver).or_insert_with(Default::default);
 
-            let created = ver.created_at;
-            if let Some(expected_rust) = Self::rustc_release_from_date(&created) {
+            if let Some(expected_rust) = ver.created_at.and_then(|created| Self::rustc_release_from_date(&created)) {
                 c.add_compat(expected_rust, Compat::ProbablyWorks, Some("Assumed from release date".into()));
             }
         }
@@ -2515,6 +2885,75 @@ impl KitchenSink {
         Ok(self.rustc_compatibility_inner(all, in_progress, 0).await?.unwrap())
     }
 
+    /// Explains which dependencies raise the MSRV of this crate version, and suggests dependency versions that work with the `target` Rust version.
+    /// The default target is the MSRV of the crate's own code.
+    pub async fn msrv_blame(&self, all: &RichCrate, ver: &SemVer, target: Option<RustcMinorVersion>) -> Result<MsrvBlame, KitchenSinkErr> {
+        let own_msrv = self.rustc_compatibility_no_deps(all)?
+            .get(ver).and_then(CompatRanges::msrv);
+        let msrv = self.rustc_compatibility(all).await?
+            .get(ver).and_then(CompatRanges::msrv);
+        let target = target.or(own_msrv);
+        let deps = match target {
+            Some(target) => self.msrv_blame_deps(all.origin(), ver, target, 3, &MsrvBlameMemo::default()).await,
+            None => Vec::new(),
+        };
+        Ok(MsrvBlame { own_msrv, msrv, target, deps })
+    }
+
+    async fn msrv_blame_crate_data(&self, origin: &Origin, memo: &MsrvBlameMemo) -> Option<Arc<BlameCrateData>> {
+        if let Some(data) = memo.crates.lock().get(origin) {
+            return data.clone();
+        }
+        let data = async {
+            let c = self.rich_crate_async(origin).await.ok()?;
+            let compat = self.rustc_compatibility(&c).await.ok()?;
+            let own_compat = self.rustc_compatibility_no_deps(&c).ok()?;
+            let yanked = c.versions().iter().filter(|v| v.yanked).filter_map(|v| SemVer::parse(&v.num).ok()).collect();
+            Some(Arc::new(BlameCrateData { compat, own_compat, yanked }))
+        }.await;
+        memo.crates.lock().insert(origin.clone(), data.clone());
+        data
+    }
+
+    fn msrv_blame_deps<'a>(&'a self, origin: &'a Origin, ver: &'a SemVer, target: RustcMinorVersion, depth: u8, memo: &'a MsrvBlameMemo) -> BoxFuture<'a, Vec<MsrvBlameDep>> { async move {
+        let memo_key = (origin.clone(), ver.clone(), depth);
+        if let Some(blamed) = memo.deps.lock().get(&memo_key) {
+            return blamed.clone();
+        }
+        let versions = match self.all_crates_io_versions(origin) {
+            Ok(v) => v,
+            Err(_) => return Vec::new(),
+        };
+        let v = match versions.iter().find(|v| SemVer::parse(v.version()).map_or(false, |v| &v == ver)) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        // same deps as checked by rustc_compatibility
+        let deps = v.dependencies().iter()
+            .filter(|dep| !dep.is_optional() && dep.kind() != DependencyKind::Dev && dep.target().is_none())
+            .filter_map(|dep| Some((Origin::from_crates_io_name(dep.crate_name()), VersionReq::parse(dep.requirement()).ok()?)))
+            .filter(|(dep_origin, _)| dep_origin != origin);
+
+        let mut blamed: Vec<_> = futures::future::join_all(deps.map(|(dep_origin, req)| async move {
+            let data = self.msrv_blame_crate_data(&dep_origin, memo).await?;
+            let blame = msrv_blame::blame_dependency(dep_origin.short_crate_name(), &req, &data.compat, &data.yanked, target)?;
+            // the dependency may be fine by itself, and only its dependencies need a newer Rust
+            let own_code_works = data.own_compat.get(&blame.newest).and_then(CompatRanges::msrv).map_or(true, |m| m <= target);
+            Some((blame, dep_origin, own_code_works))
+        })).await.into_iter().flatten().collect();
+        blamed.sort_by(|(a, ..), (b, ..)| b.newest_msrv.cmp(&a.newest_msrv).then_with(|| a.name.cmp(&b.name)));
+
+        if depth > 0 {
+            // only the worst ones, because the number of transitive dependencies grows quickly
+            for (blame, dep_origin, _) in blamed.iter_mut().filter(|(.., own_code_works)| *own_code_works).take(msrv_blame::MAX_CAUSES) {
+                blame.caused_by = self.msrv_blame_deps(dep_origin, &blame.newest, target, depth - 1, memo).await;
+            }
+        }
+        let blamed: Vec<_> = blamed.into_iter().map(|(blame, ..)| blame).collect();
+        memo.deps.lock().insert(memo_key, blamed.clone());
+        blamed
+    }.boxed()}
+
     /// Relaxes heuristics to run more builds
     pub async fn rustc_compatibility_for_builder(&self, all: &RichCrate, min_relevant_rustc: u16) -> Result<CompatByCrateVersion, KitchenSinkErr> {
         let in_progress = Arc::new(Mutex::new(HashSet::new()));
@@ -2529,6 +2968,59 @@ impl KitchenSink {
         Ok(all)
     }
 
+    /// `all_crate_compat`, reloaded at most every hour
+    fn all_crate_compat_cached(&self) -> CResult<Arc<HashMap<Origin, CompatByCrateVersion>>> {
+        let fresh = || match &*self.all_crate_compat_cache.read() {
+            Some((loaded, all)) if loaded.elapsed() < Duration::from_secs(3600) => Some(Arc::clone(all)),
+            _ => None,
+        };
+        if let Some(all) = fresh() {
+            return Ok(all);
+        }
+        let _loading = self.all_crate_compat_loading.lock();
+        // another thread may have loaded it while this one was waiting
+        if let Some(all) = fresh() {
+            return Ok(all);
+        }
+        let all = Arc::new(self.all_crate_compat()?);
+        *self.all_crate_compat_cache.write() = Some((Instant::now(), Arc::clone(&all)));
+        Ok(all)
+    }
+
+    /// Dependency versions to pin, so that a project with this `Cargo.toml` or `Cargo.lock` builds with Rust 1.`rustc`.
+    ///
+    /// Blocks for a long time when the build data isn't loaded yet.
+    pub fn msrv_pins(&self, project_file: ProjectFile<'_>, rustc: RustcMinorVersion) -> CResult<MsrvPins> {
+        let compat = self.all_crate_compat_cached()?;
+        let versions_of = |name: &str| -> Vec<msrv_pins::IndexVersion> {
+            self.all_crates_io_versions(&Origin::from_crates_io_name(name)).unwrap_or_default().iter().filter_map(|v| Some(msrv_pins::IndexVersion {
+                version: v.version().parse().ok()?,
+                yanked: v.is_yanked(),
+                deps: v.dependencies().iter()
+                    .filter(|d| !d.is_optional() && d.kind() != DependencyKind::Dev)
+                    .filter_map(|d| Some((d.crate_name().to_owned(), d.requirement().parse().ok()?)))
+                    .collect(),
+            })).collect()
+        };
+        Ok(match project_file {
+            ProjectFile::Manifest(cargo_toml) => msrv_pins::pins_for_manifest(cargo_toml, rustc, &compat, &versions_of)?,
+            ProjectFile::Lockfile(cargo_lock) => msrv_pins::pins_for_lockfile(cargo_lock, rustc, &compat, &versions_of)?,
+        })
+    }
+
+    /// Build results of all crates, without anything inferred from dependencies or other versions
+    pub fn all_rustc_compat(&self) -> Result<HashMap<Origin, CompatByCrateVersion>, KitchenSinkErr> {
+        let mut all = self.build_db()?.get_all_compat_by_crate().map_err(|_| KitchenSinkErr::BadRustcCompatData)?;
+        for compat in all.values_mut() {
+            compat.values_mut().for_each(|c| c.normalize());
+            // same as get_compat: if it never built, it may be garbage data
+            if !compat.values().any(|c| c.has_ever_built()) {
+                compat.values_mut().for_each(|c| c.remove_uncertain_self_failures());
+            }
+        }
+        Ok(all)
+    }
+
     fn build_db(&self) -> Result<&BuildDb, KitchenSinkErr> {
         if stopped() {return Err(KitchenSinkErr::Stopped);}
 
@@ -2796,6 +3288,12 @@ impl KitchenSink {
     }
 
     /// (common, out of how many). A is considered more important, b is matched against it.
+    /// Compares crates.io logins, including teams
+    async fn have_common_owner_login(&self, a: &Origin, b: &Origin) -> CResult<bool> {
+        let (a_owners, b_owners) = futures::try_join!(self.crate_owners(a, CrateOwners::Strict), self.crate_owners(b, CrateOwners::Strict))?;
+        Ok(a_owners.iter().any(|a| b_owners.iter().any(|b| a.crates_io_login.eq_ignore_ascii_case(&b.crates_io_login))))
+    }
+
     async fn common_real_owners(&self, a: &Origin, b: &Origin) -> CResult<(usize, usize)> {
         let (a_owners, b_owners) = futures::try_join!(self.crate_owners(a, CrateOwners::Strict), self.crate_owners(b, CrateOwners::Strict))?;
         let a_owners: HashSet<_> = a_owners.into_iter().filter(|o| self.crates_io_login_on_blocklist(&o.crates_io_login).is_none()).filter_map(|o| o.github_id).collect();
@@ -3190,7 +3688,7 @@ impl KitchenSink {
                     res
                 }
             },
-            Origin::GitLab {..} => vec![],
+            Origin::GitLab {..} | Origin::Registry {..} => vec![],
             Origin::GitHub {repo, ..} => vec![
                 CrateOwner {
                     avatar: None,
@@ -3650,6 +4148,32 @@ impl KitchenSink {
         Ok(self.crate_db.recently_updated_crates_in_category(slug).await?)
     }
 
+    /// Saves the list of crates with changed stats, and tells other processes about it.
+    /// The list is too long for the event log, so the event only carries the timestamp.
+    pub fn post_daily_stats_updated(&self, origins: &[Origin]) -> CResult<()> {
+        let changes = DailyStatsChanges {
+            updated_at: Utc::now().to_rfc3339(),
+            origins: origins.iter().map(|o| o.to_str()).collect(),
+        };
+        let path = self.data_path.join("daily_stats_changes.json");
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&changes)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        self.event_log.post(&SharedEvent::DailyStatsUpdated(changes.updated_at))?;
+        Ok(())
+    }
+
+    /// Crates announced by `SharedEvent::DailyStatsUpdated` with this timestamp.
+    /// `None` if the list is missing or has already been replaced by a newer run.
+    pub fn daily_stats_changes(&self, updated_at: &str) -> Option<Vec<Origin>> {
+        let json = std::fs::read(self.data_path.join("daily_stats_changes.json")).ok()?;
+        let changes: DailyStatsChanges = serde_json::from_slice(&json).ok()?;
+        if changes.updated_at != updated_at {
+            return None;
+        }
+        Some(changes.origins.iter().map(|o| Origin::from_str(o)).collect())
+    }
+
     /// Case sensitive!
     pub fn rustacean_for_github_login(&self, login: &str) -> Option<Rustacean> {
         if !is_alnum(login) {
@@ -3715,6 +4239,7 @@ impl KitchenSink {
         let _ = self.readme_check_cache.save();
         let _ = self.yearly.save();
         self.crate_rustc_compat_cache.write().clear();
+        *self.all_crate_compat_cache.write() = None;
         self.crates_io.cleanup();
         self.loaded_rich_crate_version_cache.write().clear();
         self.index.clear_cache();
@@ -3754,6 +4279,8 @@ pub struct VersionPopularity {
     pub matches_latest: bool,
     pub lost_popularity: bool,
     pub deprecated: bool,
+    /// All matching versions have been yanked
+    pub only_yanked: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,124 @@
+use crate::CompatByCrateVersion;
+use crate::CompatRanges;
+use crate::Origin;
+use crate::SemVer;
+use ahash::HashMap;
+use ahash::HashSet;
+use crate_db::builddb::RustcMinorVersion;
+use parking_lot::Mutex;
+use semver::VersionReq;
+use std::sync::Arc;
+
+/// Why a crate version needs the Rust version it needs
+#[derive(Debug, Clone, Default)]
+pub struct MsrvBlame {
+    /// Oldest Rust that can build the crate's own code, ignoring dependencies
+    pub own_msrv: Option<RustcMinorVersion>,
+    /// Oldest Rust that can build the crate with its dependencies
+    pub msrv: Option<RustcMinorVersion>,
+    /// Rust version that the suggested dependency versions are for
+    pub target: Option<RustcMinorVersion>,
+    /// Dependencies that need a newer Rust than the `target`, worst first
+    pub deps: Vec<MsrvBlameDep>,
+}
+
+/// A dependency which pushes the MSRV up
+#[derive(Debug, Clone)]
+pub struct MsrvBlameDep {
+    pub name: String,
+    pub req: VersionReq,
+    /// Newest version matching the requirement, which Cargo picks by default
+    pub newest: SemVer,
+    pub newest_msrv: RustcMinorVersion,
+    /// Newest version matching the requirement that works with the target Rust version.
+    /// If `None`, the requirement itself needs to change.
+    pub compatible: Option<SemVer>,
+    /// Newest version that works with the target Rust version, even if it doesn't match the requirement
+    pub compatible_any: Option<SemVer>,
+    /// When the dependency's own code is fine, these are its dependencies that need newer Rust
+    pub caused_by: Vec<MsrvBlameDep>,
+}
+
+/// Dependencies of a blamed dependency are explained only for this many of the worst ones
+pub(crate) const MAX_CAUSES: usize = 5;
+
+/// Data of one crate in the dependency tree
+pub(crate) struct BlameCrateData {
+    /// Including dependencies
+    pub compat: CompatByCrateVersion,
+    /// Of the crate's own code
+    pub own_compat: CompatByCrateVersion,
+    pub yanked: HashSet<SemVer>,
+}
+
+/// The same crates appear many times in a dependency tree, so they're looked up only once per `msrv_blame`
+#[derive(Default)]
+pub(crate) struct MsrvBlameMemo {
+    pub crates: Mutex<HashMap<Origin, Option<Arc<BlameCrateData>>>>,
+    /// crate, version, depth
+    pub deps: Mutex<HashMap<(Origin, SemVer, u8), Vec<MsrvBlameDep>>>,
+}
+
+/// `None` if the version of the dependency that Cargo would pick works with the `target` Rust version
+pub fn blame_dependency(name: &str, req: &VersionReq, dep_compat: &CompatByCrateVersion, yanked: &HashSet<SemVer>, target: RustcMinorVersion) -> Option<MsrvBlameDep> {
+    // Cargo won't pick yanked versions, so they can't be suggested either
+    let usable = || dep_compat.iter().rev().filter(|&(v, _)| !yanked.contains(v));
+    let (newest, newest_compat) = usable().find(|(v, _)| req.matches(v))?;
+    let newest_msrv = newest_compat.msrv()?;
+    if newest_msrv <= target {
+        return None;
+    }
+
+    let works = |c: &CompatRanges| c.msrv().map_or(true, |m| m <= target);
+    let compatible = usable()
+        .find(|&(v, c)| req.matches(v) && works(c))
+        .map(|(v, _)| v.clone());
+    let compatible_any = usable()
+        .find(|&(v, c)| v.pre.is_empty() && works(c))
+        .map(|(v, _)| v.clone());
+
+    Some(MsrvBlameDep {
+        name: name.into(),
+        req: req.clone(),
+        newest: newest.clone(),
+        newest_msrv,
+        compatible,
+        compatible_any,
+        caused_by: Vec::new(),
+    })
+}
+
+#[test]
+fn blames_newest_matching() {
+    use crate_db::builddb::Compat;
+
+    let mut dep_compat = CompatByCrateVersion::new();
+    let mut add = |ver: &str, newest_bad| {
+        let mut c = CompatRanges::default();
+        c.add_compat(newest_bad, Compat::DefinitelyIncompatible, None);
+        dep_compat.insert(ver.parse().unwrap(), c);
+    };
+    add("1.0.0", 40);
+    add("1.1.0", 50);
+    add("1.2.0", 59);
+    add("2.0.0", 30);
+
+    let req = VersionReq::parse("1.0").unwrap();
+    let no_yanked = HashSet::default();
+    assert!(blame_dependency("dep", &req, &dep_compat, &no_yanked, 60).is_none());
+
+    let b = blame_dependency("dep", &req, &dep_compat, &no_yanked, 51).unwrap();
+    assert_eq!("1.2.0", b.newest.to_string());
+    assert_eq!(60, b.newest_msrv);
+    assert_eq!("1.1.0", b.compatible.unwrap().to_string());
+    assert_eq!("2.0.0", b.compatible_any.unwrap().to_string());
+
+    let b = blame_dependency("dep", &req, &dep_compat, &no_yanked, 35).unwrap();
+    assert!(b.compatible.is_none());
+    assert_eq!("2.0.0", b.compatible_any.unwrap().to_string());
+
+    let yanked = ["1.1.0".parse().unwrap(), "1.2.0".parse().unwrap()].into_iter().collect();
+    assert!(blame_dependency("dep", &req, &dep_compat, &yanked, 45).is_none());
+    let b = blame_dependency("dep", &req, &dep_compat, &yanked, 35).unwrap();
+    assert_eq!("1.0.0", b.newest.to_string());
+}
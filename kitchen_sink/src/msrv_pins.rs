@@ -0,0 +1,367 @@
+use crate::CompatByCrateVersion;
+use crate::Origin;
+use crate::SemVer;
+use ahash::HashMap;
+use ahash::HashMapExt;
+use ahash::HashSet;
+use ahash::HashSetExt;
+use crate_db::builddb::RustcMinorVersion;
+use semver::VersionReq;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// `Cargo.toml` or `Cargo.lock` of a project that needs to build with an older Rust
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectFile<'a> {
+    Manifest(&'a str),
+    Lockfile(&'a str),
+}
+
+impl<'a> ProjectFile<'a> {
+    pub fn detect(text: &'a str) -> Self {
+        if text.contains("[[package]]") {
+            Self::Lockfile(text)
+        } else {
+            Self::Manifest(text)
+        }
+    }
+}
+
+/// A version of a crate from the registry index
+#[derive(Debug, Clone)]
+pub struct IndexVersion {
+    pub version: SemVer,
+    pub yanked: bool,
+    /// Non-optional normal and build dependencies
+    pub deps: Vec<(String, VersionReq)>,
+}
+
+/// Dependency versions to pin, so that the project builds with the `rustc` version
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MsrvPins {
+    pub rustc: RustcMinorVersion,
+    pub pins: Vec<MsrvPin>,
+    /// Requirements that no compatible version matches. These need to be changed in `Cargo.toml` of the crate that has them.
+    pub unsatisfiable: Vec<UnsatisfiableDep>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MsrvPin {
+    pub name: String,
+    /// Version that Cargo picks (or has locked)
+    pub from: SemVer,
+    pub to: SemVer,
+}
+
+impl MsrvPin {
+    pub fn cargo_update_command(&self) -> String {
+        format!("cargo update -p {}:{} --precise {}", self.name, self.from, self.to)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnsatisfiableDep {
+    pub name: String,
+    pub req: VersionReq,
+    /// Crate that has the requirement, `None` for the project itself
+    pub required_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: SemVer,
+    source: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// Assumes that versions newer than a failing one fail too, because MSRV rarely goes down
+pub fn works_with(compat: Option<&CompatByCrateVersion>, ver: &SemVer, rustc: RustcMinorVersion) -> bool {
+    let compat = match compat {
+        Some(c) => c,
+        None => return true,
+    };
+    if let Some(c) = compat.get(ver) {
+        if let Some(bad) = c.newest_bad() {
+            return bad < rustc;
+        }
+        if c.oldest_ok().map_or(false, |ok| ok <= rustc) {
+            return true;
+        }
+    }
+    compat.range(..=ver).filter_map(|(_, c)| c.newest_bad()).max().map_or(true, |bad| bad < rustc)
+}
+
+/// Versions that can be used together without a semver-incompatible duplicate
+fn semver_compatible(a: &SemVer, b: &SemVer) -> bool {
+    a.major == b.major && (a.major > 0 || (a.minor == b.minor && (a.minor > 0 || a.patch == b.patch)))
+}
+
+/// Limits backtracking in pathological dependency graphs
+const MAX_RESOLVE_STEPS: u32 = 20_000;
+
+/// A requirement to satisfy: crate name, version requirement, and the crate that has it (`None` for the project itself)
+type Requirement = (String, VersionReq, Option<String>);
+
+/// Resolution from requirements in `Cargo.toml`, like Cargo's, but only using versions that work with the `rustc` version.
+///
+/// Optional dependencies are included only if they're enabled by the default features.
+pub fn pins_for_manifest(cargo_toml: &str, rustc: RustcMinorVersion, compat: &HashMap<Origin, CompatByCrateVersion>, versions_of: &dyn Fn(&str) -> Vec<IndexVersion>) -> Result<MsrvPins, cargo_toml::Error> {
+    let manifest = cargo_toml::Manifest::from_str(cargo_toml)?;
+    let enabled = default_features(&manifest.features);
+    let top_level: Vec<Requirement> = manifest.dependencies.iter()
+        .chain(&manifest.build_dependencies)
+        .chain(manifest.target.values().flat_map(|t| t.dependencies.iter().chain(&t.build_dependencies)))
+        .filter(|(_, dep)| dep.is_crates_io())
+        .filter(|(name, dep)| !dep.optional() || enabled.contains(name.as_str()) || enabled.contains(format!("dep:{name}").as_str()))
+        .filter_map(|(name, dep)| {
+            let name = dep.package().unwrap_or(name);
+            Some((name.to_owned(), dep.req().parse().ok()?, None))
+        })
+        .collect();
+
+    let mut resolver = Resolver { rustc, compat, versions_of, versions_cache: HashMap::new(), steps: 0 };
+    let mut res = MsrvPins { rustc, ..Default::default() };
+    let mut chosen: BTreeMap<String, Vec<SemVer>> = BTreeMap::new();
+    // each is resolved separately, so that one unsatisfiable dependency doesn't prevent pinning the others
+    for req in top_level {
+        let mut attempt = chosen.clone();
+        match resolver.resolve(vec![req], &mut attempt) {
+            Ok(()) => chosen = attempt,
+            Err(unsatisfiable) => res.unsatisfiable.push(unsatisfiable),
+        }
+    }
+
+    for (name, picked) in chosen {
+        let versions = resolver.versions(&name);
+        for picked in picked {
+            // Cargo would pick the newest version compatible with the requirements
+            let newest = versions.iter().rev().find(|v| !v.yanked && semver_compatible(&v.version, &picked));
+            if let Some(newest) = newest.filter(|n| n.version != picked) {
+                res.pins.push(MsrvPin { name: name.clone(), from: newest.version.clone(), to: picked });
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Features enabled by `default`, including ones enabled by other features
+fn default_features(features: &BTreeMap<String, Vec<String>>) -> HashSet<&str> {
+    let mut enabled = HashSet::new();
+    let mut queue = vec!["default"];
+    while let Some(f) = queue.pop() {
+        if !enabled.insert(f) {
+            continue;
+        }
+        if let Some(enables) = features.get(f) {
+            // "dep/feature" enables the dependency too, but "dep?/feature" doesn't
+            queue.extend(enables.iter().map(|e| e.split_once('/').map_or(e.as_str(), |(dep, _)| dep)).filter(|e| !e.ends_with('?')));
+        }
+    }
+    enabled
+}
+
+struct Resolver<'a> {
+    rustc: RustcMinorVersion,
+    compat: &'a HashMap<Origin, CompatByCrateVersion>,
+    versions_of: &'a dyn Fn(&str) -> Vec<IndexVersion>,
+    versions_cache: HashMap<String, Rc<Vec<IndexVersion>>>,
+    steps: u32,
+}
+
+impl Resolver<'_> {
+    fn versions(&mut self, name: &str) -> Rc<Vec<IndexVersion>> {
+        if let Some(v) = self.versions_cache.get(name) {
+            return Rc::clone(v);
+        }
+        let v = Rc::new((self.versions_of)(name));
+        self.versions_cache.insert(name.to_owned(), Rc::clone(&v));
+        v
+    }
+
+    /// Picks versions for all `pending` requirements and their dependencies, adding them to `chosen`.
+    /// Cargo allows only one version per semver-compatible range of a crate, so versions picked earlier may force backtracking.
+    ///
+    /// On failure `chosen` is left in an unspecified state, and the error is for the first requirement that couldn't be satisfied.
+    fn resolve(&mut self, mut pending: Vec<Requirement>, chosen: &mut BTreeMap<String, Vec<SemVer>>) -> Result<(), UnsatisfiableDep> {
+        while let Some((name, req, required_by)) = pending.pop() {
+            let already_chosen = chosen.get(&name).map(|v| v.as_slice()).unwrap_or_default();
+            if already_chosen.iter().any(|v| req.matches(v)) {
+                continue;
+            }
+            let versions = self.versions(&name);
+            if !versions.iter().any(|v| !v.yanked && req.matches(&v.version)) {
+                continue; // not in the index, Cargo will complain anyway
+            }
+            let crate_compat = self.compat.get(&Origin::from_crates_io_name(&name));
+            let candidates = versions.iter().rev()
+                .filter(|v| !v.yanked && req.matches(&v.version) && works_with(crate_compat, &v.version, self.rustc))
+                // a different version in the same semver-compatible range has already been picked
+                .filter(|v| !already_chosen.iter().any(|c| semver_compatible(c, &v.version)))
+                .collect::<Vec<_>>();
+
+            let mut first_error = None;
+            for candidate in candidates {
+                self.steps += 1;
+                if self.steps > MAX_RESOLVE_STEPS {
+                    break;
+                }
+                let mut attempt = chosen.clone();
+                attempt.entry(name.clone()).or_default().push(candidate.version.clone());
+                let mut attempt_pending = pending.clone();
+                attempt_pending.extend(candidate.deps.iter().rev().map(|(dep, req)| (dep.clone(), req.clone(), Some(name.clone()))));
+                match self.resolve(attempt_pending, &mut attempt) {
+                    Ok(()) => {
+                        *chosen = attempt;
+                        return Ok(());
+                    },
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    },
+                }
+            }
+            // blames the newest version's dependency, unless this crate itself is the problem
+            return Err(first_error.unwrap_or(UnsatisfiableDep { name, req, required_by }));
+        }
+        Ok(())
+    }
+}
+
+/// Downgrades locked registry packages that don't work with the `rustc` version, within requirements of the packages that depend on them
+pub fn pins_for_lockfile(cargo_lock: &str, rustc: RustcMinorVersion, compat: &HashMap<Origin, CompatByCrateVersion>, versions_of: &dyn Fn(&str) -> Vec<IndexVersion>) -> Result<MsrvPins, toml::de::Error> {
+    let lock: Lockfile = toml::from_str(cargo_lock)?;
+    let is_registry = |p: &LockedPackage| p.source.as_deref().map_or(false, |s| s.starts_with("registry+") || s.starts_with("sparse+"));
+
+    let mut versions_cache = HashMap::new();
+    let mut res = MsrvPins { rustc, ..Default::default() };
+    for p in lock.package.iter().filter(|p| is_registry(p)) {
+        let crate_compat = compat.get(&Origin::from_crates_io_name(&p.name));
+        if works_with(crate_compat, &p.version, rustc) {
+            continue;
+        }
+
+        // requirements of registry packages that use this one. Local packages can't be checked.
+        let mut reqs = Vec::new();
+        for dependent in lock.package.iter().filter(|d| is_registry(d) && d.dependencies.iter().any(|dep| depends_on(dep, p))) {
+            let dependent_versions: &Vec<IndexVersion> = versions_cache.entry(dependent.name.clone()).or_insert_with(|| versions_of(&dependent.name));
+            if let Some(v) = dependent_versions.iter().find(|v| v.version == dependent.version) {
+                reqs.extend(v.deps.iter()
+                    .filter(|(name, req)| *name == p.name && req.matches(&p.version))
+                    .map(|(_, req)| (req.clone(), dependent.name.clone())));
+            }
+        }
+
+        let versions: &Vec<IndexVersion> = versions_cache.entry(p.name.clone()).or_insert_with(|| versions_of(&p.name));
+        let picked = versions.iter().rev()
+            .filter(|v| !v.yanked && semver_compatible(&v.version, &p.version))
+            .filter(|v| reqs.iter().all(|(req, _)| req.matches(&v.version)))
+            .find(|v| works_with(crate_compat, &v.version, rustc));
+        match picked {
+            Some(v) => res.pins.push(MsrvPin { name: p.name.clone(), from: p.version.clone(), to: v.version.clone() }),
+            None => {
+                // blame the most restrictive requirement
+                let (req, required_by) = reqs.into_iter()
+                    .max_by_key(|(req, _)| req.comparators.iter().map(|c| (c.major, c.minor, c.patch)).max())
+                    .map_or((None, None), |(req, by)| (Some(req), Some(by)));
+                res.unsatisfiable.push(UnsatisfiableDep {
+                    name: p.name.clone(),
+                    req: req.unwrap_or_else(|| VersionReq::parse(&p.version.to_string()).expect("semver")),
+                    required_by,
+                });
+            },
+        }
+    }
+    Ok(res)
+}
+
+/// Lockfile dependency entries are "name", "name version", or "name version (source)"
+fn depends_on(dep: &str, p: &LockedPackage) -> bool {
+    let mut parts = dep.split(' ');
+    parts.next() == Some(p.name.as_str()) && parts.next().map_or(true, |v| v == p.version.to_string())
+}
+
+#[cfg(test)]
+fn test_index(name: &str) -> Vec<IndexVersion> {
+    let v = |version: &str, deps: &[(&str, &str)]| IndexVersion {
+        version: version.parse().unwrap(),
+        yanked: false,
+        deps: deps.iter().map(|&(n, r)| (n.to_string(), r.parse().unwrap())).collect(),
+    };
+    match name {
+        "app-dep" => vec![v("1.0.0", &[("leaf", "0.3")]), v("1.1.0", &[("leaf", "0.3.5")])],
+        "leaf" => vec![v("0.3.0", &[]), v("0.3.5", &[]), v("0.3.9", &[])],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+fn test_compat() -> HashMap<Origin, CompatByCrateVersion> {
+    use crate::CompatRanges;
+    use crate_db::builddb::Compat;
+
+    let mut leaf = CompatByCrateVersion::new();
+    for (ver, newest_bad) in [("0.3.0", 40), ("0.3.5", 50), ("0.3.9", 60)] {
+        let mut c = CompatRanges::default();
+        c.add_compat(newest_bad, Compat::DefinitelyIncompatible, None);
+        leaf.insert(ver.parse().unwrap(), c);
+    }
+    let mut compat = HashMap::new();
+    compat.insert(Origin::from_crates_io_name("leaf"), leaf);
+    compat
+}
+
+#[test]
+fn manifest_pins() {
+    let toml = "[package]\nname = \"proj\"\nversion = \"0.1.0\"\n[dependencies]\napp-dep = \"1\"\n";
+    let res = pins_for_manifest(toml, 55, &test_compat(), &test_index).unwrap();
+    assert_eq!(1, res.pins.len(), "{res:?}");
+    assert_eq!(("leaf", "0.3.9", "0.3.5"), (res.pins[0].name.as_str(), res.pins[0].from.to_string().as_str(), res.pins[0].to.to_string().as_str()));
+    assert!(res.unsatisfiable.is_empty());
+
+    // app-dep 1.1.0 requires leaf 0.3.5, which is too new, but app-dep 1.0.0 works with leaf 0.3.0
+    let res = pins_for_manifest(toml, 45, &test_compat(), &test_index).unwrap();
+    assert!(res.unsatisfiable.is_empty(), "{res:?}");
+    let pins: Vec<_> = res.pins.iter().map(|p| p.cargo_update_command()).collect();
+    assert_eq!(pins, ["cargo update -p app-dep:1.1.0 --precise 1.0.0", "cargo update -p leaf:0.3.9 --precise 0.3.0"]);
+
+    let res = pins_for_manifest(toml, 35, &test_compat(), &test_index).unwrap();
+    assert!(res.pins.is_empty());
+    assert_eq!(("leaf", "app-dep"), (res.unsatisfiable[0].name.as_str(), res.unsatisfiable[0].required_by.as_deref().unwrap()));
+
+    // optional dependencies are resolved only when a default feature enables them
+    let optional = "[package]\nname = \"proj\"\nversion = \"0.1.0\"\n[dependencies]\nleaf = { version = \"0.3\", optional = true }\n";
+    assert!(pins_for_manifest(optional, 45, &test_compat(), &test_index).unwrap().pins.is_empty());
+    let with_feature = format!("{optional}[features]\ndefault = [\"fast\"]\nfast = [\"leaf/std\"]\n");
+    assert_eq!(1, pins_for_manifest(&with_feature, 45, &test_compat(), &test_index).unwrap().pins.len());
+}
+
+#[test]
+fn lockfile_pins() {
+    let lock = r#"
+[[package]]
+name = "proj"
+version = "0.1.0"
+dependencies = ["app-dep"]
+
+[[package]]
+name = "app-dep"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = ["leaf"]
+
+[[package]]
+name = "leaf"
+version = "0.3.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+    let res = pins_for_lockfile(lock, 45, &test_compat(), &test_index).unwrap();
+    assert_eq!("cargo update -p leaf:0.3.9 --precise 0.3.0", res.pins[0].cargo_update_command());
+    assert!(matches!(ProjectFile::detect(lock), ProjectFile::Lockfile(_)));
+}
@@ -0,0 +1,192 @@
+//! Fuzzy lookup of crate names, for "did you mean" suggestions
+
+use ahash::HashSet;
+use ahash::HashSetExt;
+use ahash::RandomState;
+use smartstring::alias::String as SmolStr;
+use std::cmp::Reverse;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+/// Prefixes and suffixes that don't make a name meaningfully different
+const NAME_AFFIXES: [&str; 4] = ["rust-", "rs-", "-rs", "-rust"];
+
+pub struct CrateNameIndex {
+    names: Vec<NameEntry>,
+    /// Hashes of stems and stems with one character deleted, with indices into `names`. Sorted.
+    /// Any name within one edit shares a key with the query, so there's no need to scan all names.
+    by_deletion: Vec<(u64, u32)>,
+    hasher: RandomState,
+}
+
+struct NameEntry {
+    name: SmolStr,
+    /// hyphens and underscores are interchangeable
+    normalized: SmolStr,
+    /// normalized, without affixes
+    stem: SmolStr,
+    popularity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarCrateName {
+    pub name: SmolStr,
+    /// 0 = only differs by `-`/`_` or case, 1 = only differs by affix or one edit, etc.
+    pub distance: u8,
+    /// Number of direct reverse dependencies
+    pub popularity: u32,
+}
+
+impl CrateNameIndex {
+    /// Names with their popularity (number of reverse dependencies)
+    pub fn new<'a>(names: impl Iterator<Item = (&'a str, u32)>) -> Self {
+        let names: Vec<_> = names.map(|(name, popularity)| {
+            let normalized = normalize_crate_name(name);
+            NameEntry {
+                name: name.into(),
+                stem: strip_name_affixes(&normalized).into(),
+                normalized: normalized.into(),
+                popularity,
+            }
+        }).collect();
+        let hasher = RandomState::new();
+        let mut by_deletion = Vec::with_capacity(names.len() * 12);
+        for (i, e) in names.iter().enumerate() {
+            by_deletion.extend(deletions(&e.stem, 1).into_iter().map(|key| (hash_key(&hasher, &key), i as u32)));
+        }
+        by_deletion.sort_unstable();
+        by_deletion.dedup();
+        Self { names, by_deletion, hasher }
+    }
+
+    /// Crates with similar names, most similar and popular first. Doesn't include the name itself.
+    pub fn similar(&self, name: &str, limit: usize) -> Vec<SimilarCrateName> {
+        let normalized = normalize_crate_name(name);
+        let stem = strip_name_affixes(&normalized);
+        // short names have too many neighbors
+        let max_distance = match stem.len() {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+
+        // names are indexed with one deletion, so with two deletions in the query
+        // this finds two edits only when one of them is an insertion or deletion
+        let mut candidates = HashSet::new();
+        for key in deletions(stem, max_distance) {
+            let key = hash_key(&self.hasher, &key);
+            let start = self.by_deletion.partition_point(|&(k, _)| k < key);
+            candidates.extend(self.by_deletion[start..].iter().take_while(|&&(k, _)| k == key).map(|&(_, i)| i));
+        }
+
+        let mut found: Vec<_> = candidates.into_iter().map(|i| &self.names[i as usize]).filter(|e| e.name.as_str() != name).filter_map(|e| {
+            let distance = if e.normalized == normalized {
+                0
+            } else if e.stem == stem {
+                1
+            } else {
+                edit_distance_at_most(stem, &e.stem, max_distance)?.max(1)
+            };
+            Some(SimilarCrateName { name: e.name.clone(), distance, popularity: e.popularity })
+        }).collect();
+        found.sort_unstable_by(|a, b| (a.distance, Reverse(a.popularity)).cmp(&(b.distance, Reverse(b.popularity))).then_with(|| a.name.cmp(&b.name)));
+        found.truncate(limit);
+        found
+    }
+}
+
+/// Collisions only add candidates, which are checked anyway
+fn hash_key(hasher: &RandomState, key: &str) -> u64 {
+    let mut h = hasher.build_hasher();
+    key.hash(&mut h);
+    h.finish()
+}
+
+/// The string itself and all variants with up to `max` characters removed
+fn deletions(s: &str, max: u8) -> Vec<SmolStr> {
+    let mut out = vec![SmolStr::from(s)];
+    let mut start = 0;
+    for _ in 0..max {
+        let end = out.len();
+        for i in start..end {
+            let chars: Vec<char> = out[i].chars().collect();
+            for skip in 0..chars.len() {
+                let variant: SmolStr = chars.iter().enumerate().filter(|&(n, _)| n != skip).map(|(_, &c)| c).collect();
+                out.push(variant);
+            }
+        }
+        start = end;
+    }
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+/// Lowercase, with `_` replaced by `-`
+pub fn normalize_crate_name(name: &str) -> String {
+    name.chars().map(|c| if c == '_' { '-' } else { c.to_ascii_lowercase() }).collect()
+}
+
+/// Removes `rust-`, `-rs`, etc. from a normalized name
+pub fn strip_name_affixes(normalized_name: &str) -> &str {
+    let mut name = normalized_name;
+    for affix in NAME_AFFIXES {
+        let stripped = if affix.starts_with('-') { name.strip_suffix(affix) } else { name.strip_prefix(affix) };
+        if let Some(stripped) = stripped.filter(|s| !s.is_empty()) {
+            name = stripped;
+        }
+    }
+    name
+}
+
+/// Damerau-Levenshtein (optimal string alignment) distance, or `None` if it's larger than `max`
+pub fn edit_distance_at_most(a: &str, b: &str, max: u8) -> Option<u8> {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let max = max as usize;
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        curr[0] = i;
+        let mut row_min = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(prev2[j - 2] + 1);
+            }
+            curr[j] = d;
+            row_min = row_min.min(d);
+        }
+        if row_min > max {
+            return None;
+        }
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    let d = prev[b.len()];
+    if d <= max { Some(d as u8) } else { None }
+}
+
+#[test]
+fn similar_names() {
+    assert_eq!(Some(1), edit_distance_at_most("serde", "sedre", 2));
+    assert_eq!(Some(2), edit_distance_at_most("tokio", "tkoi", 2));
+    assert_eq!(None, edit_distance_at_most("tokio", "rayon", 2));
+    assert_eq!("image", strip_name_affixes("rust-image-rs"));
+    assert_eq!("rs", strip_name_affixes("rs"));
+
+    let idx = CrateNameIndex::new([("serde_json", 100), ("serde-json-rs", 1), ("serde_yaml", 50), ("sdre", 1000), ("clap", 10)].into_iter());
+    let found: Vec<_> = idx.similar("serde-json", 10).into_iter().map(|f| (f.name, f.distance)).collect();
+    assert_eq!(found, vec![("serde_json".into(), 0), ("serde-json-rs".into(), 1)]);
+    let found: Vec<_> = idx.similar("serde_jsno", 10).into_iter().map(|f| f.name.to_string()).collect();
+    assert_eq!(found, vec!["serde_json", "serde-json-rs"]);
+    assert!(idx.similar("clp", 10).is_empty());
+    let found: Vec<_> = idx.similar("serde_yml", 10).into_iter().map(|f| f.name.to_string()).collect();
+    assert_eq!(found, vec!["serde_yaml"]);
+}
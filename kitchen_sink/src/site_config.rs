@@ -0,0 +1,167 @@
+//! Optional `site.toml` in the data dir (or a file set in `SITE_CONFIG` env var),
+//! which allows running the site without some or all external services, e.g. as an internal crate catalogue:
+//!
+//! ```toml
+//! [github]
+//! enabled = false  # or set `token` instead of the GITHUB_TOKEN env var
+//! tokens = ["…", "…"]  # more tokens to spread the rate limit over
+//!
+//! [crates-io]
+//! enabled = false  # only git crates and registries from registries.toml
+//!
+//! [docs-rs]
+//! enabled = false
+//!
+//! [images]
+//! proxy = "local"
+//! ```
+//!
+//! Disabled services are never contacted. Data that has been cached from them before is still used.
+//!
+//! `offline = true` at the top level disables all of the above, and also stops updates of the crates.io index
+//! and RustSec/crev databases, so the site runs only from what's already in the data dir (used by test fixtures).
+
+use crate::KitchenSinkErr;
+use ahash::HashSet;
+use ahash::HashSetExt;
+use serde_derive::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SiteConfig {
+    /// Never use the network
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default)]
+    pub github: GitHubConfig,
+    #[serde(default)]
+    pub crates_io: ServiceConfig,
+    #[serde(default)]
+    pub docs_rs: ServiceConfig,
+    #[serde(default)]
+    pub images: ImagesConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceConfig {
+    #[serde(default = "yes")]
+    pub enabled: bool,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubConfig {
+    #[serde(default = "yes")]
+    pub enabled: bool,
+    /// Overrides `GITHUB_TOKEN` env var
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Used in addition to `token`. Requests go to the token with the most rate limit left.
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self { enabled: true, token: None, tokens: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImagesConfig {
+    /// `imageoptim` (img.gs API), `local` (fetched and served by our server), or `none` (images are linked directly)
+    #[serde(default = "default_image_proxy")]
+    pub proxy: String,
+    /// From https://imageoptim.com/api/register
+    #[serde(default = "default_imageoptim_id")]
+    pub imageoptim_id: String,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self { proxy: default_image_proxy(), imageoptim_id: default_imageoptim_id() }
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn default_image_proxy() -> String {
+    "imageoptim".into()
+}
+
+fn default_imageoptim_id() -> String {
+    "czjpqfbdkz".into()
+}
+
+impl SiteConfig {
+    /// Defaults (everything enabled) if there's no config file
+    pub fn load(data_path: &Path) -> Result<Self, KitchenSinkErr> {
+        let path = std::env::var_os("SITE_CONFIG").map(From::from).unwrap_or_else(|| data_path.join("site.toml"));
+        match std::fs::read_to_string(&path) {
+            Ok(s) => Self::parse(&s).map_err(|e| KitchenSinkErr::BadSiteConfig(format!("{}: {e}", path.display()))),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, toml::de::Error> {
+        let mut config: Self = toml::from_str(s)?;
+        if config.offline {
+            config.set_offline();
+        }
+        Ok(config)
+    }
+
+    /// Turns off all external services. crates.io stays enabled, but only its cached data is used.
+    pub fn set_offline(&mut self) {
+        self.offline = true;
+        self.github.enabled = false;
+        self.docs_rs.enabled = false;
+        self.images.proxy = "none".into();
+    }
+
+    /// From config or env, `None` if GitHub is disabled
+    pub fn github_token(&self) -> Option<String> {
+        if !self.github.enabled {
+            return None;
+        }
+        self.github.token.clone().or_else(|| std::env::var("GITHUB_TOKEN").ok()).filter(|t| !t.is_empty())
+    }
+
+    /// All tokens to rotate, empty if GitHub is disabled
+    pub fn github_tokens(&self) -> Vec<String> {
+        if !self.github.enabled {
+            return Vec::new();
+        }
+        let mut seen = HashSet::new();
+        self.github_token().into_iter().chain(self.github.tokens.iter().cloned())
+            .filter(|t| !t.is_empty() && seen.insert(t.clone()))
+            .collect()
+    }
+}
+
+#[test]
+fn parse_site_config() {
+    let c = SiteConfig::parse("[github]\nenabled = false\n[images]\nproxy = \"none\"\n").unwrap();
+    assert!(!c.github.enabled);
+    assert!(c.crates_io.enabled);
+    assert!(c.github_token().is_none());
+    assert!(c.github_tokens().is_empty());
+    assert_eq!("none", c.images.proxy);
+    assert_eq!("imageoptim", SiteConfig::default().images.proxy);
+
+    let c = SiteConfig::parse("offline = true\n[images]\nproxy = \"local\"\n").unwrap();
+    assert!(c.offline && !c.github.enabled && !c.docs_rs.enabled && c.crates_io.enabled);
+    assert_eq!("none", c.images.proxy);
+
+    let c = SiteConfig::parse("[github]\ntoken = \"a\"\ntokens = [\"b\", \"a\", \"\", \"b\"]\n").unwrap();
+    assert_eq!(c.github_tokens(), ["a", "b"]);
+}
@@ -0,0 +1,186 @@
+//! Finds names that could be mistaken for names of popular crates (typosquatting)
+
+use crate::name_index::{edit_distance_at_most, normalize_crate_name};
+use smartstring::alias::String as SmolStr;
+use std::fmt;
+
+/// Adding these to a popular name makes it look like an official part of the project
+const CONFUSING_AFFIXES: [&str; 12] = ["rust-", "rs-", "-rs", "-rust", "-core", "-lib", "-cli", "-sys", "-utils", "-util", "-derive", "-macros"];
+
+/// Suffixes of crates that are usually published alongside the main crate by the same owners
+const COMPANION_SUFFIXES: [&str; 6] = ["-utils", "-util", "-derive", "-macros", "-macro", "-cli"];
+
+/// Shorter names have too many lookalikes by coincidence
+const MIN_HOMOGLYPH_NAME_LEN: usize = 5;
+
+/// Crates with this many downloads have real users, so they're not imitating anyone, even if their names are similar
+pub(crate) const ESTABLISHED_MONTHLY_DOWNLOADS: usize = 5000;
+
+/// Lookalike character sequences, folded to the character they imitate
+const HOMOGLYPHS: [(&str, &str); 10] = [("rn", "m"), ("vv", "w"), ("cl", "d"), ("0", "o"), ("1", "l"), ("i", "l"), ("3", "e"), ("5", "s"), ("4", "a"), ("8", "b")];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ConfusionKind {
+    /// Same letters, but `-`/`_` added or removed
+    Separator,
+    /// e.g. `rn` instead of `m`, `0` instead of `o`
+    Homoglyph,
+    /// e.g. `-rs` or `-core` added to the name
+    Affix,
+    /// One or two letters different or transposed
+    Typo,
+}
+
+impl ConfusionKind {
+    /// How likely it is to be deliberate, rather than a coincidence
+    fn weight(self) -> f64 {
+        match self {
+            Self::Homoglyph => 1.,
+            Self::Separator => 0.9,
+            Self::Typo => 0.6,
+            Self::Affix => 0.4,
+        }
+    }
+}
+
+impl fmt::Display for ConfusionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Separator => "differs only by punctuation",
+            Self::Homoglyph => "uses lookalike characters",
+            Self::Affix => "adds a prefix or suffix",
+            Self::Typo => "differs by a typo",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameConfusion {
+    /// Name of the popular crate
+    pub similar_to: SmolStr,
+    pub kind: ConfusionKind,
+    /// 0..=1, takes into account popularity of the imitated crate
+    pub risk: f64,
+}
+
+pub struct NameConfusionDetector {
+    popular: Vec<PopularName>,
+}
+
+struct PopularName {
+    name: SmolStr,
+    /// lowercase, without separators
+    compact: String,
+    skeleton: String,
+    /// 0..=1, log-scaled popularity
+    weight: f64,
+}
+
+impl NameConfusionDetector {
+    /// Names with their popularity scores, from `most_popular_crates`
+    pub fn new<'a>(popular: impl Iterator<Item = (&'a str, f64)>) -> Self {
+        let popular: Vec<_> = popular.collect();
+        let max_score = popular.iter().map(|&(_, s)| s).fold(1., f64::max);
+        Self {
+            popular: popular.into_iter().map(|(name, score)| {
+                let compact = compact_name(name);
+                PopularName {
+                    name: name.into(),
+                    skeleton: homoglyph_skeleton(&compact),
+                    compact,
+                    weight: (score.max(0.) + 1.).ln() / (max_score + 1.).ln(),
+                }
+            }).collect(),
+        }
+    }
+
+    /// Popular crates the name could be mistaken for, riskiest first.
+    /// Popular crates themselves are never reported.
+    pub fn check(&self, name: &str) -> Vec<NameConfusion> {
+        let normalized = normalize_crate_name(name);
+        if self.popular.iter().any(|p| normalize_crate_name(&p.name) == normalized) {
+            return Vec::new();
+        }
+
+        let compact = compact_name(name);
+        let skeleton = homoglyph_skeleton(&compact);
+        let stems = affix_stems(&normalized);
+        // short names are all similar to each other
+        let max_typos = match compact.len() {
+            0..=4 => 0,
+            5..=8 => 1,
+            _ => 2,
+        };
+
+        let mut found: Vec<_> = self.popular.iter().filter_map(|p| {
+            let kind = if p.compact == compact {
+                ConfusionKind::Separator
+            } else if compact.len() >= MIN_HOMOGLYPH_NAME_LEN && p.skeleton == skeleton {
+                ConfusionKind::Homoglyph
+            } else if stems.iter().any(|s| *s == p.compact) {
+                ConfusionKind::Affix
+            } else if p.compact.len() > 4 && edit_distance_at_most(&compact, &p.compact, max_typos).is_some() {
+                ConfusionKind::Typo
+            } else {
+                return None;
+            };
+            Some(NameConfusion {
+                similar_to: p.name.clone(),
+                kind,
+                risk: kind.weight() * p.weight,
+            })
+        }).collect();
+        found.sort_unstable_by(|a, b| b.risk.total_cmp(&a.risk));
+        found
+    }
+}
+
+/// `foo-derive` for `foo`, etc.
+pub(crate) fn is_companion_crate(name: &str, main_crate: &str) -> bool {
+    let name = normalize_crate_name(name);
+    let main_crate = normalize_crate_name(main_crate);
+    COMPANION_SUFFIXES.iter().any(|suffix| name.strip_suffix(suffix) == Some(main_crate.as_str()))
+}
+
+/// Lowercase, without `-` and `_`
+fn compact_name(name: &str) -> String {
+    name.chars().filter(|&c| c != '-' && c != '_').map(|c| c.to_ascii_lowercase()).collect()
+}
+
+fn homoglyph_skeleton(compact: &str) -> String {
+    let mut s = compact.to_owned();
+    for (lookalike, real) in HOMOGLYPHS {
+        s = s.replace(lookalike, real);
+    }
+    s
+}
+
+/// Compact names left after removing each of the confusing affixes
+fn affix_stems(normalized: &str) -> Vec<String> {
+    CONFUSING_AFFIXES.iter().filter_map(|affix| {
+        let stripped = if affix.starts_with('-') { normalized.strip_suffix(affix) } else { normalized.strip_prefix(affix) }?;
+        Some(compact_name(stripped)).filter(|s| s.len() > 2)
+    }).collect()
+}
+
+#[test]
+fn name_confusion() {
+    let d = NameConfusionDetector::new([("serde_json", 1000.), ("tokio", 5000.), ("clap", 3000.), ("regex", 2000.)].into_iter());
+    let kinds = |name| d.check(name).into_iter().map(|c| (c.similar_to.to_string(), c.kind)).collect::<Vec<_>>();
+    assert_eq!(kinds("serdejson"), [("serde_json".into(), ConfusionKind::Separator)]);
+    assert_eq!(kinds("t0kio"), [("tokio".into(), ConfusionKind::Homoglyph)]);
+    assert_eq!(kinds("tokio-core"), [("tokio".into(), ConfusionKind::Affix)]);
+    assert_eq!(kinds("rust-regex"), [("regex".into(), ConfusionKind::Affix)]);
+    assert_eq!(kinds("serde_jsno"), [("serde_json".into(), ConfusionKind::Typo)]);
+    assert!(kinds("serde-json").is_empty());
+    assert!(kinds("clop").is_empty());
+    assert!(kinds("rayon").is_empty());
+    assert!(kinds("dap").is_empty(), "too short for homoglyphs");
+
+    assert!(is_companion_crate("clap_derive", "clap"));
+    assert!(!is_companion_crate("clap-rs", "clap"));
+
+    let tokio = &d.check("tokio-rs")[0];
+    let regex = &d.check("regex-rs")[0];
+    assert!(tokio.risk > regex.risk);
+}
@@ -239,8 +239,8 @@ fn versions_score(ver: &[CrateVersion]) -> Score {
         return s;
     }
 
-    let oldest = ver.iter().map(|v| &v.created_at).min().copied();
-    let newest = ver.iter().map(|v| &v.created_at).max().copied();
+    let oldest = ver.iter().filter_map(|v| v.created_at).min();
+    let newest = ver.iter().filter_map(|v| v.created_at).max();
     if let (Some(oldest), Some(newest)) = (oldest, newest) {
         s.n("development history", 40, (newest - oldest).num_days() / 11);
 
@@ -304,7 +304,8 @@ pub fn crate_score_temporal(cr: &CrateTemporalInputs<'_>) -> Score {
         score.frac("Growth", 2, (growth-1.).clamp(0., 1.));
     }
 
-    let newest = cr.versions.iter().max_by_key(|v| &v.created_at).expect("at least 1 ver?");
+    // undated versions haven't been indexed yet, so they're the newest
+    let newest = cr.versions.iter().max_by_key(|v| (v.created_at.is_none(), v.created_at)).expect("at least 1 ver?");
     // Assume higher versions, and especially patch versions, mean the crate is more mature
     // and needs fewer updates
     let version_stability_interval = match SemVer::parse(&newest.num) {
@@ -316,7 +317,8 @@ pub fn crate_score_temporal(cr: &CrateTemporalInputs<'_>) -> Score {
         _ => 80,
     };
     let expected_update_interval = version_stability_interval.min(cr.versions.len() as u32 * 50) / if cr.is_nightly { 4 } else { 1 };
-    let age = (Utc::now() - newest.created_at).num_days().max(0) as u32;
+    // not indexed yet, so it's new
+    let age = newest.created_at.map_or(0, |created_at| (Utc::now() - created_at).num_days().max(0) as u32);
     let days_past_expiration_date = age.saturating_sub(expected_update_interval);
     // score decays for a ~year after the crate should have been updated
     let decay_days = expected_update_interval/2 + if cr.is_nightly { 30 } else if is_app_only {300} else {200};
@@ -0,0 +1,26 @@
+//! Lists recently published crates that imitate names of popular crates.
+//!
+//! Output is in the `ablocklist.csv` format, commented out, so that lines can be reviewed and copied to the blocklist.
+//!
+//! Usage: `name_confusion_report [days]`
+
+use kitchen_sink::KitchenSink;
+use kitchen_sink::OwnerKind;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let days = std::env::args().nth(1).map(|d| d.parse()).transpose()?.unwrap_or(14);
+    let crates = KitchenSink::new_default().await?;
+
+    let report = crates.name_confusion_report(days, 0.2).await?;
+    for (origin, confusion, owners) in report {
+        let name = origin.short_crate_name();
+        println!("\n# {name} {} of {} (risk {:.2}) https://lib.rs/crates/{name}", confusion.kind, confusion.similar_to, confusion.risk);
+        for o in owners.iter().filter(|o| o.kind == OwnerKind::User) {
+            let login = o.crates_io_login.to_ascii_lowercase();
+            let status = if crates.crates_io_login_on_blocklist(&login).is_some() { " (already listed)" } else { "" };
+            println!("#{login},b,https://crates.io/users/{login},typosquatting {} as {name}{status}", confusion.similar_to);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Builds the optional code search index of names of public items (see `search_index::CodeSearchIndex`).
+//!
+//! Usage: `reindex_code [crate names…]`. Without arguments, reindexes all crates-io crates.
+
+use futures::stream::StreamExt;
+use kitchen_sink::{stopped, KitchenSink, Origin};
+use search_index::{CodeIndexer, CodeSearchIndex};
+
+struct CodeDocument {
+    origin: Origin,
+    name: String,
+    version: String,
+    /// kind, name
+    items: Vec<(&'static str, String)>,
+    score: f64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let crates = KitchenSink::new_default().await?;
+    let index = CodeSearchIndex::new(crates.main_cache_dir())?;
+    let mut indexer = CodeIndexer::new(&index)?;
+
+    let specific: Vec<_> = std::env::args().skip(1).filter_map(|name| Origin::try_from_crates_io_name(&name)).collect();
+    let origins = if specific.is_empty() {
+        crates.all_crates_io_crates().keys().map(|name| Origin::from_crates_io_name(name)).collect()
+    } else {
+        specific
+    };
+
+    let crates = &crates;
+    let mut docs = futures::stream::iter(origins).map(|origin| async move {
+        let res = code_document(crates, &origin).await;
+        (origin, res)
+    }).buffer_unordered(8);
+
+    let mut n = 0usize;
+    while let Some((origin, res)) = docs.next().await {
+        if stopped() {
+            break;
+        }
+        match res {
+            Ok(Some(doc)) => {
+                let items = doc.items.iter().map(|(kind, name)| (*kind, name.as_str()));
+                tokio::task::block_in_place(|| indexer.add(&doc.origin, &doc.name, &doc.version, items, doc.score))?;
+            },
+            Ok(None) => indexer.delete(&origin),
+            Err(e) => eprintln!("{origin:?}: {e}"),
+        }
+        n += 1;
+        if n % 1000 == 0 {
+            println!("{n}…");
+            tokio::task::block_in_place(|| indexer.commit())?;
+        }
+    }
+    tokio::task::block_in_place(|| indexer.commit())?;
+    indexer.bye()?;
+    Ok(())
+}
+
+/// `None` if the crate shouldn't be searchable
+async fn code_document(crates: &KitchenSink, origin: &Origin) -> anyhow::Result<Option<CodeDocument>> {
+    let ver = crates.rich_crate_version_async(origin).await?;
+    if ver.is_yanked() || !ver.has_lib() {
+        return Ok(None);
+    }
+    let files = crates.crate_files_summary_from_crates_io_tarball(ver.short_name(), ver.version()).await?;
+    let public_items = tokio::task::block_in_place(|| files.public_items());
+    if public_items.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(CodeDocument {
+        origin: origin.clone(),
+        name: ver.short_name().into(),
+        version: ver.version().into(),
+        items: public_items.into_iter().map(|i| (i.kind.as_str(), i.name)).collect(),
+        score: crates.crate_db.crate_rank(origin).await.unwrap_or(0.1),
+    }))
+}
@@ -20,7 +20,7 @@ use ranking::CrateVersionInputs;
 use ranking::OverallScoreInputs;
 use render_readme::Links;
 use render_readme::Renderer;
-use search_index::{CrateSearchIndex, Indexer};
+use search_index::{CrateFilterFields, CrateSearchIndex, Indexer};
 use simple_cache::TempCache;
 use ahash::HashSet;
 use std::convert::TryInto;
@@ -50,14 +50,15 @@ fn main() {
 
 
     let crates = rt.block_on(kitchen_sink::KitchenSink::new_default()).unwrap();
+    crates.set_github_priority(kitchen_sink::GitHubPriority::Background);
     let deblist = DebcargoList::new(crates.main_cache_dir()).expect("deblist");
     if everything {
         deblist.update().expect("debcargo"); // it needs to be updated sometime, but not frequently
     }
     let r = Arc::new(Reindexer { crates, deblist });
-    let mut indexer = Indexer::new(CrateSearchIndex::new(r.crates.main_cache_dir()).expect("init search")).expect("init search indexer");
+    let search_index = CrateSearchIndex::new(r.crates.main_cache_dir()).expect("init search");
     let lines = TempCache::new(r.crates.main_cache_dir().join("search-uniq-lines.dat"), Duration::ZERO).expect("init lines cache");
-    let (tx, mut rx) = mpsc::channel::<(Arc<_>, _, _)>(64);
+    let (tx, mut rx) = mpsc::channel::<(Arc<_>, _, _, _)>(64);
 
     let r2 = r.clone();
     std::thread::spawn(move || loop {
@@ -71,13 +72,14 @@ fn main() {
 
     let index_thread = rt.spawn({
         async move {
+            let mut indexer = Indexer::new(&search_index).expect("init search indexer");
             let renderer = Arc::new(Renderer::new(None));
             let mut n = 0usize;
             let mut next_n = 100usize;
-            while let Some((ver, downloads_per_month, score)) = rx.recv().await {
+            while let Some((ver, downloads_per_month, score, msrv)) = rx.recv().await {
                 if stopped() {break;}
                 tokio::task::block_in_place(|| {
-                    index_search(&mut indexer, &lines, &renderer, &ver, downloads_per_month, score)?;
+                    index_search(&mut indexer, &lines, &renderer, &ver, downloads_per_month, score, msrv)?;
                     n += 1;
                     if n == next_n {
                         next_n *= 2;
@@ -88,7 +90,7 @@ fn main() {
                 })?;
             }
             tokio::task::block_in_place(|| indexer.commit())?;
-            let _ = indexer.bye()?;
+            indexer.bye()?;
             Ok::<_, anyhow::Error>(())
         }
     });
@@ -112,7 +114,7 @@ fn main() {
     })).unwrap();
 }
 
-async fn main_indexing_loop(ref r: Arc<Reindexer>, crate_origins: Box<dyn Iterator<Item=Origin> + Send>, tx: mpsc::Sender<(Arc<RichCrateVersion>, usize, f64)>, repos: bool, search_only: bool, reindexing_all_crates: bool) {
+async fn main_indexing_loop(ref r: Arc<Reindexer>, crate_origins: Box<dyn Iterator<Item=Origin> + Send>, tx: mpsc::Sender<(Arc<RichCrateVersion>, usize, f64, Option<u16>)>, repos: bool, search_only: bool, reindexing_all_crates: bool) {
     let renderer = &Renderer::new(None);
     let seen_repos = &Mutex::new(HashSet::new());
     let repo_concurrency = &tokio::sync::Semaphore::new(4);
@@ -159,7 +161,7 @@ async fn main_indexing_loop(ref r: Arc<Reindexer>, crate_origins: Box<dyn Iterat
 }
 
 impl Reindexer {
-async fn index_crate(&self, origin: &Origin, renderer: &Renderer, search_sender: &mut mpsc::Sender<(ArcRichCrateVersion, usize, f64)>) -> Result<ArcRichCrateVersion, anyhow::Error> {
+async fn index_crate(&self, origin: &Origin, renderer: &Renderer, search_sender: &mut mpsc::Sender<(ArcRichCrateVersion, usize, f64, Option<u16>)>) -> Result<ArcRichCrateVersion, anyhow::Error> {
     let crates = &self.crates;
     let (k, v) = futures::try_join!(
         run_timeout("rca", 31, crates.rich_crate_async(origin)),
@@ -168,9 +170,14 @@ async fn index_crate(&self, origin: &Origin, renderer: &Renderer, search_sender:
 
     let (downloads_per_month, score) = run_timeout("score", 41, self.crate_overall_score(&k, &v, renderer)).await?;
     debug!("{origin:?} has score {score} and {downloads_per_month}dl/mo");
+    let msrv = crates.rustc_compatibility(&k).await.ok().and_then(|compat| {
+        let semver: SemVer = v.version().parse().ok()?;
+        let c = compat.get(&semver)?;
+        c.msrv()
+    });
     let (_, index_res) = futures::join!(
         run_timeout("ssend", 10, async {
-            search_sender.send((v.clone(), downloads_per_month, score)).await
+            search_sender.send((v.clone(), downloads_per_month, score, msrv)).await
                 .map_err(|e| {stop();e})
         }),
         run_timeout("ic", 50, crates.index_crate(&k, score))
@@ -180,7 +187,7 @@ async fn index_crate(&self, origin: &Origin, renderer: &Renderer, search_sender:
 }
 }
 
-fn index_search(indexer: &mut Indexer, lines: &TempCache<(String, f64), [u8; 16]>, renderer: &Renderer, k: &RichCrateVersion, downloads_per_month: usize, score: f64) -> Result<(), anyhow::Error> {
+fn index_search(indexer: &mut Indexer, lines: &TempCache<(String, f64), [u8; 16]>, renderer: &Renderer, k: &RichCrateVersion, downloads_per_month: usize, score: f64, msrv: Option<u16>) -> Result<(), anyhow::Error> {
     let keywords: Vec<_> = k.keywords().iter().map(|s| s.as_str()).collect();
     let version = k.version();
 
@@ -233,7 +240,13 @@ fn index_search(indexer: &mut Indexer, lines: &TempCache<(String, f64), [u8; 16]
         }
     }
 
-    indexer.add(k.origin(), k.short_name(), version, k.description().unwrap_or(""), &keywords, Some(unique_text.as_str()).filter(|s| !s.trim_start().is_empty()), downloads_per_month as u64, score)?;
+    let category_slugs: Vec<_> = k.category_slugs().iter().map(|s| &**s).collect();
+    let filter_fields = CrateFilterFields {
+        category_slugs: &category_slugs,
+        license: k.license(),
+        msrv,
+    };
+    indexer.add(k.origin(), k.short_name(), version, k.description().unwrap_or(""), &keywords, Some(unique_text.as_str()).filter(|s| !s.trim_start().is_empty()), downloads_per_month as u64, score, &filter_fields)?;
     Ok(())
 }
 
@@ -321,7 +334,7 @@ impl Reindexer {
                 Some(async move {
                     let pop = run_timeout("depf", 15, crates.version_popularity(&origin, &req)).await
                         .map_err(|e| log::error!("ver1pop {}", e)).unwrap_or(None);
-                    (richdep.is_optional(), pop.unwrap_or(VersionPopularity { matches_latest: true, pop: 0., lost_popularity: false, deprecated: false}))
+                    (richdep.is_optional(), pop.unwrap_or(VersionPopularity { matches_latest: true, pop: 0., lost_popularity: false, deprecated: false, only_yanked: false }))
                 })
             })).await
             .into_iter().map(|(is_optional, pop)| {
@@ -1,7 +1,7 @@
 use anyhow::bail;
 use futures::future::FutureExt;
 use futures::stream::StreamExt;
-use kitchen_sink::{stopped, KitchenSink};
+use kitchen_sink::{stopped, GitHubPriority, KitchenSink};
 use std::{
     collections::HashSet,
     sync::{Arc, Mutex},
@@ -19,6 +19,7 @@ async fn main() {
                 std::process::exit(1);
             },
         });
+        crates.set_github_priority(GitHubPriority::Background);
 
         let all_crates = crates.all_crates();
         let waiting = futures::stream::FuturesUnordered::new();
@@ -0,0 +1,40 @@
+//! Prints the dependency tree a crate would have in a new project, resolved from the local index without network access.
+//!
+//! Usage: `resolve_deps <crate> [version] [--features a,b] [--no-default-features] [--dev]`
+
+use kitchen_sink::{KitchenSink, Origin, ResolveQuery};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut query = ResolveQuery::default();
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--features" => query.features.extend(args.next().unwrap_or_default().split(',').filter(|f| !f.is_empty()).map(From::from)),
+            "--no-default-features" => query.default_features = false,
+            "--dev" => query.dev = true,
+            _ => positional.push(arg),
+        }
+    }
+    let name = positional.first().ok_or_else(|| anyhow::anyhow!("Specify a crate name"))?;
+    let origin = Origin::try_from_crates_io_name(name).ok_or_else(|| anyhow::anyhow!("Bad crate name {name}"))?;
+
+    let crates = KitchenSink::new_default().await?;
+    let res = crates.resolve_dependencies(&origin, positional.get(1).map(|s| s.as_str()), &query).await?;
+
+    for (depth, p) in res.tree() {
+        println!("{:indent$}{} {}", "", p.name, p.version, indent = depth * 2);
+    }
+    println!();
+    for (name, versions) in res.duplicates() {
+        let versions = versions.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+        println!("duplicate: {name} {versions}");
+    }
+    for (parent, name, req) in &res.unresolved {
+        println!("unresolved: {parent} requires {name} {req}");
+    }
+    let (size, unknown) = res.total_tarball_size();
+    println!("{} crates, {}KB of tarballs (size of {unknown} crates unknown)", res.crate_count(), size / 1000);
+    Ok(())
+}
@@ -20,6 +20,8 @@ pub enum Origin {
     CratesIo(Box<str>),
     GitHub { repo: SimpleRepo, package: Box<str> },
     GitLab { repo: SimpleRepo, package: Box<str> },
+    /// Alternative Cargo registry, by the name it's configured under
+    Registry { registry: Box<str>, package: Box<str> },
 }
 
 impl fmt::Debug for Origin {
@@ -28,6 +30,7 @@ impl fmt::Debug for Origin {
             Origin::CratesIo(name) => write!(f, "Origin( lib.rs/{} )", name),
             Origin::GitHub { repo, package } => write!(f, "Origin( github.com/{}/{} {} )", repo.owner, repo.repo, package),
             Origin::GitLab { repo, package } => write!(f, "Origin( gitlab.com/{}/{} {} )", repo.owner, repo.repo, package),
+            Origin::Registry { registry, package } => write!(f, "Origin( {} registry {} )", registry, package),
         }
     }
 }
@@ -66,6 +69,16 @@ impl Origin {
         Origin::GitLab { repo, package: package.into() }
     }
 
+    /// Registry names are the same as in Cargo's config, e.g. `[registries.name]`
+    #[inline]
+    pub fn try_from_registry(registry: &str, package: &str) -> Option<Self> {
+        if Self::is_valid_crate_name(registry) && Self::is_valid_crate_name(package) {
+            Some(Origin::Registry { registry: registry.into(), package: package.to_ascii_lowercase().into() })
+        } else {
+            None
+        }
+    }
+
     #[inline]
     pub fn from_repo(r: &Repo, package: &str) -> Option<Self> {
         match r.host() {
@@ -82,6 +95,10 @@ impl Origin {
         let host = n.next().unwrap();
         match host {
             "crates.io" => Self::from_crates_io_name(n.next().expect("parse")),
+            "registry" => {
+                let (registry, package) = n.next().and_then(|n| n.split_once('/')).expect("parse");
+                Self::try_from_registry(registry, package).expect("parse")
+            },
             "github" | "gitlab" => {
                 let mut n = n.next().expect("parse").splitn(3, '/');
                 let owner = n.next().expect("parse").into();
@@ -102,6 +119,7 @@ impl Origin {
             Origin::CratesIo(ref s) => format!("crates.io:{}", s),
             Origin::GitHub { ref repo, ref package } => format!("github:{}/{}/{}", repo.owner, repo.repo, package),
             Origin::GitLab { ref repo, ref package } => format!("gitlab:{}/{}/{}", repo.owner, repo.repo, package),
+            Origin::Registry { ref registry, ref package } => format!("registry:{}/{}", registry, package),
         }
     }
 
@@ -110,7 +128,17 @@ impl Origin {
         match *self {
             Origin::CratesIo(ref s) => s,
             Origin::GitHub { ref package, .. } |
-            Origin::GitLab { ref package, .. } => package,
+            Origin::GitLab { ref package, .. } |
+            Origin::Registry { ref package, .. } => package,
+        }
+    }
+
+    /// Name of the alternative registry, `None` for crates.io and git repos
+    #[inline]
+    pub fn registry_name(&self) -> Option<&str> {
+        match self {
+            Origin::Registry { registry, .. } => Some(registry),
+            _ => None,
         }
     }
 
@@ -122,7 +150,7 @@ impl Origin {
     #[inline]
     pub fn repo(&self) -> Option<(Repo, SmolStr)> {
         match self {
-            Origin::CratesIo(_) => None,
+            Origin::CratesIo(_) | Origin::Registry { .. } => None,
             Origin::GitHub { repo, package } => Some((Repo::GitHub(repo.clone()).try_into().ok()?, SmolStr::from(&**package))),
             Origin::GitLab { repo, package } => Some((Repo::GitLab(repo.clone()).try_into().ok()?, SmolStr::from(&**package))),
         }
@@ -131,7 +159,7 @@ impl Origin {
     #[inline]
     pub fn simple_repo(&self) -> Option<(&SimpleRepo, &str)> {
         match self {
-            Origin::CratesIo(_) => None,
+            Origin::CratesIo(_) | Origin::Registry { .. } => None,
             Origin::GitHub { repo, package } |
             Origin::GitLab { repo, package } => Some((repo, package)),
         }
@@ -140,7 +168,7 @@ impl Origin {
     #[inline]
     pub fn into_repo(self) -> Option<(Repo, Box<str>)> {
         match self {
-            Origin::CratesIo(_) => None,
+            Origin::CratesIo(_) | Origin::Registry { .. } => None,
             Origin::GitHub { package, repo } => Some((Repo::GitHub(repo), package)),
             Origin::GitLab { package, repo } => Some((Repo::GitLab(repo), package)),
         }
@@ -165,6 +193,16 @@ fn roundtrip_gh() {
     assert_eq!("baz", o2.short_crate_name());
 }
 
+#[test]
+fn roundtrip_registry() {
+    let o1 = Origin::try_from_registry("my-company", "Foo_Bar").unwrap();
+    let o2 = Origin::from_str(o1.to_str());
+    assert_eq!(o1, o2);
+    assert_eq!("foo_bar", o2.short_crate_name());
+    assert_eq!(Some("my-company"), o2.registry_name());
+    assert_ne!(o1, Origin::from_crates_io_name("foo_bar"));
+}
+
 fn is_alnum(q: &str) -> bool {
     q.as_bytes().iter().copied().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-')
 }
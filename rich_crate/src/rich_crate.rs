@@ -1,5 +1,6 @@
 use crate::Origin;
 pub use crates_io_client::CrateOwner;
+pub use crates_io_client::OwnerKind;
 use smartstring::alias::String as SmolStr;
 use chrono::{DateTime, Utc};
 
@@ -17,8 +18,10 @@ pub struct RichCrate {
 #[derive(Debug, Clone)]
 pub struct CrateVersion {
     pub num: SmolStr, // "1.4.0",
-    pub updated_at: DateTime<Utc>, // "2018-01-29T23:10:11.539889+00:00",
-    pub created_at: DateTime<Utc>, // "2018-01-29T23:10:11.539889+00:00",
+    /// `None` if unknown, e.g. for registry versions that haven't been indexed yet
+    pub updated_at: Option<DateTime<Utc>>, // "2018-01-29T23:10:11.539889+00:00",
+    /// `None` if unknown
+    pub created_at: Option<DateTime<Utc>>, // "2018-01-29T23:10:11.539889+00:00",
     // pub downloads: usize,   // 154,
     // pub features: HashMap<String, Vec<String>>,
     pub yanked: bool,
@@ -42,8 +45,9 @@ impl RichCrate {
         &self.versions
     }
 
-    pub fn most_recent_release(&self) -> DateTime<Utc> {
-        *self.versions.iter().map(|v| &v.created_at).max().unwrap()
+    /// `None` if no version has a known date
+    pub fn most_recent_release(&self) -> Option<DateTime<Utc>> {
+        self.versions.iter().filter_map(|v| v.created_at).max()
     }
 
     pub fn is_yanked(&self) -> bool {
@@ -0,0 +1,256 @@
+//! Separate index of names of public items (fns, types, traits, macros) defined by crates.
+//!
+//! It's optional, built by `reindex_code`. One document per item.
+
+use ahash::HashMap;
+use ahash::HashMapExt;
+use rich_crate::Origin;
+use std::{fs, path::Path};
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, BoostQuery, Occur, Query, TermQuery};
+use tantivy::schema::*;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy};
+
+use crate::{take_int, take_string, CRATE_SCORE_MAX};
+
+/// Item kinds that can be used at the start of a query, as produced by `tarball::ItemKind::as_str`
+const ITEM_KINDS: [&str; 7] = ["fn", "struct", "enum", "union", "trait", "type", "macro"];
+
+pub struct CodeSearchIndex {
+    /// Origin.to_str
+    origin_pkey: Field,
+    crate_name_field: Field,
+    crate_version: Field,
+    /// 0..=CRATE_SCORE_MAX
+    crate_score: Field,
+    /// `fn`, `trait`, etc.
+    kind_field: Field,
+    /// as-is
+    name_field: Field,
+    /// lowercase
+    name_exact_field: Field,
+    /// split on `_` and camelCase
+    name_words_field: Field,
+
+    tantivy_index: Index,
+    reader: IndexReader,
+}
+
+/// A code search query, e.g. `trait Service` or `fn decode_png`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeQuery {
+    pub kind: Option<String>,
+    /// Identifier or words of an identifier
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeCrateFound {
+    pub origin: Origin,
+    pub crate_name: String,
+    pub version: String,
+    /// kind and name of items that matched the query, best first
+    pub items: Vec<(String, String)>,
+    pub score: f32,
+}
+
+/// Writing requires a lock on the index directory, so only one `CodeIndexer` can exist at a time across all processes.
+pub struct CodeIndexer<'index> {
+    index: &'index CodeSearchIndex,
+    writer: IndexWriter,
+}
+
+impl CodeQuery {
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut words = query.split_whitespace();
+        let first = words.next()?;
+        let kind = ITEM_KINDS.iter().find(|&&k| first.eq_ignore_ascii_case(k) || (k == "macro" && first == "macro_rules!"));
+        let name: Vec<_> = match kind {
+            Some(_) => words.collect(),
+            None => std::iter::once(first).chain(words).collect(),
+        };
+        let name = name.join(" ").trim_end_matches(['(', ')', '!', '<', '>', '{', '}', ';'].as_slice()).to_owned();
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self { kind: kind.map(|&k| k.to_owned()), name })
+    }
+}
+
+impl CodeSearchIndex {
+    /// `None` if the index hasn't been built
+    pub fn open(data_dir: impl AsRef<Path>) -> tantivy::Result<Option<Self>> {
+        let index_dir = data_dir.as_ref().join("code-tantivy1");
+        if !index_dir.exists() {
+            return Ok(None);
+        }
+        Self::from_index(Index::open_in_dir(index_dir)?).map(Some)
+    }
+
+    /// Creates the index if it doesn't exist
+    pub fn new(data_dir: impl AsRef<Path>) -> tantivy::Result<Self> {
+        let index_dir = data_dir.as_ref().join("code-tantivy1");
+        if index_dir.exists() {
+            return Self::from_index(Index::open_in_dir(index_dir)?);
+        }
+        let _ = fs::create_dir_all(&index_dir);
+
+        let mut schema_builder = SchemaBuilder::default();
+        schema_builder.add_text_field("origin", STRING | STORED);
+        schema_builder.add_text_field("crate_name", STORED);
+        schema_builder.add_text_field("crate_version", STORED);
+        schema_builder.add_u64_field("crate_score", STORED);
+        schema_builder.add_text_field("kind", STRING | STORED);
+        schema_builder.add_text_field("name", STORED);
+        schema_builder.add_text_field("name_exact", STRING);
+        schema_builder.add_text_field("name_words", TEXT);
+        Self::from_index(Index::create_in_dir(index_dir, schema_builder.build())?)
+    }
+
+    fn from_index(tantivy_index: Index) -> tantivy::Result<Self> {
+        let schema = tantivy_index.schema();
+        Ok(Self {
+            origin_pkey: schema.get_field("origin").expect("schema"),
+            crate_name_field: schema.get_field("crate_name").expect("schema"),
+            crate_version: schema.get_field("crate_version").expect("schema"),
+            crate_score: schema.get_field("crate_score").expect("schema"),
+            kind_field: schema.get_field("kind").expect("schema"),
+            name_field: schema.get_field("name").expect("schema"),
+            name_exact_field: schema.get_field("name_exact").expect("schema"),
+            name_words_field: schema.get_field("name_words").expect("schema"),
+            reader: tantivy_index.reader_builder().reload_policy(ReloadPolicy::OnCommit).try_into()?,
+            tantivy_index,
+        })
+    }
+
+    fn build_query(&self, query: &CodeQuery) -> Option<BooleanQuery> {
+        let words = identifier_words(&query.name);
+        if words.is_empty() {
+            return None;
+        }
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = words.split(' ').map(|w| {
+            (Occur::Must, Box::new(TermQuery::new(Term::from_field_text(self.name_words_field, w), IndexRecordOption::WithFreqs)) as Box<dyn Query>)
+        }).collect();
+        // exact identifier is much better than containing the words
+        let exact = query.name.replace(' ', "_").to_lowercase();
+        let exact = TermQuery::new(Term::from_field_text(self.name_exact_field, &exact), IndexRecordOption::Basic);
+        clauses.push((Occur::Should, Box::new(BoostQuery::new(Box::new(exact), 3.))));
+        if let Some(kind) = &query.kind {
+            clauses.push((Occur::Must, Box::new(TermQuery::new(Term::from_field_text(self.kind_field, kind), IndexRecordOption::Basic))));
+        }
+        Some(BooleanQuery::new(clauses))
+    }
+
+    /// Crates defining matching items, sorted by relevance × crate score
+    pub fn search(&self, query: &CodeQuery, limit: usize) -> tantivy::Result<Vec<CodeCrateFound>> {
+        let query = match self.build_query(query) {
+            Some(q) => q,
+            None => return Ok(Vec::new()),
+        };
+        let searcher = self.reader.searcher();
+        // crates have many similarly-named items, so fetch more to have enough crates after grouping
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(limit * 10 + 100))?;
+
+        let mut by_crate: HashMap<String, CodeCrateFound> = HashMap::with_capacity(top_docs.len());
+        for (relevance, addr) in top_docs {
+            let mut doc = self.tantivy_index.schema().to_named_doc(&searcher.doc(addr)?).0;
+            let origin_str = take_string(doc.remove("origin"));
+            let crate_score = take_int(doc.get("crate_score")) as f64 / CRATE_SCORE_MAX;
+            let score = relevance * crate_score as f32;
+            let item = (take_string(doc.remove("kind")), take_string(doc.remove("name")));
+            let found = by_crate.entry(origin_str).or_insert_with_key(|origin_str| CodeCrateFound {
+                origin: Origin::from_str(origin_str),
+                crate_name: take_string(doc.remove("crate_name")),
+                version: take_string(doc.remove("crate_version")),
+                items: Vec::new(),
+                score,
+            });
+            found.score = found.score.max(score);
+            if found.items.len() < 10 {
+                found.items.push(item);
+            }
+        }
+        let mut crates: Vec<_> = by_crate.into_values().collect();
+        crates.sort_unstable_by(|a, b| b.score.total_cmp(&a.score));
+        crates.truncate(limit);
+        Ok(crates)
+    }
+}
+
+impl<'index> CodeIndexer<'index> {
+    pub fn new(index: &'index CodeSearchIndex) -> tantivy::Result<Self> {
+        Ok(Self { writer: index.tantivy_index.writer(100_000_000)?, index })
+    }
+
+    /// Replaces all items of the crate. Items are `(kind, name)`.
+    ///
+    /// score is float 0..=1 range
+    pub fn add<'a>(&mut self, origin: &Origin, crate_name: &str, version: &str, items: impl Iterator<Item = (&'a str, &'a str)>, score: f64) -> tantivy::Result<()> {
+        self.delete(origin);
+        let origin = origin.to_str();
+        let crate_score = (score * CRATE_SCORE_MAX).ceil() as u64;
+        for (kind, name) in items {
+            let mut doc = Document::default();
+            doc.add_text(self.index.origin_pkey, &origin);
+            doc.add_text(self.index.crate_name_field, crate_name);
+            doc.add_text(self.index.crate_version, version);
+            doc.add_u64(self.index.crate_score, crate_score);
+            doc.add_text(self.index.kind_field, kind);
+            doc.add_text(self.index.name_field, name);
+            doc.add_text(self.index.name_exact_field, &name.to_lowercase());
+            doc.add_text(self.index.name_words_field, &identifier_words(name));
+            self.writer.add_document(doc)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, origin: &Origin) {
+        self.writer.delete_term(Term::from_field_text(self.index.origin_pkey, &origin.to_str()));
+    }
+
+    pub fn commit(&mut self) -> tantivy::Result<()> {
+        self.writer.commit()?;
+        self.index.reader.reload()?;
+        Ok(())
+    }
+
+    pub fn bye(self) -> tantivy::Result<()> {
+        self.writer.wait_merging_threads()?;
+        Ok(())
+    }
+}
+
+/// Lowercase words of `snake_case`, `CamelCase` or space-separated names, separated by a space
+fn identifier_words(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        if !c.is_alphanumeric() {
+            prev = None;
+            continue;
+        }
+        let boundary = match prev {
+            None => !out.is_empty(),
+            // HTTPServer -> http server is too hard, but HttpServer and http2Server are easy
+            Some(p) => c.is_uppercase() && !p.is_uppercase(),
+        };
+        if boundary {
+            out.push(' ');
+        }
+        out.extend(c.to_lowercase());
+        prev = Some(c);
+    }
+    out
+}
+
+#[test]
+fn code_query() {
+    assert_eq!("decode png", identifier_words("decode_png"));
+    assert_eq!("into iterator", identifier_words("IntoIterator"));
+    assert_eq!("http2 server", identifier_words("http2Server"));
+    assert_eq!(CodeQuery::parse("trait Service"), Some(CodeQuery { kind: Some("trait".into()), name: "Service".into() }));
+    assert_eq!(CodeQuery::parse("fn decode_png()"), Some(CodeQuery { kind: Some("fn".into()), name: "decode_png".into() }));
+    assert_eq!(CodeQuery::parse("macro_rules! lazy_static"), Some(CodeQuery { kind: Some("macro".into()), name: "lazy_static".into() }));
+    assert_eq!(CodeQuery::parse("decode png"), Some(CodeQuery { kind: None, name: "decode png".into() }));
+    assert_eq!(CodeQuery::parse("fn "), None);
+}
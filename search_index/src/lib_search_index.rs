@@ -5,22 +5,193 @@ use itertools::Itertools;
 use rich_crate::Origin;
 use ahash::HashMap;
 use ahash::HashSet;
+use std::str::FromStr;
 use std::{fs, path::Path};
 use tantivy::query::Query;
 use tantivy::query::QueryParserError;
 use tantivy::TantivyError;
-use tantivy::{Index, IndexWriter};
-use tantivy::collector::TopDocs;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy};
+use tantivy::collector::{Count, FacetCollector, TopDocs};
 use tantivy::schema::*;
-use tantivy::query::QueryParser;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, PhraseQuery, QueryParser, RangeQuery, RegexQuery, TermQuery};
+
+mod code_index;
+mod query;
+pub use crate::code_index::*;
+pub use crate::query::*;
 
 const CRATE_SCORE_MAX: f64 = 1_000_000.;
 
+/// Deep pagination requires fetching and sorting everything before the page, so `search_filtered` refuses pages after this one
+pub const MAX_SEARCH_PAGE: usize = 20;
+
+/// Coarse grouping of licenses, for filtering
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LicenseFamily {
+    /// MIT, Apache, BSD, and similar
+    Permissive,
+    /// LGPL, MPL
+    WeakCopyleft,
+    /// GPL, AGPL
+    Copyleft,
+    /// CC0, Unlicense
+    PublicDomain,
+    Other,
+}
+
+impl LicenseFamily {
+    /// Dual licenses count as the most permissive option.
+    /// Crates-io allows non-SPDX syntax, so this is a loose match.
+    pub fn from_license(license: &str) -> Self {
+        license.split("AND").map(|l| {
+            l.split('/').flat_map(|l| l.split("OR"))
+                .map(|l| Self::from_single_license(l.trim()))
+                .min_by_key(|f| f.virality()).unwrap_or(Self::Other)
+        }).max_by_key(|f| f.virality()).unwrap_or(Self::Other)
+    }
+
+    fn from_single_license(l: &str) -> Self {
+        let l = l.trim_start_matches('(');
+        if l.starts_with("CC0") || l.starts_with("Unlicense") || l.starts_with("WTFPL") || l.starts_with("0BSD") {
+            Self::PublicDomain
+        } else if l.starts_with("MIT") || l.starts_with("Apache") || l.starts_with("BSD") || l.starts_with("Zlib") ||
+            l.starts_with("ISC") || l.starts_with("BSL") || l.starts_with("IJG") || l.starts_with("FTL") {
+            Self::Permissive
+        } else if l.starts_with("LGPL") || l.starts_with("MPL") || l.starts_with("EPL") {
+            Self::WeakCopyleft
+        } else if l.starts_with("GPL") || l.starts_with("AGPL") {
+            Self::Copyleft
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Higher is more restrictive. Unknown licenses are treated as the most restrictive.
+    fn virality(self) -> u8 {
+        match self {
+            Self::PublicDomain => 0,
+            Self::Permissive => 1,
+            Self::WeakCopyleft => 2,
+            Self::Copyleft => 3,
+            Self::Other => 4,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Permissive => "permissive",
+            Self::WeakCopyleft => "weak-copyleft",
+            Self::Copyleft => "copyleft",
+            Self::PublicDomain => "public-domain",
+            Self::Other => "other",
+        }
+    }
+
+}
+
+impl FromStr for LicenseFamily {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s {
+            "permissive" => Self::Permissive,
+            "weak-copyleft" => Self::WeakCopyleft,
+            "copyleft" => Self::Copyleft,
+            "public-domain" => Self::PublicDomain,
+            "other" => Self::Other,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OriginKind {
+    CratesIo,
+    /// GitHub or GitLab
+    Git,
+}
+
+impl OriginKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CratesIo => "crates-io",
+            Self::Git => "git",
+        }
+    }
+
+    fn of(origin: &Origin) -> Self {
+        if origin.is_crates_io() { Self::CratesIo } else { Self::Git }
+    }
+}
+
+impl FromStr for OriginKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "crates-io" | "crates.io" => Ok(Self::CratesIo),
+            "git" | "github" | "gitlab" => Ok(Self::Git),
+            _ => Err(()),
+        }
+    }
+}
+
+/// All filters are optional and combined with AND
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilters {
+    /// Category slug, e.g. `command-line-utilities` or `development-tools::cargo-plugins`. Includes subcategories.
+    pub category: Option<String>,
+    /// Matched after synonym normalization
+    pub keyword: Option<String>,
+    pub min_monthly_downloads: Option<u64>,
+    pub origin_kind: Option<OriginKind>,
+    pub license_family: Option<LicenseFamily>,
+    /// Rust 1.x minor version. Crates with unknown MSRV are excluded when set.
+    pub max_msrv: Option<u16>,
+}
+
+impl SearchFilters {
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none() && self.keyword.is_none() && self.min_monthly_downloads.is_none() &&
+            self.origin_kind.is_none() && self.license_family.is_none() && self.max_msrv.is_none()
+    }
+}
+
+/// Extra per-crate data used only for filtering
+#[derive(Debug, Clone, Default)]
+pub struct CrateFilterFields<'a> {
+    pub category_slugs: &'a [&'a str],
+    pub license: Option<&'a str>,
+    /// Rust 1.x minor version
+    pub msrv: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FilteredSearchResults {
+    pub crates: Vec<CrateFound>,
+    /// Number of all matching crates, not just this page
+    pub total: usize,
+    /// 0-based
+    pub page: usize,
+    pub per_page: usize,
+    pub facets: SearchFacets,
+}
+
+/// Number of matching crates in each group, sorted by count
+#[derive(Debug, Clone, Default)]
+pub struct SearchFacets {
+    /// Category slug (subcategories of the selected category, or top-level ones)
+    pub categories: Vec<(String, u64)>,
+    pub license_families: Vec<(LicenseFamily, u64)>,
+    pub origins: Vec<(OriginKind, u64)>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct SearchResults {
     pub crates: Vec<CrateFound>,
     pub keywords: Vec<String>,
     pub normalized_query: Option<String>,
+    pub parsed_query: SearchQuery,
 }
 
 pub struct CrateSearchIndex {
@@ -38,8 +209,16 @@ pub struct CrateSearchIndex {
     crate_version: Field,
     /// number in range 0..=SCORE_MAX denoting desirability of the crate
     crate_score: Field,
+    /// `/category/<slug parts>`, `/license/<family>`, `/origin/<kind>`
+    facets_field: Field,
+    /// normalized keywords, one term each
+    keyword_field: Field,
+    /// Rust 1.x minor version, 0 if unknown
+    msrv_field: Field,
 
     tantivy_index: Index,
+    /// Reloads automatically when any process commits to the index
+    reader: IndexReader,
     synonyms: Synonyms,
 }
 
@@ -57,8 +236,9 @@ pub struct CrateFound {
     pub monthly_downloads: u64,
 }
 
-pub struct Indexer {
-    index: CrateSearchIndex,
+/// Writing requires a lock on the index directory, so only one `Indexer` can exist at a time across all processes.
+pub struct Indexer<'index> {
+    index: &'index CrateSearchIndex,
     writer: IndexWriter,
 }
 
@@ -67,15 +247,15 @@ impl CrateSearchIndex {
         let data_dir = data_dir.as_ref();
         let synonyms = Synonyms::new(data_dir)?;
 
-        let index_dir = data_dir.join("tantivy18");
+        let index_dir = data_dir.join("tantivy20");
         if !index_dir.exists() {
             return Self::reset_db_to_empty(&index_dir, synonyms);
         }
         let tantivy_index = Index::open_in_dir(index_dir)?;
         let schema = tantivy_index.schema();
 
-
         Ok(Self {
+            reader: Self::reader(&tantivy_index)?,
             tantivy_index,
             origin_pkey: schema.get_field("origin").expect("schema"),
             crate_name_field: schema.get_field("crate_name").expect("schema"),
@@ -85,6 +265,9 @@ impl CrateSearchIndex {
             monthly_downloads: schema.get_field("monthly_downloads").expect("schema"),
             crate_version: schema.get_field("crate_version").expect("schema"),
             crate_score: schema.get_field("crate_score").expect("schema"),
+            facets_field: schema.get_field("facets").expect("schema"),
+            keyword_field: schema.get_field("keyword").expect("schema"),
+            msrv_field: schema.get_field("msrv").expect("schema"),
             synonyms,
         })
     }
@@ -102,13 +285,26 @@ impl CrateSearchIndex {
         let text_options = TextOptions::default().set_indexing_options(text_field_indexing);
         let readme_field = schema_builder.add_text_field("readme", text_options);
         let crate_version = schema_builder.add_text_field("crate_version", STRING | STORED);
-        let monthly_downloads = schema_builder.add_u64_field("monthly_downloads", STORED);
-        let crate_score = schema_builder.add_u64_field("crate_score", STORED);
+        let monthly_downloads = schema_builder.add_u64_field("monthly_downloads", INDEXED | STORED);
+        let crate_score = schema_builder.add_u64_field("crate_score", STORED | FAST);
+        let facets_field = schema_builder.add_facet_field("facets", FacetOptions::default());
+        let keyword_field = schema_builder.add_text_field("keyword", STRING);
+        let msrv_field = schema_builder.add_u64_field("msrv", INDEXED | STORED);
 
         let schema = schema_builder.build();
         let tantivy_index = Index::create_in_dir(index_dir, schema)?;
 
-        Ok(Self { tantivy_index, origin_pkey, crate_name_field, keywords_field, description_field, readme_field, monthly_downloads, crate_version, crate_score, synonyms })
+        Ok(Self { reader: Self::reader(&tantivy_index)?, tantivy_index, origin_pkey, crate_name_field, keywords_field, description_field, readme_field, monthly_downloads, crate_version, crate_score, facets_field, keyword_field, msrv_field, synonyms })
+    }
+
+    fn reader(tantivy_index: &Index) -> tantivy::Result<IndexReader> {
+        tantivy_index.reader_builder().reload_policy(ReloadPolicy::OnCommit).try_into()
+    }
+
+    /// Segments committed by other processes are picked up automatically, but with a delay.
+    /// This makes them visible immediately.
+    pub fn reload(&self) -> tantivy::Result<()> {
+        self.reader.reload()
     }
 
     pub fn normalize_keyword<'a>(&'a self, kw: &'a str) -> &'a str {
@@ -132,8 +328,69 @@ impl CrateSearchIndex {
             })
     }
 
+    /// See `query.rs` for the syntax
+    fn build_query(&self, parsed: &SearchQuery) -> tantivy::Result<Box<dyn Query>> {
+        let text = parsed.text();
+        if !parsed.has_syntax() {
+            return Ok(self.parse_query(&text)?);
+        }
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(parsed.clauses.len() + 1);
+        if !text.is_empty() {
+            clauses.push((Occur::Must, self.parse_query(&text)?));
+        } else if !parsed.has_positive_clauses() {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+        for clause in &parsed.clauses {
+            let occur = if clause.exclude { Occur::MustNot } else { Occur::Must };
+            let query: Box<dyn Query> = match &clause.term {
+                QueryTerm::Word(_) if !clause.exclude => continue, // already in text
+                QueryTerm::Word(word) => self.parse_query(word)?,
+                QueryTerm::Phrase(phrase) => self.phrase_query(phrase)?,
+                QueryTerm::Keyword(keyword) => {
+                    let keyword = self.normalize_keyword(keyword).to_ascii_lowercase();
+                    Box::new(TermQuery::new(Term::from_field_text(self.keyword_field, &keyword), IndexRecordOption::Basic))
+                },
+                QueryTerm::Category(slug) => {
+                    let facet = Facet::from_path(std::iter::once("category").chain(slug.split("::")));
+                    Box::new(TermQuery::new(Term::from_facet(self.facets_field, &facet), IndexRecordOption::Basic))
+                },
+                QueryTerm::Name(_) | QueryTerm::NamePrefix(_) => Box::new(self.name_query(&clause.term)?),
+            };
+            clauses.push((occur, query));
+        }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// Matches words in order in name, keywords or description (readme doesn't have positions indexed)
+    fn phrase_query(&self, phrase: &str) -> tantivy::Result<Box<dyn Query>> {
+        let mut per_field: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(3);
+        for field in [self.crate_name_field, self.keywords_field, self.description_field] {
+            let tokenizer = self.tantivy_index.tokenizer_for_field(field)?;
+            let mut terms = Vec::new();
+            tokenizer.token_stream(phrase).process(&mut |token| terms.push(Term::from_field_text(field, &token.text)));
+            let query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                1 => Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::WithFreqs)),
+                _ => Box::new(PhraseQuery::new(terms)),
+            };
+            per_field.push((Occur::Should, query));
+        }
+        Ok(Box::new(BooleanQuery::new(per_field)))
+    }
+
+    /// Crate name from any origin
+    fn name_query(&self, term: &QueryTerm) -> tantivy::Result<RegexQuery> {
+        let pattern = term.origin_name_pattern().ok_or_else(|| TantivyError::InvalidArgument("not a name".into()))?;
+        RegexQuery::from_pattern(&pattern, self.origin_pkey)
+    }
+
     fn fetch_docs(&self, searcher: &tantivy::Searcher, query: &dyn Query, limit: usize) -> Result<Vec<CrateFound>, TantivyError> {
         let top_docs = searcher.search(query, &TopDocs::with_limit(limit))?;
+        self.found_docs(searcher, top_docs)
+    }
+
+    fn found_docs(&self, searcher: &tantivy::Searcher, top_docs: Vec<(f32, tantivy::DocAddress)>) -> Result<Vec<CrateFound>, TantivyError> {
         top_docs.into_iter().map(|(relevance_score, doc_address)| {
             let retrieved_doc = searcher.doc(doc_address)?;
             let mut doc = self.tantivy_index.schema().to_named_doc(&retrieved_doc).0;
@@ -167,11 +424,13 @@ impl CrateSearchIndex {
     /// if sort_by_query_relevance is false, sorts by internal crate score (relevance is still used to select the crates)
     /// Second arg is distinctive keywords
     pub fn search(&self, query_text: &str, limit: usize, sort_by_query_relevance: bool) -> tantivy::Result<SearchResults> {
-        let query_text = query_text.trim();
-        let query = self.parse_query(query_text)?;
+        let parsed_query = SearchQuery::parse(query_text);
+        let query = self.build_query(&parsed_query)?;
+        // plain words are used for the exact match bonus and keyword suggestions
+        let text = parsed_query.text();
+        let query_text = text.as_str();
 
-        let reader = self.tantivy_index.reader()?;
-        let searcher = reader.searcher();
+        let searcher = self.reader.searcher();
         let expanded_limit = (limit + 50 + limit / 2).max(250); // 250 is a hack for https://github.com/tantivy-search/tantivy/issues/700
         let mut docs = self.fetch_docs(&searcher, &query, expanded_limit)?;
 
@@ -226,11 +485,123 @@ impl CrateSearchIndex {
         top_crates.iter_mut().for_each(|f| if f.score < min_score { f.score = min_score; });
         top_crates.append(&mut docs);
 
-        let normalized_query = query_text.split(' ').map(|w| self.normalize_keyword(w)).join(" ");
+        let normalized_query = parsed_query.clauses.iter().map(|c| match &c.term {
+            QueryTerm::Word(w) if !c.exclude => self.normalize_keyword(w).to_string(),
+            QueryTerm::Keyword(k) => QueryClause { exclude: c.exclude, term: QueryTerm::Keyword(self.normalize_keyword(k).into()) }.to_string(),
+            _ => c.to_string(),
+        }).join(" ");
+        let normalized_query = if normalized_query != parsed_query.to_string() { Some(normalized_query) } else { None };
         Ok(SearchResults {
             crates: top_crates,
             keywords: dividing_keywords,
-            normalized_query: if normalized_query != query_text { Some(normalized_query) } else { None },
+            normalized_query,
+            parsed_query,
+        })
+    }
+
+    /// Search with filters, returning one page of results sorted by relevance × crate score, and facet counts for the entire result set.
+    ///
+    /// Empty `query_text` lists all crates matching the filters, best crates first.
+    ///
+    /// `page` is 0-based, and can't be larger than `MAX_SEARCH_PAGE`.
+    ///
+    /// The index can contain stale crates. Results for which `keep` returns false are removed before pagination,
+    /// and subtracted from the total (only the ones seen while fetching the page are known).
+    pub fn search_filtered(&self, query_text: &str, filters: &SearchFilters, page: usize, per_page: usize, keep: impl Fn(&Origin) -> bool) -> tantivy::Result<FilteredSearchResults> {
+        if page > MAX_SEARCH_PAGE {
+            return Err(TantivyError::InvalidArgument(format!("page can't be larger than {MAX_SEARCH_PAGE}")));
+        }
+        let parsed_query = SearchQuery::parse(query_text);
+        let text = parsed_query.text();
+        let query_text = text.as_str();
+        let per_page = per_page.clamp(1, 100);
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(6);
+        clauses.push((Occur::Must, if parsed_query.clauses.is_empty() { Box::new(AllQuery) } else { self.build_query(&parsed_query)? }));
+
+        let category_facet = filters.category.as_deref().map(|slug| Facet::from_path(std::iter::once("category").chain(slug.split("::"))));
+        if let Some(facet) = &category_facet {
+            clauses.push((Occur::Must, Box::new(TermQuery::new(Term::from_facet(self.facets_field, facet), IndexRecordOption::Basic))));
+        }
+        if let Some(kind) = filters.origin_kind {
+            let facet = Facet::from_path(["origin", kind.as_str()]);
+            clauses.push((Occur::Must, Box::new(TermQuery::new(Term::from_facet(self.facets_field, &facet), IndexRecordOption::Basic))));
+        }
+        if let Some(family) = filters.license_family {
+            let facet = Facet::from_path(["license", family.as_str()]);
+            clauses.push((Occur::Must, Box::new(TermQuery::new(Term::from_facet(self.facets_field, &facet), IndexRecordOption::Basic))));
+        }
+        if let Some(keyword) = filters.keyword.as_deref() {
+            let keyword = self.normalize_keyword(keyword.trim()).to_ascii_lowercase();
+            clauses.push((Occur::Must, Box::new(TermQuery::new(Term::from_field_text(self.keyword_field, &keyword), IndexRecordOption::Basic))));
+        }
+        if let Some(min) = filters.min_monthly_downloads {
+            clauses.push((Occur::Must, Box::new(RangeQuery::new_u64(self.monthly_downloads, min..u64::MAX))));
+        }
+        if let Some(max) = filters.max_msrv {
+            // 0 is unknown
+            clauses.push((Occur::Must, Box::new(RangeQuery::new_u64(self.msrv_field, 1..u64::from(max) + 1))));
+        }
+        let query = BooleanQuery::new(clauses);
+
+        let mut facet_collector = FacetCollector::for_field(self.facets_field);
+        let category_facet_root = category_facet.clone().unwrap_or_else(|| Facet::from("/category"));
+        facet_collector.add_facet(category_facet_root.clone());
+        facet_collector.add_facet(Facet::from("/license"));
+        facet_collector.add_facet(Facet::from("/origin"));
+
+        let searcher = self.reader.searcher();
+        // fetch more than needed, because re-sorting by crate score changes the order, and some may be removed
+        let limit = (page + 1) * per_page;
+        let expanded_limit = (limit + 50 + limit / 2).max(250);
+        let (top_docs, total, facet_counts) = if query_text.is_empty() {
+            // all matches are equally relevant, so pick the best crates instead of arbitrary ones
+            let by_score = TopDocs::with_limit(expanded_limit).order_by_u64_field(self.crate_score);
+            let (top_docs, total, facet_counts) = searcher.search(&query, &(by_score, Count, facet_collector))?;
+            (top_docs.into_iter().map(|(_, doc_address)| (1., doc_address)).collect(), total, facet_counts)
+        } else {
+            searcher.search(&query, &(TopDocs::with_limit(expanded_limit), Count, facet_collector))?
+        };
+        let mut docs = self.found_docs(&searcher, top_docs)?;
+        let fetched = docs.len();
+        docs.retain(|doc| keep(&doc.origin));
+        let total = total.saturating_sub(fetched - docs.len());
+
+        for doc in &mut docs {
+            doc.score = doc.crate_base_score * if query_text.is_empty() { 1. } else { doc.relevance_score };
+            if doc.crate_name.eq_ignore_ascii_case(query_text) {
+                doc.score *= 1.5;
+            }
+        }
+        docs.sort_unstable_by(|a, b| b.score.total_cmp(&a.score));
+        let crates: Vec<_> = docs.into_iter().skip(page * per_page).take(per_page).collect();
+
+        let mut categories: Vec<_> = facet_counts.get(category_facet_root).filter_map(|(facet, count)| {
+            let mut path = facet.to_path();
+            if path.first() != Some(&"category") {
+                return None;
+            }
+            path.remove(0);
+            Some((path.join("::"), count))
+        }).collect();
+        categories.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+        let mut license_families: Vec<_> = facet_counts.get("/license").filter_map(|(facet, count)| {
+            Some((facet.to_path().last()?.parse().ok()?, count))
+        }).collect();
+        license_families.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+        let mut origins: Vec<_> = facet_counts.get("/origin").filter_map(|(facet, count)| {
+            Some((facet.to_path().last()?.parse().ok()?, count))
+        }).collect();
+        origins.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+        Ok(FilteredSearchResults {
+            crates,
+            total,
+            page,
+            per_page,
+            facets: SearchFacets { categories, license_families, origins },
         })
     }
 
@@ -349,13 +720,22 @@ fn take_int(val: Option<&Vec<Value>>) -> u64 {
     }
 }
 
-impl Indexer {
-    pub fn new(index: CrateSearchIndex) -> tantivy::Result<Self> {
+impl<'index> Indexer<'index> {
+    /// For reindexing of many crates
+    pub fn new(index: &'index CrateSearchIndex) -> tantivy::Result<Self> {
         Ok(Self { writer: index.tantivy_index.writer(250_000_000)?, index })
     }
 
+    /// For updating a few crates at a time. Fails with `LockBusy` if another indexer is running.
+    pub fn new_incremental(index: &'index CrateSearchIndex) -> tantivy::Result<Self> {
+        Ok(Self { writer: index.tantivy_index.writer_with_num_threads(1, 30_000_000)?, index })
+    }
+
+    /// Inserts or replaces the crate's document.
+    ///
     /// score is float 0..=1 range
-    pub fn add(&mut self, origin: &Origin, crate_name: &str, version: &str, description: &str, keywords: &[&str], readme: Option<&str>, monthly_downloads: u64, score: f64) -> Result<(), TantivyError> {
+    pub fn add(&mut self, origin: &Origin, crate_name: &str, version: &str, description: &str, keywords: &[&str], readme: Option<&str>, monthly_downloads: u64, score: f64, filter_fields: &CrateFilterFields<'_>) -> Result<(), TantivyError> {
+        let origin_kind = OriginKind::of(origin);
         let origin = origin.to_str();
         // delete old doc if any
         let pkey = Term::from_field_text(self.index.origin_pkey, &origin);
@@ -373,17 +753,51 @@ impl Indexer {
         doc.add_text(self.index.crate_version, version);
         doc.add_u64(self.index.monthly_downloads, monthly_downloads);
         doc.add_u64(self.index.crate_score, (score * CRATE_SCORE_MAX).ceil() as u64);
+
+        for slug in filter_fields.category_slugs {
+            doc.add_facet(self.index.facets_field, Facet::from_path(std::iter::once("category").chain(slug.split("::"))));
+        }
+        let license_family = filter_fields.license.map_or(LicenseFamily::Other, LicenseFamily::from_license);
+        doc.add_facet(self.index.facets_field, Facet::from_path(["license", license_family.as_str()]));
+        doc.add_facet(self.index.facets_field, Facet::from_path(["origin", origin_kind.as_str()]));
+        let mut kw_dedupe = HashSet::new();
+        for k in keywords {
+            let normalized = self.index.synonyms.normalize(k).to_ascii_lowercase();
+            if kw_dedupe.insert(normalized.clone()) {
+                doc.add_text(self.index.keyword_field, &normalized);
+            }
+        }
+        doc.add_u64(self.index.msrv_field, filter_fields.msrv.map_or(0, u64::from));
         self.writer.add_document(doc)?;
         Ok(())
     }
 
+    /// Removes the crate from search results (after commit)
+    pub fn delete(&mut self, origin: &Origin) {
+        self.writer.delete_term(Term::from_field_text(self.index.origin_pkey, &origin.to_str()));
+    }
+
     pub fn commit(&mut self) -> tantivy::Result<()> {
         self.writer.commit()?;
+        self.index.reload()?;
         Ok(())
     }
 
-    pub fn bye(self) -> tantivy::Result<CrateSearchIndex> {
+    pub fn bye(self) -> tantivy::Result<()> {
         self.writer.wait_merging_threads()?;
-        Ok(self.index)
+        Ok(())
     }
 }
+
+#[test]
+fn license_family() {
+    assert_eq!(LicenseFamily::Permissive, LicenseFamily::from_license("MIT/Apache-2.0"));
+    assert_eq!(LicenseFamily::Permissive, LicenseFamily::from_license("GPL-3.0 OR MIT"));
+    assert_eq!(LicenseFamily::Copyleft, LicenseFamily::from_license("AGPL-3.0 AND MIT"));
+    assert_eq!(LicenseFamily::WeakCopyleft, LicenseFamily::from_license("MPL-2.0"));
+    assert_eq!(LicenseFamily::PublicDomain, LicenseFamily::from_license("Unlicense OR MIT"));
+    assert_eq!(LicenseFamily::Other, LicenseFamily::from_license("proprietary"));
+    assert_eq!(Ok(LicenseFamily::WeakCopyleft), LicenseFamily::WeakCopyleft.as_str().parse());
+    assert_eq!(Ok(OriginKind::CratesIo), "crates.io".parse());
+    assert!("gpl".parse::<LicenseFamily>().is_err());
+}
@@ -0,0 +1,216 @@
+//! Search query mini-language
+//!
+//! * `word` — matches name, keywords, description and readme (all words are required)
+//! * `"exact phrase"` — words next to each other in the name, keywords or description
+//! * `keyword:async` (or `kw:`) — has the keyword (synonyms are normalized, e.g. `keyword:cli` = `keyword:command-line`)
+//! * `cat:parser-implementations` (or `category:`) — in the category or its subcategories. Use `::` for subcategories.
+//! * `name:serde` — exact crate name, `name:serde*` — name prefix
+//! * `-term` — exclude crates matching any of the above, e.g. `-tokio`, `-keyword:async`, `-"web framework"`
+//!
+//! Anything that doesn't parse as syntax is searched as plain text.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub clauses: Vec<QueryClause>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryClause {
+    /// `-` prefix
+    pub exclude: bool,
+    pub term: QueryTerm,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryTerm {
+    Word(String),
+    Phrase(String),
+    Keyword(String),
+    /// Slug, `::`-separated
+    Category(String),
+    /// Lowercase
+    Name(String),
+    /// Lowercase, without `*`
+    NamePrefix(String),
+}
+
+impl QueryTerm {
+    /// Regex for the `origin` field matching the crate name from any origin (`-` and `_` are interchangeable),
+    /// for `Name` and `NamePrefix` terms
+    pub fn origin_name_pattern(&self) -> Option<String> {
+        let (name, is_prefix) = match self {
+            Self::Name(name) => (name, false),
+            Self::NamePrefix(name) => (name, true),
+            _ => return None,
+        };
+        // name_term() has already removed characters that aren't allowed in crate names
+        let name_pattern = name.replace(['-', '_'], "[-_]");
+        Some(format!("(crates\\.io:|registry:[^/]*/|git(hub|lab):[^/]*/[^/]*/){name_pattern}{}", if is_prefix { ".*" } else { "" }))
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut clauses = Vec::new();
+        let mut rest = query.trim_start();
+        while !rest.is_empty() {
+            let (clause, remaining) = Self::parse_clause(rest);
+            if let Some(clause) = clause {
+                clauses.push(clause);
+            }
+            rest = remaining.trim_start();
+        }
+        Self { clauses }
+    }
+
+    fn parse_clause(input: &str) -> (Option<QueryClause>, &str) {
+        let (exclude, input) = match input.strip_prefix('-') {
+            Some(rest) if rest.starts_with(|c: char| !c.is_whitespace() && c != '-') => (true, rest),
+            _ => (false, input),
+        };
+
+        if let Some(quoted) = input.strip_prefix('"') {
+            let (phrase, rest) = quoted.split_once('"').unwrap_or((quoted, ""));
+            let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+            let term = if phrase.is_empty() { None } else { Some(QueryTerm::Phrase(phrase)) };
+            return (term.map(|term| QueryClause { exclude, term }), rest);
+        }
+
+        let end = match input.find(":\"") {
+            // field:"quoted value"
+            Some(colon) if !input[..colon].contains(char::is_whitespace) => {
+                input[colon + 2..].find('"').map_or(input.len(), |quote| colon + 2 + quote + 1)
+            },
+            _ => input.find(char::is_whitespace).unwrap_or(input.len()),
+        };
+        let (token, rest) = input.split_at(end);
+        let term = match token.split_once(':').map(|(field, value)| (field, value.trim_matches('"'))) {
+            Some((field, value)) if !value.is_empty() && !value.starts_with(':') => match field.to_ascii_lowercase().as_str() {
+                "keyword" | "keywords" | "kw" => Some(QueryTerm::Keyword(value.trim_start_matches('#').to_lowercase())),
+                "cat" | "category" => Some(QueryTerm::Category(value.trim_matches(':').to_ascii_lowercase())),
+                "name" => Self::name_term(value),
+                // could be a path like std::io or a URL
+                _ => Self::word_term(token),
+            },
+            _ => Self::word_term(token),
+        };
+        (term.map(|term| QueryClause { exclude, term }), rest)
+    }
+
+    fn name_term(value: &str) -> Option<QueryTerm> {
+        let (name, is_prefix) = match value.strip_suffix('*') {
+            Some(name) => (name, true),
+            None => (value, false),
+        };
+        // crate names can't contain anything else, and this also makes the name safe to use in a regex
+        let name: String = name.chars().filter(|&c| c.is_ascii_alphanumeric() || c == '-' || c == '_').map(|c| c.to_ascii_lowercase()).collect();
+        if name.is_empty() {
+            return None;
+        }
+        Some(if is_prefix { QueryTerm::NamePrefix(name) } else { QueryTerm::Name(name) })
+    }
+
+    fn word_term(token: &str) -> Option<QueryTerm> {
+        let token = token.trim_matches('"');
+        if token.chars().any(|c| c.is_alphanumeric()) {
+            Some(QueryTerm::Word(token.to_owned()))
+        } else {
+            None
+        }
+    }
+
+    /// True if anything other than plain included words has been used
+    pub fn has_syntax(&self) -> bool {
+        self.clauses.iter().any(|c| c.exclude || !matches!(c.term, QueryTerm::Word(_)))
+    }
+
+    /// Included plain words, for query parser and keyword suggestions
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for c in &self.clauses {
+            if let (false, QueryTerm::Word(w)) = (c.exclude, &c.term) {
+                if !out.is_empty() {
+                    out.push(' ');
+                }
+                out.push_str(w);
+            }
+        }
+        out
+    }
+
+    pub fn has_positive_clauses(&self) -> bool {
+        self.clauses.iter().any(|c| !c.exclude)
+    }
+}
+
+impl fmt::Display for QueryClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exclude {
+            f.write_str("-")?;
+        }
+        match &self.term {
+            QueryTerm::Word(w) => f.write_str(w),
+            QueryTerm::Phrase(p) => write!(f, "\"{p}\""),
+            QueryTerm::Keyword(k) => write!(f, "keyword:{k}"),
+            QueryTerm::Category(c) => write!(f, "cat:{c}"),
+            QueryTerm::Name(n) => write!(f, "name:{n}"),
+            QueryTerm::NamePrefix(n) => write!(f, "name:{n}*"),
+        }
+    }
+}
+
+impl fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, c) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            c.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_query_syntax() {
+    let q = SearchQuery::parse(r#"http  -tokio keyword:Async cat:parser-implementations name:serde* "exact   phrase" -"web framework" std::io"#);
+    assert_eq!(q.clauses, vec![
+        QueryClause { exclude: false, term: QueryTerm::Word("http".into()) },
+        QueryClause { exclude: true, term: QueryTerm::Word("tokio".into()) },
+        QueryClause { exclude: false, term: QueryTerm::Keyword("async".into()) },
+        QueryClause { exclude: false, term: QueryTerm::Category("parser-implementations".into()) },
+        QueryClause { exclude: false, term: QueryTerm::NamePrefix("serde".into()) },
+        QueryClause { exclude: false, term: QueryTerm::Phrase("exact phrase".into()) },
+        QueryClause { exclude: true, term: QueryTerm::Phrase("web framework".into()) },
+        QueryClause { exclude: false, term: QueryTerm::Word("std::io".into()) },
+    ]);
+    assert!(q.has_syntax());
+    assert_eq!("http std::io", q.text());
+
+    let q = SearchQuery::parse("c++ - --");
+    assert!(!q.has_syntax());
+    assert_eq!("c++", q.text());
+    assert_eq!("name:foo-bar", SearchQuery::parse("name:Foo-Bar").to_string());
+    assert_eq!("keyword:command-line -cat:a::b", SearchQuery::parse(r#"keywords:"command-line" -category:a::b"#).to_string());
+    assert_eq!(r#"-"unterminated""#, SearchQuery::parse(r#"-"unterminated"#).to_string());
+}
+
+#[test]
+fn name_matches_all_origins() {
+    // the index matches the regex against the whole origin string
+    let matcher = |term: &QueryTerm| regex::Regex::new(&format!("^(?:{})$", term.origin_name_pattern().unwrap())).unwrap();
+    let name = matcher(&QueryTerm::Name("foo-bar".into()));
+    assert!(name.is_match("crates.io:foo_bar"));
+    assert!(name.is_match("registry:my-company/foo-bar"));
+    assert!(name.is_match("github:owner/repo/foo-bar"));
+    assert!(name.is_match("gitlab:owner/repo/foo_bar"));
+    assert!(!name.is_match("crates.io:foo-bar2"));
+    assert!(!name.is_match("registry:foo-bar/baz"));
+
+    let prefix = matcher(&QueryTerm::NamePrefix("foo".into()));
+    assert!(prefix.is_match("registry:my-company/foo-bar"));
+    assert!(!prefix.is_match("registry:foo/bar"));
+    assert!(QueryTerm::Word("foo".into()).origin_name_pattern().is_none());
+}
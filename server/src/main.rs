@@ -15,13 +15,15 @@ use chrono::prelude::*;
 use anyhow::{anyhow, Context};
 use futures::future::Future;
 use futures::future::FutureExt;
-use kitchen_sink::filter::ImageOptimAPIFilter;
+use kitchen_sink::filter::{ImageOptimAPIFilter, LocalImageProxy, NoImageFilter};
 use kitchen_sink::KitchenSink;
+use kitchen_sink::SiteConfig;
 use kitchen_sink::Origin;
+use kitchen_sink::ProjectFile;
 use locale::Numeric;
-use render_readme::{Highlighter, Markup, Renderer};
+use render_readme::{Highlighter, ImageFilter, Markup, Renderer};
 use repo_url::SimpleRepo;
-use search_index::CrateSearchIndex;
+use search_index::{CodeQuery, CodeSearchIndex, CrateSearchIndex, LicenseFamily, OriginKind, SearchFilters, MAX_SEARCH_PAGE};
 use ahash::HashMap;
 use std::convert::TryInto;
 use std::env;
@@ -36,6 +38,7 @@ use urlencoding::Encoded;
 use once_cell::sync::Lazy;
 use log::{debug, info, warn, error};
 
+mod search_updater;
 mod writer;
 
 #[global_allocator]
@@ -48,7 +51,11 @@ static PAGES_RENDER: Lazy<std::sync::Mutex<HashMap<Box<str>, Arc<tokio::sync::Mu
 
 struct ServerState {
     markup: Renderer,
+    /// Serves images if `site.toml` sets `proxy = "local"`
+    image_proxy: Option<Arc<LocalImageProxy>>,
     index: CrateSearchIndex,
+    /// Built separately by `reindex_code`, may be missing
+    code_index: Option<CodeSearchIndex>,
     crates: ArcSwap<KitchenSink>,
     page_cache_dir: PathBuf,
     data_dir: PathBuf,
@@ -102,7 +109,10 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
     let public_document_root: PathBuf = env::var_os("DOCUMENT_ROOT").map(From::from).unwrap_or_else(|| "../style/public".into());
     let page_cache_dir: PathBuf = "/var/tmp/crates-server".into();
     let data_dir: PathBuf = env::var_os("CRATE_DATA_DIR").map(From::from).unwrap_or_else(|| "../data".into());
-    let github_token = env::var("GITHUB_TOKEN").context("GITHUB_TOKEN missing")?;
+    let site_config = SiteConfig::load(&data_dir)?;
+    if site_config.github.enabled && site_config.github_token().is_none() {
+        anyhow::bail!("GITHUB_TOKEN missing. Set `enabled = false` in the [github] section of site.toml to run without GitHub");
+    }
 
     let _ = std::fs::create_dir_all(&page_cache_dir);
     assert!(page_cache_dir.exists(), "{} does not exist", page_cache_dir.display());
@@ -112,19 +122,32 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
 
     let crates = rt.spawn({
         let data_dir = data_dir.clone();
-        let github_token = github_token.clone();
+        let site_config = site_config.clone();
         async move {
-            KitchenSink::new(&data_dir, &github_token).await
+            KitchenSink::new_with_config(&data_dir, site_config).await
         }
     }).await??;
-    let image_filter = Arc::new(ImageOptimAPIFilter::new("czjpqfbdkz", crates.main_cache_dir().join("images.db")).await?);
+    let mut image_proxy = None;
+    let image_filter: Arc<dyn ImageFilter> = match site_config.images.proxy.as_str() {
+        "imageoptim" => Arc::new(ImageOptimAPIFilter::new(&site_config.images.imageoptim_id, crates.main_cache_dir().join("images.db")).await?),
+        "local" => {
+            let proxy = Arc::new(LocalImageProxy::new("/img/", crates.main_cache_dir().join("images")).await?);
+            image_proxy = Some(proxy.clone());
+            proxy
+        },
+        "none" => Arc::new(NoImageFilter),
+        other => anyhow::bail!("unknown image proxy '{other}' in site.toml"),
+    };
     let markup = Renderer::new_filter(Some(Highlighter::new()), image_filter);
 
     let index = CrateSearchIndex::new(&data_dir)?;
+    let code_index = CodeSearchIndex::open(&data_dir)?;
 
     let state = web::Data::new(ServerState {
         markup,
+        image_proxy,
         index,
+        code_index,
         crates: ArcSwap::from_pointee(crates),
         page_cache_dir,
         data_dir: data_dir.clone(),
@@ -153,6 +176,7 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
                             let o = Origin::from_str(&origin_str);
                             let cache_path = state.page_cache_dir.join(cache_file_name_for_origin(&o));
                             let _ = std::fs::remove_file(&cache_path);
+                            remove_api_cache_files(&state, &o);
                         },
                         CrateIndexed(origin_str) => {
                             info!("Purging local cache {}", origin_str);
@@ -160,6 +184,7 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
                             state.crates.load().reload_indexed_crate(&o);
                             let cache_path = state.page_cache_dir.join(cache_file_name_for_origin(&o));
                             let _ = std::fs::remove_file(&cache_path);
+                            remove_api_cache_files(&state, &o);
                             background_refresh(state.clone(), cache_path, render_crate_page(state.clone(), o));
                         },
                         CrateNeedsReindexing(origin_str) => {
@@ -171,8 +196,20 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
                                 let _ = s2.crates.load().rich_crate_version_async(&o).await;
                             });
                         },
-                        DailyStatsUpdated => {
+                        DailyStatsUpdated(updated_at) => {
                             let _ = std::fs::remove_file(state.page_cache_dir.join("_stats_.html"));
+                            let origins = match state.crates.load().daily_stats_changes(&updated_at) {
+                                Some(o) => o,
+                                None => {
+                                    warn!("List of crates with stats changed at {} is gone", updated_at);
+                                    continue;
+                                },
+                            };
+                            info!("Purging local cache of {} crates with new stats", origins.len());
+                            for o in &origins {
+                                let _ = std::fs::remove_file(state.page_cache_dir.join(cache_file_name_for_origin(o)));
+                                remove_api_cache_files(&state, o);
+                            }
                         },
                     }
                 }
@@ -181,12 +218,20 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
         }
     });
 
+    state.rt.spawn(search_updater::run(state.clone()));
+
     // refresher thread
     state.rt.spawn({
         let state = state.clone();
         let timestamp = timestamp.clone();
         async move {
             let mut last_reload = Instant::now();
+            // fetched crates are visible only after a reload
+            let mut needs_seeding = state.crates.load().crates_io_index_needs_seeding();
+            if needs_seeding {
+                info!("Crates index is empty, seeding it");
+                state.crates.load_full().update().await;
+            }
             state.crates.load().prewarm().await;
             loop {
                 tokio::time::sleep(Duration::from_secs(1)).await;
@@ -197,11 +242,15 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
                 } else if last_reload.elapsed() > Duration::from_secs(30 * 60) {
                     info!("Reloading state on a timer");
                     true
+                } else if needs_seeding {
+                    needs_seeding = false;
+                    true
                 } else {
                     false
                 };
                 if should_reload {
-                    match KitchenSink::new(&data_dir, &github_token).await {
+                    let site_config = SiteConfig::load(&data_dir).unwrap_or_else(|e| { error!("{e}"); site_config.clone() });
+                    match KitchenSink::new_with_config(&data_dir, site_config).await {
                         Ok(k) => {
                             info!("Reloading state");
                             timestamp.store(state.start_time.elapsed().as_secs() as u32, Ordering::SeqCst);
@@ -260,35 +309,7 @@ async fn run_server(rt: Handle) -> Result<(), anyhow::Error> {
             .wrap(middleware::Compress::default())
             .wrap(middleware::DefaultHeaders::new().add(("x-powered-by", HeaderValue::from_static(concat!("actix-web lib.rs/", env!("CARGO_PKG_VERSION"))))))
             .wrap(middleware::Logger::default())
-            .route("/", web::get().to(handle_home))
-            .route("/search", web::get().to(handle_search))
-            .route("/game-engines", web::get().to(handle_game_redirect))
-            .route("/index", web::get().to(handle_search)) // old crates.rs/index url
-            .route("/categories/{rest:.*}", web::get().to(handle_redirect))
-            .route("/new", web::get().to(handle_new_trending))
-            .route("/stats", web::get().to(handle_global_stats))
-            .route("/keywords/{keyword}", web::get().to(handle_keyword))
-            .route("/crates/{crate}", web::get().to(handle_crate))
-            .route("/crates/{crate}/versions", web::get().to(handle_crate_all_versions))
-            .route("/crates/{crate}/source", web::get().to(handle_crate_source_redirect))
-            .route("/crates/{crate}/rev", web::get().to(handle_crate_reverse_dependencies))
-            .route("/crates/{crate}/reverse_dependencies", web::get().to(handle_crate_reverse_dependencies_redir))
-            .route("/crates/{crate}/crev", web::get().to(handle_crate_reviews))
-            .route("/~{author}", web::get().to(handle_author))
-            .route("/~{author}/dash", web::get().to(handle_maintainer_dashboard_html))
-            .route("/~{author}/dash.xml", web::get().to(handle_maintainer_dashboard_xml))
-            .route("/~", web::get().to(handle_maintainer_form))
-            .route("/dash", web::get().to(handle_maintainer_form))
-            .route("/users/{author}", web::get().to(handle_author_redirect))
-            .route("/install/{crate:.*}", web::get().to(handle_install))
-            .route("/compat/{crate:.*}", web::get().to(handle_compat))
-            .route("/debug/{crate:.*}", web::get().to(handle_debug))
-            .route("/{host}/{owner}/{repo}/{crate}", web::get().to(handle_repo_crate))
-            .route("/{host}/{owner}/{repo}/{crate}/versions", web::get().to(handle_repo_crate_all_versions))
-            .route("/atom.xml", web::get().to(handle_feed))
-            .route("/sitemap.xml", web::get().to(handle_sitemap))
-            .route("/{crate}/info/refs", web::get().to(handle_git_clone))
-            .route("/crates/{crate}/info/refs", web::get().to(handle_git_clone))
+            .configure(routes)
             .service(actix_files::Files::new("/", &public_document_root))
             .default_service(web::route().to(default_handler))
     })
@@ -377,6 +398,46 @@ async fn handle_maintainer_form(req: HttpRequest) -> Result<HttpResponse, Server
         .body(page))
 }
 
+/// All routes, except static files and the fallback `default_handler`
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(handle_home))
+        .route("/search", web::get().to(handle_search))
+        .route("/game-engines", web::get().to(handle_game_redirect))
+        .route("/index", web::get().to(handle_search)) // old crates.rs/index url
+        .route("/categories/{rest:.*}", web::get().to(handle_redirect))
+        .route("/new", web::get().to(handle_new_trending))
+        .route("/stats", web::get().to(handle_global_stats))
+        .route("/keywords/{keyword}", web::get().to(handle_keyword))
+        .route("/crates/{crate}", web::get().to(handle_crate))
+        .route("/crates/{crate}/versions", web::get().to(handle_crate_all_versions))
+        .route("/crates/{crate}/source", web::get().to(handle_crate_source_redirect))
+        .route("/crates/{crate}/rev", web::get().to(handle_crate_reverse_dependencies))
+        .route("/crates/{crate}/reverse_dependencies", web::get().to(handle_crate_reverse_dependencies_redir))
+        .route("/crates/{crate}/crev", web::get().to(handle_crate_reviews))
+        .route("/api/v1/crates/{crate}", web::get().to(handle_api_crate))
+        .route("/api/v1/crates/{crate}/versions", web::get().to(handle_api_crate_all_versions))
+        .route("/api/v1/crates/{crate}/rev", web::get().to(handle_api_crate_reverse_dependencies))
+        .route("/api/v1/search", web::get().to(handle_api_search))
+        .route("/api/v1/msrv-pins", web::post().to(handle_api_msrv_pins))
+        .route("/~{author}", web::get().to(handle_author))
+        .route("/~{author}/dash", web::get().to(handle_maintainer_dashboard_html))
+        .route("/~{author}/dash.xml", web::get().to(handle_maintainer_dashboard_xml))
+        .route("/~", web::get().to(handle_maintainer_form))
+        .route("/dash", web::get().to(handle_maintainer_form))
+        .route("/users/{author}", web::get().to(handle_author_redirect))
+        .route("/install/{crate:.*}", web::get().to(handle_install))
+        .route("/compat/{crate:.*}", web::get().to(handle_compat))
+        .route("/debug/{crate:.*}", web::get().to(handle_debug))
+        .route("/reg/{registry}/{crate}", web::get().to(handle_registry_crate))
+        .route("/{host}/{owner}/{repo}/{crate}", web::get().to(handle_repo_crate))
+        .route("/{host}/{owner}/{repo}/{crate}/versions", web::get().to(handle_repo_crate_all_versions))
+        .route("/img/{name}", web::get().to(handle_image))
+        .route("/atom.xml", web::get().to(handle_feed))
+        .route("/sitemap.xml", web::get().to(handle_sitemap))
+        .route("/{crate}/info/refs", web::get().to(handle_git_clone))
+        .route("/crates/{crate}/info/refs", web::get().to(handle_git_clone));
+}
+
 async fn default_handler(req: HttpRequest) -> Result<HttpResponse, ServerError> {
     let state: &AServerState = req.app_data().expect("appdata");
     let path = req.uri().path();
@@ -438,6 +499,19 @@ fn invert_hyphens(name: &str) -> String {
     name.chars().map(|c| if c == '-' {'_'} else if c == '_' {'-'} else {c.to_ascii_lowercase()}).collect()
 }
 
+/// Takes the crate name out of a bare name, `/{crate}`, `/crates/{crate}/…` or `/install/{crate}`.
+/// Other routes (git repos, registries, users) don't have crates.io names.
+fn crates_io_name_from_path(path: &str) -> Option<&str> {
+    let mut segments = path.trim_matches('/').split('/');
+    let name = match (segments.next()?, segments.next()) {
+        (name, None) => name,
+        ("crates", Some(name)) => name,
+        ("install" | "compat", Some(name)) if segments.next().is_none() => name,
+        _ => return None,
+    };
+    Some(name).filter(|name| Origin::is_valid_crate_name(name))
+}
+
 fn render_404_page(state: &AServerState, path: &str, item_name: &str) -> impl Future<Output = Result<HttpResponse, ServerError>> {
     let item_name = item_name.to_owned();
     let decoded = decode(path).ok();
@@ -445,22 +519,34 @@ fn render_404_page(state: &AServerState, path: &str, item_name: &str) -> impl Fu
 
     let query = rawtext.chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).take(100).collect::<String>();
     let query = query.trim().to_owned();
+    // only crates.io names can be guessed
+    let crate_name = match item_name.as_str() {
+        "crate" | "crate or category" => crates_io_name_from_path(rawtext).map(String::from),
+        _ => None,
+    };
     let state = state.clone();
 
-    tokio::task::spawn_blocking(move || {
-        let results = state.index.search(&query, 7, false).unwrap_or_default();
-        let mut page: Vec<u8> = Vec::with_capacity(32000);
-        front_end::render_404_page(&mut page, &query, &item_name, &results.crates, &state.markup)?;
-        Ok(page)
-    }).map(|res| {
-        res?.map(|page| {
-            HttpResponse::NotFound()
-                .content_type("text/html;charset=UTF-8")
-                .no_chunking(page.len() as u64)
-                .insert_header(("Cache-Control", "public, max-age=60, stale-while-revalidate=3600, stale-if-error=3600"))
-                .body(page)
-        })
-    })
+    async move {
+        let similar_names = match crate_name {
+            Some(name) => {
+                let crates = state.crates.load_full();
+                rt_run_timeout(&state.rt, "similar", 5, async move { crates.similar_crate_names(&name, 5).await }).await
+                    .map_err(|e| warn!("similar names: {e}")).unwrap_or_default()
+            },
+            None => Vec::new(),
+        };
+        let page = tokio::task::spawn_blocking(move || {
+            let results = state.index.search(&query, 7, false).unwrap_or_default();
+            let mut page: Vec<u8> = Vec::with_capacity(32000);
+            front_end::render_404_page(&mut page, &query, &item_name, &results.crates, &similar_names, &state.markup)?;
+            Ok::<_, anyhow::Error>(page)
+        }).await??;
+        Ok(HttpResponse::NotFound()
+            .content_type("text/html;charset=UTF-8")
+            .no_chunking(page.len() as u64)
+            .insert_header(("Cache-Control", "public, max-age=60, stale-while-revalidate=3600, stale-if-error=3600"))
+            .body(page))
+    }
 }
 
 async fn handle_category(req: HttpRequest, cat: &'static Category) -> Result<HttpResponse, ServerError> {
@@ -581,6 +667,20 @@ async fn handle_repo_crate(req: HttpRequest) -> Result<HttpResponse, ServerError
     }).await?))
 }
 
+async fn handle_registry_crate(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let state: &AServerState = req.app_data().expect("appdata");
+    let inf = req.match_info();
+    let crate_name = inf.query("crate");
+    let origin = match Origin::try_from_registry(inf.query("registry"), crate_name) {
+        Some(o) if state.crates.load().crate_exists(&o) => o,
+        _ => return render_404_page(state, crate_name, "registry crate").await,
+    };
+
+    Ok(serve_page(with_file_cache(state, &cache_file_name_for_origin(&origin), 86400, {
+        render_crate_page(state.clone(), origin)
+    }).await?))
+}
+
 fn get_origin_from_req_match(req: &HttpRequest) -> Result<Origin, &str> {
     let inf = req.match_info();
     let slug = inf.query("host");
@@ -606,6 +706,7 @@ fn get_origin_from_subpath(q: &actix_web::dev::Path<Url>) -> Option<Origin> {
     let first = parts.next()?;
     match parts.next() {
         None => Origin::try_from_crates_io_name(first),
+        Some(registry) if first == "reg" => Origin::try_from_registry(registry, parts.next()?),
         Some(owner) => {
             let repo = parts.next()?;
             let package = parts.next()?;
@@ -620,13 +721,16 @@ fn get_origin_from_subpath(q: &actix_web::dev::Path<Url>) -> Option<Origin> {
 
 async fn handle_compat(req: HttpRequest) -> Result<HttpResponse, ServerError> {
     let origin = get_origin_from_subpath(req.match_info()).ok_or_else(|| anyhow!("boo"))?;
+    // ?rustc=1.56 suggests dependency versions for that Rust version
+    let qs = qstring::QString::from(req.query_string());
+    let target_rustc = qs.get("rustc").and_then(parse_rustc_minor);
     let state: &AServerState = req.app_data().expect("appdata");
     let crates = state.crates.load();
     crates.reload_indexed_crate(&origin);
     let page = rt_run_timeout(&state.rt, "dbgcrate", 60, async move {
         let all = crates.rich_crate_async(&origin).await?;
         let mut page: Vec<u8> = Vec::with_capacity(32000);
-        front_end::render_compat_page(&mut page, all, &crates).await?;
+        front_end::render_compat_page(&mut page, all, &crates, target_rustc).await?;
         Ok(page)
     }).await?;
     Ok(HttpResponse::Ok()
@@ -808,6 +912,24 @@ fn cache_file_name_for_origin(origin: &Origin) -> String {
             let slug = if let Origin::GitHub {..} = origin { "gh" } else { "lab" };
             format!("{slug},{},{},{package}.html", repo.owner, repo.repo)
         }
+        Origin::Registry { registry, package } => {
+            // names are validated, so they can't contain path separators
+            format!("reg,{registry},{package}.html")
+        }
+    }
+}
+
+/// Variants of crate pages served as JSON by the API
+const API_PAGE_KINDS: [&str; 3] = ["crate", "versions", "rev"];
+
+fn api_cache_file_name_for_origin(origin: &Origin, kind: &str) -> String {
+    let html_name = cache_file_name_for_origin(origin);
+    format!("api,{kind},{}.json", html_name.trim_end_matches(".html"))
+}
+
+fn remove_api_cache_files(state: &ServerState, origin: &Origin) {
+    for kind in API_PAGE_KINDS {
+        let _ = std::fs::remove_file(state.page_cache_dir.join(api_cache_file_name_for_origin(origin, kind)));
     }
 }
 
@@ -843,7 +965,7 @@ async fn handle_crate_all_versions(req: HttpRequest) -> Result<HttpResponse, Ser
     let crates = state.crates.load();
     let origin = match Origin::try_from_crates_io_name(crate_name).filter(|o| crates.crate_exists(o)) {
         Some(o) => o,
-        None => return render_404_page(state, crate_name, "git crate").await,
+        None => return render_404_page(state, crate_name, "crate").await,
     };
     Ok(serve_page(render_crate_all_versions(state.clone(), origin).await?))
 }
@@ -858,7 +980,7 @@ async fn handle_crate_source_redirect(req: HttpRequest) -> Result<HttpResponse,
     let crates = state.crates.load();
     let origin = match Origin::try_from_crates_io_name(crate_name).filter(|o| crates.crate_exists(o)) {
         Some(o) => o,
-        None => return render_404_page(state, crate_name, "git crate").await,
+        None => return render_404_page(state, crate_name, "crate").await,
     };
 
     let at_ver = at_ver.to_owned();
@@ -1102,7 +1224,7 @@ async fn render_crate_all_versions(state: AServerState, origin: Origin) -> Resul
     rt_run_timeout(&s.rt, "allver", 60, async move {
         let crates = state.crates.load();
         let (all, ver) = futures::try_join!(crates.rich_crate_async(&origin), crates.rich_crate_version_async(&origin))?;
-        let last_modified = Some(all.most_recent_release());
+        let last_modified = all.most_recent_release();
         let mut page: Vec<u8> = Vec::with_capacity(60000);
         front_end::render_all_versions_page(&mut page, all, &ver, &crates).await?;
         minify_html(&mut page);
@@ -1124,6 +1246,158 @@ async fn render_crate_reverse_dependencies(state: AServerState, origin: Origin)
     }).await
 }
 
+fn api_crate_origin(req: &HttpRequest, state: &ServerState) -> Result<Origin, HttpResponse> {
+    let crate_name = req.match_info().query("crate");
+    let crates = state.crates.load();
+    Origin::try_from_crates_io_name(crate_name).filter(|o| crates.crate_exists(o))
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "crate not found"))
+}
+
+async fn handle_api_crate(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let state: &AServerState = req.app_data().expect("appdata");
+    let origin = match api_crate_origin(&req, state) {
+        Ok(o) => o,
+        Err(res) => return Ok(res),
+    };
+    Ok(serve_json(with_file_cache(state, &api_cache_file_name_for_origin(&origin, "crate"), 600, {
+        let state = state.clone();
+        run_timeout("apicrate", 30, async move {
+            let crates = state.crates.load();
+            let (all, ver) = futures::try_join!(crates.rich_crate_async(&origin), crates.rich_crate_version_async(&origin))?;
+            let mut page: Vec<u8> = Vec::with_capacity(8000);
+            Box::pin(front_end::render_crate_api_json(&mut page, &all, &ver, &crates, &state.markup)).await?;
+            mark_server_still_alive(&state);
+            Ok::<_, anyhow::Error>((page, all.most_recent_release()))
+        })
+    }).await?))
+}
+
+async fn handle_api_crate_all_versions(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let state: &AServerState = req.app_data().expect("appdata");
+    let origin = match api_crate_origin(&req, state) {
+        Ok(o) => o,
+        Err(res) => return Ok(res),
+    };
+    Ok(serve_json(with_file_cache(state, &api_cache_file_name_for_origin(&origin, "versions"), 24 * 3600, {
+        let state = state.clone();
+        run_timeout("apiallver", 60, async move {
+            let crates = state.crates.load();
+            let (all, ver) = futures::try_join!(crates.rich_crate_async(&origin), crates.rich_crate_version_async(&origin))?;
+            let last_modified = all.most_recent_release();
+            let mut page: Vec<u8> = Vec::with_capacity(16000);
+            front_end::render_all_versions_api_json(&mut page, all, &ver, &crates).await?;
+            mark_server_still_alive(&state);
+            Ok::<_, anyhow::Error>((page, last_modified))
+        })
+    }).await?))
+}
+
+async fn handle_api_crate_reverse_dependencies(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let state: &AServerState = req.app_data().expect("appdata");
+    let origin = match api_crate_origin(&req, state) {
+        Ok(o) => o,
+        Err(res) => return Ok(res),
+    };
+    Ok(serve_json(with_file_cache(state, &api_cache_file_name_for_origin(&origin, "rev"), 24 * 3600, {
+        let state = state.clone();
+        run_timeout("apirevdeps", 30, async move {
+            let crates = state.crates.load();
+            let ver = crates.rich_crate_version_async(&origin).await?;
+            let mut page: Vec<u8> = Vec::with_capacity(16000);
+            front_end::render_crate_reverse_dependencies_api_json(&mut page, &ver, &crates, &state.markup).await?;
+            mark_server_still_alive(&state);
+            Ok::<_, anyhow::Error>((page, None))
+        })
+    }).await?))
+}
+
+/// `?q=&category=&keyword=&min_downloads=&origin=crates-io|git&license=&msrv=1.56&page=&per_page=`
+async fn handle_api_search(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let qs = req.query_string().replace('+', "%20");
+    let qs = qstring::QString::from(qs.as_str());
+    let query = qs.get("q").unwrap_or("").trim().to_owned();
+
+    let filters = match parse_search_filters(&qs) {
+        Ok(f) => f,
+        Err(msg) => return Ok(api_error(StatusCode::BAD_REQUEST, msg)),
+    };
+    if query.is_empty() && filters.is_empty() {
+        return Ok(api_error(StatusCode::BAD_REQUEST, "missing q or a filter"));
+    }
+    let page_num = qs.get("page").and_then(|p| p.parse().ok()).unwrap_or(0);
+    if page_num > MAX_SEARCH_PAGE {
+        return Ok(api_error(StatusCode::BAD_REQUEST, "page is too large, narrow down the search instead"));
+    }
+    let per_page = qs.get("per_page").and_then(|p| p.parse().ok()).unwrap_or(20);
+
+    let state: &AServerState = req.app_data().expect("appdata");
+    let page = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || {
+            let crates = state.crates.load();
+            // search index can contain stale items
+            let results = state.index.search_filtered(&query, &filters, page_num, per_page, |origin| crates.crate_exists(origin))?;
+
+            let mut page = Vec::with_capacity(8000);
+            front_end::render_search_api_json(&mut page, &query, &results)?;
+            Ok::<_, anyhow::Error>(Rendered {page, cache_time: 600, refresh: false, last_modified: None})
+        }
+    }).await??;
+    Ok(serve_json(page))
+}
+
+fn parse_search_filters(qs: &qstring::QString) -> Result<SearchFilters, &'static str> {
+    let non_empty = |name: &str| qs.get(name).map(str::trim).filter(|v| !v.is_empty());
+    Ok(SearchFilters {
+        category: non_empty("category").map(|c| c.trim_matches(':').to_owned()),
+        keyword: non_empty("keyword").map(|k| k.to_lowercase()),
+        min_monthly_downloads: non_empty("min_downloads").map(|d| d.parse().map_err(|_| "invalid min_downloads")).transpose()?,
+        origin_kind: non_empty("origin").map(|o| o.parse::<OriginKind>().map_err(|_| "origin must be crates-io or git")).transpose()?,
+        license_family: non_empty("license").map(|l| l.parse::<LicenseFamily>().map_err(|_| "license must be permissive, weak-copyleft, copyleft, public-domain or other")).transpose()?,
+        // accepts "1.56", "1.56.1" or "56"
+        max_msrv: non_empty("msrv").map(|v| parse_rustc_minor(v).ok_or("invalid msrv")).transpose()?,
+    })
+}
+
+/// Accepts "1.56", "1.56.1" or "56"
+fn parse_rustc_minor(v: &str) -> Option<u16> {
+    let mut parts = v.split('.');
+    let first = parts.next().and_then(|p| p.parse::<u16>().ok());
+    match (first, parts.next()) {
+        (Some(1), Some(minor)) => minor.parse().ok(),
+        (Some(minor), None) => Some(minor),
+        _ => None,
+    }
+}
+
+/// POST `Cargo.toml` or `Cargo.lock` to get dependency versions to pin for `?rustc=1.56`
+async fn handle_api_msrv_pins(req: HttpRequest, body: String) -> Result<HttpResponse, ServerError> {
+    let qs = qstring::QString::from(req.query_string());
+    let rustc = match qs.get("rustc").and_then(parse_rustc_minor) {
+        Some(r) => r,
+        None => return Ok(api_error(StatusCode::BAD_REQUEST, "missing or invalid rustc version")),
+    };
+
+    let state: &AServerState = req.app_data().expect("appdata");
+    let page = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || {
+            let crates = state.crates.load();
+            let pins = match crates.msrv_pins(ProjectFile::detect(&body), rustc) {
+                Ok(pins) => pins,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            let mut page = Vec::with_capacity(2000);
+            front_end::render_msrv_pins_json(&mut page, &pins)?;
+            Ok::<_, anyhow::Error>(Ok(Rendered {page, cache_time: 0, refresh: false, last_modified: None}))
+        }
+    }).await??;
+    Ok(match page {
+        Ok(page) => serve_json(page),
+        Err(msg) => api_error(StatusCode::BAD_REQUEST, &msg),
+    })
+}
+
 async fn handle_keyword(req: HttpRequest) -> Result<HttpResponse, ServerError> {
     let query = match decode(req.match_info().query("keyword")) {
         Ok(q) if !q.is_empty() => q,
@@ -1142,7 +1416,7 @@ async fn handle_keyword(req: HttpRequest) -> Result<HttpResponse, ServerError> {
         if !is_alnum(&query) {
             return Ok::<_, anyhow::Error>((query, None));
         }
-        let keyword_query = format!("keywords:\"{query}\"");
+        let keyword_query = format!("keyword:{query}");
         let mut results = state2.index.search(&keyword_query, 250, false)?;
 
         let crates = state2.crates.load();
@@ -1178,7 +1452,26 @@ struct Rendered {
     last_modified: Option<DateTime<Utc>>,
 }
 
-fn serve_page(Rendered {page, cache_time, refresh, last_modified}: Rendered) -> HttpResponse {
+fn serve_page(rendered: Rendered) -> HttpResponse {
+    serve_with_content_type(rendered, "text/html;charset=UTF-8")
+}
+
+/// API responses use the same caching rules as HTML pages, and can be fetched cross-origin
+fn serve_json(rendered: Rendered) -> HttpResponse {
+    let mut res = serve_with_content_type(rendered, "application/json;charset=UTF-8");
+    res.headers_mut().insert(actix_web::http::header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    res
+}
+
+fn api_error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/json;charset=UTF-8")
+        .insert_header(("Access-Control-Allow-Origin", "*"))
+        .insert_header(("Cache-Control", "public, max-age=60"))
+        .body(serde_json::json!({"error": message}).to_string())
+}
+
+fn serve_with_content_type(Rendered {page, cache_time, refresh, last_modified}: Rendered, content_type: &'static str) -> HttpResponse {
     let err_max = (cache_time * 10).max(3600 * 24 * 2);
 
     let last_modified_secs = last_modified.map(|l| Utc::now().signed_duration_since(l).num_seconds().max(0) as u32).unwrap_or(0);
@@ -1193,7 +1486,7 @@ fn serve_page(Rendered {page, cache_time, refresh, last_modified}: Rendered) ->
     let etag = format!("\"{:.16}\"", base64::encode(hasher.finalize().as_bytes()));
 
     let mut h = HttpResponse::Ok();
-    h.content_type("text/html;charset=UTF-8");
+    h.content_type(content_type);
     h.insert_header(("etag", etag));
     if !refresh {
         h.insert_header(("Cache-Control", format!("public, max-age={cache_time}, stale-while-revalidate={}, stale-if-error={err_max}", cache_time * 3)));
@@ -1246,9 +1539,43 @@ async fn handle_search(req: HttpRequest) -> Result<HttpResponse, ServerError> {
     let qs = req.query_string().replace('+', "%20");
     let qs = qstring::QString::from(qs.as_str());
     match qs.get("q").unwrap_or("") {
+        q if !q.trim_start().is_empty() && qs.get("mode") == Some("code") => {
+            let state: &AServerState = req.app_data().expect("appdata");
+            let query = q.trim().to_owned();
+            let page = tokio::task::spawn_blocking({
+                let state = state.clone();
+                move || {
+                    let results = match (&state.code_index, CodeQuery::parse(&query)) {
+                        (Some(index), Some(parsed)) => {
+                            let mut results = index.search(&parsed, 100)?;
+                            let crates = state.crates.load();
+                            results.retain(|res| crates.crate_exists(&res.origin));
+                            Some(results)
+                        },
+                        (Some(_), None) => Some(Vec::new()),
+                        (None, _) => None,
+                    };
+                    let mut page = Vec::with_capacity(32000);
+                    front_end::render_code_search_page(&mut page, &query, results.as_deref())?;
+                    minify_html(&mut page);
+                    Ok::<_, anyhow::Error>(Rendered {page, cache_time: 600, refresh: false, last_modified: None})
+                }
+            }).await??;
+            Ok(serve_page(page))
+        },
         q if !q.trim_start().is_empty() => {
             let state: &AServerState = req.app_data().expect("appdata");
             let query = q.to_owned();
+            // the query may be a misspelled crate name
+            let similar_names = match Origin::try_from_crates_io_name(query.trim()) {
+                Some(origin) if !state.crates.load().crate_exists(&origin) => {
+                    let crates = state.crates.load_full();
+                    let name = query.trim().to_owned();
+                    rt_run_timeout(&state.rt, "similar", 5, async move { crates.similar_crate_names(&name, 3).await }).await
+                        .map_err(|e| warn!("similar names: {e}")).unwrap_or_default()
+                },
+                _ => Vec::new(),
+            };
             let page = tokio::task::spawn_blocking({
                 let state = state.clone();
                 move || {
@@ -1257,7 +1584,7 @@ async fn handle_search(req: HttpRequest) -> Result<HttpResponse, ServerError> {
                     results.crates.retain(|res| crates.crate_exists(&res.origin)); // search index can contain stale items
 
                     let mut page = Vec::with_capacity(32000);
-                    front_end::render_serp_page(&mut page, &query, &results, &state.markup)?;
+                    front_end::render_serp_page(&mut page, &query, &results, &similar_names, &state.markup)?;
                     minify_html(&mut page);
                     Ok::<_, anyhow::Error>(Rendered {page, cache_time: 600, refresh: false, last_modified: None})
                 }
@@ -1289,6 +1616,27 @@ async fn handle_sitemap(req: HttpRequest) -> Result<HttpResponse, ServerError> {
         .streaming::<_, ServerError>(page))
 }
 
+async fn handle_image(req: HttpRequest) -> Result<HttpResponse, ServerError> {
+    let state: &AServerState = req.app_data().expect("appdata");
+    let proxy = match &state.image_proxy {
+        Some(p) => p.clone(),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let name = req.match_info().query("name").to_owned();
+    let image = rt_run_timeout(&state.rt, "image", 30, async move {
+        Ok(proxy.image_file(&name).await)
+    }).await?;
+    Ok(match image {
+        Some((data, mime)) => HttpResponse::Ok()
+            .content_type(mime)
+            .insert_header(("Cache-Control", "public, max-age=604800"))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .no_chunking(data.len() as u64)
+            .body(data),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
 async fn handle_feed(req: HttpRequest) -> Result<HttpResponse, ServerError> {
     let state: &AServerState = req.app_data().expect("appdata");
     let state2 = state.clone();
@@ -1396,3 +1744,62 @@ fn minify_html(page: &mut Vec<u8>) {
         page.extend_from_slice(out);
     }
 }
+
+#[test]
+fn crate_names_in_404_paths() {
+    assert_eq!(Some("serde"), crates_io_name_from_path("/serde"));
+    assert_eq!(Some("serde"), crates_io_name_from_path("/crates/serde/versions"));
+    assert_eq!(Some("serde"), crates_io_name_from_path("/install/serde"));
+    assert_eq!(None, crates_io_name_from_path("/gh/owner/repo/serde"));
+    assert_eq!(None, crates_io_name_from_path("/reg/company/serde"));
+    assert_eq!(None, crates_io_name_from_path("/install/reg/company/serde"));
+}
+
+#[actix_web::test]
+async fn serves_fixture_crate_page() {
+    use actix_web::test;
+
+    let data_dir = tempfile::tempdir().unwrap();
+    let page_cache_dir = tempfile::tempdir().unwrap();
+    // KitchenSink needs a multi-threaded runtime, like in main()
+    let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let crates = rt.spawn({
+        let dir = data_dir.path().to_owned();
+        async move {
+            let crates = test_fixtures::default_crates();
+            test_fixtures::generate(&dir, crates)?;
+            test_fixtures::populate(&dir, crates).await
+        }
+    }).await.unwrap().unwrap();
+
+    let state = web::Data::new(ServerState {
+        markup: Renderer::new(None),
+        image_proxy: None,
+        index: CrateSearchIndex::new(data_dir.path()).unwrap(),
+        code_index: None,
+        crates: ArcSwap::from_pointee(crates),
+        page_cache_dir: page_cache_dir.path().to_owned(),
+        data_dir: data_dir.path().to_owned(),
+        rt: rt.handle().clone(),
+        background_job: tokio::sync::Semaphore::new(5),
+        foreground_job: tokio::sync::Semaphore::new(32),
+        start_time: Instant::now(),
+        last_ok_response: AtomicU32::new(0),
+    });
+    let app = test::init_service(App::new()
+        .app_data(state)
+        .configure(routes)
+        .default_service(web::route().to(default_handler))).await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/crates/fixture-parser").to_request()).await;
+    assert_eq!(StatusCode::OK, res.status());
+    let page = test::read_body(res).await;
+    assert!(String::from_utf8_lossy(&page).contains("A fast parser for the fixture file format"));
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/crates/fixture-parsr").to_request()).await;
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/api/v1/crates/fixture-parser").to_request()).await;
+    assert_eq!(StatusCode::OK, res.status());
+    rt.shutdown_background();
+}
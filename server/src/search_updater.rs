@@ -0,0 +1,173 @@
+//! Applies crate updates from the event log to the search index,
+//! so that new releases are searchable without waiting for the next full `reindex_crates` run.
+
+use crate::AServerState;
+use ahash::HashSet;
+use ahash::HashSetExt;
+use kitchen_sink::KitchenSink;
+use kitchen_sink::Origin;
+use kitchen_sink::SemVer;
+use kitchen_sink::SharedEvent;
+use log::{debug, error, info, warn};
+use render_readme::Renderer;
+use search_index::{CrateFilterFields, Indexer};
+use std::time::{Duration, Instant};
+
+/// Commits are expensive, so updates are batched
+const FLUSH_INTERVAL: Duration = Duration::from_secs(3 * 60);
+const FLUSH_MAX_PENDING: usize = 100;
+/// If `reindex_crates` holds the index lock for long, new events are left unread in the event log
+/// until the pending ones are written, instead of growing the queue without a limit
+const MAX_PENDING: usize = 5000;
+
+/// Crates that haven't been scored by `reindex_crates` yet
+const UNRANKED_CRATE_SCORE: f64 = 0.15;
+
+struct SearchDocument {
+    origin: Origin,
+    name: String,
+    version: String,
+    description: String,
+    keywords: Vec<String>,
+    categories: Vec<Box<str>>,
+    license: Option<String>,
+    readme: String,
+    monthly_downloads: u64,
+    score: f64,
+    msrv: Option<u16>,
+}
+
+/// Runs forever. Events are acknowledged before the index is updated,
+/// so updates lost in a crash are only caught up by a full reindex.
+pub(crate) async fn run(state: AServerState) {
+    let mut subscriber = match state.crates.load().event_log().subscribe("search index") {
+        Ok(s) => s,
+        Err(e) => {
+            error!("Search index updates disabled: {e}");
+            return;
+        },
+    };
+    let mut pending = HashSet::new();
+    let mut last_flush = Instant::now();
+    loop {
+        // timeout allows retrying pending updates when there are no new events
+        if pending.len() >= MAX_PENDING {
+            // backpressure: the events stay in the log, and will be read after the flush succeeds
+            warn!("Search index update queue is full ({} crates), waiting", pending.len());
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        } else if let Ok(batch) = tokio::time::timeout(Duration::from_secs(30), subscriber.next_batch()).await {
+            match batch {
+                Ok(batch) => for ev in batch.filter_map(|e| e.ok()) {
+                    match ev {
+                        SharedEvent::CrateIndexed(origin_str) | SharedEvent::CrateUpdated(origin_str) => {
+                            pending.insert(Origin::from_str(origin_str));
+                        },
+                        SharedEvent::CrateNeedsReindexing(_) | SharedEvent::DailyStatsUpdated(_) => {},
+                    }
+                },
+                Err(e) => {
+                    error!("search index events: {e}");
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                },
+            }
+        }
+
+        if pending.is_empty() || (pending.len() < FLUSH_MAX_PENDING && last_flush.elapsed() < FLUSH_INTERVAL) {
+            continue;
+        }
+        last_flush = Instant::now();
+        match update_search_index(&state, &pending).await {
+            Ok(()) => pending.clear(),
+            Err(e) => {
+                // most likely reindex_crates is running and holds the lock
+                warn!("Search index update of {} crates postponed: {e}", pending.len());
+            },
+        }
+    }
+}
+
+async fn update_search_index(state: &AServerState, origins: &HashSet<Origin>) -> Result<(), anyhow::Error> {
+    let crates = state.crates.load_full();
+    let mut deleted = Vec::new();
+    let mut docs = Vec::with_capacity(origins.len());
+    for origin in origins {
+        if !crates.crate_exists(origin) {
+            deleted.push(origin.clone());
+            continue;
+        }
+        match tokio::time::timeout(Duration::from_secs(60), search_document(&crates, origin, &state.markup)).await {
+            Ok(Ok(Some(doc))) => docs.push(doc),
+            Ok(Ok(None)) => deleted.push(origin.clone()),
+            Ok(Err(e)) => warn!("Can't update search index for {origin:?}: {e}"),
+            Err(_) => warn!("Search document for {origin:?} timed out"),
+        }
+    }
+
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut indexer = Indexer::new_incremental(&state.index)?;
+        for origin in &deleted {
+            debug!("Removing {origin:?} from search");
+            indexer.delete(origin);
+        }
+        for d in &docs {
+            let keywords: Vec<_> = d.keywords.iter().map(|k| k.as_str()).collect();
+            let category_slugs: Vec<_> = d.categories.iter().map(|c| &**c).collect();
+            let filter_fields = CrateFilterFields {
+                category_slugs: &category_slugs,
+                license: d.license.as_deref(),
+                msrv: d.msrv,
+            };
+            indexer.add(&d.origin, &d.name, &d.version, &d.description, &keywords,
+                Some(d.readme.as_str()).filter(|s| !s.trim_start().is_empty()), d.monthly_downloads, d.score, &filter_fields)?;
+        }
+        indexer.commit()?;
+        indexer.bye()?;
+        info!("Search index updated: {} crates, {} removed", docs.len(), deleted.len());
+        Ok(())
+    }).await?
+}
+
+/// `None` if the crate shouldn't be in search results
+async fn search_document(crates: &KitchenSink, origin: &Origin, renderer: &Renderer) -> Result<Option<SearchDocument>, anyhow::Error> {
+    let (all, ver) = futures::try_join!(crates.rich_crate_async(origin), crates.rich_crate_version_async(origin))?;
+    if ver.is_yanked() {
+        return Ok(None);
+    }
+
+    let (downloads, score, compat) = futures::join!(
+        crates.downloads_per_month_or_equivalent(origin),
+        crates.crate_db.crate_rank(origin),
+        crates.rustc_compatibility(&all),
+    );
+    let msrv = compat.ok().and_then(|compat| {
+        let semver: SemVer = ver.version().parse().ok()?;
+        let c = compat.get(&semver)?;
+        c.msrv()
+    });
+
+    // not deduplicated against other crates like in reindex_crates, but it's good enough until the next full reindex
+    let mut readme = String::new();
+    if let Some(markup) = ver.readme().map(|readme| &readme.markup) {
+        for (s, par) in &renderer.visible_text_by_section(markup) {
+            readme.push_str(s);
+            readme.push('\n');
+            readme.push_str(par);
+            readme.push('\n');
+        }
+    }
+
+    Ok(Some(SearchDocument {
+        origin: origin.clone(),
+        name: ver.short_name().into(),
+        version: ver.version().into(),
+        description: ver.description().unwrap_or("").into(),
+        keywords: ver.keywords().to_vec(),
+        categories: ver.category_slugs().to_vec(),
+        license: ver.license().map(From::from),
+        readme,
+        monthly_downloads: downloads.ok().flatten().unwrap_or(0) as u64,
+        score: score.unwrap_or(UNRANKED_CRATE_SCORE),
+        msrv,
+    }))
+}
@@ -1,7 +1,9 @@
+pub use api_surface::{is_compatible_upgrade, ApiChange, ApiSurface, SemverViolation};
 use cargo_toml::Manifest;
 use cargo_toml::Package;
 use libflate::gzip::Decoder;
 use render_readme::Markup;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::io;
 use std::io::Read;
@@ -11,6 +13,9 @@ use tar::{Archive, Entry, EntryType};
 use log::debug;
 use udedokei::LanguageExt;
 
+mod public_items;
+pub use crate::public_items::*;
+
 #[derive(Debug, thiserror::Error)]
 pub enum UnarchiverError {
     #[error("Cargo.toml not found.\nGot files: {0}")]
@@ -47,6 +52,8 @@ enum ReadAs {
 }
 
 const MAX_FILE_SIZE: u64 = 50_000_000;
+/// Generated bindings can be huge, and aren't worth parsing
+const MAX_LIB_SOURCES_SIZE: usize = 5_000_000;
 
 pub fn read_repo(repo: &crate_git_checkout::Repository, path_in_tree: crate_git_checkout::Oid) -> Result<CrateFilesSummary, UnarchiverError> {
     let mut collect = Collector::new(0);
@@ -97,6 +104,32 @@ pub struct CrateFilesSummary {
 
     /// From all code-like files
     pub language_stats: udedokei::Stats,
+
+    /// Library's Rust sources, parsed only on demand
+    pub lib_sources: Option<LibSources>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LibSources {
+    /// Root file, e.g. `src/lib.rs`
+    pub lib_path: PathBuf,
+    /// By path relative to the crate root
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl CrateFilesSummary {
+    /// Signatures of the public API, for comparing versions.
+    ///
+    /// Parses all library sources, so it's slow and not done when reading the tarball.
+    pub fn api_surface(&self) -> Option<ApiSurface> {
+        let lib = self.lib_sources.as_ref()?;
+        ApiSurface::from_sources(&lib.lib_path, &lib.files)
+    }
+
+    /// Names of `pub` items from library sources. Parses the sources, like `api_surface()`.
+    pub fn public_items(&self) -> Vec<PublicItem> {
+        self.api_surface().map(|s| public_items(&s)).unwrap_or_default()
+    }
 }
 
 struct Collector {
@@ -115,6 +148,11 @@ struct Collector {
 
     // From all code-like files
     stats: udedokei::Collect,
+
+    lib_path: Option<PathBuf>,
+    /// For `ApiSurface`
+    lib_sources: BTreeMap<PathBuf, String>,
+    lib_sources_size: usize,
 }
 
 impl Collector {
@@ -131,6 +169,9 @@ impl Collector {
             is_nightly: false,
             vcs_info_path: None,
             vcs_info_git_sha1: None,
+            lib_path: None,
+            lib_sources: BTreeMap::new(),
+            lib_sources_size: 0,
         }
     }
 
@@ -163,6 +204,8 @@ impl Collector {
             }
         };
 
+        let is_lib_source = is_lib_source_path(&relpath);
+        let source_path = if path_match == ReadAs::Lib || is_lib_source { Some(relpath.clone()) } else { None };
         self.files.push(relpath);
         if path_match == ReadAs::Skip {
             return Ok(());
@@ -184,6 +227,8 @@ impl Collector {
                 if check_if_uses_nightly_features(&data) {
                     self.is_nightly = true;
                 }
+                self.lib_path = source_path.clone();
+                self.add_lib_source(source_path, &data);
                 self.lib_file = Some(data);
             },
             ReadAs::Bin => {
@@ -209,12 +254,24 @@ impl Collector {
             },
             ReadAs::GetStatsOfFile(lang) => {
                 self.stats.add_to_stats(lang, &data);
+                if lang == udedokei::Language::Rust && is_lib_source {
+                    self.add_lib_source(source_path, &data);
+                }
             },
             ReadAs::Skip => unreachable!(),
         }
         Ok(())
     }
 
+    fn add_lib_source(&mut self, path: Option<PathBuf>, data: &str) {
+        if let Some(path) = path {
+            if self.lib_sources_size + data.len() <= MAX_LIB_SOURCES_SIZE {
+                self.lib_sources_size += data.len();
+                self.lib_sources.insert(path, data.to_owned());
+            }
+        }
+    }
+
     fn finish(self) -> Result<CrateFilesSummary, UnarchiverError> {
         let mut manifest = match self.manifest {
             Some(m) => m,
@@ -235,6 +292,7 @@ impl Collector {
             is_nightly: self.is_nightly,
             path_in_repo: self.vcs_info_path,
             vcs_info_git_sha1: self.vcs_info_git_sha1,
+            lib_sources: self.lib_path.map(|lib_path| LibSources { lib_path, files: self.lib_sources }),
         })
     }
 }
@@ -316,6 +374,11 @@ fn is_bin_filename(path: &Path, manifest: Option<&Manifest>) -> bool {
     path == Path::new("src/main.rs")
 }
 
+/// Modules of the library, not binaries or build scripts
+fn is_lib_source_path(path: &Path) -> bool {
+    path.starts_with("src") && !path.starts_with("src/bin") && path != Path::new("src/main.rs")
+}
+
 /// Check if given filename is a README. If `package` is missing, guess.
 fn is_readme_filename(path: &Path, package: Option<&Package>) -> bool {
     path.to_str().map_or(false, |pathstr| {
@@ -0,0 +1,102 @@
+//! Names of public items in Rust source code, for searching.
+//!
+//! They're taken from the `ApiSurface`, so only items reachable from the crate root are found.
+//! Methods are reported as functions.
+
+use api_surface::ApiSurface;
+use std::fmt;
+
+/// Crates with generated bindings can have tens of thousands of items, and they're not interesting to search.
+const MAX_ITEMS_PER_CRATE: usize = 5000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ItemKind {
+    Fn,
+    Struct,
+    Enum,
+    Union,
+    Trait,
+    Type,
+    /// `#[macro_export] macro_rules!`
+    Macro,
+}
+
+impl ItemKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fn => "fn",
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::Union => "union",
+            Self::Trait => "trait",
+            Self::Type => "type",
+            Self::Macro => "macro",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Some(match s {
+            "fn" => Self::Fn,
+            "struct" => Self::Struct,
+            "enum" => Self::Enum,
+            "union" => Self::Union,
+            "trait" => Self::Trait,
+            "type" => Self::Type,
+            "macro" | "macro_rules" => Self::Macro,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PublicItem {
+    pub kind: ItemKind,
+    pub name: String,
+}
+
+/// Names of items in the public API, skipping duplicates
+pub fn public_items(surface: &ApiSurface) -> Vec<PublicItem> {
+    let mut out: Vec<_> = surface.item_names()
+        .filter_map(|(kind, name)| Some(PublicItem { kind: ItemKind::from_str(kind)?, name: name.trim_start_matches("r#").into() }))
+        .collect();
+    out.sort_unstable();
+    out.dedup();
+    out.truncate(MAX_ITEMS_PER_CRATE);
+    out
+}
+
+#[test]
+fn finds_public_items() {
+    let src = r##"
+        //! pub fn in_docs() {}
+        pub struct Foo<'a> { pub field: &'a str }
+        pub(crate) fn private() {}
+        fn also_private() -> [char; 2] { ['"', '\''] }
+        pub const fn konst() {}
+        pub const NOT_A_FN: &str = "pub fn in_string()";
+        pub unsafe extern "C" fn ffi() {}
+        /* pub trait /* nested */ Commented {} */
+        pub trait Service: Send {}
+        pub type r#Type = Foo<'static>;
+        pub(super) enum Hidden {}
+        mod private_mod { pub fn unreachable() {} }
+        #[macro_export]
+        macro_rules! exported { () => {} }
+        macro_rules! not_exported { () => {} }
+        pub use foo::bar;
+        impl Foo<'_> { pub async fn decode_png(&self) {} pub fn new() {} }
+        struct NotPub;
+        impl NotPub { pub fn method_of_private_type() {} }
+        pub fn new() {}
+    "##;
+    let files = [(std::path::PathBuf::from("src/lib.rs"), src.to_string())].into_iter().collect();
+    let surface = ApiSurface::from_sources(std::path::Path::new("src/lib.rs"), &files).unwrap();
+    let items: Vec<_> = public_items(&surface).iter().map(|i| format!("{} {}", i.kind, i.name)).collect();
+    assert_eq!(items, ["fn decode_png", "fn ffi", "fn konst", "fn new", "struct Foo", "trait Service", "type Type", "macro exported"]);
+}
@@ -0,0 +1,318 @@
+//! Generates a small, self-consistent data dir for hermetic tests, so that `KitchenSink`,
+//! the search index, and the front end can be used without the real data dump or network access.
+//!
+//! The data dir has `site.toml` with `offline = true`, and contains:
+//!
+//! * a sparse crates.io index (`index-sparse/`) and `.crate` tarballs in `tarballs/`,
+//! * crates.io API metadata in `cratesiometadb.bin`,
+//! * RustSec advisories in `rustsec/`,
+//! * tag synonyms and other small config files.
+//!
+//! `populate` then indexes the crates, their owners and download stats in `crate_data.db`, `users.db`,
+//! and the search index, using the same `KitchenSink` methods as the real indexer.
+
+use anyhow::Context;
+use chrono::prelude::*;
+use chrono::Duration;
+use crates_io_client::{CrateMeta, CrateMetaFile, CrateMetaVersion};
+use kitchen_sink::{index_path, CrateOwner, KitchenSink, Origin, OwnerKind, SiteConfig};
+use libflate::gzip::Encoder;
+use search_index::{CrateFilterFields, CrateSearchIndex, Indexer};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use simple_cache::SimpleCache;
+use std::fs;
+use std::path::Path;
+
+pub type CResult<T> = anyhow::Result<T>;
+
+/// Never contacted, because the fixture is offline
+const FAKE_INDEX_URL: &str = "sparse+http://127.0.0.1:9/";
+
+pub struct FixtureCrate {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keywords: &'static [&'static str],
+    /// crates.io category slugs
+    pub categories: &'static [&'static str],
+    pub license: &'static str,
+    /// GitHub login and id of the only owner
+    pub owner: (&'static str, u32),
+    /// Oldest first
+    pub versions: &'static [FixtureVersion],
+    /// For every day of the last `DOWNLOAD_DAYS`, split evenly across non-yanked versions
+    pub daily_downloads: u32,
+    /// Versions before this one get a RustSec advisory
+    pub vulnerable_before: Option<&'static str>,
+}
+
+pub struct FixtureVersion {
+    pub num: &'static str,
+    /// Name and requirement of a normal dependency
+    pub deps: &'static [(&'static str, &'static str)],
+    pub yanked: bool,
+    pub days_ago: i64,
+}
+
+/// How much download history is generated
+pub const DOWNLOAD_DAYS: i64 = 60;
+
+/// A few crates that depend on each other, with a yanked version and a security advisory
+pub fn default_crates() -> &'static [FixtureCrate] {
+    &[
+        FixtureCrate {
+            name: "fixture-core",
+            description: "Core traits for the fixture parser",
+            keywords: &["parser", "traits"],
+            categories: &["parsing"],
+            license: "MIT OR Apache-2.0",
+            owner: ("alice", 1001),
+            versions: &[
+                FixtureVersion { num: "0.1.0", deps: &[], yanked: false, days_ago: 400 },
+                FixtureVersion { num: "0.2.0", deps: &[], yanked: false, days_ago: 200 },
+                FixtureVersion { num: "1.0.0", deps: &[], yanked: false, days_ago: 30 },
+            ],
+            daily_downloads: 900,
+            vulnerable_before: None,
+        },
+        FixtureCrate {
+            name: "fixture-parser",
+            description: "A fast parser for the fixture file format",
+            keywords: &["parser", "fixture", "format"],
+            categories: &["parsing", "encoding"],
+            license: "MIT",
+            owner: ("alice", 1001),
+            versions: &[
+                FixtureVersion { num: "0.1.0", deps: &[("fixture-core", "0.2")], yanked: false, days_ago: 190 },
+                FixtureVersion { num: "0.1.1", deps: &[("fixture-core", "0.2")], yanked: true, days_ago: 100 },
+                FixtureVersion { num: "0.2.0", deps: &[("fixture-core", "1.0")], yanked: false, days_ago: 20 },
+            ],
+            daily_downloads: 300,
+            vulnerable_before: Some("0.2.0"),
+        },
+        FixtureCrate {
+            name: "fixture-cli",
+            description: "Command-line tool for converting fixture files",
+            keywords: &["cli", "fixture"],
+            categories: &["command-line-utilities"],
+            license: "Apache-2.0",
+            owner: ("bob", 1002),
+            versions: &[
+                FixtureVersion { num: "0.5.0", deps: &[("fixture-parser", "0.2"), ("fixture-core", "1")], yanked: false, days_ago: 10 },
+            ],
+            daily_downloads: 20,
+            vulnerable_before: None,
+        },
+    ]
+}
+
+/// Writes files for the crates to `dir`. Run `populate` afterwards to fill the databases.
+pub fn generate(dir: &Path, crates: &[FixtureCrate]) -> CResult<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("site.toml"), "offline = true\n")?;
+    fs::write(dir.join("registries.toml"), format!("[crates-io]\nindex = \"{FAKE_INDEX_URL}\"\n"))?;
+    fs::write(dir.join("tag-synonyms.csv"), "# FIND, REPLACE, votes\nparsing,parser,5\nparsers,parser,5\ncommand-line,cli,5\n")?;
+    for empty in ["category_overrides.txt", "ablocklist.csv", "git_crates.txt", "crate_data.db", "users.db"] {
+        fs::write(dir.join(empty), "")?;
+    }
+    for collection in ["crates", "rust"] {
+        fs::create_dir_all(dir.join("rustsec").join(collection))?;
+    }
+
+    let index_dir = dir.join("index-sparse");
+    fs::create_dir_all(&index_dir)?;
+    fs::write(index_dir.join("config.json"), r#"{"dl":"http://127.0.0.1:9/crates","api":"http://127.0.0.1:9"}"#)?;
+
+    let meta_cache = SimpleCache::new(dir.join("cratesiometadb.bin"), true)?;
+    let now = Utc::now();
+    let mut next_version_id = 1;
+    for c in crates {
+        let latest = c.versions.last().context("fixture crate without versions")?;
+        let mut index_lines = String::new();
+        let mut meta_versions = Vec::with_capacity(c.versions.len());
+        for v in c.versions {
+            let tarball = crate_tarball(c, v)?;
+            let tarball_dir = dir.join("tarballs").join(c.name);
+            fs::create_dir_all(&tarball_dir)?;
+            fs::write(tarball_dir.join(format!("{}.crate", v.num)), &tarball)?;
+
+            index_lines.push_str(&index_line(c, v, &tarball));
+            index_lines.push('\n');
+
+            let date = (now - Duration::days(v.days_ago)).to_rfc3339();
+            meta_versions.push(CrateMetaVersion {
+                id: next_version_id,
+                krate: c.name.into(),
+                num: v.num.into(),
+                updated_at: date.clone().into(),
+                created_at: date.into(),
+                downloads: (c.daily_downloads as i64 * v.days_ago.min(DOWNLOAD_DAYS)) as usize,
+                features: Default::default(),
+                yanked: v.yanked,
+                license: Some(c.license.into()),
+                audit_actions: vec![],
+            });
+            next_version_id += 1;
+        }
+        let index_file = index_dir.join(index_path(c.name));
+        fs::create_dir_all(index_file.parent().unwrap())?;
+        fs::write(index_file, index_lines)?;
+
+        let meta = CrateMetaFile {
+            krate: CrateMeta {
+                id: c.name.into(),
+                name: c.name.into(),
+                updated_at: meta_versions.last().unwrap().updated_at.clone(),
+                versions: meta_versions.iter().rev().map(|v| v.id).collect(),
+                keywords: c.keywords.iter().map(|&k| k.into()).collect(),
+                categories: c.categories.iter().map(|&k| k.into()).collect(),
+                badges: vec![],
+                created_at: meta_versions[0].created_at.clone(),
+                recent_downloads: Some(c.daily_downloads as usize * 90),
+                max_version: latest.num.into(),
+                description: Some(c.description.into()),
+                homepage: None,
+                documentation: None,
+                repository: None,
+            },
+            versions: meta_versions,
+            keywords: vec![],
+            categories: vec![],
+        };
+        // CratesIoClient looks it up by the latest version in the index
+        meta_cache.set_serialize((c.name, latest.num), &meta)?;
+
+        if let Some(patched) = c.vulnerable_before {
+            let advisory_dir = dir.join("rustsec/crates").join(c.name);
+            fs::create_dir_all(&advisory_dir)?;
+            let id = format!("RUSTSEC-2020-{:04}", next_version_id);
+            fs::write(advisory_dir.join(format!("{id}.md")), advisory(&id, c.name, patched))?;
+        }
+    }
+    Ok(())
+}
+
+/// Indexes the generated crates in the data dir's databases and the search index
+pub async fn populate(dir: &Path, crates: &[FixtureCrate]) -> CResult<KitchenSink> {
+    let ks = KitchenSink::new_with_config(dir, SiteConfig::load(dir)?).await?;
+
+    let today = Utc::today();
+    let all_owners = crates.iter().map(|c| (Origin::from_crates_io_name(c.name), vec![owner(c.owner)])).collect();
+    ks.index_crates_io_crate_all_owners(all_owners).await?;
+
+    for c in crates {
+        let live_versions: Vec<_> = c.versions.iter().filter(|v| !v.yanked).collect();
+        let per_version = c.daily_downloads / live_versions.len().max(1) as u32;
+        let days: Vec<_> = (0..DOWNLOAD_DAYS).map(|n| (today - Duration::days(n), per_version, true)).collect();
+        let by_ver = live_versions.iter().map(|v| (v.num, &days[..])).collect();
+        ks.index_crate_downloads(c.name, &by_ver)?;
+    }
+
+    let search = CrateSearchIndex::new(dir)?;
+    let mut indexer = Indexer::new(&search)?;
+    for c in crates {
+        let origin = Origin::from_crates_io_name(c.name);
+        let k = ks.rich_crate_async(&origin).await?;
+        ks.index_crate(&k, 0.5).await?;
+        ks.index_crate_highest_version(&origin, false).await?;
+
+        let latest = c.versions.iter().rev().find(|v| !v.yanked).unwrap_or(&c.versions[c.versions.len() - 1]);
+        let filter_fields = CrateFilterFields { category_slugs: c.categories, license: Some(c.license), msrv: None };
+        indexer.add(&origin, c.name, latest.num, c.description, c.keywords, Some(&readme(c)), c.daily_downloads as u64 * 30, 0.5, &filter_fields)?;
+    }
+    indexer.commit()?;
+    indexer.bye()?;
+    Ok(ks)
+}
+
+fn owner((login, github_id): (&str, u32)) -> CrateOwner {
+    CrateOwner {
+        crates_io_login: login.into(),
+        kind: OwnerKind::User,
+        url: Some(format!("https://github.com/{login}").into()),
+        name: Some(login.into()),
+        avatar: None,
+        github_id: Some(github_id),
+        invited_at: None,
+        invited_by_github_id: None,
+        last_seen_at: None,
+        contributor_only: false,
+    }
+}
+
+fn readme(c: &FixtureCrate) -> String {
+    format!("# {}\n\n{}.\n\n```rust\nuse {};\n```\n", c.name, c.description, c.name.replace('-', "_"))
+}
+
+fn manifest(c: &FixtureCrate, v: &FixtureVersion) -> String {
+    let list = |items: &[&str]| items.iter().map(|i| format!("{i:?}")).collect::<Vec<_>>().join(", ");
+    let mut toml = format!("[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\ndescription = \"{}\"\nlicense = \"{}\"\nreadme = \"README.md\"\nkeywords = [{}]\ncategories = [{}]\n\n[dependencies]\n",
+        c.name, v.num, c.description, c.license, list(c.keywords), list(c.categories));
+    for (name, req) in v.deps {
+        toml.push_str(&format!("{name} = \"{req}\"\n"));
+    }
+    toml
+}
+
+/// `.crate` file, which is a gzipped tarball with files in a `name-version/` dir
+fn crate_tarball(c: &FixtureCrate, v: &FixtureVersion) -> CResult<Vec<u8>> {
+    let lib_rs = format!("//! {}\n\npub fn version() -> &'static str {{\n    \"{}\"\n}}\n", c.description, v.num);
+    let files = [("Cargo.toml", manifest(c, v)), ("README.md", readme(c)), ("src/lib.rs", lib_rs)];
+
+    let mut tar = tar::Builder::new(Encoder::new(Vec::new())?);
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, format!("{}-{}/{path}", c.name, v.num), content.as_bytes())?;
+    }
+    Ok(tar.into_inner()?.finish().into_result()?)
+}
+
+fn index_line(c: &FixtureCrate, v: &FixtureVersion, tarball: &[u8]) -> String {
+    let deps: Vec<_> = v.deps.iter().map(|(name, req)| json!({
+        "name": name, "req": req, "features": [], "optional": false,
+        "default_features": true, "target": null, "kind": "normal",
+    })).collect();
+    json!({
+        "name": c.name, "vers": v.num, "deps": deps,
+        "cksum": hex::encode(Sha256::digest(tarball)),
+        "features": {}, "yanked": v.yanked, "links": null,
+    }).to_string()
+}
+
+fn advisory(id: &str, crate_name: &str, patched: &str) -> String {
+    format!("```toml\n[advisory]\nid = \"{id}\"\npackage = \"{crate_name}\"\ndate = \"2020-01-01\"\n\
+        categories = [\"memory-corruption\"]\n\n[versions]\npatched = [\">= {patched}\"]\n```\n\n\
+        # Buffer overflow in {crate_name}\n\nParsing of untrusted input could write out of bounds.\n")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hermetic_kitchen_sink() {
+    let dir = tempfile::tempdir().unwrap();
+    let crates = default_crates();
+    generate(dir.path(), crates).unwrap();
+    let ks = populate(dir.path(), crates).await.unwrap();
+
+    let origin = Origin::from_crates_io_name("fixture-parser");
+    let k = ks.rich_crate_version_async(&origin).await.unwrap();
+    assert_eq!("0.2.0", k.version());
+    assert_eq!(Some("A fast parser for the fixture file format"), k.description());
+
+    let owners = ks.crate_owners(&origin, kitchen_sink::CrateOwners::Strict).await.unwrap();
+    assert_eq!("alice", owners[0].crates_io_login);
+    assert!(ks.downloads_per_month(&origin).await.unwrap().unwrap() > 0);
+    assert_eq!(1, ks.advisories_for_crate(&origin).len());
+
+    let search = CrateSearchIndex::new(dir.path()).unwrap();
+    let found = search.search("parser", 10, true).unwrap();
+    assert_eq!("fixture-parser", found.crates[0].crate_name);
+
+    let all = ks.rich_crate_async(&origin).await.unwrap();
+    let mut page = Vec::new();
+    front_end::render_crate_page(&mut page, &all, &k, &ks, &render_readme::Renderer::new(None)).await.unwrap();
+    let page = String::from_utf8(page).unwrap();
+    assert!(page.contains("fixture-parser"));
+    assert!(page.contains("A fast parser for the fixture file format"));
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+/// Creates a fixture data dir, which can be used with `CRATES_DATA_DIR=<dir>` to run the server offline
+#[tokio::main]
+async fn main() -> test_fixtures::CResult<()> {
+    let dir = match std::env::args_os().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("Usage: make_fixture <empty data dir>");
+            std::process::exit(1);
+        },
+    };
+    if dir.join("crate_data.db").exists() {
+        eprintln!("{} already has a data dir", dir.display());
+        std::process::exit(1);
+    }
+    let crates = test_fixtures::default_crates();
+    test_fixtures::generate(&dir, crates)?;
+    let ks = test_fixtures::populate(&dir, crates).await?;
+    ks.cleanup();
+    println!("Fixture with {} crates written to {}", crates.len(), dir.display());
+    Ok(())
+}
@@ -3,6 +3,7 @@ use chrono::Utc;
 use feat_extractor::{is_deprecated_requirement, is_squatspam};
 use kitchen_sink::CResult;
 use kitchen_sink::Edition;
+use kitchen_sink::FeatureSet;
 use kitchen_sink::KitchenSink;
 use kitchen_sink::MaintenanceStatus;
 use kitchen_sink::Origin;
@@ -15,6 +16,7 @@ use kitchen_sink::Warning;
 use semver::Op;
 use semver::Version as SemVer;
 use ahash::HashSet;
+use std::time::Duration;
 
 pub async fn warnings_for_crate(c: &KitchenSink, k: &RichCrateVersion, all: &RichCrate) -> CResult<HashSet<Warning>> {
     if k.category_slugs().iter().any(|c| &**c == "cryptography::cryptocurrencies") {
@@ -34,6 +36,22 @@ pub async fn warnings_for_crate(c: &KitchenSink, k: &RichCrateVersion, all: &Ric
         return Ok(warnings);
     }
 
+    // crates with a long history are not typosquatting
+    if all.versions().len() < 5 {
+        if let Ok(found) = c.name_confusions(k.origin()).await {
+            if let Some(top) = found.into_iter().find(|f| f.risk >= 0.3) {
+                warnings.insert(Warning::ConfusableName(top.similar_to.as_str().into(), top.kind));
+            }
+        }
+    }
+
+    // needs tarballs of older versions
+    if let Ok(Ok(violations)) = tokio::time::timeout(Duration::from_secs(30), c.semver_violations(all, 3)).await {
+        if let Some(v) = violations.into_iter().next() {
+            warnings.insert(Warning::SemverBreak(v.version.into(), v.prev_version.into(), v.changes.len() as u32));
+        }
+    }
+
     let bad_ver = all.versions().iter()
         .filter(|v| !v.yanked)
         .find_map(|v| match SemVer::parse(&v.num) {
@@ -138,6 +156,17 @@ pub async fn warnings_for_crate(c: &KitchenSink, k: &RichCrateVersion, all: &Ric
         warnings.insert(Warning::DocsRs);
     }
     let (runtime, dev, build) = k.direct_dependencies();
+    if let Ok(ver) = k.version_semver() {
+        for f in c.feature_set_build_failures(k.origin(), &ver).unwrap_or_default() {
+            warnings.insert(match f.feature_set {
+                FeatureSet::MinimalVersions => {
+                    let dep = f.reason.as_deref().and_then(|r| dependency_blamed_for_build_failure(r, runtime.iter().chain(&build)));
+                    Warning::MinimalVersionsBuildFails(dep.map(Box::from), f.rustc_version)
+                },
+                other => Warning::FeatureSetBuildFails(other.to_string().into(), f.rustc_version),
+            });
+        }
+    }
     warn_outdated_deps(&runtime, &mut warnings, c).await;
     warn_outdated_deps(&build, &mut warnings, c).await;
     warn_bad_requirements(k, &runtime, &mut warnings, c).await;
@@ -149,8 +178,22 @@ pub async fn warnings_for_crate(c: &KitchenSink, k: &RichCrateVersion, all: &Ric
     Ok(warnings)
 }
 
+/// Reasons of broken dependencies have "name@version: error" lines, and errors in the crate itself may mention paths of dependencies
+fn dependency_blamed_for_build_failure<'a>(reason: &str, mut dependencies: impl Iterator<Item = &'a RichDep> + Clone) -> Option<&'a str> {
+    let broken_dep = reason.lines().find_map(|line| {
+        let name = line.split_once('@')?.0;
+        dependencies.clone().find(|d| &*d.package == name)
+    });
+    broken_dep.or_else(|| dependencies.find(|d| {
+        let lib_name = d.user_alias.replace('-', "_");
+        reason.contains(&format!("`{lib_name}")) || reason.contains(&format!("{lib_name}::"))
+    })).map(|d| &*d.package)
+}
+
 fn find_most_recent_release<'a>(versions: &'a [(SemVer, &CrateVersion)], pre: bool) -> Option<(&'a SemVer, DateTime<Utc>)> {
-    versions.iter().filter(move |(v, _)| pre != v.pre.is_empty()).max_by(|a,b| a.1.created_at.cmp(&b.1.created_at)).map(|(v, c)| (v, c.created_at))
+    versions.iter().filter(move |(v, _)| pre != v.pre.is_empty())
+        .filter_map(|(v, c)| Some((v, c.created_at?)))
+        .max_by_key(|&(_, date)| date)
 }
 
 async fn warn_bad_requirements(k: &RichCrateVersion, dependencies: &[RichDep], warnings: &mut HashSet<Warning>, c: &KitchenSink) {
@@ -211,6 +254,10 @@ async fn warn_outdated_deps(dependencies: &[RichDep], warnings: &mut HashSet<War
             }
             let origin = Origin::from_crates_io_name(&richdep.package);
             if let Ok(Some(pop)) = c.version_popularity(&origin, &req).await {
+                if pop.only_yanked {
+                    warnings.insert(Warning::YankedDependency(richdep.package.clone(), richdep.dep.req().into()));
+                    continue;
+                }
                 if pop.deprecated || (pop.lost_popularity && pop.pop < 0.2) {
                     warnings.insert(Warning::DeprecatedDependency(richdep.package.clone(), richdep.dep.req().into()));
                     continue;